@@ -0,0 +1,203 @@
+//! A file-backed [`SessionStore`] for `sansio-mqtt-v5-protocol`.
+//!
+//! [`FileSessionStore`] keeps the session as an append-only log: a snapshot
+//! written with [`ClientSession::to_bytes`] followed by one record per
+//! [`SessionChange`]. Each record is synced to disk before
+//! [`SessionStore::record`] returns. Once enough records pile up, the log is
+//! compacted into a fresh snapshot, written to a temporary file and renamed
+//! over the log so a crash leaves either the old or the new one.
+//!
+//! Every entry is framed as a big-endian `u32` length followed by its bytes.
+//! A frame cut short by a crash is discarded when the log is opened.
+
+#![forbid(unsafe_code)]
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use sansio_mqtt_v5_protocol::ClientSession;
+use sansio_mqtt_v5_protocol::Error;
+use sansio_mqtt_v5_protocol::SessionChange;
+use sansio_mqtt_v5_protocol::SessionStore;
+
+/// A [`SessionStore`] persisting the session to an append-only log file.
+#[derive(Debug)]
+pub struct FileSessionStore {
+    path: PathBuf,
+    file: File,
+    /// Length of the log up to its last complete frame.
+    len: u64,
+    session: ClientSession,
+    records: usize,
+    compact_after: usize,
+}
+
+impl FileSessionStore {
+    /// Records appended after the snapshot before the log is compacted,
+    /// unless changed with [`Self::set_compact_after`].
+    pub const DEFAULT_COMPACT_AFTER: usize = 1024;
+
+    /// Opens the log at `path`, creating it with an empty session if it does
+    /// not exist, and replays it.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if a complete entry cannot
+    /// be decoded.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        let mut frames = Frames {
+            bytes: &contents,
+            offset: 0,
+        };
+        let Some(snapshot) = frames.next() else {
+            let mut store = Self {
+                path,
+                file,
+                len: 0,
+                session: ClientSession::default(),
+                records: 0,
+                compact_after: Self::DEFAULT_COMPACT_AFTER,
+            };
+            store.compact()?;
+            return Ok(store);
+        };
+        let mut session = ClientSession::from_bytes(snapshot).map_err(invalid_data)?;
+        let mut records = 0;
+        for record in frames.by_ref() {
+            session.apply(&SessionChange::from_bytes(record).map_err(invalid_data)?);
+            records += 1;
+        }
+
+        let len = u64::try_from(frames.offset).expect("file offsets fit in a u64");
+        if len < u64::try_from(contents.len()).expect("file offsets fit in a u64") {
+            tracing::warn!(path = %path.display(), "discarding incomplete session log entry");
+            file.set_len(len)?;
+            file.sync_data()?;
+        }
+
+        Ok(Self {
+            file: OpenOptions::new().append(true).open(&path)?,
+            path,
+            len,
+            session,
+            records,
+            compact_after: Self::DEFAULT_COMPACT_AFTER,
+        })
+    }
+
+    /// Compacts the log once `records` changes have been appended after its
+    /// snapshot.
+    pub fn set_compact_after(&mut self, records: usize) {
+        self.compact_after = records;
+    }
+
+    /// The session rebuilt from the log, to hand to
+    /// [`Client::with_settings_and_session`](sansio_mqtt_v5_protocol::Client::with_settings_and_session)
+    /// on startup.
+    pub fn session(&self) -> &ClientSession {
+        &self.session
+    }
+
+    /// Replaces the log with a single snapshot of the current session.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut temporary_path = OsString::from(&self.path);
+        temporary_path.push(".compact");
+        let temporary_path = PathBuf::from(temporary_path);
+
+        let mut frame = Vec::new();
+        push_frame(&mut frame, &self.session.to_bytes());
+        let mut temporary = File::create(&temporary_path)?;
+        temporary.write_all(&frame)?;
+        temporary.sync_all()?;
+        drop(temporary);
+        std::fs::rename(&temporary_path, &self.path)?;
+        #[cfg(unix)]
+        if let Some(directory) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(directory)?.sync_all()?;
+        }
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.len = u64::try_from(frame.len()).expect("file offsets fit in a u64");
+        self.records = 0;
+        Ok(())
+    }
+
+    fn append(&mut self, change: &SessionChange) -> io::Result<()> {
+        let mut frame = Vec::new();
+        push_frame(&mut frame, &change.to_bytes());
+        let written = self
+            .file
+            .write_all(&frame)
+            .and_then(|()| self.file.sync_data());
+        if let Err(err) = written {
+            // Drop whatever part of the frame reached the file so later
+            // records do not land after a torn one.
+            let _ = self.file.set_len(self.len);
+            return Err(err);
+        }
+        self.len += u64::try_from(frame.len()).expect("file offsets fit in a u64");
+        self.records += 1;
+        Ok(())
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn record(&mut self, change: &SessionChange) -> Result<(), Error> {
+        if let Err(err) = self.append(change) {
+            tracing::error!(path = %self.path.display(), %err, "failed to append to session log");
+            return Err(Error::SessionStoreFailed);
+        }
+        self.session.apply(change);
+        if self.records >= self.compact_after
+            && let Err(err) = self.compact()
+        {
+            // The change is already durable in the uncompacted log.
+            tracing::warn!(path = %self.path.display(), %err, "failed to compact session log");
+        }
+        Ok(())
+    }
+}
+
+fn push_frame(out: &mut Vec<u8>, entry: &[u8]) {
+    let len = u32::try_from(entry.len()).expect("session log entries fit in a u32");
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(entry);
+}
+
+fn invalid_data(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Complete frames of a log, stopping at the first incomplete one.
+struct Frames<'a> {
+    bytes: &'a [u8],
+    /// End of the last frame returned.
+    offset: usize,
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.bytes[self.offset..];
+        let header: [u8; 4] = rest.get(..4)?.try_into().ok()?;
+        let len = usize::try_from(u32::from_be_bytes(header)).ok()?;
+        let entry = rest.get(4..4 + len)?;
+        self.offset += 4 + len;
+        Some(entry)
+    }
+}
@@ -0,0 +1,365 @@
+use bytes::Bytes;
+use core::num::NonZero;
+use core::time::Duration;
+use encode::Encodable;
+use sansio::Protocol;
+use sansio_mqtt_v5_file_store::FileSessionStore;
+use sansio_mqtt_v5_protocol::Client;
+use sansio_mqtt_v5_protocol::ClientMessage;
+use sansio_mqtt_v5_protocol::ClientSettings;
+use sansio_mqtt_v5_protocol::ConnectionOptions;
+use sansio_mqtt_v5_protocol::DriverEventIn;
+use sansio_mqtt_v5_protocol::IncomingData;
+use sansio_mqtt_v5_protocol::MemorySessionStore;
+use sansio_mqtt_v5_protocol::SessionChange;
+use sansio_mqtt_v5_protocol::SessionStore;
+use sansio_mqtt_v5_protocol::SubscribeOptions;
+use sansio_mqtt_v5_protocol::UnsubscribeOptions;
+use sansio_mqtt_v5_protocol::UserWriteIn;
+use sansio_mqtt_v5_protocol::UserWriteOut;
+use sansio_mqtt_v5_types::ConnAck;
+use sansio_mqtt_v5_types::ConnAckKind;
+use sansio_mqtt_v5_types::ConnAckProperties;
+use sansio_mqtt_v5_types::ConnackReasonCode;
+use sansio_mqtt_v5_types::ControlPacket;
+use sansio_mqtt_v5_types::GuaranteedQoS;
+use sansio_mqtt_v5_types::Payload;
+use sansio_mqtt_v5_types::PubRec;
+use sansio_mqtt_v5_types::PubRecProperties;
+use sansio_mqtt_v5_types::PubRecReasonCode;
+use sansio_mqtt_v5_types::PubRel;
+use sansio_mqtt_v5_types::PubRelProperties;
+use sansio_mqtt_v5_types::PubRelReasonCode;
+use sansio_mqtt_v5_types::Publish;
+use sansio_mqtt_v5_types::PublishKind;
+use sansio_mqtt_v5_types::PublishProperties;
+use sansio_mqtt_v5_types::Qos;
+use sansio_mqtt_v5_types::RetainHandling;
+use sansio_mqtt_v5_types::SubAck;
+use sansio_mqtt_v5_types::SubAckProperties;
+use sansio_mqtt_v5_types::SubAckReasonCode;
+use sansio_mqtt_v5_types::Subscription;
+use sansio_mqtt_v5_types::Topic;
+use sansio_mqtt_v5_types::TopicFilter;
+use sansio_mqtt_v5_types::UnsubAck;
+use sansio_mqtt_v5_types::UnsubAckProperties;
+use sansio_mqtt_v5_types::UnsubAckReasonCode;
+use sansio_mqtt_v5_types::Utf8String;
+use std::io::Write;
+
+fn encode_packet(packet: &ControlPacket) -> Bytes {
+    let mut buffer = Vec::new();
+    packet.encode(&mut buffer).expect("packet should encode");
+    Bytes::from(buffer)
+}
+
+fn read(client: &mut Client<Duration>, packet: ControlPacket) {
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&packet),
+            received_at: Duration::ZERO,
+        }),
+        Ok(())
+    );
+}
+
+fn connect(client: &mut Client<Duration>, clean_start: bool, kind: ConnAckKind) {
+    assert_eq!(
+        client.handle_write(UserWriteIn::Connect(ConnectionOptions {
+            clean_start,
+            session_expiry_interval: Some(300),
+            ..ConnectionOptions::default()
+        })),
+        Ok(())
+    );
+    while client.poll_event().is_some() {}
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    read(
+        client,
+        ControlPacket::ConnAck(ConnAck {
+            kind,
+            properties: ConnAckProperties::default(),
+        }),
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+}
+
+fn topic() -> Topic {
+    Topic::try_from(Utf8String::try_from("plant/line-1").expect("valid utf8")).expect("valid topic")
+}
+
+fn publish(packet_id: u16, qos: GuaranteedQoS, dup: bool) -> Publish {
+    Publish {
+        kind: PublishKind::Repetible {
+            packet_id: NonZero::new(packet_id).expect("non-zero packet id"),
+            qos,
+            dup,
+        },
+        retain: false,
+        payload: Payload::from(&b"persisted"[..]),
+        topic: topic(),
+        properties: PublishProperties::default(),
+    }
+}
+
+fn packet_id(value: u16) -> NonZero<u16> {
+    NonZero::new(value).expect("non-zero packet id")
+}
+
+#[test]
+fn session_recorded_by_a_client_survives_reopening_the_log() {
+    let directory = tempfile::tempdir().expect("temporary directory");
+    let path = directory.path().join("session.log");
+
+    let store = FileSessionStore::open(&path).expect("log opens");
+    let mut client = Client::<Duration>::with_settings_and_session(
+        ClientSettings::default(),
+        store.session().clone(),
+    );
+    client.set_session_store(store);
+    connect(
+        &mut client,
+        true,
+        ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+    );
+    for qos in [Qos::AtLeastOnce, Qos::ExactlyOnce] {
+        assert_eq!(
+            client.handle_write(UserWriteIn::PublishMessage(ClientMessage {
+                topic: topic(),
+                qos,
+                payload: Payload::from(&b"persisted"[..]),
+                ..ClientMessage::default()
+            })),
+            Ok(())
+        );
+    }
+    read(
+        &mut client,
+        ControlPacket::PubRec(PubRec {
+            packet_id: packet_id(2),
+            reason_code: PubRecReasonCode::Success,
+            properties: PubRecProperties::default(),
+        }),
+    );
+    drop(client);
+
+    let reopened = FileSessionStore::open(&path).expect("log reopens");
+    let mut restored = Client::<Duration>::with_settings_and_session(
+        ClientSettings::default(),
+        reopened.session().clone(),
+    );
+    connect(&mut restored, false, ConnAckKind::ResumePreviousSession);
+
+    assert_eq!(
+        restored.poll_write(),
+        Some(encode_packet(&ControlPacket::Publish(publish(
+            1,
+            GuaranteedQoS::AtLeastOnce,
+            true
+        ))))
+    );
+    assert_eq!(
+        restored.poll_write(),
+        Some(encode_packet(&ControlPacket::PubRel(PubRel {
+            packet_id: packet_id(2),
+            reason_code: PubRelReasonCode::Success,
+            properties: PubRelProperties::default(),
+        })))
+    );
+    assert_eq!(restored.poll_write(), None);
+}
+
+fn subscription(filter: &str) -> Subscription {
+    Subscription {
+        topic_filter: TopicFilter::try_from(filter).expect("valid topic filter"),
+        qos: Qos::AtLeastOnce,
+        no_local: false,
+        retain_as_published: false,
+        retain_handling: RetainHandling::default(),
+    }
+}
+
+#[test]
+fn subscriptions_recorded_by_a_client_survive_reopening_the_log() {
+    let directory = tempfile::tempdir().expect("temporary directory");
+    let path = directory.path().join("session.log");
+
+    let store = FileSessionStore::open(&path).expect("log opens");
+    let mut client = Client::<Duration>::with_settings_and_session(
+        ClientSettings::default(),
+        store.session().clone(),
+    );
+    client.set_session_store(store);
+    connect(
+        &mut client,
+        true,
+        ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+    );
+    assert_eq!(
+        client.handle_write(UserWriteIn::Subscribe(SubscribeOptions {
+            subscription: subscription("plant/line-1"),
+            extra_subscriptions: vec![subscription("plant/line-2")],
+            subscription_identifier: None,
+            user_properties: Vec::new(),
+            token: None,
+        })),
+        Ok(())
+    );
+    read(
+        &mut client,
+        ControlPacket::SubAck(SubAck {
+            packet_id: packet_id(1),
+            reason_codes: vec![SubAckReasonCode::SuccessQoS1; 2],
+            properties: SubAckProperties::default(),
+        }),
+    );
+    assert_eq!(
+        client.handle_write(UserWriteIn::Unsubscribe(UnsubscribeOptions {
+            filter: TopicFilter::try_from("plant/line-1").expect("valid topic filter"),
+            extra_filters: Vec::new(),
+            user_properties: Vec::new(),
+            token: None,
+        })),
+        Ok(())
+    );
+    read(
+        &mut client,
+        ControlPacket::UnsubAck(UnsubAck {
+            packet_id: packet_id(2),
+            reason_codes: vec![UnsubAckReasonCode::Success],
+            properties: UnsubAckProperties::default(),
+        }),
+    );
+    drop(client);
+
+    let reopened = FileSessionStore::open(&path).expect("log reopens");
+
+    assert_eq!(
+        reopened.session().subscriptions().collect::<Vec<_>>(),
+        vec![&subscription("plant/line-2")]
+    );
+}
+
+#[test]
+fn compaction_keeps_the_session_and_shrinks_the_log() {
+    let directory = tempfile::tempdir().expect("temporary directory");
+    let path = directory.path().join("session.log");
+    let changes = [
+        SessionChange::OutboundPublished {
+            packet_id: packet_id(1),
+            publish: publish(1, GuaranteedQoS::ExactlyOnce, false),
+        },
+        SessionChange::OutboundPublished {
+            packet_id: packet_id(2),
+            publish: publish(2, GuaranteedQoS::AtLeastOnce, false),
+        },
+        SessionChange::OutboundReleased(packet_id(1)),
+        SessionChange::OutboundCompleted(packet_id(2)),
+        SessionChange::InboundAcknowledged {
+            packet_id: packet_id(7),
+            reason_code: PubRecReasonCode::Success,
+        },
+    ];
+
+    let mut store = FileSessionStore::open(&path).expect("log opens");
+    store.set_compact_after(4);
+    let mut expected = MemorySessionStore::default();
+    let snapshot_len = |expected: &MemorySessionStore| {
+        u64::try_from(4 + expected.session().to_bytes().len()).expect("small snapshot")
+    };
+    for change in &changes[..4] {
+        assert_eq!(store.record(change), Ok(()));
+        assert_eq!(expected.record(change), Ok(()));
+    }
+
+    // The fourth record triggered a compaction into a lone snapshot.
+    assert_eq!(
+        std::fs::metadata(&path).expect("log exists").len(),
+        snapshot_len(&expected)
+    );
+
+    assert_eq!(store.record(&changes[4]), Ok(()));
+    assert_eq!(expected.record(&changes[4]), Ok(()));
+    assert!(std::fs::metadata(&path).expect("log exists").len() > snapshot_len(&expected));
+    store.compact().expect("log compacts");
+
+    assert_eq!(
+        std::fs::metadata(&path).expect("log exists").len(),
+        snapshot_len(&expected)
+    );
+    assert_eq!(store.session(), expected.session());
+    assert_eq!(
+        FileSessionStore::open(&path)
+            .expect("log reopens")
+            .session(),
+        expected.session()
+    );
+}
+
+#[test]
+fn incomplete_trailing_record_is_discarded_on_open() {
+    let directory = tempfile::tempdir().expect("temporary directory");
+    let path = directory.path().join("session.log");
+    let mut store = FileSessionStore::open(&path).expect("log opens");
+    let change = SessionChange::OutboundPublished {
+        packet_id: packet_id(1),
+        publish: publish(1, GuaranteedQoS::AtLeastOnce, false),
+    };
+    assert_eq!(store.record(&change), Ok(()));
+    let expected = store.session().clone();
+    let complete_len = std::fs::metadata(&path).expect("log exists").len();
+    drop(store);
+
+    let torn = SessionChange::OutboundCompleted(packet_id(1)).to_bytes();
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .expect("log opens for append");
+    file.write_all(
+        &u32::try_from(torn.len())
+            .expect("small record")
+            .to_be_bytes(),
+    )
+    .expect("frame header written");
+    file.write_all(&torn[..1])
+        .expect("part of the record written");
+    drop(file);
+
+    let reopened = FileSessionStore::open(&path).expect("log reopens");
+
+    assert_eq!(reopened.session(), &expected);
+    assert_eq!(
+        std::fs::metadata(&path).expect("log exists").len(),
+        complete_len
+    );
+}
+
+#[test]
+fn undecodable_record_fails_to_open() {
+    let directory = tempfile::tempdir().expect("temporary directory");
+    let path = directory.path().join("session.log");
+    drop(FileSessionStore::open(&path).expect("log opens"));
+
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .expect("log opens for append");
+    file.write_all(&[0, 0, 0, 1, u8::MAX])
+        .expect("bogus record written");
+    drop(file);
+
+    assert_eq!(
+        FileSessionStore::open(&path)
+            .expect_err("log is corrupt")
+            .kind(),
+        std::io::ErrorKind::InvalidData
+    );
+}
@@ -0,0 +1,90 @@
+//! Enhanced authentication driven by the protocol itself
+//! ([§4.12](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901256)).
+//!
+//! An [`Authenticator`] installed with
+//! [`Client::set_authenticator`](crate::Client::set_authenticator) supplies
+//! the Authentication Method and initial data for every CONNECT, answers each
+//! server `ContinueAuthentication` AUTH without involving the application,
+//! and checks the final server data carried by CONNACK (or by the AUTH
+//! `Success` that ends a re-authentication).
+
+#[cfg(feature = "scram")]
+mod scram;
+
+#[cfg(feature = "scram")]
+pub use scram::Scram;
+#[cfg(feature = "scram")]
+pub use scram::ScramMechanism;
+
+use crate::types::AuthPacket;
+use crate::types::Error;
+use sansio_mqtt_v5_types::AuthProperties;
+use sansio_mqtt_v5_types::AuthReasonCode;
+use sansio_mqtt_v5_types::AuthenticationKind;
+use sansio_mqtt_v5_types::BinaryData;
+use sansio_mqtt_v5_types::Utf8String;
+
+/// A client-side SASL-style mechanism for MQTT enhanced authentication.
+///
+/// The protocol calls [`start`](Self::start) whenever it builds a CONNECT,
+/// so an implementation must be able to restart its exchange from scratch on
+/// every (re)connection. Returning an error from any method aborts the
+/// connection with [`Error::AuthenticationFailed`].
+pub trait Authenticator: core::fmt::Debug + Send {
+    /// Authentication Method name sent in CONNECT and in every AUTH
+    /// ([§3.1.2.11.9](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901055)).
+    fn method(&self) -> Utf8String;
+
+    /// Begins a new exchange and returns the Authentication Data for the
+    /// packet that opens it (CONNECT, or AUTH `ReAuthenticate`).
+    fn start(&mut self) -> Result<Option<BinaryData>, Error>;
+
+    /// Answers a server `ContinueAuthentication` challenge with the data for
+    /// the client's `ContinueAuthentication` AUTH.
+    fn challenge(&mut self, data: Option<&BinaryData>) -> Result<Option<BinaryData>, Error>;
+
+    /// Verifies the Authentication Data sent with the server's final
+    /// CONNACK or AUTH `Success`.
+    fn finish(&mut self, data: Option<&BinaryData>) -> Result<(), Error>;
+}
+
+pub(crate) fn authentication_kind(
+    method: Utf8String,
+    data: Option<BinaryData>,
+) -> AuthenticationKind {
+    match data {
+        Some(data) => AuthenticationKind::WithData { method, data },
+        None => AuthenticationKind::WithoutData { method },
+    }
+}
+
+pub(crate) fn authentication_data(kind: Option<&AuthenticationKind>) -> Option<&BinaryData> {
+    match kind {
+        Some(AuthenticationKind::WithData { data, .. }) => Some(data),
+        Some(AuthenticationKind::WithoutData { .. }) | None => None,
+    }
+}
+
+/// Starts an exchange and returns the Authentication Method / Data pair for
+/// the opening CONNECT or AUTH.
+pub(crate) fn start(authenticator: &mut dyn Authenticator) -> Result<AuthenticationKind, Error> {
+    let data = authenticator.start()?;
+    Ok(authentication_kind(authenticator.method(), data))
+}
+
+/// Builds the `ContinueAuthentication` AUTH answering a server challenge.
+pub(crate) fn answer_challenge(
+    authenticator: &mut dyn Authenticator,
+    challenge: &AuthPacket,
+) -> Result<AuthPacket, Error> {
+    let data = authenticator.challenge(authentication_data(
+        challenge.properties.authentication.as_ref(),
+    ))?;
+    Ok(AuthPacket {
+        reason_code: AuthReasonCode::ContinueAuthentication,
+        properties: AuthProperties {
+            authentication: Some(authentication_kind(authenticator.method(), data)),
+            ..AuthProperties::default()
+        },
+    })
+}
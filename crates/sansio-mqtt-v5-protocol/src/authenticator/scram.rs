@@ -0,0 +1,278 @@
+//! SCRAM-SHA-256 and SCRAM-SHA-512 client
+//! ([RFC 5802](https://www.rfc-editor.org/rfc/rfc5802),
+//! [RFC 7677](https://www.rfc-editor.org/rfc/rfc7677)).
+//!
+//! The method names match the ones EMQX and HiveMQ advertise
+//! (`SCRAM-SHA-256`, `SCRAM-SHA-512`). Channel binding is not supported, so
+//! the GS2 header is always `n,,`. User names and passwords are used as
+//! given: SASLprep normalisation is left to the caller.
+
+use super::Authenticator;
+use crate::types::Error;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hmac::Mac;
+use hmac::SimpleHmac;
+use hmac::digest::FixedOutput;
+use hmac::digest::KeyInit;
+use hmac::digest::Update;
+use hmac::digest::core_api::BlockSizeUser;
+use sansio_mqtt_v5_types::BinaryData;
+use sansio_mqtt_v5_types::Utf8String;
+use sha2::Digest;
+use sha2::Sha256;
+use sha2::Sha512;
+
+/// GS2 header for a client without channel binding (RFC 5802 §7).
+const GS2_HEADER: &str = "n,,";
+
+/// Hash function of a SCRAM mechanism.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScramMechanism {
+    /// `SCRAM-SHA-256` ([RFC 7677](https://www.rfc-editor.org/rfc/rfc7677)).
+    Sha256,
+    /// `SCRAM-SHA-512`.
+    Sha512,
+}
+
+impl ScramMechanism {
+    /// Authentication Method name for this mechanism.
+    pub fn method(self) -> &'static str {
+        match self {
+            Self::Sha256 => "SCRAM-SHA-256",
+            Self::Sha512 => "SCRAM-SHA-512",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ScramState {
+    Initial,
+    ClientFirstSent {
+        client_nonce: String,
+        client_first_bare: String,
+    },
+    ClientFinalSent {
+        server_key: Vec<u8>,
+        auth_message: String,
+    },
+    Done,
+}
+
+/// SCRAM client [`Authenticator`].
+///
+/// The protocol crate has no source of randomness, so the client nonce comes
+/// from the `nonce` callback, which is invoked once per exchange. It must
+/// return a fresh, unpredictable string of printable ASCII without `,`.
+pub struct Scram {
+    mechanism: ScramMechanism,
+    username: String,
+    password: String,
+    nonce: Box<dyn FnMut() -> String + Send>,
+    state: ScramState,
+}
+
+impl core::fmt::Debug for Scram {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Scram")
+            .field("mechanism", &self.mechanism)
+            .field("username", &self.username)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Scram {
+    pub fn new(
+        mechanism: ScramMechanism,
+        username: impl Into<String>,
+        password: impl Into<String>,
+        nonce: impl FnMut() -> String + Send + 'static,
+    ) -> Self {
+        Self {
+            mechanism,
+            username: username.into(),
+            password: password.into(),
+            nonce: Box::new(nonce),
+            state: ScramState::Initial,
+        }
+    }
+
+    fn proof(&self, salt: &[u8], iterations: u32, auth_message: &str) -> (Vec<u8>, Vec<u8>) {
+        match self.mechanism {
+            ScramMechanism::Sha256 => {
+                client_proof::<Sha256>(self.password.as_bytes(), salt, iterations, auth_message)
+            }
+            ScramMechanism::Sha512 => {
+                client_proof::<Sha512>(self.password.as_bytes(), salt, iterations, auth_message)
+            }
+        }
+    }
+
+    fn verify(&self, server_key: &[u8], auth_message: &str, signature: &[u8]) -> bool {
+        match self.mechanism {
+            ScramMechanism::Sha256 => {
+                verify_server_signature::<Sha256>(server_key, auth_message, signature)
+            }
+            ScramMechanism::Sha512 => {
+                verify_server_signature::<Sha512>(server_key, auth_message, signature)
+            }
+        }
+    }
+}
+
+/// RFC 5802 §3: returns `(ClientProof, ServerKey)`.
+fn client_proof<D>(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    auth_message: &str,
+) -> (Vec<u8>, Vec<u8>)
+where
+    D: Digest + BlockSizeUser + Clone + Sync,
+{
+    let mut salted_password = alloc::vec![0u8; <D as Digest>::output_size()];
+    pbkdf2::pbkdf2::<SimpleHmac<D>>(password, salt, iterations, &mut salted_password)
+        .expect("HMAC accepts keys of any length");
+
+    let client_key = hmac::<D>(&salted_password, b"Client Key");
+    let stored_key = D::digest(&client_key);
+    let client_signature = hmac::<D>(&stored_key, auth_message.as_bytes());
+    let proof = client_key
+        .iter()
+        .zip(client_signature.iter())
+        .map(|(key, signature)| key ^ signature)
+        .collect();
+
+    (proof, hmac::<D>(&salted_password, b"Server Key"))
+}
+
+fn verify_server_signature<D>(server_key: &[u8], auth_message: &str, signature: &[u8]) -> bool
+where
+    D: Digest + BlockSizeUser,
+{
+    let mut mac = <SimpleHmac<D> as KeyInit>::new_from_slice(server_key)
+        .expect("HMAC accepts keys of any length");
+    Update::update(&mut mac, auth_message.as_bytes());
+    mac.verify_slice(signature).is_ok()
+}
+
+fn hmac<D>(key: &[u8], message: &[u8]) -> Vec<u8>
+where
+    D: Digest + BlockSizeUser,
+{
+    let mut mac =
+        <SimpleHmac<D> as KeyInit>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    Update::update(&mut mac, message);
+    mac.finalize_fixed().to_vec()
+}
+
+/// RFC 5802 §5.1: `=` and `,` are escaped in `saslname`.
+fn escape_username(username: &str) -> String {
+    username.replace('=', "=3D").replace(',', "=2C")
+}
+
+/// Looks up the `key=value` attribute `key` in a SCRAM message.
+fn attribute(message: &str, key: char) -> Option<&str> {
+    message.split(',').find_map(|attribute| {
+        let mut chars = attribute.chars();
+        (chars.next() == Some(key) && chars.next() == Some('=')).then(|| &attribute[2..])
+    })
+}
+
+fn utf8(data: Option<&BinaryData>) -> Result<&str, Error> {
+    let data = data.ok_or(Error::AuthenticationFailed)?;
+    core::str::from_utf8(data).map_err(|_| Error::AuthenticationFailed)
+}
+
+fn binary_data(message: String) -> Result<Option<BinaryData>, Error> {
+    BinaryData::try_new(message.into_bytes())
+        .map(Some)
+        .map_err(|_| Error::AuthenticationFailed)
+}
+
+impl Authenticator for Scram {
+    fn method(&self) -> Utf8String {
+        Utf8String::try_from(self.mechanism.method()).expect("SCRAM method names are valid UTF-8")
+    }
+
+    fn start(&mut self) -> Result<Option<BinaryData>, Error> {
+        let client_nonce = (self.nonce)();
+        if client_nonce.is_empty() || client_nonce.contains(',') {
+            return Err(Error::AuthenticationFailed);
+        }
+        let client_first_bare = format!("n={},r={}", escape_username(&self.username), client_nonce);
+        let client_first = format!("{GS2_HEADER}{client_first_bare}");
+        self.state = ScramState::ClientFirstSent {
+            client_nonce,
+            client_first_bare,
+        };
+        binary_data(client_first)
+    }
+
+    fn challenge(&mut self, data: Option<&BinaryData>) -> Result<Option<BinaryData>, Error> {
+        let ScramState::ClientFirstSent {
+            client_nonce,
+            client_first_bare,
+        } = core::mem::replace(&mut self.state, ScramState::Done)
+        else {
+            return Err(Error::AuthenticationFailed);
+        };
+
+        let server_first = utf8(data)?;
+        if attribute(server_first, 'm').is_some() {
+            // RFC 5802 §5.1: mandatory extensions are not understood.
+            return Err(Error::AuthenticationFailed);
+        }
+        let nonce = attribute(server_first, 'r').ok_or(Error::AuthenticationFailed)?;
+        // RFC 5802 §5.1: the server nonce extends the client's.
+        if nonce.len() <= client_nonce.len() || !nonce.starts_with(client_nonce.as_str()) {
+            return Err(Error::AuthenticationFailed);
+        }
+        let salt = attribute(server_first, 's')
+            .and_then(|salt| BASE64.decode(salt).ok())
+            .ok_or(Error::AuthenticationFailed)?;
+        let iterations = attribute(server_first, 'i')
+            .and_then(|iterations| iterations.parse::<u32>().ok())
+            .filter(|iterations| *iterations > 0)
+            .ok_or(Error::AuthenticationFailed)?;
+
+        let client_final_without_proof = format!("c={},r={}", BASE64.encode(GS2_HEADER), nonce);
+        let auth_message =
+            format!("{client_first_bare},{server_first},{client_final_without_proof}");
+        let (proof, server_key) = self.proof(&salt, iterations, &auth_message);
+
+        self.state = ScramState::ClientFinalSent {
+            server_key,
+            auth_message,
+        };
+        binary_data(format!(
+            "{client_final_without_proof},p={}",
+            BASE64.encode(proof)
+        ))
+    }
+
+    fn finish(&mut self, data: Option<&BinaryData>) -> Result<(), Error> {
+        let ScramState::ClientFinalSent {
+            server_key,
+            auth_message,
+        } = core::mem::replace(&mut self.state, ScramState::Done)
+        else {
+            return Err(Error::AuthenticationFailed);
+        };
+
+        let server_final = utf8(data)?;
+        let signature = attribute(server_final, 'v')
+            .and_then(|signature| BASE64.decode(signature).ok())
+            .ok_or(Error::AuthenticationFailed)?;
+        if self.verify(&server_key, &auth_message, &signature) {
+            Ok(())
+        } else {
+            Err(Error::AuthenticationFailed)
+        }
+    }
+}
@@ -1,7 +1,11 @@
+use crate::authenticator::Authenticator;
 use crate::limits;
+use crate::pending_publish::PendingPublishes;
 use crate::queues;
 use crate::scratchpad::ClientScratchpad;
 use crate::session::ClientSession;
+use crate::session_store;
+use crate::session_store::SessionStore;
 use crate::state::ClientState;
 use crate::state::StateHandler;
 use crate::types::ClientSettings;
@@ -12,6 +16,7 @@ use crate::types::IncomingData;
 use crate::types::ProtocolTime;
 use crate::types::UserWriteIn;
 use crate::types::UserWriteOut;
+use alloc::boxed::Box;
 use bytes::BytesMut;
 use sansio::Protocol;
 use sansio_mqtt_v5_types::ControlPacket;
@@ -57,6 +62,45 @@ impl<Time> Client<Time> {
         Self::with_settings_and_session(settings, Default::default())
     }
 
+    /// Lets `authenticator` run enhanced authentication
+    /// ([§4.12](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901256))
+    /// from the next CONNECT on.
+    ///
+    /// Server challenges are then answered internally instead of being
+    /// surfaced as [`UserWriteOut::Auth`].
+    pub fn set_authenticator(&mut self, authenticator: impl Authenticator + 'static) {
+        self.scratchpad.authenticator = Some(Box::new(authenticator));
+    }
+
+    /// Records every later session change in `store` before the packets
+    /// that depend on it reach `poll_write`.
+    ///
+    /// `store` should start from the same session as this client, e.g. the
+    /// one it was created with by [`Self::with_settings_and_session`].
+    pub fn set_session_store(&mut self, store: impl SessionStore + 'static) {
+        self.scratchpad.session_store = Some(Box::new(store));
+    }
+
+    /// Handles `msg` like [`Protocol::handle_write`], as written at `now`.
+    ///
+    /// Publishes queued by it age from `now`, both for their Message Expiry
+    /// Interval and for [`ClientSettings::pending_publish_max_age`]; with
+    /// `handle_write` they age from the latest instant supplied to
+    /// `handle_read` or `handle_timeout`, and never expire if there was none.
+    pub fn handle_write_at(&mut self, msg: UserWriteIn, now: Time) -> Result<(), Error>
+    where
+        Time: ProtocolTime,
+    {
+        self.scratchpad.now = Some(now);
+        self.handle_write(msg)
+    }
+
+    /// The current session state, e.g. to persist it with
+    /// [`ClientSession::to_bytes`].
+    pub fn session(&self) -> &ClientSession {
+        &self.session
+    }
+
     fn parser_settings(&self) -> ParserSettings {
         ParserSettings {
             max_bytes_string: self.scratchpad.effective_client_max_bytes_string,
@@ -69,7 +113,12 @@ impl<Time> Client<Time> {
                 .effective_client_max_subscription_identifiers_len,
         }
     }
+}
 
+impl<Time> Client<Time>
+where
+    Time: ProtocolTime,
+{
     #[inline(always)]
     fn dispatch<F>(&mut self, f: F) -> Result<(), Error>
     where
@@ -81,6 +130,18 @@ impl<Time> Client<Time> {
         ) -> (ClientState, Result<(), Error>),
     {
         let state = core::mem::take(&mut self.state);
+        self.scratchpad.pending_publish.forget_sent();
+        let checkpoint = Checkpoint {
+            queued_packets: self.scratchpad.write_queue.len(),
+            queued_events: self.scratchpad.read_queue.len(),
+            queued_changes: self.scratchpad.session_changes.len(),
+            snapshot: self.scratchpad.session_store.is_some().then(|| {
+                (
+                    self.session.clone(),
+                    self.scratchpad.pending_publish.clone(),
+                )
+            }),
+        };
         let (next, result) = f(
             state,
             &self.settings,
@@ -88,8 +149,63 @@ impl<Time> Client<Time> {
             &mut self.scratchpad,
         );
         self.state = next;
+        if let Err(recorded) = session_store::flush(&mut self.scratchpad) {
+            self.abort_unrecorded_session_changes(checkpoint, recorded);
+            return Err(Error::SessionStoreFailed);
+        }
         result
     }
+
+    /// Keeps the wire, the application and the session from getting ahead
+    /// of the session store: drops the packets and events queued since
+    /// `checkpoint`, rolls the session and the pending publishes back to
+    /// its snapshot plus the `recorded` changes, and closes the connection.
+    ///
+    /// Changes left over from earlier calls, such as the `Cleared` of a
+    /// disconnect, stay queued for the next flush: the session already
+    /// reflects them.
+    fn abort_unrecorded_session_changes(&mut self, checkpoint: Checkpoint<Time>, recorded: usize) {
+        self.scratchpad
+            .write_queue
+            .truncate(checkpoint.queued_packets);
+        self.scratchpad
+            .read_queue
+            .truncate(checkpoint.queued_events);
+        let changes = &mut self.scratchpad.session_changes;
+        if let Some((mut session, pending_publish)) = checkpoint.snapshot {
+            for change in &changes[..recorded] {
+                session.apply(change);
+            }
+            self.session = session;
+            self.scratchpad
+                .pending_publish
+                .roll_back(pending_publish, recorded);
+        }
+        changes.truncate(checkpoint.queued_changes.max(recorded));
+        changes.drain(..recorded);
+        if matches!(
+            self.state,
+            ClientState::Connecting(_) | ClientState::Connected(_)
+        ) {
+            let _ = queues::fail_protocol_and_disconnect(
+                &self.settings,
+                &mut self.session,
+                &mut self.scratchpad,
+                DisconnectReasonCode::UnspecifiedError,
+            );
+            self.state = ClientState::Disconnected(crate::state::Disconnected);
+        }
+    }
+}
+
+/// Queue lengths and state captured before a call, for
+/// [`Client::abort_unrecorded_session_changes`]. The snapshot is only taken
+/// when a session store is installed.
+struct Checkpoint<Time> {
+    queued_packets: usize,
+    queued_events: usize,
+    queued_changes: usize,
+    snapshot: Option<(ClientSession, PendingPublishes<Time>)>,
 }
 
 impl<Time> Protocol<IncomingData<Time>, UserWriteIn, DriverEventIn> for Client<Time>
@@ -105,6 +221,7 @@ where
     #[tracing::instrument(skip_all)]
     fn handle_read(&mut self, msg: IncomingData<Time>) -> Result<(), Self::Error> {
         let received_at = msg.received_at;
+        self.scratchpad.now = Some(received_at);
         let packet_bytes = if self.scratchpad.read_buffer.is_empty() {
             msg.bytes
         } else {
@@ -174,6 +291,7 @@ where
 
     #[tracing::instrument(skip_all)]
     fn handle_timeout(&mut self, now: Self::Time) -> Result<(), Self::Error> {
+        self.scratchpad.now = Some(now);
         self.dispatch(|s, set, ses, sp| s.handle_timeout(set, ses, sp, now))
     }
 
@@ -195,6 +313,6 @@ where
     }
 
     fn poll_timeout(&mut self) -> Option<Self::Time> {
-        self.scratchpad.next_timeout
+        self.scratchpad.earliest_deadline()
     }
 }
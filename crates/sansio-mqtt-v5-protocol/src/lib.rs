@@ -2,15 +2,29 @@
 #![forbid(unsafe_code)]
 extern crate alloc;
 
+mod authenticator;
 mod client;
 mod limits;
+mod pending_publish;
 mod queues;
+mod router;
 mod scratchpad;
 mod session;
+mod session_encoding;
 mod session_ops;
+mod session_store;
 mod state;
+mod topic_alias;
 mod types;
 
+pub use authenticator::*;
 pub use client::Client;
+pub use router::HandlerId;
+pub use router::Route;
+pub use router::Router;
 pub use session::ClientSession;
+pub use session_encoding::SessionDecodeError;
+pub use session_store::MemorySessionStore;
+pub use session_store::SessionChange;
+pub use session_store::SessionStore;
 pub use types::*;
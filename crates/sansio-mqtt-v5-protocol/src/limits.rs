@@ -1,9 +1,14 @@
 use crate::scratchpad::ClientScratchpad;
 use crate::session::ClientSession;
+use crate::types::AuthPacket;
 use crate::types::ClientMessage;
 use crate::types::ClientSettings;
+use crate::types::ConnectionOptions;
+use crate::types::DisconnectOptions;
 use crate::types::Error;
 use core::num::NonZero;
+use sansio_mqtt_v5_types::AuthReasonCode;
+use sansio_mqtt_v5_types::AuthenticationKind;
 use sansio_mqtt_v5_types::MaximumQoS;
 use sansio_mqtt_v5_types::Publish;
 
@@ -81,6 +86,7 @@ pub(crate) fn reset_negotiated_limits<Time>(
     // MUST NOT be carried over to a new connection. Clear them here so every
     // reconnection starts with a fresh, empty alias mapping.
     session.inbound_topic_aliases.clear();
+    scratchpad.outbound_topic_aliases.clear();
     recompute_effective_limits(settings, scratchpad);
 }
 
@@ -100,10 +106,15 @@ pub(crate) fn ensure_outbound_receive_maximum_capacity<Time>(
 }
 
 pub(crate) fn validate_outbound_topic_alias<Time>(
+    settings: &ClientSettings,
     scratchpad: &ClientScratchpad<Time>,
     topic_alias: Option<NonZero<u16>>,
 ) -> Result<(), Error> {
     if let Some(alias) = topic_alias {
+        // Automatic assignment owns the alias space.
+        if settings.auto_topic_alias {
+            return Err(Error::ProtocolError);
+        }
         let topic_alias_maximum = scratchpad.effective_broker_topic_alias_maximum;
         if topic_alias_maximum == 0 || alias.get() > topic_alias_maximum {
             return Err(Error::ProtocolError);
@@ -150,6 +161,66 @@ pub(crate) fn validate_outbound_publish_capabilities<Time>(
     Ok(())
 }
 
+/// Returns `true` when `authentication` carries the same Authentication
+/// Method that was sent in CONNECT.
+///
+/// Every AUTH of an exchange must name the method the connection was
+/// authenticated with ([§4.12](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901256),
+/// [MQTT-4.12.1-1]); an AUTH without a method is a Protocol Error
+/// ([§3.15.2.2.2](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901223)).
+pub(crate) fn authentication_method_matches(
+    configured: Option<&AuthenticationKind>,
+    authentication: Option<&AuthenticationKind>,
+) -> bool {
+    fn method(kind: &AuthenticationKind) -> &str {
+        match kind {
+            AuthenticationKind::WithoutData { method }
+            | AuthenticationKind::WithData { method, .. } => method.as_ref(),
+        }
+    }
+
+    match (configured, authentication) {
+        (Some(configured), Some(authentication)) => method(configured) == method(authentication),
+        _ => false,
+    }
+}
+
+/// Validates an AUTH the application wants to send.
+///
+/// Only Continue Authentication (`0x18`) and Re-authenticate (`0x19`) are
+/// client reason codes; Success (`0x00`) is reserved to the server. Which of
+/// the two is acceptable depends on the state, so the caller passes it in.
+pub(crate) fn validate_outbound_auth(
+    configured: Option<&AuthenticationKind>,
+    auth: &AuthPacket,
+    allowed_reason_codes: &[AuthReasonCode],
+) -> Result<(), Error> {
+    if !allowed_reason_codes.contains(&auth.reason_code) {
+        return Err(Error::ProtocolError);
+    }
+
+    if !authentication_method_matches(configured, auth.properties.authentication.as_ref()) {
+        return Err(Error::ProtocolError);
+    }
+
+    Ok(())
+}
+
+/// Validates a DISCONNECT the application wants to send.
+///
+/// [MQTT-3.14.2-2] A Session Expiry Interval that was zero in CONNECT must not
+/// be made non-zero at disconnect time.
+pub(crate) fn validate_outbound_disconnect(
+    connect: &ConnectionOptions,
+    options: &DisconnectOptions,
+) -> Result<(), Error> {
+    let connect_interval = connect.session_expiry_interval.unwrap_or(0);
+    match options.session_expiry_interval {
+        Some(interval) if connect_interval == 0 && interval != 0 => Err(Error::ProtocolError),
+        _ => Ok(()),
+    }
+}
+
 pub(crate) fn apply_inbound_publish_topic_alias<Time>(
     session: &mut ClientSession,
     scratchpad: &ClientScratchpad<Time>,
@@ -0,0 +1,155 @@
+//! Publishes accepted from the application but not sent yet.
+//!
+//! QoS 1 and QoS 2 publishes wait here while the server's Receive Maximum
+//! is reached ([MQTT-4.9.0-2]); with
+//! [`ClientSettings::queue_publishes_while_offline`](crate::ClientSettings)
+//! publishes of any QoS also wait here while there is no connection. The
+//! queue is flushed in order as soon as the connection allows it.
+
+use crate::types::ClientMessage;
+use crate::types::ClientSettings;
+use crate::types::Error;
+use crate::types::PendingPublishOverflow;
+use crate::types::ProtocolTime;
+use crate::types::UserWriteOut;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
+
+/// A queued publish and the latest instant the driver had supplied when it
+/// was queued.
+#[derive(Debug, Clone)]
+pub(crate) struct PendingPublish<Time> {
+    pub(crate) message: ClientMessage,
+    pub(crate) queued_at: Option<Time>,
+}
+
+impl<Time> PendingPublish<Time>
+where
+    Time: ProtocolTime,
+{
+    /// Whether, by `now`, the publish outlived
+    /// [`ClientSettings::pending_publish_max_age`] or its own Message Expiry
+    /// Interval. Publishes queued before the driver supplied any instant
+    /// never expire.
+    pub(crate) fn is_expired(&self, settings: &ClientSettings, now: Time) -> bool {
+        let Some(queued_at) = self.queued_at else {
+            return false;
+        };
+        [
+            settings.pending_publish_max_age,
+            self.message.message_expiry_interval,
+        ]
+        .into_iter()
+        .flatten()
+        .any(|lifetime| queued_at + lifetime <= now)
+    }
+}
+
+/// Bounded FIFO of [`PendingPublish`] entries, with the payload bytes they
+/// hold.
+#[derive(Debug, Clone)]
+pub(crate) struct PendingPublishes<Time> {
+    queue: VecDeque<PendingPublish<Time>>,
+    payload_bytes: usize,
+    /// Entries popped so far; numbers each pop.
+    popped: usize,
+    /// Entries sent as QoS 1 or QoS 2 since [`Self::forget_sent`]: the
+    /// number of their pop and the index of their `OutboundPublished` among
+    /// the queued session changes.
+    sent: Vec<(usize, usize)>,
+}
+
+impl<Time> Default for PendingPublishes<Time> {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            payload_bytes: 0,
+            popped: 0,
+            sent: Vec::new(),
+        }
+    }
+}
+
+impl<Time> PendingPublishes<Time> {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub(crate) fn front(&self) -> Option<&PendingPublish<Time>> {
+        self.queue.front()
+    }
+
+    pub(crate) fn pop_front(&mut self) -> Option<PendingPublish<Time>> {
+        let entry = self.queue.pop_front()?;
+        self.payload_bytes -= entry.message.payload.len();
+        self.popped += 1;
+        Some(entry)
+    }
+
+    /// Notes that the entry popped last was sent, its `OutboundPublished`
+    /// being queued at index `change` of the session changes.
+    pub(crate) fn mark_sent(&mut self, change: usize) {
+        self.sent.push((self.popped - 1, change));
+    }
+
+    pub(crate) fn forget_sent(&mut self) {
+        self.sent.clear();
+    }
+
+    /// Goes back to `snapshot`, taken after the last
+    /// [`forget_sent`](Self::forget_sent), keeping out the entries sent
+    /// since whose `OutboundPublished` is among the `recorded` first session
+    /// changes: the recorded session already holds them.
+    pub(crate) fn roll_back(&mut self, mut snapshot: Self, recorded: usize) {
+        for &(popped, change) in self.sent.iter().rev() {
+            if change < recorded
+                && let Some(entry) = snapshot.queue.remove(popped - snapshot.popped)
+            {
+                snapshot.payload_bytes -= entry.message.payload.len();
+            }
+        }
+        *self = snapshot;
+    }
+
+    fn has_room_for(&self, settings: &ClientSettings, payload_len: usize) -> bool {
+        self.queue.len() < settings.max_pending_publishes
+            && settings
+                .max_pending_publish_bytes
+                .is_none_or(|max| self.payload_bytes + payload_len <= max)
+    }
+
+    /// Appends `message`, applying
+    /// [`ClientSettings::pending_publish_overflow`] when the count or byte
+    /// limit is reached. Publishes dropped to make room are reported through
+    /// `read_queue`.
+    pub(crate) fn push(
+        &mut self,
+        settings: &ClientSettings,
+        read_queue: &mut VecDeque<UserWriteOut>,
+        message: ClientMessage,
+        queued_at: Option<Time>,
+    ) -> Result<(), Error> {
+        let payload_len = message.payload.len();
+        if settings.max_pending_publishes == 0
+            || settings
+                .max_pending_publish_bytes
+                .is_some_and(|max| payload_len > max)
+        {
+            return Err(Error::PublishQueueFull);
+        }
+        while !self.has_room_for(settings, payload_len) {
+            match settings.pending_publish_overflow {
+                PendingPublishOverflow::RejectNewest => return Err(Error::PublishQueueFull),
+                PendingPublishOverflow::DropOldest => {
+                    if let Some(oldest) = self.pop_front() {
+                        read_queue.push_back(UserWriteOut::PendingPublishDropped(oldest.message));
+                    }
+                }
+            }
+        }
+        self.payload_bytes += payload_len;
+        self.queue.push_back(PendingPublish { message, queued_at });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+//! Routing of received messages to handlers.
+//!
+//! A [`Router`] maps [`Route`]s — topic filters or Subscription Identifiers
+//! — to handlers. Filters are kept in a trie keyed by topic level, so
+//! matching a topic costs one walk of its levels rather than one comparison
+//! per filter. A handler reachable through several routes that all match a
+//! message (overlapping filters, or a filter and a Subscription Identifier)
+//! is returned once for that message.
+//!
+//! Messages listing the subscriptions that delivered them in
+//! [`BrokerMessage::subscriptions`] are routed by those subscriptions' topic
+//! filters exactly instead, so `a/+` and `a/#` are not both matched by a
+//! message only the `a/#` subscription delivered. Subscriptions without a
+//! Subscription Identifier, listed in
+//! [`BrokerMessage::unidentified_subscriptions`], still match by topic.
+
+use crate::types::BrokerMessage;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::num::NonZero;
+use core::str::Split;
+use sansio_mqtt_v5_types::Topic;
+use sansio_mqtt_v5_types::TopicFilter;
+
+/// What a handler is routed by.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Route {
+    /// Messages whose topic matches the filter
+    /// ([§4.7](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901241)).
+    /// A Shared Subscription matches through its inner filter.
+    Filter(TopicFilter),
+    /// [MQTT-3.3.4-3] Messages carrying this Subscription Identifier,
+    /// i.e. delivered because of the subscription made with it.
+    SubscriptionIdentifier(NonZero<u64>),
+}
+
+impl From<TopicFilter> for Route {
+    fn from(filter: TopicFilter) -> Self {
+        Self::Filter(filter)
+    }
+}
+
+impl From<NonZero<u64>> for Route {
+    fn from(identifier: NonZero<u64>) -> Self {
+        Self::SubscriptionIdentifier(identifier)
+    }
+}
+
+/// Identifies a handler added to a [`Router`].
+///
+/// Identifiers are never reused by the router that issued them, and order
+/// handlers by when they were added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HandlerId(u64);
+
+#[derive(Debug)]
+struct Entry<H> {
+    handler: H,
+    routes: Vec<Route>,
+}
+
+/// Maps topic filters and Subscription Identifiers to handlers of type `H`.
+#[derive(Debug)]
+pub struct Router<H> {
+    handlers: BTreeMap<HandlerId, Entry<H>>,
+    filters: Node,
+    /// Handlers by the exact filter they were routed by.
+    exact_filters: BTreeMap<TopicFilter, BTreeSet<HandlerId>>,
+    identifiers: BTreeMap<NonZero<u64>, BTreeSet<HandlerId>>,
+    next_id: u64,
+}
+
+impl<H> Default for Router<H> {
+    fn default() -> Self {
+        Self {
+            handlers: BTreeMap::new(),
+            filters: Node::default(),
+            exact_filters: BTreeMap::new(),
+            identifiers: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<H> Router<H> {
+    /// An empty router.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `handler`, reachable through `route`.
+    pub fn add(&mut self, route: impl Into<Route>, handler: H) -> HandlerId {
+        let id = HandlerId(self.next_id);
+        self.next_id += 1;
+        self.handlers.insert(
+            id,
+            Entry {
+                handler,
+                routes: Vec::new(),
+            },
+        );
+        self.add_route(id, route);
+        id
+    }
+
+    /// Makes the handler `id` also reachable through `route`.
+    ///
+    /// Returns `false` if there is no such handler. Adding a route the
+    /// handler already has changes nothing.
+    pub fn add_route(&mut self, id: HandlerId, route: impl Into<Route>) -> bool {
+        let route = route.into();
+        let Some(entry) = self.handlers.get_mut(&id) else {
+            return false;
+        };
+        if entry.routes.contains(&route) {
+            return true;
+        }
+        match &route {
+            Route::Filter(filter) => {
+                self.filters.insert(filter.filter(), id);
+                self.exact_filters
+                    .entry(filter.clone())
+                    .or_default()
+                    .insert(id);
+            }
+            Route::SubscriptionIdentifier(identifier) => {
+                self.identifiers.entry(*identifier).or_default().insert(id);
+            }
+        }
+        entry.routes.push(route);
+        true
+    }
+
+    /// Stops routing `route` to the handler `id`, which is kept even when
+    /// left without routes.
+    ///
+    /// Returns `false` if the handler did not have that route.
+    pub fn remove_route(&mut self, id: HandlerId, route: &Route) -> bool {
+        let Some(entry) = self.handlers.get_mut(&id) else {
+            return false;
+        };
+        let Some(position) = entry.routes.iter().position(|r| r == route) else {
+            return false;
+        };
+        entry.routes.swap_remove(position);
+        // Filters sharing an inner filter, such as `a/+` and `$share/g/a/+`,
+        // share a place in the trie.
+        let still_linked = match route {
+            Route::Filter(filter) => entry.routes.iter().any(
+                |other| matches!(other, Route::Filter(other) if other.filter() == filter.filter()),
+            ),
+            Route::SubscriptionIdentifier(_) => false,
+        };
+        if still_linked {
+            if let Route::Filter(filter) = route {
+                self.unlink_exact_filter(id, filter);
+            }
+        } else {
+            self.unlink(id, route);
+        }
+        true
+    }
+
+    /// Removes the handler `id` with all of its routes, returning it.
+    pub fn remove(&mut self, id: HandlerId) -> Option<H> {
+        let entry = self.handlers.remove(&id)?;
+        for route in &entry.routes {
+            self.unlink(id, route);
+        }
+        Some(entry.handler)
+    }
+
+    /// The handler `id`, if it was not removed.
+    pub fn get(&self, id: HandlerId) -> Option<&H> {
+        self.handlers.get(&id).map(|entry| &entry.handler)
+    }
+
+    /// The handler `id`, if it was not removed.
+    pub fn get_mut(&mut self, id: HandlerId) -> Option<&mut H> {
+        self.handlers.get_mut(&id).map(|entry| &mut entry.handler)
+    }
+
+    /// The routes of the handler `id`, in the order they were added.
+    pub fn routes(&self, id: HandlerId) -> Option<&[Route]> {
+        self.handlers.get(&id).map(|entry| entry.routes.as_slice())
+    }
+
+    /// Number of handlers.
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// Returns `true` if the router has no handlers.
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Handlers with a filter matching `topic`, each once, in the order they
+    /// were added.
+    pub fn matching_topic(&self, topic: &Topic) -> Vec<HandlerId> {
+        let mut matched = BTreeSet::new();
+        self.filters.collect(topic, &mut matched);
+        matched.into_iter().collect()
+    }
+
+    /// Handlers with a route matching `message` — a filter matching its topic
+    /// or one of its Subscription Identifiers — each once, in the order they
+    /// were added.
+    ///
+    /// When `message` lists the [`BrokerMessage::subscriptions`] that
+    /// delivered it, a filter matches only if it is one of theirs or one of
+    /// the [`BrokerMessage::unidentified_subscriptions`], which the server
+    /// cannot report. Giving every subscription a Subscription Identifier
+    /// keeps overlapping filters apart, see
+    /// [`ClientSettings::auto_subscription_identifiers`](crate::ClientSettings::auto_subscription_identifiers).
+    pub fn matching(&self, message: &BrokerMessage) -> Vec<HandlerId> {
+        let mut matched = BTreeSet::new();
+        if message.subscriptions.is_empty() {
+            self.filters.collect(&message.topic, &mut matched);
+        }
+        for filter in message
+            .subscriptions
+            .iter()
+            .flat_map(|subscription| &subscription.topic_filters)
+            .chain(&message.unidentified_subscriptions)
+        {
+            if let Some(ids) = self.exact_filters.get(filter) {
+                matched.extend(ids);
+            }
+        }
+        for identifier in &message.subscription_identifiers {
+            if let Some(ids) = self.identifiers.get(identifier) {
+                matched.extend(ids);
+            }
+        }
+        matched.into_iter().collect()
+    }
+
+    /// Calls every handler matching `message` once, in the order they were
+    /// added, and returns how many were called.
+    pub fn dispatch(&mut self, message: &BrokerMessage) -> usize
+    where
+        H: FnMut(&BrokerMessage),
+    {
+        let matched = self.matching(message);
+        for id in &matched {
+            if let Some(handler) = self.get_mut(*id) {
+                handler(message);
+            }
+        }
+        matched.len()
+    }
+
+    fn unlink(&mut self, id: HandlerId, route: &Route) {
+        match route {
+            Route::Filter(filter) => {
+                self.filters.remove(filter.filter(), id);
+                self.unlink_exact_filter(id, filter);
+            }
+            Route::SubscriptionIdentifier(identifier) => {
+                if let Some(ids) = self.identifiers.get_mut(identifier) {
+                    ids.remove(&id);
+                    if ids.is_empty() {
+                        self.identifiers.remove(identifier);
+                    }
+                }
+            }
+        }
+    }
+
+    fn unlink_exact_filter(&mut self, id: HandlerId, filter: &TopicFilter) {
+        if let Some(ids) = self.exact_filters.get_mut(filter) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.exact_filters.remove(filter);
+            }
+        }
+    }
+}
+
+/// One topic level of the filter trie.
+#[derive(Debug, Default)]
+struct Node {
+    /// Filters ending at this level.
+    exact: BTreeSet<HandlerId>,
+    /// Filters ending with `#` after this level.
+    multi_level: BTreeSet<HandlerId>,
+    /// The `+` level below this one.
+    single_level: Option<Box<Node>>,
+    /// Literal levels below this one.
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn insert(&mut self, filter: &str, id: HandlerId) {
+        let mut node = self;
+        for level in filter.split('/') {
+            node = match level {
+                // [MQTT-4.7.1-1] `#` is always the last level.
+                "#" => {
+                    node.multi_level.insert(id);
+                    return;
+                }
+                "+" => node.single_level.get_or_insert_default(),
+                level => node.children.entry(String::from(level)).or_default(),
+            };
+        }
+        node.exact.insert(id);
+    }
+
+    fn remove(&mut self, filter: &str, id: HandlerId) {
+        self.remove_levels(filter.split('/'), id);
+    }
+
+    /// Removes `id` from the end of `levels`, pruning the levels it leaves
+    /// empty. Returns `true` when this node is left empty.
+    fn remove_levels(&mut self, mut levels: Split<'_, char>, id: HandlerId) -> bool {
+        match levels.next() {
+            None => {
+                self.exact.remove(&id);
+            }
+            Some("#") => {
+                self.multi_level.remove(&id);
+            }
+            Some("+") => {
+                if let Some(child) = self.single_level.as_mut()
+                    && child.remove_levels(levels, id)
+                {
+                    self.single_level = None;
+                }
+            }
+            Some(level) => {
+                if let Some(child) = self.children.get_mut(level)
+                    && child.remove_levels(levels, id)
+                {
+                    self.children.remove(level);
+                }
+            }
+        }
+        self.is_empty()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.exact.is_empty()
+            && self.multi_level.is_empty()
+            && self.single_level.is_none()
+            && self.children.is_empty()
+    }
+
+    fn collect(&self, topic: &Topic, matched: &mut BTreeSet<HandlerId>) {
+        let topic: &str = topic;
+        let mut levels = topic.split('/');
+        let first = levels.next().unwrap_or_default();
+        if let Some(child) = self.children.get(first) {
+            child.collect_levels(levels.clone(), matched);
+        }
+        // [MQTT-4.7.2-1] Wildcards in the first level do not match topics
+        // starting with `$`.
+        if !topic.starts_with('$') {
+            matched.extend(&self.multi_level);
+            if let Some(child) = &self.single_level {
+                child.collect_levels(levels, matched);
+            }
+        }
+    }
+
+    fn collect_levels(&self, mut levels: Split<'_, char>, matched: &mut BTreeSet<HandlerId>) {
+        // `#` also matches the parent level, so `sport/#` matches `sport`.
+        matched.extend(&self.multi_level);
+        let Some(level) = levels.next() else {
+            matched.extend(&self.exact);
+            return;
+        };
+        if let Some(child) = self.children.get(level) {
+            child.collect_levels(levels.clone(), matched);
+        }
+        if let Some(child) = &self.single_level {
+            child.collect_levels(levels, matched);
+        }
+    }
+}
@@ -1,8 +1,16 @@
+use crate::authenticator::Authenticator;
+use crate::pending_publish::PendingPublishes;
+use crate::session_store::SessionChange;
+use crate::session_store::SessionStore;
+use crate::topic_alias::OutboundTopicAliases;
 use crate::types::ConnectionOptions;
 use crate::types::DriverEventOut;
 use crate::types::ProtocolTime;
 use crate::types::UserWriteOut;
+use alloc::boxed::Box;
+use alloc::collections::btree_map::BTreeMap;
 use alloc::collections::vec_deque::VecDeque;
+use alloc::vec::Vec;
 use bytes::Bytes;
 use bytes::BytesMut;
 use core::num::NonZero;
@@ -15,6 +23,15 @@ where
     Time: 'static,
 {
     pub(crate) pending_connect_options: ConnectionOptions,
+    /// Drives enhanced authentication on behalf of the application when set;
+    /// its method and data replace `ConnectionOptions::authentication`.
+    pub(crate) authenticator: Option<Box<dyn Authenticator>>,
+    /// Receives every session change before the packets depending on it are
+    /// handed to the driver.
+    pub(crate) session_store: Option<Box<dyn SessionStore>>,
+    /// Session changes made by the current `handle_*` call, not yet recorded
+    /// in `session_store`.
+    pub(crate) session_changes: Vec<SessionChange>,
     pub(crate) session_should_persist: bool,
     pub(crate) effective_client_max_bytes_string: u16,
     pub(crate) effective_client_max_bytes_binary_data: u16,
@@ -28,6 +45,7 @@ where
     pub(crate) effective_broker_receive_maximum: NonZero<u16>,
     pub(crate) effective_broker_maximum_packet_size: Option<NonZero<u32>>,
     pub(crate) effective_broker_topic_alias_maximum: u16,
+    pub(crate) outbound_topic_aliases: OutboundTopicAliases,
     pub(crate) effective_broker_maximum_qos: Option<MaximumQoS>,
     pub(crate) effective_retain_available: bool,
     pub(crate) effective_wildcard_subscription_available: bool,
@@ -50,6 +68,16 @@ where
     pub(crate) write_queue: VecDeque<Bytes>,
     pub(crate) action_queue: VecDeque<DriverEventOut>,
     pub(crate) next_timeout: Option<Time>,
+    /// When the current credentials are due for re-authentication; polled
+    /// alongside the keep-alive deadline in `next_timeout`.
+    pub(crate) reauthenticate_deadline: Option<Time>,
+    /// Latest instant supplied by the driver, through
+    /// `IncomingData::received_at`, `handle_timeout` or `handle_write_at`.
+    pub(crate) now: Option<Time>,
+    pub(crate) pending_publish: PendingPublishes<Time>,
+    /// When each in-flight QoS1/QoS2 PUBLISH with a Message Expiry Interval
+    /// expires, counted from the instant it was queued.
+    pub(crate) publish_expiry_deadlines: BTreeMap<NonZero<u16>, Time>,
 }
 
 impl<Time> ClientScratchpad<Time>
@@ -58,11 +86,29 @@ where
 {
     /// Schedules the next keep-alive deadline `secs` seconds after `from`.
     ///
-    /// The only place in the crate where an instant is advanced; everything
-    /// else stores and compares `Time` values supplied by the driver.
+    /// Together with [`Self::arm_reauthenticate_deadline`], the only place in
+    /// the crate where an instant is advanced; everything else stores and
+    /// compares `Time` values supplied by the driver.
     pub(crate) fn arm_keep_alive_deadline(&mut self, from: Time, secs: u64) {
         self.next_timeout = Some(from + Duration::from_secs(secs));
     }
+
+    /// Schedules re-authentication `ConnectionOptions::reauthenticate_after`
+    /// past `from`, or clears the deadline when it is not configured.
+    pub(crate) fn arm_reauthenticate_deadline(&mut self, from: Time) {
+        self.reauthenticate_deadline = self
+            .pending_connect_options
+            .reauthenticate_after
+            .map(|after| from + after);
+    }
+
+    /// The earliest pending deadline, reported through `poll_timeout`.
+    pub(crate) fn earliest_deadline(&self) -> Option<Time> {
+        match (self.next_timeout, self.reauthenticate_deadline) {
+            (Some(keep_alive), Some(reauthenticate)) => Some(keep_alive.min(reauthenticate)),
+            (keep_alive, reauthenticate) => keep_alive.or(reauthenticate),
+        }
+    }
 }
 
 impl<Time> Default for ClientScratchpad<Time>
@@ -72,6 +118,9 @@ where
     fn default() -> Self {
         Self {
             pending_connect_options: ConnectionOptions::default(),
+            authenticator: None,
+            session_store: None,
+            session_changes: Vec::new(),
             session_should_persist: false,
             effective_client_max_bytes_string: u16::MAX,
             effective_client_max_bytes_binary_data: u16::MAX,
@@ -87,6 +136,7 @@ where
                 .expect("u16::MAX is always non-zero for receive_maximum"),
             effective_broker_maximum_packet_size: None,
             effective_broker_topic_alias_maximum: u16::MAX,
+            outbound_topic_aliases: OutboundTopicAliases::default(),
             effective_broker_maximum_qos: None,
             effective_retain_available: true,
             effective_wildcard_subscription_available: true,
@@ -110,6 +160,10 @@ where
             write_queue: VecDeque::new(),
             action_queue: VecDeque::new(),
             next_timeout: None,
+            reauthenticate_deadline: None,
+            now: None,
+            pending_publish: PendingPublishes::default(),
+            publish_expiry_deadlines: BTreeMap::new(),
         }
     }
 }
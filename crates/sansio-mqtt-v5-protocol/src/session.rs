@@ -1,8 +1,13 @@
+use crate::types::SubscriptionHandle;
 use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::btree_set::BTreeSet;
+use alloc::vec::Vec;
 use core::num::NonZero;
 use sansio_mqtt_v5_types::PubRecReasonCode;
 use sansio_mqtt_v5_types::Publish;
+use sansio_mqtt_v5_types::Subscription;
 use sansio_mqtt_v5_types::Topic;
+use sansio_mqtt_v5_types::TopicFilter;
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum OutboundInflightState {
@@ -19,6 +24,14 @@ pub(crate) enum InboundInflightState {
     Qos2Rejected(PubRecReasonCode),
 }
 
+/// A topic filter with the options and Subscription Identifier it was
+/// requested with.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SubscriptionEntry {
+    pub(crate) subscription: Subscription,
+    pub(crate) subscription_identifier: Option<NonZero<u64>>,
+}
+
 /// Persistent per-connection MQTT session state.
 ///
 /// # Message ordering
@@ -33,8 +46,15 @@ pub(crate) enum InboundInflightState {
 pub struct ClientSession {
     pub(crate) on_flight_sent: BTreeMap<NonZero<u16>, OutboundInflightState>,
     pub(crate) on_flight_received: BTreeMap<NonZero<u16>, InboundInflightState>,
-    pub(crate) pending_subscribe: BTreeMap<NonZero<u16>, ()>,
-    pub(crate) pending_unsubscribe: BTreeMap<NonZero<u16>, ()>,
+    /// Subscriptions of each outstanding SUBSCRIBE, in packet order, so the
+    /// SUBACK reason codes can be checked against them ([MQTT-3.9.3-1]).
+    pub(crate) pending_subscribe: BTreeMap<NonZero<u16>, Vec<SubscriptionEntry>>,
+    /// Topic filters of each outstanding UNSUBSCRIBE, in packet order, so the
+    /// UNSUBACK reason codes can be checked against them ([MQTT-3.11.3-1]).
+    pub(crate) pending_unsubscribe: BTreeMap<NonZero<u16>, Vec<TopicFilter>>,
+    /// Subscriptions the server granted, by topic filter, until an UNSUBACK
+    /// removes them or a new session starts.
+    pub(crate) subscriptions: BTreeMap<TopicFilter, SubscriptionEntry>,
     pub(crate) inbound_topic_aliases: BTreeMap<NonZero<u16>, Topic>,
     pub(crate) next_packet_id: u16,
 }
@@ -46,8 +66,61 @@ impl Default for ClientSession {
             on_flight_received: BTreeMap::new(),
             pending_subscribe: BTreeMap::new(),
             pending_unsubscribe: BTreeMap::new(),
+            subscriptions: BTreeMap::new(),
             inbound_topic_aliases: BTreeMap::new(),
             next_packet_id: 1,
         }
     }
 }
+
+impl ClientSession {
+    /// Subscriptions the server granted and that are still active, ordered
+    /// by topic filter.
+    pub fn subscriptions(&self) -> impl Iterator<Item = &Subscription> {
+        self.subscriptions.values().map(|entry| &entry.subscription)
+    }
+
+    /// Outstanding subscriptions, then granted ones.
+    fn subscription_entries(&self) -> impl Iterator<Item = &SubscriptionEntry> {
+        self.pending_subscribe
+            .values()
+            .flatten()
+            .chain(self.subscriptions.values())
+    }
+
+    /// Subscription Identifiers used by outstanding or granted subscriptions.
+    pub(crate) fn subscription_identifiers(&self) -> BTreeSet<NonZero<u64>> {
+        self.subscription_entries()
+            .filter_map(|entry| entry.subscription_identifier)
+            .collect()
+    }
+
+    /// Topic filters of outstanding or granted subscriptions made without a
+    /// Subscription Identifier that match `topic`, ordered by topic filter.
+    pub(crate) fn unidentified_subscriptions(&self, topic: &Topic) -> Vec<TopicFilter> {
+        let topic_filters: BTreeSet<_> = self
+            .subscription_entries()
+            .filter(|entry| entry.subscription_identifier.is_none())
+            .map(|entry| &entry.subscription.topic_filter)
+            .filter(|topic_filter| topic_filter.matches(topic))
+            .cloned()
+            .collect();
+        topic_filters.into_iter().collect()
+    }
+
+    /// The topic filters subscribed with `subscription_identifier`, if any.
+    pub(crate) fn subscription_handle(
+        &self,
+        subscription_identifier: NonZero<u64>,
+    ) -> Option<SubscriptionHandle> {
+        let topic_filters: BTreeSet<_> = self
+            .subscription_entries()
+            .filter(|entry| entry.subscription_identifier == Some(subscription_identifier))
+            .map(|entry| entry.subscription.topic_filter.clone())
+            .collect();
+        (!topic_filters.is_empty()).then(|| SubscriptionHandle {
+            subscription_identifier,
+            topic_filters: topic_filters.into_iter().collect(),
+        })
+    }
+}
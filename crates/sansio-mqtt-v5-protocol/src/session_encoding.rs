@@ -0,0 +1,533 @@
+//! Versioned binary encoding of [`ClientSession`].
+//!
+//! Layout (all integers big-endian):
+//!
+//! ```text
+//! magic "SMQS" | version u8 | next_packet_id u16
+//! on_flight_sent:      u32 count, then packet_id u16, state u8
+//!                      [, u32 length + PUBLISH packet]
+//! on_flight_received:  u32 count, then packet_id u16, state u8
+//!                      [, PUBREC reason code u8]
+//! pending_subscribe:   u32 count, then packet_id u16, subscriptions
+//! pending_unsubscribe: u32 count, then packet_id u16, filters
+//! subscriptions
+//! inbound_topic_aliases: u32 count, then alias u16, string
+//! ```
+//!
+//! Strings are a `u16` length followed by UTF-8 bytes; `filters` is a `u32`
+//! count of strings. `subscriptions` is a `u32` count of topic filter
+//! string, Subscription Options `u8` (as in SUBSCRIBE) and Subscription
+//! Identifier `u64`, `0` when absent. Stored PUBLISH packets use their MQTT
+//! wire encoding.
+//!
+//! New fields bump the version; older versions stay decodable.
+//!
+//! A [`SessionChange`] is encoded as a kind `u8` followed by the packet id
+//! and, depending on the kind, the PUBLISH packet or the PUBREC reason code;
+//! subscription changes carry a single `subscriptions` entry or a topic
+//! filter string instead of a packet id.
+//! Changes carry no header of their own: logs keep them after a session
+//! snapshot, whose version they share.
+
+use crate::session::ClientSession;
+use crate::session::InboundInflightState;
+use crate::session::OutboundInflightState;
+use crate::session::SubscriptionEntry;
+use crate::session_store::SessionChange;
+use alloc::collections::btree_map::BTreeMap;
+use alloc::vec::Vec;
+use core::num::NonZero;
+use encode::Encodable;
+use sansio_mqtt_v5_types::ControlPacket;
+use sansio_mqtt_v5_types::ParserSettings;
+use sansio_mqtt_v5_types::PubRecReasonCode;
+use sansio_mqtt_v5_types::Publish;
+use sansio_mqtt_v5_types::Qos;
+use sansio_mqtt_v5_types::RetainHandling;
+use sansio_mqtt_v5_types::Subscription;
+use sansio_mqtt_v5_types::Topic;
+use sansio_mqtt_v5_types::TopicFilter;
+use sansio_mqtt_v5_types::Utf8String;
+use winnow::Parser;
+use winnow::error::ErrMode;
+use winnow::stream::Partial;
+
+const MAGIC: &[u8; 4] = b"SMQS";
+const VERSION: u8 = 1;
+
+const QOS1_AWAIT_PUBACK: u8 = 0;
+const QOS2_AWAIT_PUBREC: u8 = 1;
+const QOS2_AWAIT_PUBCOMP: u8 = 2;
+
+const OUTBOUND_PUBLISHED: u8 = 0;
+const OUTBOUND_RELEASED: u8 = 1;
+const OUTBOUND_COMPLETED: u8 = 2;
+const INBOUND_ACKNOWLEDGED: u8 = 3;
+const INBOUND_RELEASED: u8 = 4;
+const CLEARED: u8 = 5;
+const SUBSCRIPTION_ADDED: u8 = 6;
+const SUBSCRIPTION_REMOVED: u8 = 7;
+
+const QOS1_AWAIT_APP_DECISION: u8 = 0;
+const QOS2_AWAIT_APP_DECISION: u8 = 1;
+const QOS2_AWAIT_PUBREL: u8 = 2;
+const QOS2_REJECTED: u8 = 3;
+
+/// Why [`ClientSession::from_bytes`] or [`SessionChange::from_bytes`]
+/// refused its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum SessionDecodeError {
+    /// The input does not start with the session magic number.
+    #[error("not an encoded client session")]
+    UnknownFormat,
+    /// The input was written by a newer, unknown format version.
+    #[error("unsupported session encoding version {0}")]
+    UnsupportedVersion(u8),
+    /// The input is truncated, has trailing bytes or holds invalid values.
+    #[error("malformed session encoding")]
+    Malformed,
+}
+
+impl ClientSession {
+    /// Encodes the session in a stable, versioned binary format, to be
+    /// restored with [`ClientSession::from_bytes`] and handed to
+    /// [`Client::with_settings_and_session`](crate::Client::with_settings_and_session)
+    /// after a restart.
+    ///
+    /// Message Expiry deadlines of in-flight publishes depend on the driver's
+    /// clock and are not part of the encoding: after a restore, they are
+    /// resent with the interval they were last sent with.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        put_u16(&mut out, self.next_packet_id);
+
+        put_count(&mut out, self.on_flight_sent.len());
+        for (packet_id, state) in &self.on_flight_sent {
+            put_u16(&mut out, packet_id.get());
+            match state {
+                OutboundInflightState::Qos1AwaitPubAck { publish } => {
+                    out.push(QOS1_AWAIT_PUBACK);
+                    put_publish(&mut out, publish);
+                }
+                OutboundInflightState::Qos2AwaitPubRec { publish } => {
+                    out.push(QOS2_AWAIT_PUBREC);
+                    put_publish(&mut out, publish);
+                }
+                OutboundInflightState::Qos2AwaitPubComp => out.push(QOS2_AWAIT_PUBCOMP),
+            }
+        }
+
+        put_count(&mut out, self.on_flight_received.len());
+        for (packet_id, state) in &self.on_flight_received {
+            put_u16(&mut out, packet_id.get());
+            match state {
+                InboundInflightState::Qos1AwaitAppDecision => out.push(QOS1_AWAIT_APP_DECISION),
+                InboundInflightState::Qos2AwaitAppDecision => out.push(QOS2_AWAIT_APP_DECISION),
+                InboundInflightState::Qos2AwaitPubRel => out.push(QOS2_AWAIT_PUBREL),
+                InboundInflightState::Qos2Rejected(reason_code) => {
+                    out.push(QOS2_REJECTED);
+                    out.push(u8::from(*reason_code));
+                }
+            }
+        }
+
+        put_count(&mut out, self.pending_subscribe.len());
+        for (packet_id, entries) in &self.pending_subscribe {
+            put_u16(&mut out, packet_id.get());
+            put_subscriptions(&mut out, entries);
+        }
+
+        put_count(&mut out, self.pending_unsubscribe.len());
+        for (packet_id, filters) in &self.pending_unsubscribe {
+            put_u16(&mut out, packet_id.get());
+            put_count(&mut out, filters.len());
+            for filter in filters {
+                put_str(&mut out, filter.as_bytes());
+            }
+        }
+
+        put_subscriptions(&mut out, self.subscriptions.values());
+
+        put_count(&mut out, self.inbound_topic_aliases.len());
+        for (alias, topic) in &self.inbound_topic_aliases {
+            put_u16(&mut out, alias.get());
+            put_str(&mut out, topic.as_bytes());
+        }
+
+        out
+    }
+
+    /// Restores a session encoded by [`ClientSession::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SessionDecodeError> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(SessionDecodeError::UnknownFormat);
+        }
+        match reader.u8()? {
+            VERSION => {}
+            version => return Err(SessionDecodeError::UnsupportedVersion(version)),
+        }
+
+        let next_packet_id = reader.u16()?;
+
+        let mut on_flight_sent = BTreeMap::new();
+        for _ in 0..reader.u32()? {
+            let packet_id = reader.packet_id()?;
+            let state = match reader.u8()? {
+                QOS1_AWAIT_PUBACK => OutboundInflightState::Qos1AwaitPubAck {
+                    publish: reader.publish()?,
+                },
+                QOS2_AWAIT_PUBREC => OutboundInflightState::Qos2AwaitPubRec {
+                    publish: reader.publish()?,
+                },
+                QOS2_AWAIT_PUBCOMP => OutboundInflightState::Qos2AwaitPubComp,
+                _ => return Err(SessionDecodeError::Malformed),
+            };
+            on_flight_sent.insert(packet_id, state);
+        }
+
+        let mut on_flight_received = BTreeMap::new();
+        for _ in 0..reader.u32()? {
+            let packet_id = reader.packet_id()?;
+            let state = match reader.u8()? {
+                QOS1_AWAIT_APP_DECISION => InboundInflightState::Qos1AwaitAppDecision,
+                QOS2_AWAIT_APP_DECISION => InboundInflightState::Qos2AwaitAppDecision,
+                QOS2_AWAIT_PUBREL => InboundInflightState::Qos2AwaitPubRel,
+                QOS2_REJECTED => InboundInflightState::Qos2Rejected(
+                    PubRecReasonCode::try_from(reader.u8()?)
+                        .map_err(|_| SessionDecodeError::Malformed)?,
+                ),
+                _ => return Err(SessionDecodeError::Malformed),
+            };
+            on_flight_received.insert(packet_id, state);
+        }
+
+        let mut pending_subscribe = BTreeMap::new();
+        for _ in 0..reader.u32()? {
+            let packet_id = reader.packet_id()?;
+            pending_subscribe.insert(packet_id, reader.subscriptions()?);
+        }
+
+        let mut pending_unsubscribe = BTreeMap::new();
+        for _ in 0..reader.u32()? {
+            let packet_id = reader.packet_id()?;
+            pending_unsubscribe.insert(packet_id, reader.filters()?);
+        }
+
+        let subscriptions = reader
+            .subscriptions()?
+            .into_iter()
+            .map(|entry| (entry.subscription.topic_filter.clone(), entry))
+            .collect();
+
+        let mut inbound_topic_aliases = BTreeMap::new();
+        for _ in 0..reader.u32()? {
+            let alias = reader.packet_id()?;
+            let topic =
+                Topic::try_from(reader.string()?).map_err(|_| SessionDecodeError::Malformed)?;
+            inbound_topic_aliases.insert(alias, topic);
+        }
+
+        if !reader.0.is_empty() {
+            return Err(SessionDecodeError::Malformed);
+        }
+
+        Ok(Self {
+            on_flight_sent,
+            on_flight_received,
+            pending_subscribe,
+            pending_unsubscribe,
+            subscriptions,
+            inbound_topic_aliases,
+            next_packet_id,
+        })
+    }
+}
+
+impl SessionChange {
+    /// Encodes the change as a record for an append-only log, decoded with
+    /// [`SessionChange::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            SessionChange::OutboundPublished { packet_id, publish } => {
+                out.push(OUTBOUND_PUBLISHED);
+                put_u16(&mut out, packet_id.get());
+                put_publish(&mut out, publish);
+            }
+            SessionChange::OutboundReleased(packet_id) => {
+                out.push(OUTBOUND_RELEASED);
+                put_u16(&mut out, packet_id.get());
+            }
+            SessionChange::OutboundCompleted(packet_id) => {
+                out.push(OUTBOUND_COMPLETED);
+                put_u16(&mut out, packet_id.get());
+            }
+            SessionChange::InboundAcknowledged {
+                packet_id,
+                reason_code,
+            } => {
+                out.push(INBOUND_ACKNOWLEDGED);
+                put_u16(&mut out, packet_id.get());
+                out.push(u8::from(*reason_code));
+            }
+            SessionChange::InboundReleased(packet_id) => {
+                out.push(INBOUND_RELEASED);
+                put_u16(&mut out, packet_id.get());
+            }
+            SessionChange::Cleared => out.push(CLEARED),
+            SessionChange::SubscriptionAdded {
+                subscription,
+                subscription_identifier,
+            } => {
+                out.push(SUBSCRIPTION_ADDED);
+                put_subscription(&mut out, subscription, *subscription_identifier);
+            }
+            SessionChange::SubscriptionRemoved(topic_filter) => {
+                out.push(SUBSCRIPTION_REMOVED);
+                put_str(&mut out, topic_filter.as_bytes());
+            }
+        }
+        out
+    }
+
+    /// Decodes a record written by [`SessionChange::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SessionDecodeError> {
+        let mut reader = Reader(bytes);
+        let change = match reader.u8()? {
+            OUTBOUND_PUBLISHED => SessionChange::OutboundPublished {
+                packet_id: reader.packet_id()?,
+                publish: reader.publish()?,
+            },
+            OUTBOUND_RELEASED => SessionChange::OutboundReleased(reader.packet_id()?),
+            OUTBOUND_COMPLETED => SessionChange::OutboundCompleted(reader.packet_id()?),
+            INBOUND_ACKNOWLEDGED => SessionChange::InboundAcknowledged {
+                packet_id: reader.packet_id()?,
+                reason_code: PubRecReasonCode::try_from(reader.u8()?)
+                    .map_err(|_| SessionDecodeError::Malformed)?,
+            },
+            INBOUND_RELEASED => SessionChange::InboundReleased(reader.packet_id()?),
+            CLEARED => SessionChange::Cleared,
+            SUBSCRIPTION_ADDED => {
+                let entry = reader.subscription()?;
+                SessionChange::SubscriptionAdded {
+                    subscription: entry.subscription,
+                    subscription_identifier: entry.subscription_identifier,
+                }
+            }
+            SUBSCRIPTION_REMOVED => SessionChange::SubscriptionRemoved(reader.topic_filter()?),
+            _ => return Err(SessionDecodeError::Malformed),
+        };
+        if !reader.0.is_empty() {
+            return Err(SessionDecodeError::Malformed);
+        }
+        Ok(change)
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Counts are `u32`: a SUBSCRIBE or UNSUBSCRIBE is not limited to 65535
+/// topic filters, and no collection held in memory reaches 2^32 entries.
+fn put_count(out: &mut Vec<u8>, count: usize) {
+    out.extend_from_slice(
+        &u32::try_from(count)
+            .expect("session collections fit in a u32")
+            .to_be_bytes(),
+    );
+}
+
+/// Strings come from [`Utf8String`] values, at most 65535 bytes long.
+fn put_str(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_u16(
+        out,
+        u16::try_from(bytes.len()).expect("UTF-8 strings fit in a u16"),
+    );
+    out.extend_from_slice(bytes);
+}
+
+fn put_subscriptions<'a>(
+    out: &mut Vec<u8>,
+    entries: impl IntoIterator<Item = &'a SubscriptionEntry, IntoIter: ExactSizeIterator>,
+) {
+    let entries = entries.into_iter();
+    put_count(out, entries.len());
+    for entry in entries {
+        put_subscription(out, &entry.subscription, entry.subscription_identifier);
+    }
+}
+
+fn put_subscription(
+    out: &mut Vec<u8>,
+    subscription: &Subscription,
+    subscription_identifier: Option<NonZero<u64>>,
+) {
+    put_str(out, subscription.topic_filter.as_bytes());
+    // Laid out as the Subscription Options byte of SUBSCRIBE (§3.8.3.1).
+    out.push(
+        subscription.qos as u8
+            | u8::from(subscription.no_local) << 2
+            | u8::from(subscription.retain_as_published) << 3
+            | u8::from(subscription.retain_handling) << 4,
+    );
+    out.extend_from_slice(
+        &subscription_identifier
+            .map_or(0, NonZero::get)
+            .to_be_bytes(),
+    );
+}
+
+fn put_publish(out: &mut Vec<u8>, publish: &Publish) {
+    let mut packet = Vec::new();
+    ControlPacket::Publish(publish.clone())
+        .encode(&mut packet)
+        .expect("in-flight PUBLISH was encodable when it was sent");
+    out.extend_from_slice(
+        &u32::try_from(packet.len())
+            .expect("PUBLISH packets fit in a u32")
+            .to_be_bytes(),
+    );
+    out.extend_from_slice(&packet);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SessionDecodeError> {
+        if self.0.len() < len {
+            return Err(SessionDecodeError::Malformed);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, SessionDecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SessionDecodeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, SessionDecodeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, SessionDecodeError> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_be_bytes(
+            bytes.try_into().expect("eight bytes were taken"),
+        ))
+    }
+
+    fn packet_id(&mut self) -> Result<NonZero<u16>, SessionDecodeError> {
+        NonZero::new(self.u16()?).ok_or(SessionDecodeError::Malformed)
+    }
+
+    fn string(&mut self) -> Result<Utf8String, SessionDecodeError> {
+        let len = usize::from(self.u16()?);
+        let bytes = self.take(len)?;
+        let string = core::str::from_utf8(bytes).map_err(|_| SessionDecodeError::Malformed)?;
+        Utf8String::try_from(string).map_err(|_| SessionDecodeError::Malformed)
+    }
+
+    fn topic_filter(&mut self) -> Result<TopicFilter, SessionDecodeError> {
+        TopicFilter::try_from(self.string()?).map_err(|_| SessionDecodeError::Malformed)
+    }
+
+    fn filters(&mut self) -> Result<Vec<TopicFilter>, SessionDecodeError> {
+        (0..self.u32()?).map(|_| self.topic_filter()).collect()
+    }
+
+    fn subscriptions(&mut self) -> Result<Vec<SubscriptionEntry>, SessionDecodeError> {
+        (0..self.u32()?).map(|_| self.subscription()).collect()
+    }
+
+    fn subscription(&mut self) -> Result<SubscriptionEntry, SessionDecodeError> {
+        let topic_filter = self.topic_filter()?;
+        let options = self.u8()?;
+        if options & 0b1100_0000 != 0 {
+            return Err(SessionDecodeError::Malformed);
+        }
+        let subscription = Subscription {
+            topic_filter,
+            qos: Qos::try_from(options & 0b11).map_err(|_| SessionDecodeError::Malformed)?,
+            no_local: options & 0b0100 != 0,
+            retain_as_published: options & 0b1000 != 0,
+            retain_handling: RetainHandling::try_from(options >> 4)
+                .map_err(|_| SessionDecodeError::Malformed)?,
+        };
+        Ok(SubscriptionEntry {
+            subscription,
+            subscription_identifier: NonZero::new(self.u64()?),
+        })
+    }
+
+    fn publish(&mut self) -> Result<Publish, SessionDecodeError> {
+        let len = usize::try_from(self.u32()?).map_err(|_| SessionDecodeError::Malformed)?;
+        let mut input = Partial::new(self.take(len)?);
+        let settings = ParserSettings::unlimited();
+        match ControlPacket::parser::<_, ErrMode<()>, ErrMode<()>>(&settings).parse_next(&mut input)
+        {
+            Ok(ControlPacket::Publish(publish)) if input.into_inner().is_empty() => Ok(publish),
+            _ => Err(SessionDecodeError::Malformed),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ClientSession {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+/// Deserializes the [`ClientSession::to_bytes`] encoding, from either a byte
+/// string or, for formats without one, a sequence of bytes.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ClientSession {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct SessionVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for SessionVisitor {
+            type Value = ClientSession;
+
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                formatter.write_str("an encoded client session")
+            }
+
+            fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                ClientSession::from_bytes(bytes).map_err(E::custom)
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                self.visit_bytes(&bytes)
+            }
+        }
+
+        deserializer.deserialize_bytes(SessionVisitor)
+    }
+}
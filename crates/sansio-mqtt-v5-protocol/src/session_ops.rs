@@ -1,24 +1,33 @@
 use crate::scratchpad::ClientScratchpad;
 use crate::session::ClientSession;
 use crate::session::OutboundInflightState;
+use crate::session::SubscriptionEntry;
+use crate::session_store;
+use crate::session_store::SessionChange;
 use crate::types::Error;
+use crate::types::ProtocolTime;
 use crate::types::UserWriteOut;
 use core::num::NonZero;
+use core::time::Duration;
 use sansio_mqtt_v5_types::ControlPacket;
 use sansio_mqtt_v5_types::PubRel;
 use sansio_mqtt_v5_types::PubRelProperties;
 use sansio_mqtt_v5_types::PubRelReasonCode;
 use sansio_mqtt_v5_types::PublishKind;
+use sansio_mqtt_v5_types::TopicFilter;
 
-/// Resets all keep-alive fields on the scratchpad.
+/// Resets all keep-alive fields on the scratchpad, along with the
+/// re-authentication deadline.
 ///
 /// [MQTT-3.1.2-22] [MQTT-3.1.2-23] Keep Alive tracking resets on connection
-/// lifecycle boundaries.
+/// lifecycle boundaries; so do the timers that only make sense while a
+/// connection is open.
 pub(crate) fn reset_keepalive<Time: 'static>(scratchpad: &mut ClientScratchpad<Time>) {
     scratchpad.keep_alive_interval_secs = None;
     scratchpad.keep_alive_saw_network_activity = false;
     scratchpad.keep_alive_ping_outstanding = false;
     scratchpad.next_timeout = None;
+    scratchpad.reauthenticate_deadline = None;
 }
 
 /// Clears session inflight/pending state if `session_should_persist` is false.
@@ -27,21 +36,69 @@ pub(crate) fn reset_keepalive<Time: 'static>(scratchpad: &mut ClientScratchpad<T
 /// discarded.
 pub(crate) fn maybe_reset_session_state<Time: 'static>(
     session: &mut ClientSession,
-    scratchpad: &ClientScratchpad<Time>,
+    scratchpad: &mut ClientScratchpad<Time>,
 ) {
     if !scratchpad.session_should_persist {
-        reset_session_state(session);
+        reset_session_state(session, scratchpad);
     }
 }
 
 /// Unconditionally clears all session inflight/pending maps.
-pub(crate) fn reset_session_state(session: &mut ClientSession) {
+pub(crate) fn reset_session_state<Time: 'static>(
+    session: &mut ClientSession,
+    scratchpad: &mut ClientScratchpad<Time>,
+) {
+    if !session.on_flight_sent.is_empty() || !session.on_flight_received.is_empty() {
+        session_store::record(scratchpad, SessionChange::Cleared);
+    }
     session.on_flight_sent.clear();
     session.on_flight_received.clear();
     session.pending_subscribe.clear();
     session.pending_unsubscribe.clear();
 }
 
+/// Adds a subscription the server granted to the registry.
+pub(crate) fn add_subscription<Time: 'static>(
+    session: &mut ClientSession,
+    scratchpad: &mut ClientScratchpad<Time>,
+    entry: SubscriptionEntry,
+) {
+    session_store::record(
+        scratchpad,
+        SessionChange::SubscriptionAdded {
+            subscription: entry.subscription.clone(),
+            subscription_identifier: entry.subscription_identifier,
+        },
+    );
+    session
+        .subscriptions
+        .insert(entry.subscription.topic_filter.clone(), entry);
+}
+
+/// Removes the subscription to `topic_filter` from the registry, if any.
+pub(crate) fn remove_subscription<Time: 'static>(
+    session: &mut ClientSession,
+    scratchpad: &mut ClientScratchpad<Time>,
+    topic_filter: &TopicFilter,
+) {
+    if session.subscriptions.remove(topic_filter).is_some() {
+        session_store::record(
+            scratchpad,
+            SessionChange::SubscriptionRemoved(topic_filter.clone()),
+        );
+    }
+}
+
+/// Empties the subscription registry.
+pub(crate) fn clear_subscriptions<Time: 'static>(
+    session: &mut ClientSession,
+    scratchpad: &mut ClientScratchpad<Time>,
+) {
+    for topic_filter in core::mem::take(&mut session.subscriptions).into_keys() {
+        session_store::record(scratchpad, SessionChange::SubscriptionRemoved(topic_filter));
+    }
+}
+
 /// Advances and returns the packet id counter, wrapping from u16::MAX back to
 /// 1.
 pub(crate) fn next_packet_id(session: &mut ClientSession) -> NonZero<u16> {
@@ -90,6 +147,35 @@ pub(crate) fn next_outbound_publish_packet_id(
     Err(Error::ReceiveMaximumExceeded)
 }
 
+/// Largest Subscription Identifier, the largest Variable Byte Integer.
+const MAX_SUBSCRIPTION_IDENTIFIER: u64 = 268_435_455;
+
+/// Picks a Subscription Identifier no outstanding or granted subscription
+/// uses: one past the largest in use, or the smallest free one once those
+/// run out. Returns `None` if every identifier is taken.
+///
+/// [MQTT-3.8.2.1.2] The Subscription Identifier ranges from 1 to 268,435,455.
+pub(crate) fn next_subscription_identifier(session: &ClientSession) -> Option<NonZero<u64>> {
+    let in_use = session.subscription_identifiers();
+    let after_last = in_use.last().map_or(1, |last| last.get() + 1);
+    if after_last <= MAX_SUBSCRIPTION_IDENTIFIER {
+        return NonZero::new(after_last);
+    }
+    // `in_use` is ordered, so the first gap is where it stops counting up
+    // from 1.
+    let mut smallest_free = 1;
+    for identifier in &in_use {
+        if identifier.get() != smallest_free {
+            break;
+        }
+        smallest_free += 1;
+    }
+    if smallest_free > MAX_SUBSCRIPTION_IDENTIFIER {
+        return None;
+    }
+    NonZero::new(smallest_free)
+}
+
 /// Pushes `UserWriteOut::PublishDroppedDueToSessionNotResumed` for every
 /// in-flight packet.
 pub(crate) fn emit_publish_dropped_for_all_inflight<Time: 'static>(
@@ -105,14 +191,51 @@ pub(crate) fn emit_publish_dropped_for_all_inflight<Time: 'static>(
     }
 }
 
+/// Whole seconds left until `deadline`, rounded up and capped at `max_secs`,
+/// or `None` once it has passed.
+///
+/// `Time` only supports adding a `Duration`, so the remainder is found by
+/// bisection instead of subtraction.
+pub(crate) fn remaining_secs<Time>(deadline: Time, now: Time, max_secs: u64) -> Option<u64>
+where
+    Time: ProtocolTime,
+{
+    if deadline <= now {
+        return None;
+    }
+    let (mut low, mut high) = (1, max_secs.max(1));
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if now + Duration::from_secs(mid) >= deadline {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    Some(low)
+}
+
 /// Retransmits unacknowledged QoS1/QoS2 PUBLISH with DUP=1 on session resume.
 ///
 /// [MQTT-4.4.0-1] [MQTT-4.4.0-2] On session resume, retransmit unacknowledged
 /// QoS1/QoS2 PUBLISH with DUP=1.
-pub(crate) fn replay_outbound_inflight_with_dup<Time: 'static>(
+///
+/// [§3.3.2.3.3](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901112)
+/// A PUBLISH carrying a Message Expiry Interval is resent with what is left
+/// of it at `now`, or dropped with [`UserWriteOut::PublishExpired`] once it
+/// has run out.
+pub(crate) fn replay_outbound_inflight_with_dup<Time>(
     session: &mut ClientSession,
     scratchpad: &mut ClientScratchpad<Time>,
-) -> Result<(), Error> {
+    now: Time,
+) -> Result<(), Error>
+where
+    Time: ProtocolTime,
+{
+    scratchpad
+        .publish_expiry_deadlines
+        .retain(|packet_id, _| session.on_flight_sent.contains_key(packet_id));
+
     for (packet_id, state) in session.on_flight_sent.clone() {
         let publish = match state {
             OutboundInflightState::Qos1AwaitPubAck { mut publish }
@@ -120,6 +243,29 @@ pub(crate) fn replay_outbound_inflight_with_dup<Time: 'static>(
                 if let PublishKind::Repetible { dup, .. } = &mut publish.kind {
                     *dup = true;
                 }
+                if let (Some(deadline), Some(interval)) = (
+                    scratchpad.publish_expiry_deadlines.get(&packet_id).copied(),
+                    publish.properties.message_expiry_interval,
+                ) {
+                    match remaining_secs(deadline, now, u64::from(interval)) {
+                        Some(remaining) => {
+                            publish.properties.message_expiry_interval =
+                                Some(u32::try_from(remaining).unwrap_or(interval));
+                        }
+                        None => {
+                            let _ = session.on_flight_sent.remove(&packet_id);
+                            let _ = scratchpad.publish_expiry_deadlines.remove(&packet_id);
+                            session_store::record(
+                                scratchpad,
+                                SessionChange::OutboundCompleted(packet_id),
+                            );
+                            scratchpad
+                                .read_queue
+                                .push_back(UserWriteOut::PublishExpired(packet_id));
+                            continue;
+                        }
+                    }
+                }
                 publish
             }
             OutboundInflightState::Qos2AwaitPubComp => {
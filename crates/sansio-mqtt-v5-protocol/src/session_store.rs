@@ -0,0 +1,198 @@
+//! Write-through persistence of [`ClientSession`] changes.
+//!
+//! A [`SessionStore`] installed with
+//! [`Client::set_session_store`](crate::Client::set_session_store) receives
+//! every change to the in-flight state and the subscription registry of the
+//! session before any packet that depends on it can be taken from `poll_write`,
+//! so the wire is never ahead of storage. Replaying the recorded changes with
+//! [`ClientSession::apply`] rebuilds the session to hand to
+//! [`Client::with_settings_and_session`](crate::Client::with_settings_and_session)
+//! after a restart.
+//!
+//! Only state that outlives the process is recorded: inbound publishes still
+//! waiting for the application's decision are redelivered by the server on
+//! resume and are left out.
+
+use crate::scratchpad::ClientScratchpad;
+use crate::session::ClientSession;
+use crate::session::InboundInflightState;
+use crate::session::OutboundInflightState;
+use crate::session::SubscriptionEntry;
+use crate::types::Error;
+use core::num::NonZero;
+use sansio_mqtt_v5_types::GuaranteedQoS;
+use sansio_mqtt_v5_types::PubRecReasonCode;
+use sansio_mqtt_v5_types::Publish;
+use sansio_mqtt_v5_types::PublishKind;
+use sansio_mqtt_v5_types::Subscription;
+use sansio_mqtt_v5_types::TopicFilter;
+
+/// A change to the in-flight state or the subscription registry of a
+/// [`ClientSession`].
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum SessionChange {
+    /// A QoS 1 or QoS 2 PUBLISH was sent and awaits PUBACK or PUBREC.
+    OutboundPublished {
+        packet_id: NonZero<u16>,
+        publish: Publish,
+    },
+    /// [MQTT-4.3.3-4] The server accepted an outbound QoS 2 PUBLISH with
+    /// PUBREC; its PUBREL was sent and awaits PUBCOMP.
+    OutboundReleased(NonZero<u16>),
+    /// An outbound exchange ended: it was acknowledged, refused with a PUBREC
+    /// failure, or its message expired.
+    OutboundCompleted(NonZero<u16>),
+    /// An inbound QoS 2 PUBLISH was answered with PUBREC carrying
+    /// `reason_code`; a PUBREL for it is still expected.
+    InboundAcknowledged {
+        packet_id: NonZero<u16>,
+        reason_code: PubRecReasonCode,
+    },
+    /// The PUBREL for an inbound QoS 2 PUBLISH arrived and was answered with
+    /// PUBCOMP.
+    InboundReleased(NonZero<u16>),
+    /// [MQTT-3.1.2-4] All in-flight state was discarded because the session
+    /// was not resumed.
+    Cleared,
+    /// The server granted `subscription`, replacing any granted earlier with
+    /// the same topic filter.
+    SubscriptionAdded {
+        subscription: Subscription,
+        subscription_identifier: Option<NonZero<u64>>,
+    },
+    /// The subscription to a topic filter ended: it was unsubscribed,
+    /// refused, could not be replayed or its session was discarded.
+    SubscriptionRemoved(TopicFilter),
+}
+
+/// Durable storage for the changes of a [`ClientSession`].
+///
+/// The protocol calls [`record`](Self::record) in the order changes happen,
+/// once per change, at the end of each `handle_*` call. Returning an error
+/// withholds the packets and events produced by that call, undoes its
+/// changes that were not recorded, closes the connection and fails the call
+/// with [`Error::SessionStoreFailed`].
+pub trait SessionStore: core::fmt::Debug + Send {
+    /// Durably records `change` before returning.
+    fn record(&mut self, change: &SessionChange) -> Result<(), Error>;
+}
+
+/// A [`SessionStore`] keeping the recorded session in memory, e.g. to survive
+/// the loss of a [`Client`](crate::Client) but not of the process.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemorySessionStore {
+    session: ClientSession,
+}
+
+impl MemorySessionStore {
+    /// A store starting from `session`, as restored on startup.
+    pub fn new(session: ClientSession) -> Self {
+        Self { session }
+    }
+
+    /// The session rebuilt from every change recorded so far.
+    pub fn session(&self) -> &ClientSession {
+        &self.session
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn record(&mut self, change: &SessionChange) -> Result<(), Error> {
+        self.session.apply(change);
+        Ok(())
+    }
+}
+
+impl ClientSession {
+    /// Applies a change reported to a [`SessionStore`].
+    pub fn apply(&mut self, change: &SessionChange) {
+        match change {
+            SessionChange::OutboundPublished { packet_id, publish } => {
+                let state = match publish.kind {
+                    PublishKind::Repetible {
+                        qos: GuaranteedQoS::AtLeastOnce,
+                        ..
+                    } => OutboundInflightState::Qos1AwaitPubAck {
+                        publish: publish.clone(),
+                    },
+                    PublishKind::Repetible {
+                        qos: GuaranteedQoS::ExactlyOnce,
+                        ..
+                    } => OutboundInflightState::Qos2AwaitPubRec {
+                        publish: publish.clone(),
+                    },
+                    PublishKind::FireAndForget => return,
+                };
+                self.on_flight_sent.insert(*packet_id, state);
+            }
+            SessionChange::OutboundReleased(packet_id) => {
+                self.on_flight_sent
+                    .insert(*packet_id, OutboundInflightState::Qos2AwaitPubComp);
+            }
+            SessionChange::OutboundCompleted(packet_id) => {
+                let _ = self.on_flight_sent.remove(packet_id);
+            }
+            SessionChange::InboundAcknowledged {
+                packet_id,
+                reason_code,
+            } => {
+                let state = match reason_code {
+                    PubRecReasonCode::Success | PubRecReasonCode::NoMatchingSubscribers => {
+                        InboundInflightState::Qos2AwaitPubRel
+                    }
+                    reason_code => InboundInflightState::Qos2Rejected(*reason_code),
+                };
+                self.on_flight_received.insert(*packet_id, state);
+            }
+            SessionChange::InboundReleased(packet_id) => {
+                let _ = self.on_flight_received.remove(packet_id);
+            }
+            SessionChange::Cleared => {
+                self.on_flight_sent.clear();
+                self.on_flight_received.clear();
+            }
+            SessionChange::SubscriptionAdded {
+                subscription,
+                subscription_identifier,
+            } => {
+                self.subscriptions.insert(
+                    subscription.topic_filter.clone(),
+                    SubscriptionEntry {
+                        subscription: subscription.clone(),
+                        subscription_identifier: *subscription_identifier,
+                    },
+                );
+            }
+            SessionChange::SubscriptionRemoved(topic_filter) => {
+                let _ = self.subscriptions.remove(topic_filter);
+            }
+        }
+    }
+}
+
+/// Queues `change` for the installed store, if any; recorded by [`flush`] at
+/// the end of the current `handle_*` call.
+pub(crate) fn record<Time: 'static>(
+    scratchpad: &mut ClientScratchpad<Time>,
+    change: SessionChange,
+) {
+    if scratchpad.session_store.is_some() {
+        scratchpad.session_changes.push(change);
+    }
+}
+
+/// Hands the queued changes to the installed store, in order. On failure
+/// returns how many of them it recorded, and leaves them all queued.
+pub(crate) fn flush<Time: 'static>(scratchpad: &mut ClientScratchpad<Time>) -> Result<(), usize> {
+    let Some(store) = scratchpad.session_store.as_mut() else {
+        return Ok(());
+    };
+    for (recorded, change) in scratchpad.session_changes.iter().enumerate() {
+        if store.record(change).is_err() {
+            return Err(recorded);
+        }
+    }
+    scratchpad.session_changes.clear();
+    Ok(())
+}
@@ -1,13 +1,19 @@
+use crate::authenticator;
 use crate::limits;
 use crate::queues;
 use crate::scratchpad::ClientScratchpad;
 use crate::session::ClientSession;
 use crate::session::InboundInflightState;
 use crate::session::OutboundInflightState;
+use crate::session::SubscriptionEntry;
 use crate::session_ops;
+use crate::session_store;
+use crate::session_store::SessionChange;
 use crate::state::ClientState;
 use crate::state::StateHandler;
 use crate::state::disconnected::Disconnected;
+use crate::topic_alias::TopicAliasing;
+use crate::types::AuthPacket;
 use crate::types::BrokerMessage;
 use crate::types::ClientMessage;
 use crate::types::ClientSettings;
@@ -17,10 +23,17 @@ use crate::types::Error;
 use crate::types::InboundMessageId;
 use crate::types::IncomingRejectReason;
 use crate::types::ProtocolTime;
+use crate::types::UserToken;
 use crate::types::UserWriteIn;
 use crate::types::UserWriteOut;
+use alloc::collections::btree_map::BTreeMap;
 use alloc::vec::Vec;
+use core::num::NonZero;
 use core::time::Duration;
+use encode::EncodableSize;
+use sansio_mqtt_v5_types::AuthProperties;
+use sansio_mqtt_v5_types::AuthReasonCode;
+use sansio_mqtt_v5_types::AuthenticationKind;
 use sansio_mqtt_v5_types::ControlPacket;
 use sansio_mqtt_v5_types::Disconnect;
 use sansio_mqtt_v5_types::DisconnectProperties;
@@ -34,21 +47,41 @@ use sansio_mqtt_v5_types::Publish;
 use sansio_mqtt_v5_types::PublishKind;
 use sansio_mqtt_v5_types::PublishProperties;
 use sansio_mqtt_v5_types::Qos;
+use sansio_mqtt_v5_types::SubAckReasonCode;
 use sansio_mqtt_v5_types::Subscribe;
 use sansio_mqtt_v5_types::SubscribeProperties;
+use sansio_mqtt_v5_types::Subscription;
+use sansio_mqtt_v5_types::Topic;
+use sansio_mqtt_v5_types::UnsubAckReasonCode;
 use sansio_mqtt_v5_types::Unsubscribe;
 use sansio_mqtt_v5_types::UnsubscribeProperties;
+use sansio_mqtt_v5_types::Utf8String;
 
 #[derive(Debug)]
 pub(crate) struct Connected;
 
-fn map_inbound_publish_to_broker_message(publish: Publish) -> BrokerMessage {
+fn map_inbound_publish_to_broker_message(
+    session: &ClientSession,
+    publish: Publish,
+) -> BrokerMessage {
     let qos = match &publish.kind {
         PublishKind::FireAndForget => Qos::AtMostOnce,
         PublishKind::Repetible { qos, .. } => Qos::from(*qos),
     };
     let retain = publish.retain;
     let properties = publish.properties;
+    let subscriptions: Vec<_> = properties
+        .subscription_identifiers
+        .iter()
+        .filter_map(|identifier| session.subscription_handle(*identifier))
+        .collect();
+    // Subscriptions without an identifier never show up in the PUBLISH, so
+    // any of them may have delivered it as well.
+    let unidentified_subscriptions = if subscriptions.is_empty() {
+        Vec::new()
+    } else {
+        session.unidentified_subscriptions(&publish.topic)
+    };
 
     BrokerMessage {
         qos,
@@ -62,6 +95,8 @@ fn map_inbound_publish_to_broker_message(publish: Publish) -> BrokerMessage {
         topic_alias: properties.topic_alias,
         response_topic: properties.response_topic,
         correlation_data: properties.correlation_data,
+        subscriptions,
+        unidentified_subscriptions,
         subscription_identifiers: properties.subscription_identifiers,
         content_type: properties.content_type,
         user_properties: properties.user_properties,
@@ -106,7 +141,7 @@ fn handle_inbound_qos1_publish<Time: 'static>(
             scratchpad.read_queue.push_back(
                 UserWriteOut::ReceivedMessageWithRequiredAcknowledgement(
                     InboundMessageId::new(packet_id),
-                    map_inbound_publish_to_broker_message(publish),
+                    map_inbound_publish_to_broker_message(session, publish),
                 ),
             );
             session
@@ -169,7 +204,7 @@ fn handle_inbound_qos2_publish<Time: 'static>(
             scratchpad.read_queue.push_back(
                 UserWriteOut::ReceivedMessageWithRequiredAcknowledgement(
                     InboundMessageId::new(packet_id),
-                    map_inbound_publish_to_broker_message(publish),
+                    map_inbound_publish_to_broker_message(session, publish),
                 ),
             );
             session
@@ -180,6 +215,55 @@ fn handle_inbound_qos2_publish<Time: 'static>(
     }
 }
 
+fn push_packet_id_assigned<Time>(
+    scratchpad: &mut ClientScratchpad<Time>,
+    token: Option<UserToken>,
+    packet_id: NonZero<u16>,
+) where
+    Time: ProtocolTime,
+{
+    if let Some(token) = token {
+        scratchpad
+            .read_queue
+            .push_back(UserWriteOut::PacketIdAssigned { token, packet_id });
+    }
+}
+
+/// Builds the AUTH `ReAuthenticate` that opens a client-initiated
+/// re-authentication ([MQTT-4.12.1-1]).
+fn reauthenticate_packet(authentication: AuthenticationKind) -> AuthPacket {
+    AuthPacket {
+        reason_code: AuthReasonCode::ReAuthenticate,
+        properties: AuthProperties {
+            authentication: Some(authentication),
+            ..AuthProperties::default()
+        },
+    }
+}
+
+/// Acts on an elapsed re-authentication deadline: restarts the installed
+/// authenticator, or asks the application for fresh credentials.
+fn start_scheduled_reauthentication<Time>(
+    scratchpad: &mut ClientScratchpad<Time>,
+) -> Result<(), Error>
+where
+    Time: ProtocolTime,
+{
+    // Re-armed by the server's AUTH `Success`.
+    scratchpad.reauthenticate_deadline = None;
+    let Some(authenticator) = scratchpad.authenticator.as_deref_mut() else {
+        scratchpad
+            .read_queue
+            .push_back(UserWriteOut::ReauthenticationRequired);
+        return Ok(());
+    };
+    let authentication = authenticator::start(authenticator)?;
+    queues::enqueue_packet(
+        scratchpad,
+        &ControlPacket::Auth(reauthenticate_packet(authentication)),
+    )
+}
+
 fn build_outbound_publish(
     msg: ClientMessage,
     session: &mut ClientSession,
@@ -248,6 +332,297 @@ fn build_outbound_publish(
     Ok((publish, inflight_state))
 }
 
+/// Sends `msg` now, giving QoS1/QoS2 publishes a Packet Identifier and an
+/// in-flight slot.
+///
+/// [§3.3.2.3.3](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901112)
+/// The Message Expiry Interval runs from `queued_at`: a publish that waited
+/// in the pending queue is sent with what is left of it.
+fn send_publish<Time>(
+    settings: &ClientSettings,
+    session: &mut ClientSession,
+    scratchpad: &mut ClientScratchpad<Time>,
+    mut msg: ClientMessage,
+    queued_at: Option<Time>,
+) -> Result<(), Error>
+where
+    Time: ProtocolTime,
+{
+    limits::validate_outbound_topic_alias(settings, scratchpad, msg.topic_alias)?;
+    limits::validate_outbound_publish_capabilities(scratchpad, &msg)?;
+
+    if matches!(msg.qos, Qos::AtLeastOnce | Qos::ExactlyOnce) {
+        // [MQTT-4.9.0-1] Apply peer Receive Maximum before sending QoS1/QoS2 PUBLISH.
+        limits::ensure_outbound_receive_maximum_capacity(session, scratchpad)?;
+    }
+
+    let expiry_deadline = queued_at
+        .zip(msg.message_expiry_interval)
+        .map(|(queued_at, interval)| queued_at + interval);
+    if let (Some(deadline), Some(now), Some(interval)) =
+        (expiry_deadline, scratchpad.now, msg.message_expiry_interval)
+        && let Some(remaining) = session_ops::remaining_secs(deadline, now, interval.as_secs())
+    {
+        msg.message_expiry_interval = Some(Duration::from_secs(remaining).min(interval));
+    }
+
+    let token = msg.token;
+    let (mut publish, inflight_state) = build_outbound_publish(msg, session)?;
+    // Only the wire packet is aliased: the in-flight copy keeps the full
+    // topic so it can be replayed on a connection with no mapping.
+    let aliasing = if settings.auto_topic_alias {
+        scratchpad
+            .outbound_topic_aliases
+            .lookup(
+                &publish.topic,
+                scratchpad.effective_broker_topic_alias_maximum,
+            )
+            .map(|aliasing| (publish.topic.clone(), aliasing))
+    } else {
+        None
+    };
+    if let Some((_, aliasing)) = &aliasing {
+        publish.properties.topic_alias = Some(aliasing.alias());
+        if let TopicAliasing::Established(_) = aliasing {
+            publish.topic = Topic::default();
+        }
+    }
+    let kind = publish.kind.clone();
+    let packet = ControlPacket::Publish(publish);
+
+    queues::enqueue_packet(scratchpad, &packet)?;
+    if let Some((topic, aliasing)) = aliasing {
+        scratchpad.outbound_topic_aliases.commit(topic, aliasing);
+    }
+
+    if let (PublishKind::Repetible { packet_id, .. }, Some(inflight_state)) = (kind, inflight_state)
+    {
+        if let OutboundInflightState::Qos1AwaitPubAck { publish }
+        | OutboundInflightState::Qos2AwaitPubRec { publish } = &inflight_state
+        {
+            session_store::record(
+                scratchpad,
+                SessionChange::OutboundPublished {
+                    packet_id,
+                    publish: publish.clone(),
+                },
+            );
+        }
+        session.on_flight_sent.insert(packet_id, inflight_state);
+        match expiry_deadline {
+            Some(deadline) => {
+                scratchpad
+                    .publish_expiry_deadlines
+                    .insert(packet_id, deadline);
+            }
+            None => {
+                let _ = scratchpad.publish_expiry_deadlines.remove(&packet_id);
+            }
+        }
+        push_packet_id_assigned(scratchpad, token, packet_id);
+    }
+
+    Ok(())
+}
+
+/// Sends one SUBSCRIBE for `subscriptions` and remembers them until SUBACK.
+fn send_subscribe<Time: 'static>(
+    session: &mut ClientSession,
+    scratchpad: &mut ClientScratchpad<Time>,
+    subscriptions: Vec<Subscription>,
+    subscription_identifier: Option<NonZero<u64>>,
+    user_properties: Vec<(Utf8String, Utf8String)>,
+) -> Result<NonZero<u16>, Error> {
+    let mut subscriptions = subscriptions.into_iter();
+    let subscription = subscriptions.next().ok_or(Error::ProtocolError)?;
+    let packet_id = session_ops::next_packet_id_checked(session)?;
+    let entries = core::iter::once(&subscription)
+        .chain(subscriptions.as_slice())
+        .map(|subscription| SubscriptionEntry {
+            subscription: subscription.clone(),
+            subscription_identifier,
+        })
+        .collect();
+
+    queues::enqueue_packet(
+        scratchpad,
+        &ControlPacket::Subscribe(Subscribe {
+            packet_id,
+            subscription,
+            extra_subscriptions: subscriptions.collect(),
+            properties: SubscribeProperties {
+                subscription_identifier,
+                user_properties,
+            },
+        }),
+    )?;
+    session.pending_subscribe.insert(packet_id, entries);
+    Ok(packet_id)
+}
+
+/// Replays the subscriptions of the previous session on a new one, with
+/// [`ClientSettings::resubscribe_on_new_session`], or forgets them.
+///
+/// Subscriptions sharing a Subscription Identifier go out together, in as
+/// few SUBSCRIBEs as the server's Maximum Packet Size allows; their SUBACK is
+/// reported like any other. Ones that cannot be sent at all are forgotten and
+/// reported with [`UserWriteOut::ResubscribeFailed`]. The identifier is left
+/// out when the new server does not support it.
+pub(crate) fn resubscribe<Time: 'static>(
+    settings: &ClientSettings,
+    session: &mut ClientSession,
+    scratchpad: &mut ClientScratchpad<Time>,
+) {
+    if !settings.resubscribe_on_new_session {
+        session_ops::clear_subscriptions(session, scratchpad);
+        return;
+    }
+
+    let mut groups = BTreeMap::<Option<NonZero<u64>>, Vec<Subscription>>::new();
+    for entry in session.subscriptions.values() {
+        let subscription_identifier = entry
+            .subscription_identifier
+            .filter(|_| scratchpad.effective_subscription_identifiers_available);
+        groups
+            .entry(subscription_identifier)
+            .or_default()
+            .push(entry.subscription.clone());
+    }
+    for (subscription_identifier, subscriptions) in groups {
+        for subscriptions in split_subscribe(scratchpad, subscription_identifier, subscriptions) {
+            let Err(err) = send_subscribe(
+                session,
+                scratchpad,
+                subscriptions.clone(),
+                subscription_identifier,
+                Vec::new(),
+            ) else {
+                continue;
+            };
+            tracing::warn!(%err, "dropping subscriptions that could not be replayed");
+            for subscription in &subscriptions {
+                session_ops::remove_subscription(session, scratchpad, &subscription.topic_filter);
+            }
+            scratchpad
+                .read_queue
+                .push_back(UserWriteOut::ResubscribeFailed { subscriptions });
+        }
+    }
+}
+
+/// Splits `subscriptions` into runs that each fit one SUBSCRIBE under the
+/// server's Maximum Packet Size. A subscription too large on its own keeps a
+/// run to itself.
+fn split_subscribe<Time: 'static>(
+    scratchpad: &ClientScratchpad<Time>,
+    subscription_identifier: Option<NonZero<u64>>,
+    subscriptions: Vec<Subscription>,
+) -> Vec<Vec<Subscription>> {
+    let Some(maximum_packet_size) = scratchpad.negotiated_maximum_packet_size else {
+        return alloc::vec![subscriptions];
+    };
+    let Some(first) = subscriptions.first() else {
+        return Vec::new();
+    };
+    // Everything but the subscriptions, plus room for the Remaining Length
+    // to grow to its longest encoding.
+    let packet = ControlPacket::Subscribe(Subscribe {
+        packet_id: NonZero::<u16>::MIN,
+        subscription: first.clone(),
+        extra_subscriptions: Vec::new(),
+        properties: SubscribeProperties {
+            subscription_identifier,
+            user_properties: Vec::new(),
+        },
+    });
+    let overhead = packet
+        .encoded_size()
+        .unwrap_or(0)
+        .saturating_sub(subscription_size(first))
+        + 3;
+
+    let mut runs = Vec::new();
+    let mut run = Vec::new();
+    let mut size = overhead;
+    for subscription in subscriptions {
+        let subscription_size = subscription_size(&subscription);
+        if !run.is_empty() && size + subscription_size > maximum_packet_size.get() as usize {
+            runs.push(core::mem::take(&mut run));
+            size = overhead;
+        }
+        size += subscription_size;
+        run.push(subscription);
+    }
+    runs.push(run);
+    runs
+}
+
+/// The bytes `subscription` takes in a SUBSCRIBE payload.
+fn subscription_size(subscription: &Subscription) -> usize {
+    subscription.encoded_size().unwrap_or(0)
+}
+
+/// Parks a publish in the pending queue, stamped with the latest instant
+/// the driver supplied.
+pub(crate) fn queue_publish<Time>(
+    settings: &ClientSettings,
+    scratchpad: &mut ClientScratchpad<Time>,
+    msg: ClientMessage,
+) -> Result<(), Error>
+where
+    Time: ProtocolTime,
+{
+    let queued_at = scratchpad.now;
+    scratchpad
+        .pending_publish
+        .push(settings, &mut scratchpad.read_queue, msg, queued_at)
+}
+
+/// Sends queued publishes, oldest first, for as long as the server's Receive
+/// Maximum leaves in-flight slots free for the QoS1/QoS2 ones.
+///
+/// A publish older than [`ClientSettings::pending_publish_max_age`] at `now`,
+/// or one the current connection can no longer carry (for instance because
+/// the server lowered its Maximum QoS or Maximum Packet Size), is dropped
+/// and reported with [`UserWriteOut::PendingPublishDropped`].
+pub(crate) fn send_pending_publishes<Time>(
+    settings: &ClientSettings,
+    session: &mut ClientSession,
+    scratchpad: &mut ClientScratchpad<Time>,
+    now: Time,
+) where
+    Time: ProtocolTime,
+{
+    while let Some(pending) = scratchpad.pending_publish.front() {
+        if !pending.is_expired(settings, now)
+            && matches!(pending.message.qos, Qos::AtLeastOnce | Qos::ExactlyOnce)
+            && limits::ensure_outbound_receive_maximum_capacity(session, scratchpad).is_err()
+        {
+            break;
+        }
+        let Some(pending) = scratchpad.pending_publish.pop_front() else {
+            break;
+        };
+        let change = scratchpad.session_changes.len();
+        if pending.is_expired(settings, now)
+            || send_publish(
+                settings,
+                session,
+                scratchpad,
+                pending.message.clone(),
+                pending.queued_at,
+            )
+            .is_err()
+        {
+            scratchpad
+                .read_queue
+                .push_back(UserWriteOut::PendingPublishDropped(pending.message));
+        } else if pending.message.qos != Qos::AtMostOnce {
+            scratchpad.pending_publish.mark_sent(change);
+        }
+    }
+}
+
 impl<Time> StateHandler<Time> for Connected
 where
     Time: ProtocolTime,
@@ -258,7 +633,7 @@ where
         session: &mut ClientSession,
         scratchpad: &mut ClientScratchpad<Time>,
         packet: ControlPacket,
-        _received_at: Time,
+        received_at: Time,
     ) -> (ClientState, Result<(), Error>) {
         match packet {
             ControlPacket::Publish(mut publish) => {
@@ -282,7 +657,7 @@ where
                         scratchpad
                             .read_queue
                             .push_back(UserWriteOut::ReceivedMessage(
-                                map_inbound_publish_to_broker_message(publish),
+                                map_inbound_publish_to_broker_message(session, publish),
                             ));
                         (ClientState::Connected(self), Ok(()))
                     }
@@ -318,6 +693,10 @@ where
                 match session.on_flight_received.get(&packet_id).copied() {
                     Some(InboundInflightState::Qos2AwaitPubRel) => {
                         let _ = session.on_flight_received.remove(&packet_id);
+                        session_store::record(
+                            scratchpad,
+                            SessionChange::InboundReleased(packet_id),
+                        );
                         let result = queues::enqueue_pubcomp_or_fail_protocol(
                             settings,
                             session,
@@ -347,6 +726,10 @@ where
                     }
                     Some(InboundInflightState::Qos2Rejected(_)) => {
                         let _ = session.on_flight_received.remove(&packet_id);
+                        session_store::record(
+                            scratchpad,
+                            SessionChange::InboundReleased(packet_id),
+                        );
                         let result = queues::enqueue_pubcomp_or_fail_protocol(
                             settings,
                             session,
@@ -383,9 +766,15 @@ where
                         // [MQTT-4.3.2-3] QoS1 sender keeps PUBLISH unacknowledged until matching
                         // PUBACK is received.
                         let _ = session.on_flight_sent.remove(&packet_id);
+                        let _ = scratchpad.publish_expiry_deadlines.remove(&packet_id);
+                        session_store::record(
+                            scratchpad,
+                            SessionChange::OutboundCompleted(packet_id),
+                        );
                         scratchpad
                             .read_queue
                             .push_back(UserWriteOut::PublishAcknowledged(packet_id, reason_code));
+                        send_pending_publishes(settings, session, scratchpad, received_at);
                         (ClientState::Connected(self), Ok(()))
                     }
                     _ => {
@@ -418,21 +807,34 @@ where
                                 settings, session, scratchpad, packet_id,
                             ) {
                                 Ok(()) => {
+                                    // The message has been delivered; only PUBREL is
+                                    // resent from here on, whatever its expiry.
+                                    let _ = scratchpad.publish_expiry_deadlines.remove(&packet_id);
                                     session
                                         .on_flight_sent
                                         .insert(packet_id, OutboundInflightState::Qos2AwaitPubComp);
+                                    session_store::record(
+                                        scratchpad,
+                                        SessionChange::OutboundReleased(packet_id),
+                                    );
                                     (ClientState::Connected(self), Ok(()))
                                 }
                                 Err(e) => (ClientState::Disconnected(Disconnected), Err(e)),
                             }
                         } else {
                             let _ = session.on_flight_sent.remove(&packet_id);
+                            let _ = scratchpad.publish_expiry_deadlines.remove(&packet_id);
+                            session_store::record(
+                                scratchpad,
+                                SessionChange::OutboundCompleted(packet_id),
+                            );
                             scratchpad.read_queue.push_back(
                                 UserWriteOut::PublishDroppedDueToBrokerRejectedPubRec(
                                     packet_id,
                                     reason_code,
                                 ),
                             );
+                            send_pending_publishes(settings, session, scratchpad, received_at);
                             (ClientState::Connected(self), Ok(()))
                         }
                     }
@@ -469,9 +871,14 @@ where
                         // [MQTT-4.3.3-5] QoS2 sender treats PUBREL as unacknowledged until matching
                         // PUBCOMP is received.
                         let _ = session.on_flight_sent.remove(&packet_id);
+                        session_store::record(
+                            scratchpad,
+                            SessionChange::OutboundCompleted(packet_id),
+                        );
                         scratchpad
                             .read_queue
                             .push_back(UserWriteOut::PublishCompleted(packet_id, reason_code));
+                        send_pending_publishes(settings, session, scratchpad, received_at);
                         (ClientState::Connected(self), Ok(()))
                     }
                     _ => {
@@ -496,12 +903,13 @@ where
             }
             ControlPacket::SubAck(suback) => {
                 // [MQTT-3.8.4-1] SUBACK MUST correspond to an outstanding SUBSCRIBE Packet
-                // Identifier.
-                if session
+                // Identifier. [MQTT-3.9.3-1] It carries one Reason Code per Topic Filter
+                // of that SUBSCRIBE, in the same order.
+                let entries = session
                     .pending_subscribe
                     .remove(&suback.packet_id)
-                    .is_none()
-                {
+                    .filter(|entries| entries.len() == suback.reason_codes.len());
+                let Some(entries) = entries else {
                     let _ = queues::fail_protocol_and_disconnect(
                         settings,
                         session,
@@ -512,17 +920,39 @@ where
                         ClientState::Disconnected(Disconnected),
                         Err(Error::ProtocolError),
                     );
+                };
+                for (entry, reason_code) in entries.into_iter().zip(&suback.reason_codes) {
+                    if matches!(
+                        reason_code,
+                        SubAckReasonCode::SuccessQoS0
+                            | SubAckReasonCode::SuccessQoS1
+                            | SubAckReasonCode::SuccessQoS2
+                    ) {
+                        session_ops::add_subscription(session, scratchpad, entry);
+                    } else {
+                        let filter = &entry.subscription.topic_filter;
+                        session_ops::remove_subscription(session, scratchpad, filter);
+                    }
                 }
+                scratchpad
+                    .read_queue
+                    .push_back(UserWriteOut::SubscribeAcknowledged {
+                        packet_id: suback.packet_id,
+                        reason_codes: suback.reason_codes,
+                        reason_string: suback.properties.reason_string,
+                        user_properties: suback.properties.user_properties,
+                    });
                 (ClientState::Connected(self), Ok(()))
             }
             ControlPacket::UnsubAck(unsuback) => {
                 // [MQTT-3.10.4-1] UNSUBACK MUST correspond to an outstanding UNSUBSCRIBE Packet
-                // Identifier.
-                if session
+                // Identifier. [MQTT-3.11.3-1] It carries one Reason Code per Topic Filter
+                // of that UNSUBSCRIBE, in the same order.
+                let filters = session
                     .pending_unsubscribe
                     .remove(&unsuback.packet_id)
-                    .is_none()
-                {
+                    .filter(|filters| filters.len() == unsuback.reason_codes.len());
+                let Some(filters) = filters else {
                     let _ = queues::fail_protocol_and_disconnect(
                         settings,
                         session,
@@ -533,7 +963,23 @@ where
                         ClientState::Disconnected(Disconnected),
                         Err(Error::ProtocolError),
                     );
+                };
+                for (filter, reason_code) in filters.iter().zip(&unsuback.reason_codes) {
+                    if matches!(
+                        reason_code,
+                        UnsubAckReasonCode::Success | UnsubAckReasonCode::NoSubscriptionExisted
+                    ) {
+                        session_ops::remove_subscription(session, scratchpad, filter);
+                    }
                 }
+                scratchpad
+                    .read_queue
+                    .push_back(UserWriteOut::UnsubscribeAcknowledged {
+                        packet_id: unsuback.packet_id,
+                        reason_codes: unsuback.reason_codes,
+                        reason_string: unsuback.properties.reason_string,
+                        user_properties: unsuback.properties.user_properties,
+                    });
                 (ClientState::Connected(self), Ok(()))
             }
             ControlPacket::Disconnect(disconnect) => {
@@ -550,6 +996,16 @@ where
                 scratchpad
                     .action_queue
                     .push_back(DriverEventOut::CloseSocket);
+                // [MQTT-4.11] Follow a server redirect with the stored CONNECT options.
+                if let (
+                    DisconnectReasonCode::UseAnotherServer | DisconnectReasonCode::ServerMoved,
+                    Some(reference),
+                ) = (reason_code, disconnect.properties.server_reference)
+                {
+                    scratchpad
+                        .action_queue
+                        .push_back(DriverEventOut::OpenSocketTo(reference));
+                }
                 (ClientState::Disconnected(Disconnected), Ok(()))
             }
             ControlPacket::Auth(auth) => {
@@ -557,8 +1013,62 @@ where
                 // CONNECT to initiate re-authentication. Forward it to the application;
                 // the application is responsible for responding with AUTH or DISCONNECT.
                 // [MQTT-4.12.0-4] The client MUST respond to an AUTH packet from the server.
-                scratchpad.read_queue.push_back(UserWriteOut::Auth(auth));
-                (ClientState::Connected(self), Ok(()))
+                // AUTH is only valid if CONNECT carried an Authentication Method, and the
+                // server must keep using that same method.
+                if !limits::authentication_method_matches(
+                    scratchpad.pending_connect_options.authentication.as_ref(),
+                    auth.properties.authentication.as_ref(),
+                ) {
+                    let _ = queues::fail_protocol_and_disconnect(
+                        settings,
+                        session,
+                        scratchpad,
+                        DisconnectReasonCode::ProtocolError,
+                    );
+                    return (
+                        ClientState::Disconnected(Disconnected),
+                        Err(Error::ProtocolError),
+                    );
+                }
+                if auth.reason_code == AuthReasonCode::Success {
+                    // The server accepted fresh credentials; they are good for another
+                    // `reauthenticate_after` from now.
+                    scratchpad.arm_reauthenticate_deadline(received_at);
+                }
+                let Some(authenticator) = scratchpad.authenticator.as_deref_mut() else {
+                    scratchpad.read_queue.push_back(UserWriteOut::Auth(auth));
+                    return (ClientState::Connected(self), Ok(()));
+                };
+                let result = match auth.reason_code {
+                    AuthReasonCode::ContinueAuthentication => {
+                        authenticator::answer_challenge(authenticator, &auth)
+                            .map(|response| Some(ControlPacket::Auth(response)))
+                    }
+                    AuthReasonCode::Success => authenticator
+                        .finish(authenticator::authentication_data(
+                            auth.properties.authentication.as_ref(),
+                        ))
+                        .map(|()| None),
+                    // Only the client may initiate re-authentication.
+                    AuthReasonCode::ReAuthenticate => Err(Error::ProtocolError),
+                };
+                match result {
+                    Ok(Some(response)) => match queues::enqueue_packet(scratchpad, &response) {
+                        Ok(()) => (ClientState::Connected(self), Ok(())),
+                        Err(e) => (ClientState::Connected(self), Err(e)),
+                    },
+                    Ok(None) => (ClientState::Connected(self), Ok(())),
+                    Err(e) => {
+                        let reason = match e {
+                            Error::ProtocolError => DisconnectReasonCode::ProtocolError,
+                            _ => DisconnectReasonCode::UnspecifiedError,
+                        };
+                        let _ = queues::fail_protocol_and_disconnect(
+                            settings, session, scratchpad, reason,
+                        );
+                        (ClientState::Disconnected(Disconnected), Err(e))
+                    }
+                }
             }
             _ => {
                 let _ = queues::fail_protocol_and_disconnect(
@@ -588,40 +1098,25 @@ where
                 Err(Error::InvalidStateTransition),
             ),
             UserWriteIn::PublishMessage(msg) => {
-                if let Err(e) = limits::validate_outbound_topic_alias(scratchpad, msg.topic_alias) {
-                    return (ClientState::Connected(self), Err(e));
-                }
-                if let Err(e) = limits::validate_outbound_publish_capabilities(scratchpad, &msg) {
-                    return (ClientState::Connected(self), Err(e));
-                }
-
-                if matches!(msg.qos, Qos::AtLeastOnce | Qos::ExactlyOnce) {
-                    // [MQTT-4.9.0-1] Apply peer Receive Maximum before sending QoS1/QoS2 PUBLISH.
-                    if let Err(e) =
-                        limits::ensure_outbound_receive_maximum_capacity(session, scratchpad)
-                    {
-                        return (ClientState::Connected(self), Err(e));
-                    }
-                }
-
-                let (publish, inflight_state) = match build_outbound_publish(msg, session) {
-                    Ok(v) => v,
-                    Err(e) => return (ClientState::Connected(self), Err(e)),
-                };
-                let kind = publish.kind.clone();
-                let packet = ControlPacket::Publish(publish);
-
-                if let Err(e) = queues::enqueue_packet(scratchpad, &packet) {
-                    return (ClientState::Connected(self), Err(e));
-                }
-
-                if let (PublishKind::Repetible { packet_id, .. }, Some(inflight_state)) =
-                    (kind, inflight_state)
+                let result = if settings.max_pending_publishes > 0
+                    && matches!(msg.qos, Qos::AtLeastOnce | Qos::ExactlyOnce)
+                    && (!scratchpad.pending_publish.is_empty()
+                        || limits::ensure_outbound_receive_maximum_capacity(session, scratchpad)
+                            .is_err())
                 {
-                    session.on_flight_sent.insert(packet_id, inflight_state);
-                }
-
-                (ClientState::Connected(self), Ok(()))
+                    // Keep QoS1/QoS2 publishes in order behind the ones already
+                    // waiting, but refuse what this connection could never send
+                    // while the caller can still act on the error.
+                    limits::validate_outbound_topic_alias(settings, scratchpad, msg.topic_alias)
+                        .and_then(|()| {
+                            limits::validate_outbound_publish_capabilities(scratchpad, &msg)
+                        })
+                        .and_then(|()| queue_publish(settings, scratchpad, msg))
+                } else {
+                    let now = scratchpad.now;
+                    send_publish(settings, session, scratchpad, msg, now)
+                };
+                (ClientState::Connected(self), result)
             }
             UserWriteIn::AcknowledgeMessage(inbound_message_id) => {
                 let packet_id = inbound_message_id.get();
@@ -654,6 +1149,13 @@ where
                                 session
                                     .on_flight_received
                                     .insert(packet_id, InboundInflightState::Qos2AwaitPubRel);
+                                session_store::record(
+                                    scratchpad,
+                                    SessionChange::InboundAcknowledged {
+                                        packet_id,
+                                        reason_code: PubRecReasonCode::Success,
+                                    },
+                                );
                                 (ClientState::Connected(self), Ok(()))
                             }
                             Err(e) => (ClientState::Disconnected(Disconnected), Err(e)),
@@ -697,6 +1199,13 @@ where
                                     packet_id,
                                     InboundInflightState::Qos2Rejected(reason_code),
                                 );
+                                session_store::record(
+                                    scratchpad,
+                                    SessionChange::InboundAcknowledged {
+                                        packet_id,
+                                        reason_code,
+                                    },
+                                );
                                 (ClientState::Connected(self), Ok(()))
                             }
                             Err(e) => (ClientState::Disconnected(Disconnected), Err(e)),
@@ -717,16 +1226,13 @@ where
                 let subscriptions = core::iter::once(options.subscription)
                     .chain(options.extra_subscriptions)
                     .map(|subscription| {
-                        let topic_filter_str: &str = subscription.topic_filter.as_ref();
-                        let is_shared = topic_filter_str.starts_with("$share/");
-                        let has_wildcard =
-                            topic_filter_str.contains('+') || topic_filter_str.contains('#');
-
-                        if has_wildcard && !scratchpad.effective_wildcard_subscription_available {
+                        if subscription.topic_filter.has_wildcards()
+                            && !scratchpad.effective_wildcard_subscription_available
+                        {
                             return Err(Error::ProtocolError);
                         }
 
-                        if is_shared {
+                        if subscription.topic_filter.is_shared() {
                             if !scratchpad.effective_shared_subscription_available {
                                 return Err(Error::ProtocolError);
                             }
@@ -745,30 +1251,21 @@ where
                     Ok(v) => v,
                     Err(e) => return (ClientState::Connected(self), Err(e)),
                 };
-                let mut subscriptions = subscriptions.into_iter();
-                let subscription = match subscriptions.next() {
-                    Some(s) => s,
-                    None => return (ClientState::Connected(self), Err(Error::ProtocolError)),
-                };
-                let packet_id = match session_ops::next_packet_id_checked(session) {
-                    Ok(id) => id,
-                    Err(e) => return (ClientState::Connected(self), Err(e)),
-                };
-
-                match queues::enqueue_packet(
+                let subscription_identifier = options.subscription_identifier.or_else(|| {
+                    (settings.auto_subscription_identifiers
+                        && scratchpad.effective_subscription_identifiers_available)
+                        .then(|| session_ops::next_subscription_identifier(session))
+                        .flatten()
+                });
+                match send_subscribe(
+                    session,
                     scratchpad,
-                    &ControlPacket::Subscribe(Subscribe {
-                        packet_id,
-                        subscription,
-                        extra_subscriptions: subscriptions.collect(),
-                        properties: SubscribeProperties {
-                            subscription_identifier: options.subscription_identifier,
-                            user_properties: options.user_properties,
-                        },
-                    }),
+                    subscriptions,
+                    subscription_identifier,
+                    options.user_properties,
                 ) {
-                    Ok(()) => {
-                        session.pending_subscribe.insert(packet_id, ());
+                    Ok(packet_id) => {
+                        push_packet_id_assigned(scratchpad, options.token, packet_id);
                         (ClientState::Connected(self), Ok(()))
                     }
                     Err(e) => (ClientState::Connected(self), Err(e)),
@@ -779,6 +1276,10 @@ where
                     Ok(id) => id,
                     Err(e) => return (ClientState::Connected(self), Err(e)),
                 };
+                let filters = core::iter::once(&options.filter)
+                    .chain(&options.extra_filters)
+                    .cloned()
+                    .collect();
 
                 match queues::enqueue_packet(
                     scratchpad,
@@ -792,19 +1293,72 @@ where
                     }),
                 ) {
                     Ok(()) => {
-                        session.pending_unsubscribe.insert(packet_id, ());
+                        session.pending_unsubscribe.insert(packet_id, filters);
+                        push_packet_id_assigned(scratchpad, options.token, packet_id);
                         (ClientState::Connected(self), Ok(()))
                     }
                     Err(e) => (ClientState::Connected(self), Err(e)),
                 }
             }
-            UserWriteIn::Disconnect => {
+            UserWriteIn::Auth(auth) => {
+                // [MQTT-4.12.1-1] Re-authentication reuses the Authentication Method of
+                // the original CONNECT; the client either starts it or continues a
+                // server-initiated one.
+                if let Err(e) = limits::validate_outbound_auth(
+                    scratchpad.pending_connect_options.authentication.as_ref(),
+                    &auth,
+                    &[
+                        AuthReasonCode::ContinueAuthentication,
+                        AuthReasonCode::ReAuthenticate,
+                    ],
+                ) {
+                    return (ClientState::Connected(self), Err(e));
+                }
+                match queues::enqueue_packet(scratchpad, &ControlPacket::Auth(auth)) {
+                    Ok(()) => (ClientState::Connected(self), Ok(())),
+                    Err(e) => (ClientState::Connected(self), Err(e)),
+                }
+            }
+            UserWriteIn::Reauthenticate(authentication) => {
+                // An installed authenticator owns the exchange and re-authenticates on
+                // its own schedule.
+                if scratchpad.authenticator.is_some() {
+                    return (
+                        ClientState::Connected(self),
+                        Err(Error::InvalidStateTransition),
+                    );
+                }
+                let auth = reauthenticate_packet(authentication.clone());
+                if let Err(e) = limits::validate_outbound_auth(
+                    scratchpad.pending_connect_options.authentication.as_ref(),
+                    &auth,
+                    &[AuthReasonCode::ReAuthenticate],
+                ) {
+                    return (ClientState::Connected(self), Err(e));
+                }
+                if let Err(e) = queues::enqueue_packet(scratchpad, &ControlPacket::Auth(auth)) {
+                    return (ClientState::Connected(self), Err(e));
+                }
+                // Later reconnections present the refreshed credentials in CONNECT.
+                scratchpad.pending_connect_options.authentication = Some(authentication);
+                scratchpad.reauthenticate_deadline = None;
+                (ClientState::Connected(self), Ok(()))
+            }
+            UserWriteIn::Disconnect(options) => {
+                if let Err(e) = limits::validate_outbound_disconnect(
+                    &scratchpad.pending_connect_options,
+                    &options,
+                ) {
+                    return (ClientState::Connected(self), Err(e));
+                }
+                // [MQTT-3.14.2-2] The interval sent now decides whether the session
+                // outlives this connection.
+                if let Some(interval) = options.session_expiry_interval {
+                    scratchpad.session_should_persist = interval > 0;
+                }
                 let _ = queues::enqueue_packet(
                     scratchpad,
-                    &ControlPacket::Disconnect(Disconnect {
-                        reason_code: DisconnectReasonCode::NormalDisconnection,
-                        properties: DisconnectProperties::default(),
-                    }),
+                    &ControlPacket::Disconnect(options.into_packet()),
                 );
                 scratchpad
                     .action_queue
@@ -866,6 +1420,36 @@ where
         scratchpad: &mut ClientScratchpad<Time>,
         now: Time,
     ) -> (ClientState, Result<(), Error>) {
+        if scratchpad
+            .reauthenticate_deadline
+            .is_some_and(|deadline| deadline <= now)
+        {
+            match start_scheduled_reauthentication(scratchpad) {
+                Ok(()) => {}
+                Err(Error::AuthenticationFailed) => {
+                    let _ = queues::fail_protocol_and_disconnect(
+                        settings,
+                        session,
+                        scratchpad,
+                        DisconnectReasonCode::UnspecifiedError,
+                    );
+                    return (
+                        ClientState::Disconnected(Disconnected),
+                        Err(Error::AuthenticationFailed),
+                    );
+                }
+                Err(e) => return (ClientState::Connected(self), Err(e)),
+            }
+            // Only fall through to keep-alive handling when its deadline has
+            // elapsed as well.
+            if scratchpad
+                .next_timeout
+                .is_none_or(|deadline| deadline > now)
+            {
+                return (ClientState::Connected(self), Ok(()));
+            }
+        }
+
         let Some(interval_secs) = scratchpad.keep_alive_interval_secs else {
             scratchpad.next_timeout = None;
             return (ClientState::Connected(self), Ok(()));
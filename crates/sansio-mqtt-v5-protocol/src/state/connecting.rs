@@ -1,3 +1,4 @@
+use crate::authenticator;
 use crate::limits;
 use crate::queues;
 use crate::scratchpad::ClientScratchpad;
@@ -5,9 +6,11 @@ use crate::session::ClientSession;
 use crate::session_ops;
 use crate::state::ClientState;
 use crate::state::StateHandler;
+use crate::state::connected;
 use crate::state::connected::Connected;
 use crate::state::disconnected::Disconnected;
 use crate::types::ClientSettings;
+use crate::types::ConnectionInfo;
 use crate::types::ConnectionOptions;
 use crate::types::DriverEventIn;
 use crate::types::DriverEventOut;
@@ -16,6 +19,7 @@ use crate::types::ProtocolTime;
 use crate::types::UserWriteIn;
 use crate::types::UserWriteOut;
 use core::num::NonZero;
+use sansio_mqtt_v5_types::AuthReasonCode;
 use sansio_mqtt_v5_types::BinaryData;
 use sansio_mqtt_v5_types::ConnAckKind;
 use sansio_mqtt_v5_types::ConnackReasonCode;
@@ -125,7 +129,7 @@ fn build_connect(settings: &ClientSettings, options: &ConnectionOptions) -> Resu
 /// Resets negotiated limits, builds and enqueues the CONNECT packet, and
 /// resets keepalive tracking flags. On error, stays in Connecting.
 pub(crate) fn on_socket_connected<Time>(
-    connecting: Connecting,
+    mut connecting: Connecting,
     settings: &ClientSettings,
     session: &mut ClientSession,
     scratchpad: &mut ClientScratchpad<Time>,
@@ -134,6 +138,15 @@ where
     Time: ProtocolTime,
 {
     limits::reset_negotiated_limits(settings, session, scratchpad);
+    if let Some(authenticator) = scratchpad.authenticator.as_deref_mut() {
+        // Every CONNECT opens a fresh exchange.
+        match authenticator::start(authenticator) {
+            Ok(authentication) => {
+                connecting.pending_connect_options.authentication = Some(authentication);
+            }
+            Err(e) => return (ClientState::Connecting(connecting), Err(e)),
+        }
+    }
     let connect = match build_connect(settings, &connecting.pending_connect_options) {
         Ok(packet) => packet,
         Err(e) => return (ClientState::Connecting(connecting), Err(e)),
@@ -190,11 +203,66 @@ where
     }
 }
 
+/// Handles a CONNACK whose reason code refuses the connection.
+///
+/// [MQTT-3.2.2-7] [MQTT-3.2.2-8] The server closes the network connection
+/// after a refusal; report the reason code so the application can tell a
+/// bad password from a busy or moved server. A redirect with a Server
+/// Reference also asks the driver to reconnect there.
+fn on_connack_refused<Time>(
+    settings: &ClientSettings,
+    session: &mut ClientSession,
+    scratchpad: &mut ClientScratchpad<Time>,
+    connack: sansio_mqtt_v5_types::ConnAck,
+) -> (ClientState, Result<(), Error>)
+where
+    Time: ProtocolTime,
+{
+    let reason_code = match connack.kind {
+        ConnAckKind::Other { reason_code } => reason_code,
+        ConnAckKind::ResumePreviousSession => {
+            unreachable!("successful CONNACK kind already matched")
+        }
+    };
+    limits::reset_negotiated_limits(settings, session, scratchpad);
+    scratchpad
+        .action_queue
+        .push_back(DriverEventOut::CloseSocket);
+    scratchpad
+        .read_queue
+        .push_back(UserWriteOut::ConnectionRefused {
+            reason_code,
+            reason_string: connack.properties.reason_string.clone(),
+            server_reference: connack.properties.server_reference.clone(),
+        });
+
+    // [MQTT-4.11] A redirect is not a failure: the same CONNECT is retried
+    // against the referenced server once the driver reports SocketConnected.
+    if let (ConnackReasonCode::UseAnotherServer | ConnackReasonCode::ServerMoved, Some(reference)) =
+        (reason_code, &connack.properties.server_reference)
+    {
+        scratchpad
+            .action_queue
+            .push_back(DriverEventOut::OpenSocketTo(reference.clone()));
+        return (ClientState::Disconnected(Disconnected), Ok(()));
+    }
+
+    (
+        ClientState::Disconnected(Disconnected),
+        Err(Error::ConnectionRefused {
+            reason_code,
+            reason_string: connack.properties.reason_string,
+            server_reference: connack.properties.server_reference,
+        }),
+    )
+}
+
 /// Handles a successful CONNACK (reason code Success or ResumePreviousSession).
 ///
 /// Populates negotiated scratchpad fields from CONNACK properties, recomputes
 /// effective limits, sets keep-alive from server or options, resets keepalive
-/// tracking, then transitions to Connected and emits `UserWriteOut::Connected`.
+/// tracking, then transitions to Connected and emits `UserWriteOut::Connected`
+/// with the CONNACK details the application cares about.
 fn on_connack_success<Time>(
     connecting: Connecting,
     settings: &ClientSettings,
@@ -255,7 +323,17 @@ where
     scratchpad.keep_alive_saw_network_activity = false;
     scratchpad.keep_alive_ping_outstanding = false;
 
-    let mut connected_emitted = false;
+    // Emitted once: ahead of the dropped-publish events on a fresh session,
+    // after the replay on a resumed one.
+    let mut info = Some(ConnectionInfo {
+        session_present: matches!(connack.kind, ConnAckKind::ResumePreviousSession),
+        assigned_client_identifier: connack.properties.assigned_client_identifier,
+        response_information: connack.properties.response_information,
+        reason_string: connack.properties.reason_string,
+        server_reference: connack.properties.server_reference,
+        server_keep_alive: connack.properties.server_keep_alive,
+        user_properties: connack.properties.user_properties,
+    });
 
     match connack.kind {
         ConnAckKind::ResumePreviousSession => {
@@ -275,7 +353,9 @@ where
             }
             // [MQTT-4.4.0-1] [MQTT-4.4.0-2] Session Present=1 resumes in-flight QoS
             // transactions and replay path.
-            if session_ops::replay_outbound_inflight_with_dup(session, scratchpad).is_err() {
+            if session_ops::replay_outbound_inflight_with_dup(session, scratchpad, received_at)
+                .is_err()
+            {
                 let _ = queues::fail_protocol_and_disconnect(
                     settings,
                     session,
@@ -291,16 +371,22 @@ where
         ConnAckKind::Other {
             reason_code: ConnackReasonCode::Success,
         } => {
-            scratchpad.read_queue.push_back(UserWriteOut::Connected);
-            connected_emitted = true;
+            if let Some(info) = info.take() {
+                scratchpad
+                    .read_queue
+                    .push_back(UserWriteOut::Connected(info));
+            }
             session_ops::emit_publish_dropped_for_all_inflight(session, scratchpad);
-            session_ops::reset_session_state(session);
+            session_ops::reset_session_state(session, scratchpad);
+            connected::resubscribe(settings, session, scratchpad);
         }
         _ => unreachable!("successful CONNACK kind already matched"),
     }
 
-    if !connected_emitted {
-        scratchpad.read_queue.push_back(UserWriteOut::Connected);
+    if let Some(info) = info {
+        scratchpad
+            .read_queue
+            .push_back(UserWriteOut::Connected(info));
     }
 
     // [MQTT-3.1.2-22] Arm the keep-alive timer from the CONNACK arrival
@@ -309,6 +395,10 @@ where
     if let Some(interval_secs) = scratchpad.keep_alive_interval_secs {
         scratchpad.arm_keep_alive_deadline(received_at, u64::from(interval_secs.get()));
     }
+    // The credentials in CONNECT were accepted just now.
+    scratchpad.arm_reauthenticate_deadline(received_at);
+    // Publishes that waited for a slot go out behind the replayed ones.
+    connected::send_pending_publishes(settings, session, scratchpad, received_at);
 
     (ClientState::Connected(Connected), Ok(()))
 }
@@ -334,20 +424,41 @@ where
                             reason_code: ConnackReasonCode::Success
                         }
                 ) {
+                    // The final server data (e.g. the SCRAM server signature) must
+                    // check out before the session is considered established.
+                    if let Some(authenticator) = scratchpad.authenticator.as_deref_mut() {
+                        let authentication = connack.properties.authentication.as_ref();
+                        let result = if authentication.is_none()
+                            || limits::authentication_method_matches(
+                                self.pending_connect_options.authentication.as_ref(),
+                                authentication,
+                            ) {
+                            authenticator.finish(authenticator::authentication_data(authentication))
+                        } else {
+                            Err(Error::ProtocolError)
+                        };
+                        if let Err(e) = result {
+                            let _ = queues::fail_protocol_and_disconnect(
+                                settings,
+                                session,
+                                scratchpad,
+                                DisconnectReasonCode::UnspecifiedError,
+                            );
+                            return (ClientState::Disconnected(Disconnected), Err(e));
+                        }
+                    }
                     on_connack_success(self, settings, session, scratchpad, connack, received_at)
                 } else {
-                    limits::reset_negotiated_limits(settings, session, scratchpad);
-                    scratchpad
-                        .action_queue
-                        .push_back(DriverEventOut::CloseSocket);
-                    (
-                        ClientState::Disconnected(Disconnected),
-                        Err(Error::ProtocolError),
-                    )
+                    on_connack_refused(settings, session, scratchpad, connack)
                 }
             }
             ControlPacket::Auth(auth) => {
-                if self.pending_connect_options.authentication.is_none() {
+                // [MQTT-4.12.0-2] AUTH is only valid if CONNECT carried an Authentication
+                // Method, and the server must keep using that same method.
+                if !limits::authentication_method_matches(
+                    self.pending_connect_options.authentication.as_ref(),
+                    auth.properties.authentication.as_ref(),
+                ) {
                     let _ = queues::fail_protocol_and_disconnect(
                         settings,
                         session,
@@ -360,10 +471,7 @@ where
                     );
                 }
 
-                if !matches!(
-                    auth.reason_code,
-                    sansio_mqtt_v5_types::AuthReasonCode::ContinueAuthentication
-                ) {
+                if !matches!(auth.reason_code, AuthReasonCode::ContinueAuthentication) {
                     let _ = queues::fail_protocol_and_disconnect(
                         settings,
                         session,
@@ -376,7 +484,28 @@ where
                     );
                 }
 
-                (ClientState::Connecting(self), Ok(()))
+                let Some(authenticator) = scratchpad.authenticator.as_deref_mut() else {
+                    // The challenge is answered by the application through
+                    // `UserWriteIn::Auth`.
+                    scratchpad.read_queue.push_back(UserWriteOut::Auth(auth));
+                    return (ClientState::Connecting(self), Ok(()));
+                };
+                let response = match authenticator::answer_challenge(authenticator, &auth) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        let _ = queues::fail_protocol_and_disconnect(
+                            settings,
+                            session,
+                            scratchpad,
+                            DisconnectReasonCode::UnspecifiedError,
+                        );
+                        return (ClientState::Disconnected(Disconnected), Err(e));
+                    }
+                };
+                match queues::enqueue_packet(scratchpad, &ControlPacket::Auth(response)) {
+                    Ok(()) => (ClientState::Connecting(self), Ok(())),
+                    Err(e) => (ClientState::Connecting(self), Err(e)),
+                }
             }
             _ => {
                 let _ = queues::fail_protocol_and_disconnect(
@@ -401,14 +530,19 @@ where
         msg: UserWriteIn,
     ) -> (ClientState, Result<(), Error>) {
         match msg {
-            UserWriteIn::Disconnect => {
+            UserWriteIn::Disconnect(options) => {
+                if let Err(e) =
+                    limits::validate_outbound_disconnect(&self.pending_connect_options, &options)
+                {
+                    return (ClientState::Connecting(self), Err(e));
+                }
                 scratchpad.pending_connect_options = self.pending_connect_options;
+                if let Some(interval) = options.session_expiry_interval {
+                    scratchpad.session_should_persist = interval > 0;
+                }
                 let _ = queues::enqueue_packet(
                     scratchpad,
-                    &ControlPacket::Disconnect(Disconnect {
-                        reason_code: DisconnectReasonCode::NormalDisconnection,
-                        properties: DisconnectProperties::default(),
-                    }),
+                    &ControlPacket::Disconnect(options.into_packet()),
                 );
                 scratchpad
                     .action_queue
@@ -422,6 +556,26 @@ where
                     .push_back(UserWriteOut::Disconnected(None));
                 (ClientState::Disconnected(Disconnected), Ok(()))
             }
+            UserWriteIn::Auth(auth) if self.connect_sent => {
+                // Before CONNACK the client can only continue the exchange started by
+                // CONNECT ([§4.12](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901256)).
+                if let Err(e) = limits::validate_outbound_auth(
+                    self.pending_connect_options.authentication.as_ref(),
+                    &auth,
+                    &[AuthReasonCode::ContinueAuthentication],
+                ) {
+                    return (ClientState::Connecting(self), Err(e));
+                }
+                match queues::enqueue_packet(scratchpad, &ControlPacket::Auth(auth)) {
+                    Ok(()) => (ClientState::Connecting(self), Ok(())),
+                    Err(e) => (ClientState::Connecting(self), Err(e)),
+                }
+            }
+            UserWriteIn::PublishMessage(msg) if settings.queue_publishes_while_offline => {
+                // Sent, or dropped if the server cannot take it, after CONNACK.
+                let result = crate::state::connected::queue_publish(settings, scratchpad, msg);
+                (ClientState::Connecting(self), result)
+            }
             _ => (
                 ClientState::Connecting(self),
                 Err(Error::InvalidStateTransition),
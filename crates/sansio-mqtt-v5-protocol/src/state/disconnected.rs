@@ -44,6 +44,11 @@ where
                 );
                 (ClientState::Disconnected(self), Ok(()))
             }
+            UserWriteIn::PublishMessage(msg) if settings.queue_publishes_while_offline => {
+                // Sent, or dropped if the server cannot take it, after CONNACK.
+                let result = crate::state::connected::queue_publish(settings, scratchpad, msg);
+                (ClientState::Disconnected(self), result)
+            }
             _ => (
                 ClientState::Disconnected(self),
                 Err(Error::InvalidStateTransition),
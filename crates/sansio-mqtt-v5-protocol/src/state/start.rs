@@ -3,6 +3,8 @@ use crate::queues;
 use crate::scratchpad::ClientScratchpad;
 use crate::session::ClientSession;
 use crate::session_ops;
+use crate::session_store;
+use crate::session_store::SessionChange;
 use crate::state::ClientState;
 use crate::state::StateHandler;
 use crate::state::connecting::Connecting;
@@ -43,7 +45,10 @@ pub(crate) fn store_connect_options_and_enqueue_open_socket<Time>(
     scratchpad.pending_connect_options = options;
     limits::recompute_effective_limits(settings, scratchpad);
     if scratchpad.pending_connect_options.clean_start {
-        // [MQTT-3.1.2-4] Clean Start=1 starts a new Session.
+        // [MQTT-3.1.2-4] Clean Start=1 starts a new Session. The store
+        // must forget the old one too, or it would be restored on restart.
+        session_store::record(scratchpad, SessionChange::Cleared);
+        session_ops::clear_subscriptions(session, scratchpad);
         *session = ClientSession::default();
     }
     scratchpad.session_should_persist = scratchpad
@@ -101,6 +106,11 @@ where
                 );
                 (ClientState::Start(self), Ok(()))
             }
+            UserWriteIn::PublishMessage(msg) if settings.queue_publishes_while_offline => {
+                // Sent, or dropped if the server cannot take it, after CONNACK.
+                let result = crate::state::connected::queue_publish(settings, scratchpad, msg);
+                (ClientState::Start(self), result)
+            }
             _ => (ClientState::Start(self), Err(Error::InvalidStateTransition)),
         }
     }
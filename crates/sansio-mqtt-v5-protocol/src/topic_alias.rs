@@ -0,0 +1,74 @@
+//! Automatic outbound Topic Alias assignment
+//! ([§3.3.2.3.4](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901113)).
+//!
+//! Enabled with [`ClientSettings::auto_topic_alias`](crate::ClientSettings).
+//! Each topic gets an alias the first time it is published; the PUBLISH that
+//! registers it carries the full topic, later ones only the alias. Once every
+//! alias up to the server's Topic Alias Maximum is taken, the least recently
+//! published topic gives up its alias.
+
+use alloc::collections::btree_map::BTreeMap;
+use core::num::NonZero;
+use sansio_mqtt_v5_types::Topic;
+
+/// How a PUBLISH should use a Topic Alias.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TopicAliasing {
+    /// The server already maps the alias to this topic: send an empty topic.
+    Established(NonZero<u16>),
+    /// Send the full topic together with the alias to (re)define the mapping.
+    Register(NonZero<u16>),
+}
+
+impl TopicAliasing {
+    pub(crate) fn alias(self) -> NonZero<u16> {
+        match self {
+            Self::Established(alias) | Self::Register(alias) => alias,
+        }
+    }
+}
+
+/// Outbound Topic Alias mapping for the current Network Connection.
+#[derive(Debug, Default)]
+pub(crate) struct OutboundTopicAliases {
+    /// Alias of each registered topic and when it was last published.
+    aliases: BTreeMap<Topic, (NonZero<u16>, u64)>,
+    /// Lowest alias never handed out on this connection.
+    next_alias: u16,
+    clock: u64,
+}
+
+impl OutboundTopicAliases {
+    /// Picks the alias for `topic` without changing the mapping; `None` when
+    /// the server does not accept aliases.
+    pub(crate) fn lookup(&self, topic: &Topic, maximum: u16) -> Option<TopicAliasing> {
+        if let Some((alias, _)) = self.aliases.get(topic) {
+            return Some(TopicAliasing::Established(*alias));
+        }
+        let next_alias = self.next_alias.max(1);
+        if next_alias <= maximum {
+            return NonZero::new(next_alias).map(TopicAliasing::Register);
+        }
+        self.aliases
+            .values()
+            .min_by_key(|(_, last_used)| *last_used)
+            .map(|(alias, _)| TopicAliasing::Register(*alias))
+    }
+
+    /// Records that a PUBLISH for `topic` using `aliasing` was sent.
+    pub(crate) fn commit(&mut self, topic: Topic, aliasing: TopicAliasing) {
+        self.clock += 1;
+        let alias = aliasing.alias();
+        if let TopicAliasing::Register(alias) = aliasing {
+            // The alias may have belonged to the evicted topic.
+            self.aliases.retain(|_, (existing, _)| *existing != alias);
+            self.next_alias = self.next_alias.max(alias.get().saturating_add(1));
+        }
+        self.aliases.insert(topic, (alias, self.clock));
+    }
+
+    /// Forgets every mapping; aliases do not outlive the Network Connection.
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
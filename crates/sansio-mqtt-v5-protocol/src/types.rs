@@ -7,6 +7,9 @@ pub use sansio_mqtt_v5_types::Auth as AuthPacket;
 pub use sansio_mqtt_v5_types::AuthReasonCode;
 pub use sansio_mqtt_v5_types::AuthenticationKind;
 pub use sansio_mqtt_v5_types::BinaryData;
+pub use sansio_mqtt_v5_types::ConnackReasonCode;
+use sansio_mqtt_v5_types::Disconnect;
+use sansio_mqtt_v5_types::DisconnectProperties;
 pub use sansio_mqtt_v5_types::DisconnectReasonCode;
 pub use sansio_mqtt_v5_types::FormatIndicator;
 use sansio_mqtt_v5_types::MaximumQoS;
@@ -17,8 +20,11 @@ pub use sansio_mqtt_v5_types::PubCompReasonCode;
 pub use sansio_mqtt_v5_types::PubRecReasonCode;
 pub use sansio_mqtt_v5_types::Qos;
 pub use sansio_mqtt_v5_types::RetainHandling;
+pub use sansio_mqtt_v5_types::SubAckReasonCode;
 pub use sansio_mqtt_v5_types::Subscription;
 pub use sansio_mqtt_v5_types::Topic;
+pub use sansio_mqtt_v5_types::TopicFilter;
+pub use sansio_mqtt_v5_types::UnsubAckReasonCode;
 pub use sansio_mqtt_v5_types::Utf8String;
 
 /// Instant-like time contract for [`Client`](crate::Client).
@@ -72,6 +78,59 @@ pub struct ClientSettings {
     pub default_request_response_information: Option<bool>,
     pub default_request_problem_information: Option<bool>,
     pub default_keep_alive: Option<NonZero<u16>>,
+    /// Let the client assign outbound Topic Aliases on its own, within the
+    /// server's Topic Alias Maximum. The mapping starts empty on every
+    /// connection. While enabled, the client owns the alias space and a
+    /// caller-supplied [`ClientMessage::topic_alias`] is rejected.
+    pub auto_topic_alias: bool,
+    /// How many publishes may wait to be sent instead of failing.
+    ///
+    /// QoS 1 and QoS 2 publishes wait while the server's Receive Maximum is
+    /// reached rather than failing with [`Error::ReceiveMaximumExceeded`],
+    /// and get their Packet Identifier, oldest first, as PUBACK and PUBCOMP
+    /// release in-flight slots. `0` disables the queue.
+    pub max_pending_publishes: usize,
+    /// Upper bound on the payload bytes held by waiting publishes; `None`
+    /// leaves only the count limit.
+    pub max_pending_publish_bytes: Option<usize>,
+    /// Let publishes of any QoS wait in the pending queue while there is no
+    /// connection (before CONNACK or after a disconnect), to be sent in order
+    /// once the next CONNACK arrives. Publishes the new connection cannot
+    /// carry are reported with [`UserWriteOut::PendingPublishDropped`].
+    pub queue_publishes_while_offline: bool,
+    /// Waiting publishes older than this are dropped instead of sent, and
+    /// reported with [`UserWriteOut::PendingPublishDropped`]. Age is measured
+    /// from the instant passed to
+    /// [`Client::handle_write_at`](crate::Client::handle_write_at) with the
+    /// publish, or else from the latest instant the driver supplied before.
+    pub pending_publish_max_age: Option<Duration>,
+    /// What happens to a publish that finds the pending queue full.
+    pub pending_publish_overflow: PendingPublishOverflow,
+    /// Resend the subscriptions granted during the previous session when
+    /// CONNACK reports that it was not resumed, right after
+    /// [`UserWriteOut::Connected`]. Their SUBACK is reported with
+    /// [`UserWriteOut::SubscribeAcknowledged`] as usual; subscriptions the
+    /// server refuses are forgotten. Ones that cannot be sent at all are
+    /// forgotten too, and reported with [`UserWriteOut::ResubscribeFailed`].
+    pub resubscribe_on_new_session: bool,
+    /// Give every SUBSCRIBE sent without a
+    /// [`SubscribeOptions::subscription_identifier`] one of its own, unused
+    /// by any other subscription, when the server reports Subscription
+    /// Identifiers as available. Messages delivered because of it then list
+    /// it in [`BrokerMessage::subscriptions`].
+    pub auto_subscription_identifiers: bool,
+}
+
+/// Overflow policy of the pending publish queue, see
+/// [`ClientSettings::max_pending_publishes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PendingPublishOverflow {
+    /// Refuse the new publish with [`Error::PublishQueueFull`].
+    #[default]
+    RejectNewest,
+    /// Discard the oldest waiting publish, reported through
+    /// [`UserWriteOut::PendingPublishDropped`], to make room for the new one.
+    DropOldest,
 }
 
 impl Default for ClientSettings {
@@ -96,6 +155,14 @@ impl Default for ClientSettings {
             default_request_response_information: None,
             default_request_problem_information: None,
             default_keep_alive: None,
+            auto_topic_alias: false,
+            max_pending_publishes: 0,
+            max_pending_publish_bytes: None,
+            queue_publishes_while_offline: false,
+            pending_publish_max_age: None,
+            pending_publish_overflow: PendingPublishOverflow::RejectNewest,
+            resubscribe_on_new_session: false,
+            auto_subscription_identifiers: false,
         }
     }
 }
@@ -112,13 +179,35 @@ pub enum Error {
     PacketTooLarge,
     #[error("receive maximum exceeded")]
     ReceiveMaximumExceeded,
+    /// The pending publish queue is full and its overflow policy is
+    /// [`PendingPublishOverflow::RejectNewest`], or the publish alone exceeds
+    /// [`ClientSettings::max_pending_publish_bytes`].
+    #[error("publish queue full")]
+    PublishQueueFull,
     #[error("encode failure")]
     EncodeFailure,
+    /// The installed [`SessionStore`](crate::SessionStore) failed to record
+    /// a session change. The packets that depended on it were discarded and
+    /// the connection has been closed.
+    #[error("session store failed")]
+    SessionStoreFailed,
     /// [MQTT-3.1.4-5] The connection-establishment timeout elapsed before
     /// CONNACK was received (or before CONNECT was sent in the Start
     /// state). The socket has been closed.
     #[error("connect timeout")]
     ConnectTimeout,
+    /// The installed [`Authenticator`](crate::Authenticator) rejected the
+    /// server's authentication data. The connection has been closed.
+    #[error("authentication failed")]
+    AuthenticationFailed,
+    /// [MQTT-3.2.2-7] The server refused the connection with a CONNACK
+    /// reason code of `0x80` or greater. The socket has been closed.
+    #[error("connection refused: {reason_code}")]
+    ConnectionRefused {
+        reason_code: ConnackReasonCode,
+        reason_string: Option<Utf8String>,
+        server_reference: Option<Utf8String>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -137,6 +226,16 @@ pub struct ConnectionOptions {
     pub request_problem_information: Option<bool>,
     pub authentication: Option<AuthenticationKind>,
     pub user_properties: Vec<(Utf8String, Utf8String)>,
+    /// How long the credentials in `authentication` stay valid once the
+    /// server accepts them.
+    ///
+    /// When set, the client re-authenticates ([MQTT-4.12.1-1]) this long
+    /// after each successful CONNACK or AUTH `Success`: an installed
+    /// [`Authenticator`](crate::Authenticator) is restarted automatically,
+    /// otherwise [`UserWriteOut::ReauthenticationRequired`] asks the
+    /// application for fresh credentials. Pick a value comfortably shorter
+    /// than the real token lifetime to leave room for the exchange.
+    pub reauthenticate_after: Option<Duration>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -167,6 +266,9 @@ pub struct ClientMessage {
     pub correlation_data: Option<BinaryData>,
     pub user_properties: Vec<(Utf8String, Utf8String)>,
     pub content_type: Option<Utf8String>,
+    /// Echoed back through [`UserWriteOut::PacketIdAssigned`]. Ignored for
+    /// QoS 0, which has no Packet Identifier.
+    pub token: Option<UserToken>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -184,9 +286,49 @@ pub struct BrokerMessage {
     /// Zero or more Subscription Identifiers per [MQTT-3.3.2.3.8]. An empty
     /// `Vec` means no subscription identifier was attached on the wire.
     pub subscription_identifiers: Vec<NonZero<u64>>,
+    /// The subscriptions of this client that `subscription_identifiers`
+    /// refer to, in the same order. Identifiers no outstanding or granted
+    /// subscription uses are left out.
+    pub subscriptions: Vec<SubscriptionHandle>,
+    /// Topic filters of this client's subscriptions without a Subscription
+    /// Identifier that match `topic`, ordered by topic filter. The server
+    /// does not report them, so any of them may have delivered the message
+    /// too. Only filled in when `subscriptions` is not empty.
+    pub unidentified_subscriptions: Vec<TopicFilter>,
     pub content_type: Option<Utf8String>,
 }
 
+/// The topic filters subscribed with one Subscription Identifier, see
+/// [`BrokerMessage::subscriptions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriptionHandle {
+    pub subscription_identifier: NonZero<u64>,
+    /// Topic filters of the SUBSCRIBE packets that carried
+    /// `subscription_identifier`, ordered by topic filter.
+    pub topic_filters: Vec<TopicFilter>,
+}
+
+/// What the server told the client in a successful CONNACK
+/// ([§3.2](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901074)).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConnectionInfo {
+    /// [MQTT-3.2.2-2] The server resumed an existing session, so its
+    /// subscriptions and in-flight messages survived.
+    pub session_present: bool,
+    /// [MQTT-3.2.2-16] Client Identifier chosen by the server because CONNECT
+    /// carried an empty one. Reuse it to resume this session later.
+    pub assigned_client_identifier: Option<Utf8String>,
+    /// Basis for response topics, sent when CONNECT requested response
+    /// information.
+    pub response_information: Option<Utf8String>,
+    pub reason_string: Option<Utf8String>,
+    /// Another server the client may use instead of this one.
+    pub server_reference: Option<Utf8String>,
+    /// Keep Alive imposed by the server, replacing the one from CONNECT.
+    pub server_keep_alive: Option<u16>,
+    pub user_properties: Vec<(Utf8String, Utf8String)>,
+}
+
 #[derive(Debug)]
 pub struct InboundMessageId(NonZero<u16>);
 
@@ -200,31 +342,107 @@ impl InboundMessageId {
     }
 }
 
+/// Opaque value the application attaches to a [`ClientMessage`],
+/// [`SubscribeOptions`] or [`UnsubscribeOptions`] to learn which Packet
+/// Identifier the write was given.
+///
+/// The protocol never interprets it; it only hands it back in
+/// [`UserWriteOut::PacketIdAssigned`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UserToken(pub u64);
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SubscribeOptions {
     pub subscription: Subscription,
     pub extra_subscriptions: Vec<Subscription>,
     pub subscription_identifier: Option<NonZero<u64>>,
     pub user_properties: Vec<(Utf8String, Utf8String)>,
+    /// Echoed back through [`UserWriteOut::PacketIdAssigned`].
+    pub token: Option<UserToken>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UnsubscribeOptions {
-    pub filter: Utf8String,
-    pub extra_filters: Vec<Utf8String>,
+    pub filter: TopicFilter,
+    pub extra_filters: Vec<TopicFilter>,
     pub user_properties: Vec<(Utf8String, Utf8String)>,
+    /// Echoed back through [`UserWriteOut::PacketIdAssigned`].
+    pub token: Option<UserToken>,
 }
 
 // Things that the protocol can read from the socket (via the driver)
 #[derive(Debug)]
 pub enum UserWriteOut {
+    /// A tokenised write was queued under `packet_id`.
+    ///
+    /// Emitted by `handle_write` before the packet can possibly be
+    /// acknowledged, so every later event carrying the same Packet Identifier
+    /// (`PublishAcknowledged`, `PublishCompleted`, `PublishDropped*`,
+    /// `SubscribeAcknowledged`, `UnsubscribeAcknowledged`) belongs to that
+    /// write. The identifier stays bound to the write across session
+    /// resumption ([MQTT-4.4.0-1]) and is only reused once it has been
+    /// acknowledged.
+    PacketIdAssigned {
+        token: UserToken,
+        packet_id: NonZero<u16>,
+    },
     ReceivedMessage(BrokerMessage),
     ReceivedMessageWithRequiredAcknowledgement(InboundMessageId, BrokerMessage),
     PublishAcknowledged(NonZero<u16>, PubAckReasonCode),
     PublishCompleted(NonZero<u16>, PubCompReasonCode),
     PublishDroppedDueToSessionNotResumed(NonZero<u16>),
     PublishDroppedDueToBrokerRejectedPubRec(NonZero<u16>, PubRecReasonCode),
-    Connected,
+    /// [§3.3.2.3.3](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901112)
+    /// An unacknowledged publish outlived its Message Expiry Interval before
+    /// it could be resent, so it was dropped instead.
+    PublishExpired(NonZero<u16>),
+    /// A publish waiting in the pending queue was discarded before it was
+    /// sent: pushed out under [`PendingPublishOverflow::DropOldest`], older
+    /// than [`ClientSettings::pending_publish_max_age`], or no longer
+    /// sendable within the limits of the connection when its turn came.
+    PendingPublishDropped(ClientMessage),
+    /// The server answered a SUBSCRIBE with a SUBACK.
+    ///
+    /// [MQTT-3.9.3-1] `reason_codes` holds one entry per topic filter, in the
+    /// order of the originating [`SubscribeOptions`]: `subscription` first,
+    /// then each of `extra_subscriptions`. A code below `0x80` reports the
+    /// granted QoS; anything else means that filter was refused.
+    SubscribeAcknowledged {
+        packet_id: NonZero<u16>,
+        reason_codes: Vec<SubAckReasonCode>,
+        reason_string: Option<Utf8String>,
+        user_properties: Vec<(Utf8String, Utf8String)>,
+    },
+    /// Subscriptions of the previous session that could not be sent again on
+    /// the new one, see [`ClientSettings::resubscribe_on_new_session`]. They
+    /// were forgotten; no SUBACK will follow.
+    ResubscribeFailed {
+        subscriptions: Vec<Subscription>,
+    },
+    /// The server answered an UNSUBSCRIBE with an UNSUBACK.
+    ///
+    /// [MQTT-3.11.3-1] `reason_codes` holds one entry per topic filter, in the
+    /// order of the originating [`UnsubscribeOptions`]: `filter` first, then
+    /// each of `extra_filters`. `NoSubscriptionExisted` still counts as a
+    /// success; codes from `0x80` up mean the subscription was not removed.
+    UnsubscribeAcknowledged {
+        packet_id: NonZero<u16>,
+        reason_codes: Vec<UnsubAckReasonCode>,
+        reason_string: Option<Utf8String>,
+        user_properties: Vec<(Utf8String, Utf8String)>,
+    },
+    /// The server accepted the connection.
+    Connected(ConnectionInfo),
+    /// The server refused the connection; mirrors
+    /// [`Error::ConnectionRefused`] for applications that only watch events.
+    ///
+    /// `server_reference` names another server to try, typically with
+    /// `UseAnotherServer` or `ServerMoved`.
+    ConnectionRefused {
+        reason_code: ConnackReasonCode,
+        reason_string: Option<Utf8String>,
+        server_reference: Option<Utf8String>,
+    },
     /// The connection is now disconnected.
     ///
     /// [MQTT-4.13.0-1] When the payload carries `Some(reason_code)`, the
@@ -233,11 +451,46 @@ pub enum UserWriteOut {
     /// or the socket was closed without an explicit DISCONNECT from the
     /// server.
     Disconnected(Option<DisconnectReasonCode>),
-    /// [MQTT-4.12.0-2] The server has sent an AUTH packet initiating or
-    /// continuing re-authentication during an established session. The
-    /// application must respond by sending a corresponding AUTH or
-    /// DISCONNECT.
+    /// [MQTT-4.12.0-2] The server has sent an AUTH packet, either as a
+    /// challenge while the connection is being established or to initiate or
+    /// continue re-authentication during an established session. The
+    /// application must respond with [`UserWriteIn::Auth`] or
+    /// [`UserWriteIn::Disconnect`].
     Auth(AuthPacket),
+    /// [`ConnectionOptions::reauthenticate_after`] has elapsed and no
+    /// [`Authenticator`](crate::Authenticator) is installed. The application
+    /// should obtain fresh credentials and send them with
+    /// [`UserWriteIn::Reauthenticate`] before the server rejects the old ones.
+    ReauthenticationRequired,
+}
+
+/// What to send in the DISCONNECT that ends the connection
+/// ([§3.14](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901205)).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DisconnectOptions {
+    /// `NormalDisconnection` by default. `DisconnectWithWillMessage` asks the
+    /// server to publish the Will Message anyway.
+    pub reason_code: DisconnectReasonCode,
+    /// [MQTT-3.14.2-2] Replaces the Session Expiry Interval sent in CONNECT.
+    /// Raising it from zero (or from an absent value) is a Protocol Error and
+    /// is refused before anything is sent.
+    pub session_expiry_interval: Option<u32>,
+    pub reason_string: Option<Utf8String>,
+    pub user_properties: Vec<(Utf8String, Utf8String)>,
+}
+
+impl DisconnectOptions {
+    pub(crate) fn into_packet(self) -> Disconnect {
+        Disconnect {
+            reason_code: self.reason_code,
+            properties: DisconnectProperties {
+                session_expiry_interval: self.session_expiry_interval,
+                reason_string: self.reason_string,
+                user_properties: self.user_properties,
+                server_reference: None,
+            },
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -260,7 +513,22 @@ pub enum UserWriteIn {
     RejectMessage(InboundMessageId, IncomingRejectReason),
     Subscribe(SubscribeOptions),
     Unsubscribe(UnsubscribeOptions),
-    Disconnect,
+    /// [MQTT-4.12.0-2] Send an AUTH packet as part of enhanced authentication.
+    ///
+    /// While connecting, this answers a server challenge and must use
+    /// `ContinueAuthentication`. Once connected it may also start a
+    /// re-authentication with `ReAuthenticate` ([MQTT-4.12.1-1]). Either way
+    /// the Authentication Method must match
+    /// [`ConnectionOptions::authentication`].
+    Auth(AuthPacket),
+    /// [MQTT-4.12.1-1] Start a re-authentication with fresh credentials.
+    ///
+    /// Sends AUTH `ReAuthenticate` carrying `authentication`, whose method
+    /// must match [`ConnectionOptions::authentication`]. The new credentials
+    /// also replace the stored ones, so a later reconnection presents them
+    /// in its CONNECT. Only valid while connected.
+    Reauthenticate(AuthenticationKind),
+    Disconnect(DisconnectOptions),
 }
 
 // Driver events to the protocol
@@ -275,6 +543,16 @@ pub enum DriverEventIn {
 #[derive(Debug)]
 pub enum DriverEventOut {
     OpenSocket,
+    /// Open a socket to another server and report it with
+    /// `DriverEventIn::SocketConnected`, as for [`Self::OpenSocket`].
+    ///
+    /// Emitted after the server redirected the client with
+    /// `UseAnotherServer` or `ServerMoved`
+    /// ([§4.11](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901255)).
+    /// The Server Reference format is not defined by the specification; by
+    /// convention it is a space-separated list of `host[:port]` entries.
+    /// Drivers should cap the number of consecutive redirects.
+    OpenSocketTo(Utf8String),
     CloseSocket,
     Quit,
 }
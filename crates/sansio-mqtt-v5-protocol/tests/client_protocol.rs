@@ -7,13 +7,18 @@ use sansio_mqtt_v5_protocol::BrokerMessage;
 use sansio_mqtt_v5_protocol::Client;
 use sansio_mqtt_v5_protocol::ClientMessage;
 use sansio_mqtt_v5_protocol::ClientSettings;
+use sansio_mqtt_v5_protocol::ConnectionInfo;
 use sansio_mqtt_v5_protocol::ConnectionOptions;
+use sansio_mqtt_v5_protocol::DisconnectOptions;
 use sansio_mqtt_v5_protocol::DriverEventIn;
 use sansio_mqtt_v5_protocol::DriverEventOut;
 use sansio_mqtt_v5_protocol::Error;
 use sansio_mqtt_v5_protocol::IncomingData;
 use sansio_mqtt_v5_protocol::IncomingRejectReason;
+use sansio_mqtt_v5_protocol::PendingPublishOverflow;
 use sansio_mqtt_v5_protocol::SubscribeOptions;
+use sansio_mqtt_v5_protocol::SubscriptionHandle;
+use sansio_mqtt_v5_protocol::UserToken;
 use sansio_mqtt_v5_protocol::UserWriteIn;
 use sansio_mqtt_v5_protocol::UserWriteOut;
 use sansio_mqtt_v5_types::Auth;
@@ -48,8 +53,11 @@ use sansio_mqtt_v5_types::PublishKind;
 use sansio_mqtt_v5_types::PublishProperties;
 use sansio_mqtt_v5_types::Qos;
 use sansio_mqtt_v5_types::RetainHandling;
+use sansio_mqtt_v5_types::Subscribe;
+use sansio_mqtt_v5_types::SubscribeProperties;
 use sansio_mqtt_v5_types::Subscription;
 use sansio_mqtt_v5_types::Topic;
+use sansio_mqtt_v5_types::TopicFilter;
 use sansio_mqtt_v5_types::Utf8String;
 use winnow::Parser;
 use winnow::error::ContextError;
@@ -60,9 +68,48 @@ fn encode_packet(packet: &ControlPacket) -> Bytes {
     Bytes::from(out)
 }
 
+/// Answers the CONNECT with a successful CONNACK carrying `properties`,
+/// received at `at`.
+fn accept_connect_at(client: &mut Client<Duration>, properties: ConnAckProperties, at: Duration) {
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties,
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: at
+        }),
+        Ok(())
+    );
+}
+
+/// Connects with `options` and drains the CONNECT.
+fn open_connection(client: &mut Client<Duration>, options: ConnectionOptions) {
+    assert_eq!(client.handle_write(UserWriteIn::Connect(options)), Ok(()));
+    while client.poll_event().is_some() {}
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+}
+
+/// A client with `settings`, connected to a server that accepted it with
+/// `properties`.
+fn connected_client(settings: ClientSettings, properties: ConnAckProperties) -> Client<Duration> {
+    let mut client = Client::<Duration>::with_settings(settings);
+    open_connection(&mut client, ConnectionOptions::default());
+    accept_connect_at(&mut client, properties, Duration::ZERO);
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+    client
+}
+
 fn make_subscription(topic_filter: &str) -> Subscription {
     Subscription {
-        topic_filter: Utf8String::try_from(topic_filter).expect("valid utf8"),
+        topic_filter: TopicFilter::try_from(topic_filter).expect("valid topic filter"),
         qos: Qos::AtMostOnce,
         no_local: false,
         retain_as_published: false,
@@ -86,7 +133,10 @@ fn user_write_out_exposes_qos_delivery_events_with_packet_id() {
     let no_ack = UserWriteOut::ReceivedMessage(msg.clone());
     assert!(matches!(no_ack, UserWriteOut::ReceivedMessage(_)));
 
-    assert!(matches!(UserWriteOut::Connected, UserWriteOut::Connected));
+    assert!(matches!(
+        UserWriteOut::Connected(ConnectionInfo::default()),
+        UserWriteOut::Connected(_)
+    ));
 
     let acknowledged = UserWriteOut::PublishAcknowledged(packet_id, PubAckReasonCode::Success);
     let completed = UserWriteOut::PublishCompleted(packet_id, PubCompReasonCode::Success);
@@ -219,8 +269,12 @@ fn config_and_error_are_instantiable() {
             Error::InvalidStateTransition => "invalid state transition",
             Error::PacketTooLarge => "packet too large",
             Error::ReceiveMaximumExceeded => "receive maximum exceeded",
+            Error::PublishQueueFull => "publish queue full",
             Error::EncodeFailure => "encode failure",
+            Error::SessionStoreFailed => "session store failed",
             Error::ConnectTimeout => "connect timeout",
+            Error::AuthenticationFailed => "authentication failed",
+            Error::ConnectionRefused { .. } => "connection refused",
         }
     };
 
@@ -570,7 +624,62 @@ fn connack_transitions_to_connected_and_emits_connected() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+}
+
+#[test]
+fn connack_details_are_surfaced_on_connected() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::Connect(ConnectionOptions {
+            clean_start: false,
+            ..ConnectionOptions::default()
+        })),
+        Ok(())
+    );
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    let _ = client.poll_write().expect("connect frame expected");
+
+    let utf8 = |value: &str| Utf8String::try_from(value).expect("valid utf8");
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::ResumePreviousSession,
+        properties: ConnAckProperties {
+            assigned_client_identifier: Some(utf8("auto-7f3a")),
+            response_information: Some(utf8("replies/auto-7f3a")),
+            reason_string: Some(utf8("welcome back")),
+            server_reference: Some(utf8("backup.example.com")),
+            server_keep_alive: Some(30),
+            user_properties: vec![(utf8("region"), utf8("eu-west"))],
+            ..ConnAckProperties::default()
+        },
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+
+    let Some(UserWriteOut::Connected(info)) = client.poll_read() else {
+        panic!("expected Connected");
+    };
+    assert_eq!(
+        info,
+        ConnectionInfo {
+            session_present: true,
+            assigned_client_identifier: Some(utf8("auto-7f3a")),
+            response_information: Some(utf8("replies/auto-7f3a")),
+            reason_string: Some(utf8("welcome back")),
+            server_reference: Some(utf8("backup.example.com")),
+            server_keep_alive: Some(30),
+            user_properties: vec![(utf8("region"), utf8("eu-west"))],
+        }
+    );
 }
 
 #[test]
@@ -580,11 +689,15 @@ fn connack_rejected_reason_closes_without_connected_event() {
     assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
     let _ = client.poll_write().expect("connect frame expected");
 
+    let reason_string = Utf8String::try_from("bad credentials").expect("valid utf8");
     let connack = ControlPacket::ConnAck(ConnAck {
         kind: ConnAckKind::Other {
             reason_code: ConnackReasonCode::NotAuthorized,
         },
-        properties: ConnAckProperties::default(),
+        properties: ConnAckProperties {
+            reason_string: Some(reason_string.clone()),
+            ..ConnAckProperties::default()
+        },
     });
 
     assert_eq!(
@@ -592,8 +705,23 @@ fn connack_rejected_reason_closes_without_connected_event() {
             bytes: encode_packet(&connack),
             received_at: Duration::ZERO
         }),
-        Err(Error::ProtocolError)
-    );
+        Err(Error::ConnectionRefused {
+            reason_code: ConnackReasonCode::NotAuthorized,
+            reason_string: Some(reason_string.clone()),
+            server_reference: None,
+        })
+    );
+    let Some(UserWriteOut::ConnectionRefused {
+        reason_code,
+        reason_string: refused_reason_string,
+        server_reference,
+    }) = client.poll_read()
+    else {
+        panic!("expected ConnectionRefused");
+    };
+    assert_eq!(reason_code, ConnackReasonCode::NotAuthorized);
+    assert_eq!(refused_reason_string, Some(reason_string));
+    assert_eq!(server_reference, None);
     assert!(client.poll_read().is_none());
     assert!(matches!(
         client.poll_event(),
@@ -601,6 +729,107 @@ fn connack_rejected_reason_closes_without_connected_event() {
     ));
 }
 
+#[test]
+fn connack_redirect_reopens_socket_to_server_reference() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    let connect_frame = client.poll_write().expect("connect frame expected");
+
+    let reference = Utf8String::try_from("backup.example.com:1884").expect("valid utf8");
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::UseAnotherServer,
+        },
+        properties: ConnAckProperties {
+            server_reference: Some(reference.clone()),
+            ..ConnAckProperties::default()
+        },
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::ConnectionRefused {
+            reason_code: ConnackReasonCode::UseAnotherServer,
+            ..
+        })
+    ));
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::CloseSocket)
+    ));
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::OpenSocketTo(target)) if target == reference
+    ));
+
+    // The same CONNECT goes to the new server.
+    assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert_eq!(client.poll_write(), Some(connect_frame));
+}
+
+#[test]
+fn server_disconnect_with_server_moved_reopens_socket_to_server_reference() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    let _ = client.poll_write().expect("connect frame expected");
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let reference = Utf8String::try_from("new.example.com").expect("valid utf8");
+    let disconnect = ControlPacket::Disconnect(Disconnect {
+        reason_code: DisconnectReasonCode::ServerMoved,
+        properties: DisconnectProperties {
+            server_reference: Some(reference.clone()),
+            ..DisconnectProperties::default()
+        },
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&disconnect),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Disconnected(Some(
+            DisconnectReasonCode::ServerMoved
+        )))
+    ));
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::CloseSocket)
+    ));
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::OpenSocketTo(target)) if target == reference
+    ));
+}
+
 #[test]
 fn inbound_publish_qos0_is_forwarded_to_user_queue() {
     let mut client = Client::<Duration>::default();
@@ -621,7 +850,10 @@ fn inbound_publish_qos0_is_forwarded_to_user_queue() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let publish_topic = Topic::try_new("sensors/temp").expect("valid topic");
     let publish_payload = Payload::new(b"27.5".as_slice());
@@ -678,7 +910,10 @@ fn inbound_publish_multiple_subscription_identifiers_surface_to_user() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let publish_topic = Topic::try_new("t/multi").expect("valid topic");
     let publish_payload = Payload::new(b"hello".as_slice());
@@ -745,7 +980,10 @@ fn inbound_publish_registers_topic_alias_then_resolves_alias_only_publish() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let alias = NonZero::new(1).expect("non-zero alias");
     let topic = Topic::try_new("alias/topic").expect("valid topic");
@@ -839,7 +1077,10 @@ fn inbound_publish_alias_only_unknown_alias_is_protocol_error() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let unknown_alias_publish = ControlPacket::Publish(Publish {
         kind: PublishKind::FireAndForget,
@@ -890,7 +1131,10 @@ fn inbound_publish_empty_topic_without_alias_is_protocol_error() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let invalid_publish = ControlPacket::Publish(Publish {
         kind: PublishKind::FireAndForget,
@@ -945,7 +1189,10 @@ fn inbound_publish_alias_exceeds_client_alias_max_is_protocol_error() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let alias_too_large_publish = ControlPacket::Publish(Publish {
         kind: PublishKind::FireAndForget,
@@ -996,7 +1243,10 @@ fn inbound_qos1_publish_waits_for_app_ack_then_sends_puback() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let packet_id = NonZero::new(7).expect("non-zero packet id");
     let publish_topic = Topic::try_new("sensors/temp").expect("valid topic");
@@ -1066,7 +1316,10 @@ fn inbound_qos1_publish_reject_sends_puback_failure_reason() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let packet_id = NonZero::new(11).expect("non-zero packet id");
     let publish_topic = Topic::try_new("sensors/humidity").expect("valid topic");
@@ -1139,7 +1392,10 @@ fn inbound_qos2_publish_waits_for_app_ack_then_sends_pubrec_and_completes_on_pub
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let packet_id = NonZero::new(13).expect("non-zero packet id");
     let publish = ControlPacket::Publish(Publish {
@@ -1221,7 +1477,10 @@ fn inbound_qos2_publish_reject_sends_pubrec_failure_and_clears_state() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let packet_id = NonZero::new(13).expect("non-zero packet id");
     let publish = ControlPacket::Publish(Publish {
@@ -1306,7 +1565,10 @@ fn inbound_packet_id_reuse_conflict_causes_protocol_error() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let packet_id = NonZero::new(19).expect("non-zero packet id");
     let qos1_publish = ControlPacket::Publish(Publish {
@@ -1385,7 +1647,10 @@ fn duplicate_qos2_publish_after_reject_resends_same_failure_pubrec_without_redel
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let packet_id = NonZero::new(23).expect("non-zero packet id");
     let first_publish = ControlPacket::Publish(Publish {
@@ -1469,7 +1734,10 @@ fn manual_ack_sends_puback_success_for_pending_message_id() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let packet_id = NonZero::new(77).expect("non-zero packet id");
     let publish = ControlPacket::Publish(Publish {
@@ -1559,7 +1827,10 @@ fn reject_reason_maps_to_puback_failure_codes_for_qos1() {
             }),
             Ok(())
         );
-        assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+        assert!(matches!(
+            client.poll_read(),
+            Some(UserWriteOut::Connected(_))
+        ));
 
         let packet_id = NonZero::new((offset + 1) as u16).expect("non-zero packet id");
         let publish = ControlPacket::Publish(Publish {
@@ -1651,7 +1922,10 @@ fn reject_reason_maps_to_pubrec_failure_codes_for_qos2() {
             }),
             Ok(())
         );
-        assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+        assert!(matches!(
+            client.poll_read(),
+            Some(UserWriteOut::Connected(_))
+        ));
 
         let packet_id = NonZero::new((offset + 1) as u16).expect("non-zero packet id");
         let publish = ControlPacket::Publish(Publish {
@@ -1715,7 +1989,10 @@ fn inbound_qos2_unknown_pubrel_sends_pubcomp_packet_identifier_not_found() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let unknown_packet_id = NonZero::new(21).expect("non-zero packet id");
     let pubrel = ControlPacket::PubRel(PubRel {
@@ -1761,7 +2038,10 @@ fn socket_closed_after_disconnect_does_not_duplicate_disconnected_event() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let disconnect = ControlPacket::Disconnect(Disconnect {
         reason_code: DisconnectReasonCode::NormalDisconnection,
@@ -1807,7 +2087,10 @@ fn outbound_qos1_publish_emits_acknowledged_event_on_puback() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -1877,7 +2160,10 @@ fn unexpected_puback_without_matching_qos1_transaction_triggers_protocol_error_c
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let packet_id = NonZero::new(42).expect("non-zero packet id");
     let puback = ControlPacket::PubAck(PubAck {
@@ -1923,7 +2209,10 @@ fn qos2_inflight_receiving_puback_triggers_protocol_error_close() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -1985,7 +2274,10 @@ fn qos1_inflight_receiving_pubrec_triggers_protocol_error_close() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -2050,7 +2342,10 @@ fn connack_receive_maximum_only_limits_broker_facing_publish_flow() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -2196,7 +2491,10 @@ fn effective_limits_recompute_on_connect_socketconnected_connack_and_socketclose
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     assert_eq!(
         client.handle_write(UserWriteIn::PublishMessage(qos1_message.clone())),
@@ -2237,7 +2535,10 @@ fn effective_limits_recompute_on_connect_socketconnected_connack_and_socketclose
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     assert_eq!(
         client.handle_write(UserWriteIn::PublishMessage(qos1_message)),
@@ -2280,7 +2581,10 @@ fn effective_limits_recompute_on_connect_applies_pending_connect_options() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let qos2_message = ClientMessage {
         topic: Topic::try_new("test/qos2").expect("valid topic"),
@@ -2325,7 +2629,10 @@ fn effective_limits_recompute_on_connack_applies_broker_receive_maximum() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let first = ClientMessage {
         topic: Topic::try_new("test/first").expect("valid topic"),
@@ -2386,7 +2693,10 @@ fn app_topic_alias_zero_disables_inbound_alias_even_if_connect_requests_more() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let inbound_publish_with_alias = ControlPacket::Publish(Publish {
         kind: PublishKind::FireAndForget,
@@ -2449,7 +2759,10 @@ fn app_topic_alias_setting_is_applied_when_connect_option_omits_alias_limit() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let alias_set_publish = ControlPacket::Publish(Publish {
         kind: PublishKind::FireAndForget,
@@ -2518,7 +2831,10 @@ fn app_retain_policy_false_blocks_retain_publish_even_if_broker_allows() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let retained_message = ClientMessage {
         retain: true,
@@ -2562,7 +2878,10 @@ fn app_subscription_policy_flags_override_broker_allowances() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     assert_eq!(
         client.handle_write(UserWriteIn::Subscribe(SubscribeOptions {
@@ -2570,6 +2889,7 @@ fn app_subscription_policy_flags_override_broker_allowances() {
             extra_subscriptions: Vec::new(),
             subscription_identifier: Some(NonZero::new(1).expect("non-zero")),
             user_properties: Vec::new(),
+            token: None,
         })),
         Err(Error::ProtocolError)
     );
@@ -2580,6 +2900,7 @@ fn app_subscription_policy_flags_override_broker_allowances() {
             extra_subscriptions: Vec::new(),
             subscription_identifier: None,
             user_properties: Vec::new(),
+            token: None,
         })),
         Err(Error::ProtocolError)
     );
@@ -2605,7 +2926,10 @@ fn outbound_qos2_publish_emits_completed_event_on_pubcomp() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -2695,7 +3019,10 @@ fn unexpected_pubcomp_before_pubrec_transition_triggers_protocol_error_close() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -2759,7 +3086,10 @@ fn receive_maximum_full_returns_immediate_error_for_new_qos2_publish() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -2805,16 +3135,404 @@ fn receive_maximum_full_returns_immediate_error_for_new_qos2_publish() {
     assert_eq!(client.poll_write(), None);
 }
 
-#[test]
-fn duplicate_pubrec_in_qos2_await_pubcomp_resends_pubrel_without_disconnect() {
-    let mut client = Client::<Duration>::default();
-
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    assert!(client.poll_write().is_some());
-
-    let connack = ControlPacket::ConnAck(ConnAck {
-        kind: ConnAckKind::Other {
-            reason_code: ConnackReasonCode::Success,
+fn connected_with_pending_publish_queue(
+    max_pending_publishes: usize,
+    pending_publish_overflow: PendingPublishOverflow,
+) -> Client<Duration> {
+    connected_client(
+        ClientSettings {
+            max_pending_publishes,
+            pending_publish_overflow,
+            ..ClientSettings::default()
+        },
+        ConnAckProperties {
+            receive_maximum: NonZero::new(1),
+            ..ConnAckProperties::default()
+        },
+    )
+}
+
+fn queued_message(payload: &'static [u8], token: u64) -> ClientMessage {
+    ClientMessage {
+        topic: Topic::try_from(Utf8String::try_from("queue/topic").expect("valid utf8"))
+            .expect("valid topic"),
+        qos: Qos::AtLeastOnce,
+        payload: Payload::from(payload),
+        token: Some(UserToken(token)),
+        ..ClientMessage::default()
+    }
+}
+
+fn expected_qos1_publish(packet_id: u16, message: &ClientMessage) -> Bytes {
+    encode_packet(&ControlPacket::Publish(Publish {
+        kind: PublishKind::Repetible {
+            packet_id: NonZero::new(packet_id).expect("non-zero packet id"),
+            qos: GuaranteedQoS::AtLeastOnce,
+            dup: false,
+        },
+        retain: false,
+        payload: message.payload.clone(),
+        topic: message.topic.clone(),
+        properties: PublishProperties::default(),
+    }))
+}
+
+fn read_puback(client: &mut Client<Duration>, packet_id: u16) {
+    let puback = ControlPacket::PubAck(PubAck {
+        packet_id: NonZero::new(packet_id).expect("non-zero packet id"),
+        reason_code: PubAckReasonCode::Success,
+        properties: PubAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&puback),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+}
+
+#[test]
+fn pending_publish_is_sent_once_puback_frees_receive_maximum_slot() {
+    let mut client = connected_with_pending_publish_queue(4, PendingPublishOverflow::RejectNewest);
+    let first = queued_message(b"first", 1);
+    let second = queued_message(b"second", 2);
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(first.clone())),
+        Ok(())
+    );
+    assert_eq!(client.poll_write(), Some(expected_qos1_publish(1, &first)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::PacketIdAssigned { .. })
+    ));
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(second.clone())),
+        Ok(())
+    );
+    assert_eq!(client.poll_write(), None);
+    assert!(client.poll_read().is_none());
+
+    read_puback(&mut client, 1);
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::PublishAcknowledged(packet_id, PubAckReasonCode::Success))
+            if packet_id.get() == 1
+    ));
+    assert_eq!(client.poll_write(), Some(expected_qos1_publish(2, &second)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::PacketIdAssigned {
+            token: UserToken(2),
+            packet_id,
+        }) if packet_id.get() == 2
+    ));
+}
+
+#[test]
+fn pending_publish_queue_keeps_order_behind_waiting_publishes() {
+    let mut client = connected_with_pending_publish_queue(4, PendingPublishOverflow::RejectNewest);
+    let first = queued_message(b"first", 1);
+    let second = queued_message(b"second", 2);
+    let third = queued_message(b"third", 3);
+
+    for message in [&first, &second, &third] {
+        assert_eq!(
+            client.handle_write(UserWriteIn::PublishMessage(message.clone())),
+            Ok(())
+        );
+    }
+    assert_eq!(client.poll_write(), Some(expected_qos1_publish(1, &first)));
+    assert_eq!(client.poll_write(), None);
+
+    read_puback(&mut client, 1);
+    assert_eq!(client.poll_write(), Some(expected_qos1_publish(2, &second)));
+    assert_eq!(client.poll_write(), None);
+
+    read_puback(&mut client, 2);
+    assert_eq!(client.poll_write(), Some(expected_qos1_publish(3, &third)));
+    assert_eq!(client.poll_write(), None);
+}
+
+#[test]
+fn full_pending_publish_queue_rejects_newest_publish() {
+    let mut client = connected_with_pending_publish_queue(1, PendingPublishOverflow::RejectNewest);
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(queued_message(b"first", 1))),
+        Ok(())
+    );
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(queued_message(b"second", 2))),
+        Ok(())
+    );
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(queued_message(b"third", 3))),
+        Err(Error::PublishQueueFull)
+    );
+}
+
+#[test]
+fn full_pending_publish_queue_drops_oldest_publish() {
+    let mut client = connected_with_pending_publish_queue(1, PendingPublishOverflow::DropOldest);
+    let second = queued_message(b"second", 2);
+    let third = queued_message(b"third", 3);
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(queued_message(b"first", 1))),
+        Ok(())
+    );
+    assert!(client.poll_write().is_some());
+    assert!(client.poll_read().is_some());
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(second.clone())),
+        Ok(())
+    );
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(third.clone())),
+        Ok(())
+    );
+    match client.poll_read() {
+        Some(UserWriteOut::PendingPublishDropped(message)) => assert_eq!(message, second),
+        other => panic!("expected dropped pending publish, got {other:?}"),
+    }
+
+    read_puback(&mut client, 1);
+    assert!(client.poll_read().is_some());
+    assert_eq!(client.poll_write(), Some(expected_qos1_publish(2, &third)));
+}
+
+#[test]
+fn pending_publishes_are_sent_after_reconnect() {
+    let mut client = connected_with_pending_publish_queue(4, PendingPublishOverflow::RejectNewest);
+    let first = queued_message(b"first", 1);
+    let second = queued_message(b"second", 2);
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(first)),
+        Ok(())
+    );
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(second.clone())),
+        Ok(())
+    );
+    assert!(client.poll_write().is_some());
+    assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
+    while client.poll_read().is_some() {}
+    while client.poll_event().is_some() {}
+
+    open_connection(&mut client, ConnectionOptions::default());
+    accept_connect_at(&mut client, ConnAckProperties::default(), Duration::ZERO);
+
+    // The unacknowledged first publish died with the old session; the queued
+    // one goes out on the new connection.
+    assert_eq!(client.poll_write(), Some(expected_qos1_publish(2, &second)));
+    assert_eq!(client.poll_write(), None);
+}
+
+fn offline_client(settings: ClientSettings) -> Client<Duration> {
+    let mut client = connected_client(
+        ClientSettings {
+            queue_publishes_while_offline: true,
+            ..settings
+        },
+        ConnAckProperties::default(),
+    );
+    assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
+    while client.poll_read().is_some() {}
+    while client.poll_event().is_some() {}
+    client
+}
+
+#[test]
+fn offline_publishes_are_flushed_in_order_after_connack() {
+    let mut client = offline_client(ClientSettings {
+        max_pending_publishes: 8,
+        ..ClientSettings::default()
+    });
+    let telemetry = ClientMessage {
+        qos: Qos::AtMostOnce,
+        ..queued_message(b"telemetry", 1)
+    };
+    let alarm = queued_message(b"alarm", 2);
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(telemetry.clone())),
+        Ok(())
+    );
+    open_connection(&mut client, ConnectionOptions::default());
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(alarm.clone())),
+        Ok(())
+    );
+    assert_eq!(client.poll_write(), None);
+
+    accept_connect_at(&mut client, ConnAckProperties::default(), Duration::ZERO);
+    assert_eq!(
+        client.poll_write(),
+        Some(encode_packet(&ControlPacket::Publish(Publish {
+            kind: PublishKind::FireAndForget,
+            retain: false,
+            payload: telemetry.payload,
+            topic: telemetry.topic,
+            properties: PublishProperties::default(),
+        })))
+    );
+    assert_eq!(client.poll_write(), Some(expected_qos1_publish(1, &alarm)));
+    assert_eq!(client.poll_write(), None);
+}
+
+#[test]
+fn offline_publish_is_refused_without_opt_in() {
+    let mut client = Client::<Duration>::with_settings(ClientSettings {
+        max_pending_publishes: 8,
+        ..ClientSettings::default()
+    });
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(queued_message(b"lost", 1))),
+        Err(Error::InvalidStateTransition)
+    );
+}
+
+#[test]
+fn offline_publishes_are_bounded_by_payload_bytes() {
+    let mut client = offline_client(ClientSettings {
+        max_pending_publishes: 8,
+        max_pending_publish_bytes: Some(8),
+        ..ClientSettings::default()
+    });
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(queued_message(b"12345", 1))),
+        Ok(())
+    );
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(queued_message(b"6789", 2))),
+        Err(Error::PublishQueueFull)
+    );
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(queued_message(b"678", 3))),
+        Ok(())
+    );
+}
+
+#[test]
+fn stale_offline_publishes_are_dropped_instead_of_flushed() {
+    let mut client = offline_client(ClientSettings {
+        max_pending_publishes: 8,
+        pending_publish_max_age: Some(Duration::from_secs(30)),
+        ..ClientSettings::default()
+    });
+    let stale = queued_message(b"stale", 1);
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(stale.clone())),
+        Ok(())
+    );
+    open_connection(&mut client, ConnectionOptions::default());
+    accept_connect_at(
+        &mut client,
+        ConnAckProperties::default(),
+        Duration::from_secs(60),
+    );
+
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+    match client.poll_read() {
+        Some(UserWriteOut::PendingPublishDropped(message)) => assert_eq!(message, stale),
+        other => panic!("expected dropped stale publish, got {other:?}"),
+    }
+    assert_eq!(client.poll_write(), None);
+}
+
+#[test]
+fn offline_publish_age_runs_from_the_instant_it_was_written() {
+    let mut client = offline_client(ClientSettings {
+        max_pending_publishes: 8,
+        pending_publish_max_age: Some(Duration::from_secs(30)),
+        ..ClientSettings::default()
+    });
+    let fresh = queued_message(b"fresh", 1);
+
+    // The link was idle since t=0; the publish is 20 s old at CONNACK.
+    assert_eq!(
+        client.handle_write_at(
+            UserWriteIn::PublishMessage(fresh.clone()),
+            Duration::from_secs(40)
+        ),
+        Ok(())
+    );
+    open_connection(&mut client, ConnectionOptions::default());
+    accept_connect_at(
+        &mut client,
+        ConnAckProperties::default(),
+        Duration::from_secs(60),
+    );
+
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+    assert_eq!(client.poll_write(), Some(expected_qos1_publish(1, &fresh)));
+}
+
+#[test]
+fn offline_publish_beyond_negotiated_maximum_qos_is_dropped_on_flush() {
+    let mut client = offline_client(ClientSettings {
+        max_pending_publishes: 8,
+        ..ClientSettings::default()
+    });
+    let exactly_once = ClientMessage {
+        qos: Qos::ExactlyOnce,
+        ..queued_message(b"exactly-once", 1)
+    };
+    let at_least_once = queued_message(b"at-least-once", 2);
+
+    for message in [&exactly_once, &at_least_once] {
+        assert_eq!(
+            client.handle_write(UserWriteIn::PublishMessage(message.clone())),
+            Ok(())
+        );
+    }
+    open_connection(&mut client, ConnectionOptions::default());
+    accept_connect_at(
+        &mut client,
+        ConnAckProperties {
+            maximum_qos: Some(MaximumQoS::AtLeastOnce),
+            ..ConnAckProperties::default()
+        },
+        Duration::ZERO,
+    );
+
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+    match client.poll_read() {
+        Some(UserWriteOut::PendingPublishDropped(message)) => assert_eq!(message, exactly_once),
+        other => panic!("expected dropped QoS 2 publish, got {other:?}"),
+    }
+    assert_eq!(
+        client.poll_write(),
+        Some(expected_qos1_publish(1, &at_least_once))
+    );
+}
+
+#[test]
+fn duplicate_pubrec_in_qos2_await_pubcomp_resends_pubrel_without_disconnect() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
         },
         properties: ConnAckProperties::default(),
     });
@@ -2825,7 +3543,10 @@ fn duplicate_pubrec_in_qos2_await_pubcomp_resends_pubrel_without_disconnect() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -2923,7 +3644,10 @@ fn qos2_pubrec_failure_reason_drops_inflight_without_pubrel() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -2978,30 +3702,163 @@ fn qos2_pubrec_failure_reason_drops_inflight_without_pubrel() {
     ));
 }
 
-#[test]
-fn publish_rejects_packet_exceeding_connack_maximum_packet_size() {
-    let mut client = Client::<Duration>::default();
-
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    assert!(client.poll_write().is_some());
-
-    let connack = ControlPacket::ConnAck(ConnAck {
-        kind: ConnAckKind::Other {
-            reason_code: ConnackReasonCode::Success,
+fn connected_with_auto_topic_alias(topic_alias_maximum: u16) -> Client<Duration> {
+    connected_client(
+        ClientSettings {
+            auto_topic_alias: true,
+            ..ClientSettings::default()
         },
-        properties: ConnAckProperties {
-            maximum_packet_size: NonZero::new(16),
+        ConnAckProperties {
+            topic_alias_maximum: Some(topic_alias_maximum),
             ..ConnAckProperties::default()
         },
-    });
+    )
+}
+
+/// Publishes at QoS 0 and returns the topic and Topic Alias on the wire.
+fn publish_and_read_alias(client: &mut Client<Duration>, topic: &str) -> (String, Option<u16>) {
     assert_eq!(
-        client.handle_read(IncomingData {
-            bytes: encode_packet(&connack),
+        client.handle_write(UserWriteIn::PublishMessage(ClientMessage {
+            topic: Topic::try_new(topic.to_owned()).expect("valid topic"),
+            payload: Payload::from(&b"21.5"[..]),
+            ..ClientMessage::default()
+        })),
+        Ok(())
+    );
+    let frame = client.poll_write().expect("PUBLISH frame expected");
+    let ControlPacket::Publish(publish) =
+        ControlPacket::parser::<_, ContextError, ContextError>(&ParserSettings::default())
+            .parse(frame.as_ref())
+            .expect("publish packet should decode")
+    else {
+        panic!("expected PUBLISH");
+    };
+    (
+        String::from(publish.topic.as_ref() as &str),
+        publish.properties.topic_alias.map(NonZero::get),
+    )
+}
+
+#[test]
+fn auto_topic_alias_sends_empty_topic_once_alias_is_established() {
+    let mut client = connected_with_auto_topic_alias(2);
+
+    assert_eq!(
+        publish_and_read_alias(&mut client, "plant/7/temperature"),
+        ("plant/7/temperature".into(), Some(1))
+    );
+    assert_eq!(
+        publish_and_read_alias(&mut client, "plant/7/temperature"),
+        (String::new(), Some(1))
+    );
+    assert_eq!(
+        publish_and_read_alias(&mut client, "plant/7/humidity"),
+        ("plant/7/humidity".into(), Some(2))
+    );
+}
+
+#[test]
+fn auto_topic_alias_reuses_least_recently_used_alias_when_full() {
+    let mut client = connected_with_auto_topic_alias(2);
+
+    publish_and_read_alias(&mut client, "a");
+    publish_and_read_alias(&mut client, "b");
+    publish_and_read_alias(&mut client, "a");
+    // "b" is the least recently published topic, so "c" takes its alias.
+    assert_eq!(
+        publish_and_read_alias(&mut client, "c"),
+        ("c".into(), Some(2))
+    );
+    assert_eq!(
+        publish_and_read_alias(&mut client, "a"),
+        (String::new(), Some(1))
+    );
+    assert_eq!(
+        publish_and_read_alias(&mut client, "b"),
+        ("b".into(), Some(2))
+    );
+}
+
+#[test]
+fn auto_topic_alias_is_disabled_when_server_accepts_none() {
+    let mut client = connected_with_auto_topic_alias(0);
+
+    assert_eq!(publish_and_read_alias(&mut client, "a"), ("a".into(), None));
+    assert_eq!(publish_and_read_alias(&mut client, "a"), ("a".into(), None));
+}
+
+#[test]
+fn auto_topic_alias_mapping_resets_on_reconnect() {
+    let mut client = connected_with_auto_topic_alias(2);
+    publish_and_read_alias(&mut client, "a");
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Disconnected(None))
+    ));
+    open_connection(&mut client, ConnectionOptions::default());
+    accept_connect_at(
+        &mut client,
+        ConnAckProperties {
+            topic_alias_maximum: Some(2),
+            ..ConnAckProperties::default()
+        },
+        Duration::ZERO,
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    assert_eq!(
+        publish_and_read_alias(&mut client, "a"),
+        ("a".into(), Some(1))
+    );
+}
+
+#[test]
+fn auto_topic_alias_rejects_caller_supplied_alias() {
+    let mut client = connected_with_auto_topic_alias(2);
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(ClientMessage {
+            topic: Topic::try_new("a").expect("valid topic"),
+            topic_alias: NonZero::new(1),
+            ..ClientMessage::default()
+        })),
+        Err(Error::ProtocolError)
+    );
+    assert_eq!(client.poll_write(), None);
+}
+
+#[test]
+fn publish_rejects_packet_exceeding_connack_maximum_packet_size() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties {
+            maximum_packet_size: NonZero::new(16),
+            ..ConnAckProperties::default()
+        },
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
             received_at: Duration::ZERO
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -3042,11 +3899,15 @@ fn subscribe_rejects_packet_exceeding_connack_maximum_packet_size() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let subscribe = SubscribeOptions {
         subscription: Subscription {
-            topic_filter: Utf8String::try_from("a/very/long/topic/filter").expect("valid utf8"),
+            topic_filter: TopicFilter::try_from("a/very/long/topic/filter")
+                .expect("valid topic filter"),
             qos: Qos::AtMostOnce,
             no_local: false,
             retain_as_published: false,
@@ -3055,6 +3916,7 @@ fn subscribe_rejects_packet_exceeding_connack_maximum_packet_size() {
         extra_subscriptions: Vec::new(),
         subscription_identifier: None,
         user_properties: Vec::new(),
+        token: None,
     };
 
     assert_eq!(
@@ -3154,7 +4016,10 @@ fn reconnect_ignores_previous_connack_maximum_packet_size_for_connect() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
     assert!(matches!(
@@ -3237,7 +4102,10 @@ fn connack_resume_without_local_state_is_accepted_when_clean_start_false() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
     assert!(client.poll_event().is_none());
 }
 
@@ -3272,7 +4140,10 @@ fn resumed_session_replays_outbound_qos_publish_with_dup_set() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("replay/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -3322,7 +4193,10 @@ fn resumed_session_replays_outbound_qos_publish_with_dup_set() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let replay_publish = ControlPacket::Publish(Publish {
         kind: PublishKind::Repetible {
@@ -3356,6 +4230,220 @@ fn resumed_session_replays_outbound_qos_publish_with_dup_set() {
     ));
 }
 
+/// Connects with a persistent session at t=0, sends a QoS 1 publish with a
+/// 60 s Message Expiry Interval and loses the connection, then resumes the
+/// session with a CONNACK received at `resumed_at`, leaving its events
+/// unread.
+fn resume_with_expiring_inflight_publish(resumed_at: Duration) -> Client<Duration> {
+    let mut client = Client::<Duration>::default();
+    open_connection(
+        &mut client,
+        ConnectionOptions {
+            session_expiry_interval: Some(300),
+            ..ConnectionOptions::default()
+        },
+    );
+    accept_connect_at(&mut client, ConnAckProperties::default(), Duration::ZERO);
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let message = ClientMessage {
+        message_expiry_interval: Some(Duration::from_secs(60)),
+        ..queued_message(b"expiring", 1)
+    };
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(message)),
+        Ok(())
+    );
+    assert!(client.poll_write().is_some());
+    assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
+    while client.poll_read().is_some() {}
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    let resumed_connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::ResumePreviousSession,
+        properties: ConnAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&resumed_connack),
+            received_at: resumed_at
+        }),
+        Ok(())
+    );
+    client
+}
+
+#[test]
+fn resumed_session_replays_publish_with_remaining_message_expiry_interval() {
+    let mut client = resume_with_expiring_inflight_publish(Duration::from_secs(25));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let replay_publish = ControlPacket::Publish(Publish {
+        kind: PublishKind::Repetible {
+            packet_id: NonZero::new(1).expect("non-zero packet id"),
+            qos: GuaranteedQoS::AtLeastOnce,
+            dup: true,
+        },
+        retain: false,
+        payload: Payload::from(&b"expiring"[..]),
+        topic: Topic::try_from(Utf8String::try_from("queue/topic").expect("valid utf8"))
+            .expect("valid topic"),
+        properties: PublishProperties {
+            message_expiry_interval: Some(35),
+            ..PublishProperties::default()
+        },
+    });
+    assert_eq!(client.poll_write(), Some(encode_packet(&replay_publish)));
+}
+
+#[test]
+fn resumed_session_drops_publish_whose_message_expiry_interval_elapsed() {
+    let mut client = resume_with_expiring_inflight_publish(Duration::from_secs(90));
+
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::PublishExpired(packet_id)) if packet_id.get() == 1
+    ));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+    assert_eq!(client.poll_write(), None);
+
+    // The Packet Identifier is free again.
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(queued_message(b"next", 2))),
+        Ok(())
+    );
+    assert!(client.poll_write().is_some());
+}
+
+#[test]
+fn queued_publish_is_sent_with_remaining_message_expiry_interval() {
+    let mut client = offline_client(ClientSettings {
+        max_pending_publishes: 8,
+        ..ClientSettings::default()
+    });
+    let message = ClientMessage {
+        message_expiry_interval: Some(Duration::from_secs(60)),
+        ..queued_message(b"expiring", 1)
+    };
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(message.clone())),
+        Ok(())
+    );
+    open_connection(&mut client, ConnectionOptions::default());
+    accept_connect_at(
+        &mut client,
+        ConnAckProperties::default(),
+        Duration::from_secs(20),
+    );
+
+    assert_eq!(
+        client.poll_write(),
+        Some(encode_packet(&ControlPacket::Publish(Publish {
+            kind: PublishKind::Repetible {
+                packet_id: NonZero::new(1).expect("non-zero packet id"),
+                qos: GuaranteedQoS::AtLeastOnce,
+                dup: false,
+            },
+            retain: false,
+            payload: message.payload,
+            topic: message.topic,
+            properties: PublishProperties {
+                message_expiry_interval: Some(40),
+                ..PublishProperties::default()
+            },
+        })))
+    );
+}
+
+#[test]
+fn queued_publish_past_its_message_expiry_interval_is_dropped() {
+    let mut client = offline_client(ClientSettings {
+        max_pending_publishes: 8,
+        ..ClientSettings::default()
+    });
+    let message = ClientMessage {
+        message_expiry_interval: Some(Duration::from_secs(60)),
+        ..queued_message(b"expiring", 1)
+    };
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(message.clone())),
+        Ok(())
+    );
+    open_connection(&mut client, ConnectionOptions::default());
+    accept_connect_at(
+        &mut client,
+        ConnAckProperties::default(),
+        Duration::from_secs(60),
+    );
+
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+    match client.poll_read() {
+        Some(UserWriteOut::PendingPublishDropped(dropped)) => assert_eq!(dropped, message),
+        other => panic!("expected expired queued publish to be dropped, got {other:?}"),
+    }
+    assert_eq!(client.poll_write(), None);
+}
+
+#[test]
+fn queued_publish_expiry_runs_from_the_instant_it_was_written() {
+    let mut client = offline_client(ClientSettings {
+        max_pending_publishes: 8,
+        ..ClientSettings::default()
+    });
+    let message = ClientMessage {
+        message_expiry_interval: Some(Duration::from_secs(60)),
+        ..queued_message(b"expiring", 1)
+    };
+
+    // Nothing was read since t=0, when the connection was lost.
+    assert_eq!(
+        client.handle_write_at(
+            UserWriteIn::PublishMessage(message.clone()),
+            Duration::from_secs(50)
+        ),
+        Ok(())
+    );
+    open_connection(&mut client, ConnectionOptions::default());
+    accept_connect_at(
+        &mut client,
+        ConnAckProperties::default(),
+        Duration::from_secs(80),
+    );
+
+    assert_eq!(
+        client.poll_write(),
+        Some(encode_packet(&ControlPacket::Publish(Publish {
+            kind: PublishKind::Repetible {
+                packet_id: NonZero::new(1).expect("non-zero packet id"),
+                qos: GuaranteedQoS::AtLeastOnce,
+                dup: false,
+            },
+            retain: false,
+            payload: message.payload,
+            topic: message.topic,
+            properties: PublishProperties {
+                message_expiry_interval: Some(30),
+                ..PublishProperties::default()
+            },
+        })))
+    );
+}
+
 #[test]
 fn resumed_session_replay_failure_does_not_emit_connected_and_closes() {
     let mut client = Client::<Duration>::default();
@@ -3387,7 +4475,10 @@ fn resumed_session_replay_failure_does_not_emit_connected_and_closes() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("replay/failure").expect("valid utf8"))
         .expect("valid topic");
@@ -3480,7 +4571,10 @@ fn resumed_session_replays_unacknowledged_pubrel() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("resume/qos2").expect("valid utf8"))
         .expect("valid topic");
@@ -3553,7 +4647,10 @@ fn resumed_session_replays_unacknowledged_pubrel() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
     assert_eq!(
         client.poll_write(),
         Some(encode_packet(&ControlPacket::PubRel(PubRel {
@@ -3612,7 +4709,10 @@ fn non_resumed_session_drops_inflight_and_emits_publish_dropped_events() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("drop/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -3716,7 +4816,10 @@ fn non_resumed_session_drops_inflight_and_emits_publish_dropped_events() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
     assert!(matches!(
         client.poll_read(),
         Some(UserWriteOut::PublishDroppedDueToSessionNotResumed(id)) if id == qos1_packet_id
@@ -3770,7 +4873,10 @@ fn non_resumed_connack_discards_all_local_session_state() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let topic = Topic::try_from(Utf8String::try_from("state/topic").expect("valid utf8"))
         .expect("valid topic");
@@ -3814,6 +4920,7 @@ fn non_resumed_connack_discards_all_local_session_state() {
         extra_subscriptions: Vec::new(),
         subscription_identifier: None,
         user_properties: Vec::new(),
+        token: None,
     };
     assert_eq!(
         client.handle_write(UserWriteIn::Subscribe(subscribe)),
@@ -3822,8 +4929,10 @@ fn non_resumed_connack_discards_all_local_session_state() {
     let _ = client.poll_write().expect("subscribe frame expected");
 
     let unsubscribe = sansio_mqtt_v5_protocol::UnsubscribeOptions {
-        filter: Utf8String::try_from("state/sub").expect("valid utf8"),
-        ..Default::default()
+        filter: TopicFilter::try_from("state/sub").expect("valid topic filter"),
+        extra_filters: Vec::new(),
+        user_properties: Vec::new(),
+        token: None,
     };
     assert_eq!(
         client.handle_write(UserWriteIn::Unsubscribe(unsubscribe)),
@@ -3853,7 +4962,10 @@ fn non_resumed_connack_discards_all_local_session_state() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let pubrel = ControlPacket::PubRel(PubRel {
         packet_id: inbound_packet_id,
@@ -3903,11 +5015,16 @@ fn non_resumed_connack_discards_all_local_session_state() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
-
-    let unsubscribe = sansio_mqtt_v5_protocol::UnsubscribeOptions {
-        filter: Utf8String::try_from("state/unsub").expect("valid utf8"),
-        ..Default::default()
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let unsubscribe = sansio_mqtt_v5_protocol::UnsubscribeOptions {
+        filter: TopicFilter::try_from("state/unsub").expect("valid topic filter"),
+        extra_filters: Vec::new(),
+        user_properties: Vec::new(),
+        token: None,
     };
     assert_eq!(
         client.handle_write(UserWriteIn::Unsubscribe(unsubscribe)),
@@ -3929,7 +5046,10 @@ fn non_resumed_connack_discards_all_local_session_state() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let stale_unsuback = ControlPacket::UnsubAck(sansio_mqtt_v5_types::UnsubAck {
         packet_id: NonZero::new(1).expect("non-zero"),
@@ -3986,7 +5106,10 @@ fn stale_read_buffer_is_cleared_on_socket_closed() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 }
 
 #[test]
@@ -4029,7 +5152,10 @@ fn stale_read_buffer_is_cleared_on_socket_error() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 }
 
 #[test]
@@ -4047,7 +5173,10 @@ fn stale_read_buffer_is_cleared_on_user_disconnect() {
         Ok(())
     );
 
-    assert_eq!(client.handle_write(UserWriteIn::Disconnect), Ok(()));
+    assert_eq!(
+        client.handle_write(UserWriteIn::Disconnect(DisconnectOptions::default())),
+        Ok(())
+    );
     assert!(matches!(
         client.poll_read(),
         Some(UserWriteOut::Disconnected(_))
@@ -4073,7 +5202,10 @@ fn stale_read_buffer_is_cleared_on_user_disconnect() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 }
 
 #[test]
@@ -4117,7 +5249,10 @@ fn stale_read_buffer_is_cleared_on_close() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 }
 
 #[test]
@@ -4149,7 +5284,10 @@ fn timeout_in_connected_state_enqueues_pingreq() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     assert_eq!(client.handle_timeout(Duration::from_secs(42)), Ok(()));
     assert_eq!(client.poll_write(), Some(Bytes::from_static(&[0xC0, 0x00])));
@@ -4178,7 +5316,10 @@ fn close_enqueues_disconnect_and_close_socket() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     assert_eq!(client.close(), Ok(()));
     assert_eq!(client.poll_write(), Some(Bytes::from_static(&[0xE0, 0x00])));
@@ -4219,7 +5360,10 @@ fn close_succeeds_even_when_disconnect_packet_exceeds_maximum_packet_size() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     assert_eq!(client.close(), Ok(()));
     assert_eq!(client.poll_write(), None);
@@ -4260,9 +5404,15 @@ fn user_disconnect_succeeds_even_when_disconnect_packet_exceeds_maximum_packet_s
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
-    assert_eq!(client.handle_write(UserWriteIn::Disconnect), Ok(()));
+    assert_eq!(
+        client.handle_write(UserWriteIn::Disconnect(DisconnectOptions::default())),
+        Ok(())
+    );
     assert_eq!(client.poll_write(), None);
     assert!(matches!(
         client.poll_event(),
@@ -4315,7 +5465,7 @@ fn timeout_is_cleared_on_close() {
     );
     assert!(matches!(
         close_client.poll_read(),
-        Some(UserWriteOut::Connected)
+        Some(UserWriteOut::Connected(_))
     ));
 
     assert_eq!(close_client.handle_timeout(Duration::from_secs(42)), Ok(()));
@@ -4354,7 +5504,7 @@ fn timeout_is_cleared_on_close() {
     );
     assert!(matches!(
         socket_closed_client.poll_read(),
-        Some(UserWriteOut::Connected)
+        Some(UserWriteOut::Connected(_))
     ));
 
     assert_eq!(
@@ -4450,6 +5600,7 @@ fn connecting_auth_then_connack_success_transitions_connected() {
         }),
         Ok(())
     );
+    assert!(matches!(client.poll_read(), Some(UserWriteOut::Auth(_))));
 
     let connack = ControlPacket::ConnAck(ConnAck {
         kind: ConnAckKind::Other {
@@ -4464,7 +5615,10 @@ fn connecting_auth_then_connack_success_transitions_connected() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 }
 
 #[test]
@@ -4556,7 +5710,10 @@ fn publish_qos_above_server_maximum_qos_is_rejected() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let message = ClientMessage {
         topic: Topic::try_from(Utf8String::try_from("qos/guard").expect("valid utf8"))
@@ -4596,7 +5753,10 @@ fn publish_retain_when_server_retain_not_available_is_rejected() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let message = ClientMessage {
         topic: Topic::try_from(Utf8String::try_from("retain/guard").expect("valid utf8"))
@@ -4635,7 +5795,10 @@ fn subscribe_shared_with_no_local_is_rejected() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let subscribe = SubscribeOptions {
         subscription: Subscription {
@@ -4645,6 +5808,8 @@ fn subscribe_shared_with_no_local_is_rejected() {
         extra_subscriptions: Vec::new(),
         subscription_identifier: None,
         user_properties: Vec::new(),
+
+        token: None,
     };
 
     assert_eq!(
@@ -4677,13 +5842,17 @@ fn subscribe_wildcard_when_server_disallows_is_rejected() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let subscribe = SubscribeOptions {
         subscription: make_subscription("topic/#"),
         extra_subscriptions: Vec::new(),
         subscription_identifier: None,
         user_properties: Vec::new(),
+        token: None,
     };
 
     assert_eq!(
@@ -4716,13 +5885,17 @@ fn subscribe_shared_when_server_disallows_is_rejected() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let subscribe = SubscribeOptions {
         subscription: make_subscription("$share/g/topic"),
         extra_subscriptions: Vec::new(),
         subscription_identifier: None,
         user_properties: Vec::new(),
+        token: None,
     };
 
     assert_eq!(
@@ -4755,13 +5928,17 @@ fn subscribe_identifier_when_server_disallows_is_rejected() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let subscribe = SubscribeOptions {
         subscription: make_subscription("topic/a"),
         extra_subscriptions: Vec::new(),
         subscription_identifier: NonZero::new(1),
         user_properties: Vec::new(),
+        token: None,
     };
 
     assert_eq!(
@@ -4805,6 +5982,7 @@ fn connecting_auth_continue_then_connack_success_connects() {
         }),
         Ok(())
     );
+    assert!(matches!(client.poll_read(), Some(UserWriteOut::Auth(_))));
 
     let connack = ControlPacket::ConnAck(ConnAck {
         kind: ConnAckKind::Other {
@@ -4819,7 +5997,10 @@ fn connecting_auth_continue_then_connack_success_connects() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 }
 
 /// [MQTT-4.12.0-2] AUTH in the Connected state must be forwarded to the
@@ -4827,10 +6008,7 @@ fn connecting_auth_continue_then_connack_success_connects() {
 /// respond.
 #[test]
 fn auth_in_connected_state_is_forwarded_not_protocol_error() {
-    let mut client = Client::<Duration>::default();
-
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    assert!(client.poll_write().is_some());
+    let mut client = connecting_with_scram();
 
     let connack = ControlPacket::ConnAck(ConnAck {
         kind: ConnAckKind::Other {
@@ -4845,12 +6023,15 @@ fn auth_in_connected_state_is_forwarded_not_protocol_error() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
-    let auth = ControlPacket::Auth(Auth {
-        reason_code: AuthReasonCode::ContinueAuthentication,
-        properties: AuthProperties::default(),
-    });
+    let auth = ControlPacket::Auth(auth_packet(
+        AuthReasonCode::ContinueAuthentication,
+        scram_authentication(Some(b"server-first")),
+    ));
     // [MQTT-4.12.0-2] Must succeed (not return ProtocolError).
     assert_eq!(
         client.handle_read(IncomingData {
@@ -4868,12 +6049,76 @@ fn auth_in_connected_state_is_forwarded_not_protocol_error() {
     assert!(client.poll_event().is_none(), "no CloseSocket expected");
 }
 
-#[test]
-fn keepalive_disabled_without_interval_no_pingreq() {
+fn scram_authentication(data: Option<&'static [u8]>) -> sansio_mqtt_v5_types::AuthenticationKind {
+    let method = Utf8String::try_from("SCRAM").expect("valid utf8");
+    match data {
+        Some(data) => sansio_mqtt_v5_types::AuthenticationKind::WithData {
+            method,
+            data: sansio_mqtt_v5_types::BinaryData::try_from(Bytes::from_static(data))
+                .expect("valid binary data"),
+        },
+        None => sansio_mqtt_v5_types::AuthenticationKind::WithoutData { method },
+    }
+}
+
+fn auth_packet(
+    reason_code: AuthReasonCode,
+    authentication: sansio_mqtt_v5_types::AuthenticationKind,
+) -> Auth {
+    Auth {
+        reason_code,
+        properties: AuthProperties {
+            authentication: Some(authentication),
+            ..AuthProperties::default()
+        },
+    }
+}
+
+fn connecting_with_scram() -> Client<Duration> {
     let mut client = Client::<Duration>::default();
+    open_connection(
+        &mut client,
+        ConnectionOptions {
+            authentication: Some(scram_authentication(Some(b"client-first"))),
+            ..ConnectionOptions::default()
+        },
+    );
+    client
+}
 
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    assert!(client.poll_write().is_some());
+#[test]
+fn connecting_auth_challenge_is_answered_by_the_application() {
+    let mut client = connecting_with_scram();
+
+    let challenge = auth_packet(
+        AuthReasonCode::ContinueAuthentication,
+        scram_authentication(Some(b"server-first")),
+    );
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&ControlPacket::Auth(challenge.clone())),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    match client.poll_read() {
+        Some(UserWriteOut::Auth(auth)) => assert_eq!(auth, challenge),
+        other => panic!("expected AUTH challenge, got {other:?}"),
+    }
+
+    let response = auth_packet(
+        AuthReasonCode::ContinueAuthentication,
+        scram_authentication(Some(b"client-final")),
+    );
+    assert_eq!(
+        client.handle_write(UserWriteIn::Auth(response.clone())),
+        Ok(())
+    );
+    let frame = client.poll_write().expect("AUTH frame expected");
+    let packet = ControlPacket::parser::<_, ContextError, ContextError>(&ParserSettings::default())
+        .parse(frame.as_ref())
+        .expect("auth packet should decode");
+    assert_eq!(packet, ControlPacket::Auth(response));
 
     let connack = ControlPacket::ConnAck(ConnAck {
         kind: ConnAckKind::Other {
@@ -4888,68 +6133,74 @@ fn keepalive_disabled_without_interval_no_pingreq() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
-
-    assert_eq!(client.handle_timeout(Duration::from_secs(1)), Ok(()));
-    assert_eq!(client.poll_write(), None);
-    assert_eq!(client.poll_timeout(), None);
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 }
 
 #[test]
-fn connack_server_keep_alive_zero_disables_keepalive_without_panic() {
-    let mut client = Client::<Duration>::default();
-
-    let options = ConnectionOptions {
-        keep_alive: NonZero::new(10),
-        ..ConnectionOptions::default()
-    };
-    assert_eq!(client.handle_write(UserWriteIn::Connect(options)), Ok(()));
-    assert!(matches!(
-        client.poll_event(),
-        Some(sansio_mqtt_v5_protocol::DriverEventOut::OpenSocket)
-    ));
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    let _ = client.poll_write().expect("connect frame expected");
+fn connecting_auth_with_different_method_is_protocol_error() {
+    let mut client = connecting_with_scram();
 
-    let connack = ControlPacket::ConnAck(ConnAck {
-        kind: ConnAckKind::Other {
-            reason_code: ConnackReasonCode::Success,
+    let challenge = auth_packet(
+        AuthReasonCode::ContinueAuthentication,
+        sansio_mqtt_v5_types::AuthenticationKind::WithoutData {
+            method: Utf8String::try_from("KERBEROS").expect("valid utf8"),
         },
-        properties: ConnAckProperties {
-            server_keep_alive: Some(0),
-            ..ConnAckProperties::default()
-        },
-    });
+    );
     assert_eq!(
         client.handle_read(IncomingData {
-            bytes: encode_packet(&connack),
+            bytes: encode_packet(&ControlPacket::Auth(challenge)),
             received_at: Duration::ZERO
         }),
-        Ok(())
+        Err(Error::ProtocolError)
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::CloseSocket)
+    ));
+}
 
-    assert_eq!(client.handle_timeout(Duration::from_secs(1)), Ok(()));
+#[test]
+fn outbound_auth_is_validated_before_sending() {
+    let mut client = connecting_with_scram();
+
+    // Wrong method.
+    assert_eq!(
+        client.handle_write(UserWriteIn::Auth(auth_packet(
+            AuthReasonCode::ContinueAuthentication,
+            sansio_mqtt_v5_types::AuthenticationKind::WithoutData {
+                method: Utf8String::try_from("KERBEROS").expect("valid utf8"),
+            },
+        ))),
+        Err(Error::ProtocolError)
+    );
+    // Re-authentication is only possible once connected.
+    assert_eq!(
+        client.handle_write(UserWriteIn::Auth(auth_packet(
+            AuthReasonCode::ReAuthenticate,
+            scram_authentication(None),
+        ))),
+        Err(Error::ProtocolError)
+    );
+    // Success is a server-only reason code.
+    assert_eq!(
+        client.handle_write(UserWriteIn::Auth(auth_packet(
+            AuthReasonCode::Success,
+            scram_authentication(None),
+        ))),
+        Err(Error::ProtocolError)
+    );
     assert_eq!(client.poll_write(), None);
-    assert_eq!(client.poll_timeout(), None);
+    assert!(client.poll_event().is_none());
 }
 
 #[test]
-fn keepalive_timeout_without_pingresp_closes_connection() {
+fn outbound_auth_without_configured_authentication_is_rejected() {
     let mut client = Client::<Duration>::default();
-
-    let options = ConnectionOptions {
-        keep_alive: NonZero::new(10),
-        ..ConnectionOptions::default()
-    };
-    assert_eq!(client.handle_write(UserWriteIn::Connect(options)), Ok(()));
-    assert!(matches!(
-        client.poll_event(),
-        Some(sansio_mqtt_v5_protocol::DriverEventOut::OpenSocket)
-    ));
     assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    let _ = client.poll_write().expect("connect frame expected");
-
+    assert!(client.poll_write().is_some());
     let connack = ControlPacket::ConnAck(ConnAck {
         kind: ConnAckKind::Other {
             reason_code: ConnackReasonCode::Success,
@@ -4963,78 +6214,375 @@ fn keepalive_timeout_without_pingresp_closes_connection() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
-
-    assert_eq!(client.handle_timeout(Duration::from_secs(1)), Ok(()));
-    assert_eq!(client.poll_write(), Some(Bytes::from_static(&[0xC0, 0x00])));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     assert_eq!(
-        client.handle_timeout(Duration::from_secs(2)),
+        client.handle_write(UserWriteIn::Auth(auth_packet(
+            AuthReasonCode::ReAuthenticate,
+            scram_authentication(None),
+        ))),
         Err(Error::ProtocolError)
     );
-    assert_eq!(
-        client.poll_write(),
-        Some(Bytes::from_static(&[0xE0, 0x02, 0x8D, 0x00]))
-    );
-    assert!(matches!(
-        client.poll_event(),
-        Some(sansio_mqtt_v5_protocol::DriverEventOut::CloseSocket)
-    ));
+    assert_eq!(client.poll_write(), None);
 }
 
-/// [MQTT-3.1.4-5] If a Client does not receive a CONNACK packet from the Server
-/// within a reasonable amount of time, the Client SHOULD close the Network
-/// Connection. When handle_timeout fires in the Start state (before CONNECT is
-/// sent), it should treat the timeout as a connection-establishment timeout and
-/// close the socket.
 #[test]
-fn timeout_in_start_state_closes_connection_with_connect_timeout_error() {
-    let mut client = Client::<Duration>::default();
+fn connected_client_can_start_reauthentication() {
+    let mut client = connecting_with_scram();
+    accept_connect_at(&mut client, ConnAckProperties::default(), Duration::ZERO);
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
-    // Start state: no CONNECT sent yet, no SocketConnected.
+    let reauth = auth_packet(
+        AuthReasonCode::ReAuthenticate,
+        scram_authentication(Some(b"client-first")),
+    );
     assert_eq!(
-        client.handle_timeout(Duration::from_secs(100)),
-        Err(Error::ConnectTimeout),
-        "timeout in Start state must return ConnectTimeout"
+        client.handle_write(UserWriteIn::Auth(reauth.clone())),
+        Ok(())
     );
-    assert!(
-        matches!(client.poll_event(), Some(DriverEventOut::CloseSocket)),
-        "CloseSocket must be emitted on Start timeout"
+    let frame = client.poll_write().expect("AUTH frame expected");
+    let packet = ControlPacket::parser::<_, ContextError, ContextError>(&ParserSettings::default())
+        .parse(frame.as_ref())
+        .expect("auth packet should decode");
+    assert_eq!(packet, ControlPacket::Auth(reauth));
+}
+
+fn connected_with_reauthentication(keep_alive: Option<u16>) -> Client<Duration> {
+    let mut client = Client::<Duration>::default();
+    open_connection(
+        &mut client,
+        ConnectionOptions {
+            keep_alive: keep_alive.and_then(NonZero::new),
+            authentication: Some(scram_authentication(Some(b"token-1"))),
+            reauthenticate_after: Some(Duration::from_secs(30)),
+            ..ConnectionOptions::default()
+        },
     );
-    // No read events should be emitted (not connected yet).
-    assert!(client.poll_read().is_none());
+    accept_connect_at(&mut client, ConnAckProperties::default(), Duration::ZERO);
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+    client
 }
 
-/// [MQTT-3.1.4-5] If a Client does not receive a CONNACK packet from the Server
-/// within a reasonable amount of time, the Client SHOULD close the Network
-/// Connection. When handle_timeout fires in the Connecting state (after
-/// CONNECT, before CONNACK), it should treat the timeout as a
-/// connection-establishment timeout and close the socket.
 #[test]
-fn timeout_in_connecting_state_closes_connection_with_connect_timeout_error() {
-    let mut client = Client::<Duration>::default();
+fn reauthentication_deadline_asks_application_for_fresh_credentials() {
+    let mut client = connected_with_reauthentication(None);
+    assert_eq!(client.poll_timeout(), Some(Duration::from_secs(30)));
 
-    // Transition to Connecting state: write Connect + SocketConnected.
-    assert_eq!(
-        client.handle_write(UserWriteIn::Connect(ConnectionOptions::default())),
-        Ok(())
-    );
+    assert_eq!(client.handle_timeout(Duration::from_secs(30)), Ok(()));
     assert!(matches!(
-        client.poll_event(),
-        Some(DriverEventOut::OpenSocket)
+        client.poll_read(),
+        Some(UserWriteOut::ReauthenticationRequired)
     ));
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    let _ = client.poll_write().expect("CONNECT frame expected");
+    assert_eq!(client.poll_write(), None);
+    assert_eq!(client.poll_timeout(), None);
 
-    // Now we are in Connecting state (post-CONNECT, pre-CONNACK).
     assert_eq!(
-        client.handle_timeout(Duration::from_secs(100)),
-        Err(Error::ConnectTimeout),
-        "timeout in Connecting state must return ConnectTimeout"
-    );
-    assert!(
-        matches!(client.poll_event(), Some(DriverEventOut::CloseSocket)),
-        "CloseSocket must be emitted on Connecting timeout"
+        client.handle_write(UserWriteIn::Reauthenticate(scram_authentication(Some(
+            b"token-2"
+        )))),
+        Ok(())
+    );
+    let frame = client.poll_write().expect("AUTH frame expected");
+    let packet = ControlPacket::parser::<_, ContextError, ContextError>(&ParserSettings::default())
+        .parse(frame.as_ref())
+        .expect("auth packet should decode");
+    assert_eq!(
+        packet,
+        ControlPacket::Auth(auth_packet(
+            AuthReasonCode::ReAuthenticate,
+            scram_authentication(Some(b"token-2")),
+        ))
+    );
+
+    // The server accepting the new token restarts the credential lifetime.
+    let success = ControlPacket::Auth(auth_packet(
+        AuthReasonCode::Success,
+        scram_authentication(None),
+    ));
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&success),
+            received_at: Duration::from_secs(31)
+        }),
+        Ok(())
+    );
+    assert!(matches!(client.poll_read(), Some(UserWriteOut::Auth(_))));
+    assert_eq!(client.poll_timeout(), Some(Duration::from_secs(61)));
+}
+
+#[test]
+fn reauthentication_deadline_does_not_trigger_keep_alive_early() {
+    let mut client = connected_with_reauthentication(Some(60));
+    assert_eq!(client.poll_timeout(), Some(Duration::from_secs(30)));
+
+    assert_eq!(client.handle_timeout(Duration::from_secs(30)), Ok(()));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::ReauthenticationRequired)
+    ));
+    // No PINGREQ: the keep-alive deadline is still ahead.
+    assert_eq!(client.poll_write(), None);
+    assert_eq!(client.poll_timeout(), Some(Duration::from_secs(60)));
+}
+
+#[test]
+fn reauthenticate_with_different_method_is_rejected() {
+    let mut client = connected_with_reauthentication(None);
+    let other_method = sansio_mqtt_v5_types::AuthenticationKind::WithoutData {
+        method: Utf8String::try_from("OAUTH").expect("valid utf8"),
+    };
+
+    assert!(
+        client
+            .handle_write(UserWriteIn::Reauthenticate(other_method))
+            .is_err()
+    );
+    assert_eq!(client.poll_write(), None);
+}
+
+#[test]
+fn connected_auth_with_different_method_is_protocol_error() {
+    let mut client = connected_with_reauthentication(None);
+
+    let challenge = auth_packet(
+        AuthReasonCode::ContinueAuthentication,
+        sansio_mqtt_v5_types::AuthenticationKind::WithoutData {
+            method: Utf8String::try_from("KERBEROS").expect("valid utf8"),
+        },
+    );
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&ControlPacket::Auth(challenge)),
+            received_at: Duration::ZERO
+        }),
+        Err(Error::ProtocolError)
+    );
+    assert!(client.poll_read().is_none());
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::CloseSocket)
+    ));
+}
+
+/// [MQTT-4.12.0-2] Without an Authentication Method in CONNECT, the server
+/// must not send AUTH at all.
+#[test]
+fn connected_auth_without_configured_authentication_is_protocol_error() {
+    let mut client = make_connected_client_with_keep_alive(None);
+
+    let auth = ControlPacket::Auth(Auth {
+        reason_code: AuthReasonCode::ReAuthenticate,
+        properties: AuthProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&auth),
+            received_at: Duration::ZERO
+        }),
+        Err(Error::ProtocolError)
+    );
+    assert!(client.poll_read().is_none());
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::CloseSocket)
+    ));
+}
+
+#[test]
+fn reauthenticate_outside_connected_state_is_invalid() {
+    let mut client = connecting_with_scram();
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::Reauthenticate(scram_authentication(None))),
+        Err(Error::InvalidStateTransition)
+    );
+}
+
+#[test]
+fn keepalive_disabled_without_interval_no_pingreq() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    assert_eq!(client.handle_timeout(Duration::from_secs(1)), Ok(()));
+    assert_eq!(client.poll_write(), None);
+    assert_eq!(client.poll_timeout(), None);
+}
+
+#[test]
+fn connack_server_keep_alive_zero_disables_keepalive_without_panic() {
+    let mut client = Client::<Duration>::default();
+
+    let options = ConnectionOptions {
+        keep_alive: NonZero::new(10),
+        ..ConnectionOptions::default()
+    };
+    assert_eq!(client.handle_write(UserWriteIn::Connect(options)), Ok(()));
+    assert!(matches!(
+        client.poll_event(),
+        Some(sansio_mqtt_v5_protocol::DriverEventOut::OpenSocket)
+    ));
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    let _ = client.poll_write().expect("connect frame expected");
+
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties {
+            server_keep_alive: Some(0),
+            ..ConnAckProperties::default()
+        },
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    assert_eq!(client.handle_timeout(Duration::from_secs(1)), Ok(()));
+    assert_eq!(client.poll_write(), None);
+    assert_eq!(client.poll_timeout(), None);
+}
+
+#[test]
+fn keepalive_timeout_without_pingresp_closes_connection() {
+    let mut client = Client::<Duration>::default();
+
+    let options = ConnectionOptions {
+        keep_alive: NonZero::new(10),
+        ..ConnectionOptions::default()
+    };
+    assert_eq!(client.handle_write(UserWriteIn::Connect(options)), Ok(()));
+    assert!(matches!(
+        client.poll_event(),
+        Some(sansio_mqtt_v5_protocol::DriverEventOut::OpenSocket)
+    ));
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    let _ = client.poll_write().expect("connect frame expected");
+
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    assert_eq!(client.handle_timeout(Duration::from_secs(1)), Ok(()));
+    assert_eq!(client.poll_write(), Some(Bytes::from_static(&[0xC0, 0x00])));
+
+    assert_eq!(
+        client.handle_timeout(Duration::from_secs(2)),
+        Err(Error::ProtocolError)
+    );
+    assert_eq!(
+        client.poll_write(),
+        Some(Bytes::from_static(&[0xE0, 0x02, 0x8D, 0x00]))
+    );
+    assert!(matches!(
+        client.poll_event(),
+        Some(sansio_mqtt_v5_protocol::DriverEventOut::CloseSocket)
+    ));
+}
+
+/// [MQTT-3.1.4-5] If a Client does not receive a CONNACK packet from the Server
+/// within a reasonable amount of time, the Client SHOULD close the Network
+/// Connection. When handle_timeout fires in the Start state (before CONNECT is
+/// sent), it should treat the timeout as a connection-establishment timeout and
+/// close the socket.
+#[test]
+fn timeout_in_start_state_closes_connection_with_connect_timeout_error() {
+    let mut client = Client::<Duration>::default();
+
+    // Start state: no CONNECT sent yet, no SocketConnected.
+    assert_eq!(
+        client.handle_timeout(Duration::from_secs(100)),
+        Err(Error::ConnectTimeout),
+        "timeout in Start state must return ConnectTimeout"
+    );
+    assert!(
+        matches!(client.poll_event(), Some(DriverEventOut::CloseSocket)),
+        "CloseSocket must be emitted on Start timeout"
+    );
+    // No read events should be emitted (not connected yet).
+    assert!(client.poll_read().is_none());
+}
+
+/// [MQTT-3.1.4-5] If a Client does not receive a CONNACK packet from the Server
+/// within a reasonable amount of time, the Client SHOULD close the Network
+/// Connection. When handle_timeout fires in the Connecting state (after
+/// CONNECT, before CONNACK), it should treat the timeout as a
+/// connection-establishment timeout and close the socket.
+#[test]
+fn timeout_in_connecting_state_closes_connection_with_connect_timeout_error() {
+    let mut client = Client::<Duration>::default();
+
+    // Transition to Connecting state: write Connect + SocketConnected.
+    assert_eq!(
+        client.handle_write(UserWriteIn::Connect(ConnectionOptions::default())),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::OpenSocket)
+    ));
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    let _ = client.poll_write().expect("CONNECT frame expected");
+
+    // Now we are in Connecting state (post-CONNECT, pre-CONNACK).
+    assert_eq!(
+        client.handle_timeout(Duration::from_secs(100)),
+        Err(Error::ConnectTimeout),
+        "timeout in Connecting state must return ConnectTimeout"
+    );
+    assert!(
+        matches!(client.poll_event(), Some(DriverEventOut::CloseSocket)),
+        "CloseSocket must be emitted on Connecting timeout"
     );
     // No read events should be emitted.
     assert!(client.poll_read().is_none());
@@ -5091,7 +6639,10 @@ fn connack_session_expiry_zero_overrides_client_session_should_persist() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     // Publish a QoS1 message to create inflight state.
     let qos1_msg = ClientMessage {
@@ -5133,7 +6684,7 @@ fn connack_session_expiry_zero_overrides_client_session_should_persist() {
     );
     let first = client.poll_read();
     assert!(
-        matches!(first, Some(UserWriteOut::Connected)),
+        matches!(first, Some(UserWriteOut::Connected(_))),
         "expected Connected, got {first:?}"
     );
     assert!(
@@ -5183,9 +6734,12 @@ fn connack_session_expiry_nonzero_sets_session_should_persist() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
-
-    // Publish a QoS1 message to create inflight state.
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    // Publish a QoS1 message to create inflight state.
     let qos1_msg = ClientMessage {
         topic: Topic::try_from(Utf8String::try_from("test/topic").expect("valid utf8"))
             .expect("valid topic"),
@@ -5226,7 +6780,7 @@ fn connack_session_expiry_nonzero_sets_session_should_persist() {
     let first = client.poll_read();
     let second = client.poll_read();
     assert!(
-        matches!(first, Some(UserWriteOut::Connected)),
+        matches!(first, Some(UserWriteOut::Connected(_))),
         "expected Connected, got {first:?}"
     );
     assert!(
@@ -5271,7 +6825,10 @@ fn clean_start_true_clears_local_session_before_connect() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let qos1_message = ClientMessage {
         topic: Topic::try_from(Utf8String::try_from("clean/start").expect("valid utf8"))
@@ -5286,7 +6843,10 @@ fn clean_start_true_clears_local_session_before_connect() {
     );
     assert!(client.poll_write().is_some());
 
-    assert_eq!(client.handle_write(UserWriteIn::Disconnect), Ok(()));
+    assert_eq!(
+        client.handle_write(UserWriteIn::Disconnect(DisconnectOptions::default())),
+        Ok(())
+    );
     assert_eq!(client.poll_write(), Some(Bytes::from_static(&[0xE0, 0x00])));
     assert!(matches!(
         client.poll_event(),
@@ -5360,7 +6920,10 @@ fn session_with_expiry_keeps_inflight_across_graceful_disconnect() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let qos1_message = ClientMessage {
         topic: Topic::try_from(Utf8String::try_from("session/persist").expect("valid utf8"))
@@ -5375,7 +6938,10 @@ fn session_with_expiry_keeps_inflight_across_graceful_disconnect() {
     );
     let publish = client.poll_write().expect("publish expected");
 
-    assert_eq!(client.handle_write(UserWriteIn::Disconnect), Ok(()));
+    assert_eq!(
+        client.handle_write(UserWriteIn::Disconnect(DisconnectOptions::default())),
+        Ok(())
+    );
     assert_eq!(client.poll_write(), Some(Bytes::from_static(&[0xE0, 0x00])));
     assert!(matches!(
         client.poll_event(),
@@ -5400,7 +6966,10 @@ fn session_with_expiry_keeps_inflight_across_graceful_disconnect() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
     let replay_publish = client.poll_write().expect("replayed publish expected");
     assert_eq!(replay_publish.len(), publish.len());
     assert_eq!(replay_publish[0], publish[0] | 0b0000_1000);
@@ -5436,7 +7005,10 @@ fn zero_session_expiry_clears_inflight_on_disconnect() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let qos1_message = ClientMessage {
         topic: Topic::try_from(Utf8String::try_from("session/clear").expect("valid utf8"))
@@ -5451,7 +7023,10 @@ fn zero_session_expiry_clears_inflight_on_disconnect() {
     );
     assert!(client.poll_write().is_some());
 
-    assert_eq!(client.handle_write(UserWriteIn::Disconnect), Ok(()));
+    assert_eq!(
+        client.handle_write(UserWriteIn::Disconnect(DisconnectOptions::default())),
+        Ok(())
+    );
     assert_eq!(client.poll_write(), Some(Bytes::from_static(&[0xE0, 0x00])));
     assert!(matches!(
         client.poll_event(),
@@ -5476,7 +7051,10 @@ fn zero_session_expiry_clears_inflight_on_disconnect() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
     assert_eq!(client.poll_write(), None);
     assert!(client.poll_event().is_none());
 }
@@ -5510,7 +7088,10 @@ fn zero_session_expiry_clears_inflight_on_socket_closed() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let qos1_message = ClientMessage {
         topic: Topic::try_from(Utf8String::try_from("session/close-clear").expect("valid utf8"))
@@ -5545,7 +7126,10 @@ fn zero_session_expiry_clears_inflight_on_socket_closed() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let replay = client.poll_write();
     assert_eq!(replay, None);
@@ -5582,7 +7166,10 @@ fn keepalive_timeout_with_session_expiry_preserves_inflight_for_resume() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let qos1_message = ClientMessage {
         topic: Topic::try_from(
@@ -5632,7 +7219,10 @@ fn keepalive_timeout_with_session_expiry_preserves_inflight_for_resume() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let replay_publish = client.poll_write().expect("replayed publish expected");
     assert_eq!(replay_publish.len(), first_publish.len());
@@ -5659,13 +7249,17 @@ fn subscribe_tracks_packet_id_until_suback() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let subscribe = SubscribeOptions {
         subscription: make_subscription("topic/a"),
         extra_subscriptions: Vec::new(),
         subscription_identifier: None,
         user_properties: Vec::new(),
+        token: None,
     };
     assert_eq!(
         client.handle_write(UserWriteIn::Subscribe(subscribe)),
@@ -5684,33 +7278,761 @@ fn subscribe_tracks_packet_id_until_suback() {
         client.handle_write(UserWriteIn::PublishMessage(qos1_message)),
         Ok(())
     );
-    let publish_frame = client.poll_write().expect("publish frame expected");
-    assert_ne!(publish_frame, subscribe_frame);
+    let publish_frame = client.poll_write().expect("publish frame expected");
+    assert_ne!(publish_frame, subscribe_frame);
+
+    let suback = ControlPacket::SubAck(sansio_mqtt_v5_types::SubAck {
+        packet_id: NonZero::new(1).expect("non-zero"),
+        properties: sansio_mqtt_v5_types::SubAckProperties::default(),
+        reason_codes: vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&suback),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+}
+
+#[test]
+fn unsubscribe_tracks_packet_id_until_unsuback() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let unsubscribe = sansio_mqtt_v5_protocol::UnsubscribeOptions {
+        filter: TopicFilter::try_from("topic/a").expect("valid topic filter"),
+        extra_filters: Vec::new(),
+        user_properties: Vec::new(),
+        token: None,
+    };
+    assert_eq!(
+        client.handle_write(UserWriteIn::Unsubscribe(unsubscribe)),
+        Ok(())
+    );
+    let unsub_frame = client.poll_write().expect("unsubscribe frame expected");
+
+    let qos1_message = ClientMessage {
+        topic: Topic::try_from(Utf8String::try_from("topic/pub").expect("valid utf8"))
+            .expect("valid topic"),
+        qos: Qos::AtLeastOnce,
+        payload: Payload::from(&b"payload"[..]),
+        ..ClientMessage::default()
+    };
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(qos1_message)),
+        Ok(())
+    );
+    let publish_frame = client.poll_write().expect("publish frame expected");
+    assert_ne!(publish_frame, unsub_frame);
+
+    let unsuback = ControlPacket::UnsubAck(sansio_mqtt_v5_types::UnsubAck {
+        packet_id: NonZero::new(1).expect("non-zero"),
+        properties: sansio_mqtt_v5_types::UnsubAckProperties::default(),
+        reason_codes: vec![sansio_mqtt_v5_types::UnsubAckReasonCode::Success],
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&unsuback),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+}
+
+#[test]
+fn suback_reason_codes_are_forwarded_in_filter_order() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let subscribe = SubscribeOptions {
+        subscription: make_subscription("topic/a"),
+        extra_subscriptions: vec![make_subscription("topic/b")],
+        subscription_identifier: None,
+        user_properties: Vec::new(),
+        token: None,
+    };
+    assert_eq!(
+        client.handle_write(UserWriteIn::Subscribe(subscribe)),
+        Ok(())
+    );
+    assert!(client.poll_write().is_some());
+
+    let suback = ControlPacket::SubAck(sansio_mqtt_v5_types::SubAck {
+        packet_id: NonZero::new(1).expect("non-zero"),
+        properties: sansio_mqtt_v5_types::SubAckProperties {
+            reason_string: Some(Utf8String::try_from("denied").expect("valid utf8")),
+            user_properties: vec![(
+                Utf8String::try_from("k").expect("valid utf8"),
+                Utf8String::try_from("v").expect("valid utf8"),
+            )],
+        },
+        reason_codes: vec![
+            sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS1,
+            sansio_mqtt_v5_types::SubAckReasonCode::NotAuthorized,
+        ],
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&suback),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+
+    match client.poll_read() {
+        Some(UserWriteOut::SubscribeAcknowledged {
+            packet_id,
+            reason_codes,
+            reason_string,
+            user_properties,
+        }) => {
+            assert_eq!(packet_id.get(), 1);
+            assert_eq!(
+                reason_codes,
+                vec![
+                    sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS1,
+                    sansio_mqtt_v5_types::SubAckReasonCode::NotAuthorized,
+                ]
+            );
+            assert_eq!(
+                reason_string,
+                Some(Utf8String::try_from("denied").expect("valid utf8"))
+            );
+            assert_eq!(user_properties.len(), 1);
+        }
+        other => panic!("expected SubscribeAcknowledged, got {other:?}"),
+    }
+    assert!(client.poll_read().is_none());
+}
+
+#[test]
+fn suback_reason_code_count_mismatch_is_protocol_error() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let subscribe = SubscribeOptions {
+        subscription: make_subscription("topic/a"),
+        extra_subscriptions: vec![make_subscription("topic/b")],
+        subscription_identifier: None,
+        user_properties: Vec::new(),
+        token: None,
+    };
+    assert_eq!(
+        client.handle_write(UserWriteIn::Subscribe(subscribe)),
+        Ok(())
+    );
+    assert!(client.poll_write().is_some());
+
+    // [MQTT-3.9.3-1] Two filters were requested but only one Reason Code came
+    // back.
+    let suback = ControlPacket::SubAck(sansio_mqtt_v5_types::SubAck {
+        packet_id: NonZero::new(1).expect("non-zero"),
+        properties: sansio_mqtt_v5_types::SubAckProperties::default(),
+        reason_codes: vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&suback),
+            received_at: Duration::ZERO
+        }),
+        Err(Error::ProtocolError)
+    );
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::CloseSocket)
+    ));
+}
+
+#[test]
+fn unsuback_reason_codes_are_forwarded_in_filter_order() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let unsubscribe = sansio_mqtt_v5_protocol::UnsubscribeOptions {
+        filter: TopicFilter::try_from("topic/a").expect("valid topic filter"),
+        extra_filters: vec![TopicFilter::try_from("topic/b").expect("valid topic filter")],
+        user_properties: Vec::new(),
+        token: None,
+    };
+    assert_eq!(
+        client.handle_write(UserWriteIn::Unsubscribe(unsubscribe)),
+        Ok(())
+    );
+    assert!(client.poll_write().is_some());
+
+    let unsuback = ControlPacket::UnsubAck(sansio_mqtt_v5_types::UnsubAck {
+        packet_id: NonZero::new(1).expect("non-zero"),
+        properties: sansio_mqtt_v5_types::UnsubAckProperties {
+            reason_string: Some(Utf8String::try_from("partial").expect("valid utf8")),
+            user_properties: Vec::new(),
+        },
+        reason_codes: vec![
+            sansio_mqtt_v5_types::UnsubAckReasonCode::NoSubscriptionExisted,
+            sansio_mqtt_v5_types::UnsubAckReasonCode::NotAuthorized,
+        ],
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&unsuback),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+
+    match client.poll_read() {
+        Some(UserWriteOut::UnsubscribeAcknowledged {
+            packet_id,
+            reason_codes,
+            reason_string,
+            user_properties,
+        }) => {
+            assert_eq!(packet_id.get(), 1);
+            assert_eq!(
+                reason_codes,
+                vec![
+                    sansio_mqtt_v5_types::UnsubAckReasonCode::NoSubscriptionExisted,
+                    sansio_mqtt_v5_types::UnsubAckReasonCode::NotAuthorized,
+                ]
+            );
+            assert_eq!(
+                reason_string,
+                Some(Utf8String::try_from("partial").expect("valid utf8"))
+            );
+            assert!(user_properties.is_empty());
+        }
+        other => panic!("expected UnsubscribeAcknowledged, got {other:?}"),
+    }
+    assert!(client.poll_read().is_none());
+}
+
+#[test]
+fn tokenised_writes_report_their_packet_ids() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let topic = Topic::try_from(Utf8String::try_from("topic/pub").expect("valid utf8"))
+        .expect("valid topic");
+    // QoS 0 never gets a Packet Identifier, so its token is not echoed.
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(ClientMessage {
+            topic: topic.clone(),
+            token: Some(UserToken(10)),
+            ..ClientMessage::default()
+        })),
+        Ok(())
+    );
+    assert!(client.poll_read().is_none());
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(ClientMessage {
+            topic,
+            qos: Qos::AtLeastOnce,
+            token: Some(UserToken(11)),
+            ..ClientMessage::default()
+        })),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::PacketIdAssigned {
+            token: UserToken(11),
+            packet_id,
+        }) if packet_id.get() == 1
+    ));
+
+    let subscribe = SubscribeOptions {
+        subscription: make_subscription("topic/a"),
+        extra_subscriptions: Vec::new(),
+        subscription_identifier: None,
+        user_properties: Vec::new(),
+        token: Some(UserToken(12)),
+    };
+    assert_eq!(
+        client.handle_write(UserWriteIn::Subscribe(subscribe)),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::PacketIdAssigned {
+            token: UserToken(12),
+            packet_id,
+        }) if packet_id.get() == 2
+    ));
+
+    let unsubscribe = sansio_mqtt_v5_protocol::UnsubscribeOptions {
+        filter: TopicFilter::try_from("topic/a").expect("valid topic filter"),
+        extra_filters: Vec::new(),
+        user_properties: Vec::new(),
+        token: Some(UserToken(13)),
+    };
+    assert_eq!(
+        client.handle_write(UserWriteIn::Unsubscribe(unsubscribe)),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::PacketIdAssigned {
+            token: UserToken(13),
+            packet_id,
+        }) if packet_id.get() == 3
+    ));
+
+    // Untokenised writes stay silent.
+    let unsubscribe = sansio_mqtt_v5_protocol::UnsubscribeOptions {
+        filter: TopicFilter::try_from("topic/b").expect("valid topic filter"),
+        extra_filters: Vec::new(),
+        user_properties: Vec::new(),
+        token: None,
+    };
+    assert_eq!(
+        client.handle_write(UserWriteIn::Unsubscribe(unsubscribe)),
+        Ok(())
+    );
+    assert!(client.poll_read().is_none());
+}
+
+#[test]
+fn unknown_suback_or_unsuback_is_protocol_error() {
+    let mut client = Client::<Duration>::default();
+
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    let connack = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let suback = ControlPacket::SubAck(sansio_mqtt_v5_types::SubAck {
+        packet_id: NonZero::new(123).expect("non-zero"),
+        properties: sansio_mqtt_v5_types::SubAckProperties::default(),
+        reason_codes: vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&suback),
+            received_at: Duration::ZERO
+        }),
+        Err(Error::ProtocolError)
+    );
+    assert!(matches!(
+        client.poll_event(),
+        Some(sansio_mqtt_v5_protocol::DriverEventOut::CloseSocket)
+    ));
+
+    let mut client = Client::<Duration>::default();
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&connack),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    let unsuback = ControlPacket::UnsubAck(sansio_mqtt_v5_types::UnsubAck {
+        packet_id: NonZero::new(123).expect("non-zero"),
+        properties: sansio_mqtt_v5_types::UnsubAckProperties::default(),
+        reason_codes: vec![sansio_mqtt_v5_types::UnsubAckReasonCode::Success],
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&unsuback),
+            received_at: Duration::ZERO
+        }),
+        Err(Error::ProtocolError)
+    );
+    assert!(matches!(
+        client.poll_event(),
+        Some(sansio_mqtt_v5_protocol::DriverEventOut::CloseSocket)
+    ));
+}
+
+fn subscribe_and_ack(
+    client: &mut Client<Duration>,
+    subscriptions: Vec<Subscription>,
+    subscription_identifier: Option<NonZero<u64>>,
+    reason_codes: Vec<sansio_mqtt_v5_types::SubAckReasonCode>,
+) {
+    let mut subscriptions = subscriptions.into_iter();
+    assert_eq!(
+        client.handle_write(UserWriteIn::Subscribe(SubscribeOptions {
+            subscription: subscriptions.next().expect("at least one subscription"),
+            extra_subscriptions: subscriptions.collect(),
+            subscription_identifier,
+            user_properties: Vec::new(),
+            token: Some(UserToken(0)),
+        })),
+        Ok(())
+    );
+    let Some(UserWriteOut::PacketIdAssigned { packet_id, .. }) = client.poll_read() else {
+        panic!("expected PacketIdAssigned");
+    };
+    assert!(client.poll_write().is_some());
+    read_suback(client, packet_id, reason_codes);
+}
+
+fn read_suback(
+    client: &mut Client<Duration>,
+    packet_id: NonZero<u16>,
+    reason_codes: Vec<sansio_mqtt_v5_types::SubAckReasonCode>,
+) {
+    let suback = ControlPacket::SubAck(sansio_mqtt_v5_types::SubAck {
+        packet_id,
+        properties: sansio_mqtt_v5_types::SubAckProperties::default(),
+        reason_codes,
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&suback),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::SubscribeAcknowledged { .. })
+    ));
+}
+
+fn reconnect_with_new_session(client: &mut Client<Duration>) {
+    assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
+    while client.poll_read().is_some() {}
+    while client.poll_event().is_some() {}
+    open_connection(client, ConnectionOptions::default());
+    accept_connect_at(client, ConnAckProperties::default(), Duration::ZERO);
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+}
+
+#[test]
+fn granted_subscriptions_are_tracked_until_unsuback() {
+    let mut client = connected_client(ClientSettings::default(), ConnAckProperties::default());
+    let sensors = Subscription {
+        qos: Qos::AtLeastOnce,
+        ..make_subscription("sensors/#")
+    };
+
+    subscribe_and_ack(
+        &mut client,
+        vec![sensors.clone(), make_subscription("admin/#")],
+        None,
+        vec![
+            sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS1,
+            sansio_mqtt_v5_types::SubAckReasonCode::NotAuthorized,
+        ],
+    );
+    assert_eq!(
+        client.session().subscriptions().collect::<Vec<_>>(),
+        vec![&sensors]
+    );
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::Unsubscribe(
+            sansio_mqtt_v5_protocol::UnsubscribeOptions {
+                filter: sensors.topic_filter.clone(),
+                extra_filters: Vec::new(),
+                user_properties: Vec::new(),
+                token: None,
+            }
+        )),
+        Ok(())
+    );
+    assert!(client.poll_write().is_some());
+    let unsuback = ControlPacket::UnsubAck(sansio_mqtt_v5_types::UnsubAck {
+        packet_id: NonZero::new(2).expect("non-zero"),
+        properties: sansio_mqtt_v5_types::UnsubAckProperties::default(),
+        reason_codes: vec![sansio_mqtt_v5_types::UnsubAckReasonCode::Success],
+    });
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&unsuback),
+            received_at: Duration::ZERO
+        }),
+        Ok(())
+    );
+
+    assert_eq!(client.session().subscriptions().count(), 0);
+}
+
+#[test]
+fn subscriptions_are_replayed_when_the_session_is_not_resumed() {
+    let mut client = connected_client(
+        ClientSettings {
+            resubscribe_on_new_session: true,
+            ..ClientSettings::default()
+        },
+        ConnAckProperties::default(),
+    );
+    let identifier = NonZero::new(5).expect("non-zero");
+    subscribe_and_ack(
+        &mut client,
+        vec![make_subscription("alarms/#")],
+        Some(identifier),
+        vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
+    );
+    subscribe_and_ack(
+        &mut client,
+        vec![
+            make_subscription("sensors/a"),
+            make_subscription("sensors/b"),
+        ],
+        None,
+        vec![
+            sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0,
+            sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0,
+        ],
+    );
+
+    reconnect_with_new_session(&mut client);
+
+    assert_eq!(
+        client.poll_write(),
+        Some(encode_packet(&ControlPacket::Subscribe(Subscribe {
+            packet_id: NonZero::new(3).expect("non-zero"),
+            subscription: make_subscription("sensors/a"),
+            extra_subscriptions: vec![make_subscription("sensors/b")],
+            properties: SubscribeProperties::default(),
+        })))
+    );
+    assert_eq!(
+        client.poll_write(),
+        Some(encode_packet(&ControlPacket::Subscribe(Subscribe {
+            packet_id: NonZero::new(4).expect("non-zero"),
+            subscription: make_subscription("alarms/#"),
+            extra_subscriptions: Vec::new(),
+            properties: SubscribeProperties {
+                subscription_identifier: Some(identifier),
+                user_properties: Vec::new(),
+            },
+        })))
+    );
+    assert_eq!(client.poll_write(), None);
+
+    // A refused replay is reported as usual and forgotten.
+    read_suback(
+        &mut client,
+        NonZero::new(3).expect("non-zero"),
+        vec![
+            sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0,
+            sansio_mqtt_v5_types::SubAckReasonCode::NotAuthorized,
+        ],
+    );
+    read_suback(
+        &mut client,
+        NonZero::new(4).expect("non-zero"),
+        vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
+    );
+    assert_eq!(
+        client.session().subscriptions().collect::<Vec<_>>(),
+        vec![
+            &make_subscription("alarms/#"),
+            &make_subscription("sensors/a")
+        ]
+    );
+}
+
+#[test]
+fn replayed_subscriptions_are_split_under_the_maximum_packet_size() {
+    let mut client = connected_client(
+        ClientSettings {
+            resubscribe_on_new_session: true,
+            ..ClientSettings::default()
+        },
+        ConnAckProperties::default(),
+    );
+    let oversized = "x".repeat(40);
+    subscribe_and_ack(
+        &mut client,
+        vec![
+            make_subscription("sensors/a"),
+            make_subscription("sensors/b"),
+            make_subscription(&oversized),
+        ],
+        None,
+        vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0; 3],
+    );
 
-    let suback = ControlPacket::SubAck(sansio_mqtt_v5_types::SubAck {
-        packet_id: NonZero::new(1).expect("non-zero"),
-        properties: sansio_mqtt_v5_types::SubAckProperties::default(),
-        reason_codes: vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
-    });
+    assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
+    while client.poll_read().is_some() {}
+    open_connection(&mut client, ConnectionOptions::default());
+    // Room for one short filter per SUBSCRIBE.
+    accept_connect_at(
+        &mut client,
+        ConnAckProperties {
+            maximum_packet_size: NonZero::new(25),
+            ..ConnAckProperties::default()
+        },
+        Duration::ZERO,
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+
+    for (packet_id, topic_filter) in [(2, "sensors/a"), (3, "sensors/b")] {
+        assert_eq!(
+            client.poll_write(),
+            Some(encode_packet(&ControlPacket::Subscribe(Subscribe {
+                packet_id: NonZero::new(packet_id).expect("non-zero"),
+                subscription: make_subscription(topic_filter),
+                extra_subscriptions: Vec::new(),
+                properties: SubscribeProperties::default(),
+            })))
+        );
+    }
+    assert_eq!(client.poll_write(), None);
+    // The filter no SUBSCRIBE can carry is reported and forgotten.
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::ResubscribeFailed { subscriptions })
+            if subscriptions == vec![make_subscription(&oversized)]
+    ));
     assert_eq!(
-        client.handle_read(IncomingData {
-            bytes: encode_packet(&suback),
-            received_at: Duration::ZERO
-        }),
-        Ok(())
+        client.session().subscriptions().collect::<Vec<_>>(),
+        vec![
+            &make_subscription("sensors/a"),
+            &make_subscription("sensors/b")
+        ]
     );
 }
 
 #[test]
-fn unsubscribe_tracks_packet_id_until_unsuback() {
-    let mut client = Client::<Duration>::default();
+fn subscriptions_are_kept_without_replay_when_the_session_is_resumed() {
+    let mut client = connected_client(
+        ClientSettings {
+            resubscribe_on_new_session: true,
+            ..ClientSettings::default()
+        },
+        ConnAckProperties::default(),
+    );
+    subscribe_and_ack(
+        &mut client,
+        vec![make_subscription("sensors/#")],
+        None,
+        vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
+    );
 
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    assert!(client.poll_write().is_some());
+    assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
+    while client.poll_read().is_some() {}
+    open_connection(&mut client, ConnectionOptions::default());
     let connack = ControlPacket::ConnAck(ConnAck {
-        kind: ConnAckKind::Other {
-            reason_code: ConnackReasonCode::Success,
-        },
+        kind: ConnAckKind::ResumePreviousSession,
         properties: ConnAckProperties::default(),
     });
     assert_eq!(
@@ -5720,112 +8042,232 @@ fn unsubscribe_tracks_packet_id_until_unsuback() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
 
-    let unsubscribe = sansio_mqtt_v5_protocol::UnsubscribeOptions {
-        filter: Utf8String::try_from("topic/a").expect("valid utf8"),
-        ..Default::default()
-    };
-    assert_eq!(
-        client.handle_write(UserWriteIn::Unsubscribe(unsubscribe)),
-        Ok(())
+    assert_eq!(client.poll_write(), None);
+    assert_eq!(client.session().subscriptions().count(), 1);
+}
+
+#[test]
+fn subscriptions_are_forgotten_on_a_new_session_without_resubscribe() {
+    let mut client = connected_client(ClientSettings::default(), ConnAckProperties::default());
+    subscribe_and_ack(
+        &mut client,
+        vec![make_subscription("sensors/#")],
+        None,
+        vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
     );
-    let unsub_frame = client.poll_write().expect("unsubscribe frame expected");
 
-    let qos1_message = ClientMessage {
-        topic: Topic::try_from(Utf8String::try_from("topic/pub").expect("valid utf8"))
-            .expect("valid topic"),
-        qos: Qos::AtLeastOnce,
-        payload: Payload::from(&b"payload"[..]),
-        ..ClientMessage::default()
-    };
+    reconnect_with_new_session(&mut client);
+
+    assert_eq!(client.poll_write(), None);
+    assert_eq!(client.session().subscriptions().count(), 0);
+}
+
+/// Subscribes to `topic_filter` without choosing an identifier and checks the
+/// SUBSCRIBE carries `expected_identifier`.
+fn subscribe_expecting_identifier(
+    client: &mut Client<Duration>,
+    topic_filter: &str,
+    expected_identifier: Option<NonZero<u64>>,
+) -> NonZero<u16> {
     assert_eq!(
-        client.handle_write(UserWriteIn::PublishMessage(qos1_message)),
+        client.handle_write(UserWriteIn::Subscribe(SubscribeOptions {
+            subscription: make_subscription(topic_filter),
+            extra_subscriptions: Vec::new(),
+            subscription_identifier: None,
+            user_properties: Vec::new(),
+            token: Some(UserToken(0)),
+        })),
         Ok(())
     );
-    let publish_frame = client.poll_write().expect("publish frame expected");
-    assert_ne!(publish_frame, unsub_frame);
-
-    let unsuback = ControlPacket::UnsubAck(sansio_mqtt_v5_types::UnsubAck {
-        packet_id: NonZero::new(1).expect("non-zero"),
-        properties: sansio_mqtt_v5_types::UnsubAckProperties::default(),
-        reason_codes: vec![sansio_mqtt_v5_types::UnsubAckReasonCode::Success],
-    });
+    let Some(UserWriteOut::PacketIdAssigned { packet_id, .. }) = client.poll_read() else {
+        panic!("expected PacketIdAssigned");
+    };
     assert_eq!(
-        client.handle_read(IncomingData {
-            bytes: encode_packet(&unsuback),
-            received_at: Duration::ZERO
-        }),
-        Ok(())
+        client.poll_write(),
+        Some(encode_packet(&ControlPacket::Subscribe(Subscribe {
+            packet_id,
+            subscription: make_subscription(topic_filter),
+            extra_subscriptions: Vec::new(),
+            properties: SubscribeProperties {
+                subscription_identifier: expected_identifier,
+                user_properties: Vec::new(),
+            },
+        })))
     );
+    packet_id
 }
 
 #[test]
-fn unknown_suback_or_unsuback_is_protocol_error() {
-    let mut client = Client::<Duration>::default();
+fn automatic_subscription_identifiers_resolve_delivered_messages() {
+    let mut client = connected_client(
+        ClientSettings {
+            auto_subscription_identifiers: true,
+            ..ClientSettings::default()
+        },
+        ConnAckProperties::default(),
+    );
+    let identifier = |value| NonZero::new(value).expect("non-zero");
 
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    assert!(client.poll_write().is_some());
-    let connack = ControlPacket::ConnAck(ConnAck {
-        kind: ConnAckKind::Other {
-            reason_code: ConnackReasonCode::Success,
+    let packet_id = subscribe_expecting_identifier(&mut client, "plant/#", Some(identifier(1)));
+    read_suback(
+        &mut client,
+        packet_id,
+        vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
+    );
+    // Identifiers chosen by the application are kept and never reused.
+    subscribe_and_ack(
+        &mut client,
+        vec![
+            make_subscription("plant/+/temperature"),
+            make_subscription("alerts/#"),
+        ],
+        Some(identifier(5)),
+        vec![
+            sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0,
+            sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0,
+        ],
+    );
+    // Still awaiting its SUBACK, which the server may send after messages.
+    subscribe_expecting_identifier(&mut client, "plant/line-1/temperature", Some(identifier(6)));
+
+    let publish = ControlPacket::Publish(Publish {
+        kind: PublishKind::FireAndForget,
+        retain: false,
+        payload: Payload::new(b"21.5".as_slice()),
+        topic: Topic::try_new("plant/line-1/temperature").expect("valid topic"),
+        properties: PublishProperties {
+            subscription_identifiers: vec![identifier(6), identifier(5), identifier(9)],
+            ..PublishProperties::default()
         },
-        properties: ConnAckProperties::default(),
     });
     assert_eq!(
         client.handle_read(IncomingData {
-            bytes: encode_packet(&connack),
+            bytes: encode_packet(&publish),
             received_at: Duration::ZERO
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
 
-    let suback = ControlPacket::SubAck(sansio_mqtt_v5_types::SubAck {
-        packet_id: NonZero::new(123).expect("non-zero"),
-        properties: sansio_mqtt_v5_types::SubAckProperties::default(),
-        reason_codes: vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
-    });
-    assert_eq!(
-        client.handle_read(IncomingData {
-            bytes: encode_packet(&suback),
-            received_at: Duration::ZERO
-        }),
-        Err(Error::ProtocolError)
+    match client.poll_read() {
+        Some(UserWriteOut::ReceivedMessage(message)) => assert_eq!(
+            message.subscriptions,
+            vec![
+                SubscriptionHandle {
+                    subscription_identifier: identifier(6),
+                    topic_filters: vec![
+                        TopicFilter::try_from("plant/line-1/temperature")
+                            .expect("valid topic filter")
+                    ],
+                },
+                SubscriptionHandle {
+                    subscription_identifier: identifier(5),
+                    topic_filters: vec![
+                        TopicFilter::try_from("alerts/#").expect("valid topic filter"),
+                        TopicFilter::try_from("plant/+/temperature").expect("valid topic filter"),
+                    ],
+                },
+            ]
+        ),
+        other => panic!("expected received message, got {other:?}"),
+    }
+}
+
+#[test]
+fn messages_with_identifiers_list_matching_subscriptions_without_one() {
+    let mut client = connected_client(ClientSettings::default(), ConnAckProperties::default());
+    let identifier = NonZero::new(1).expect("non-zero");
+    subscribe_and_ack(
+        &mut client,
+        vec![make_subscription("plant/#")],
+        Some(identifier),
+        vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
+    );
+    subscribe_and_ack(
+        &mut client,
+        vec![
+            make_subscription("plant/+/temperature"),
+            make_subscription("alerts/#"),
+        ],
+        None,
+        vec![
+            sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0,
+            sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0,
+        ],
     );
-    assert!(matches!(
-        client.poll_event(),
-        Some(sansio_mqtt_v5_protocol::DriverEventOut::CloseSocket)
-    ));
 
-    let mut client = Client::<Duration>::default();
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    assert!(client.poll_write().is_some());
+    let publish = ControlPacket::Publish(Publish {
+        kind: PublishKind::FireAndForget,
+        retain: false,
+        payload: Payload::new(b"21.5".as_slice()),
+        topic: Topic::try_new("plant/line-1/temperature").expect("valid topic"),
+        properties: PublishProperties {
+            subscription_identifiers: vec![identifier],
+            ..PublishProperties::default()
+        },
+    });
     assert_eq!(
         client.handle_read(IncomingData {
-            bytes: encode_packet(&connack),
+            bytes: encode_packet(&publish),
             received_at: Duration::ZERO
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
 
-    let unsuback = ControlPacket::UnsubAck(sansio_mqtt_v5_types::UnsubAck {
-        packet_id: NonZero::new(123).expect("non-zero"),
-        properties: sansio_mqtt_v5_types::UnsubAckProperties::default(),
-        reason_codes: vec![sansio_mqtt_v5_types::UnsubAckReasonCode::Success],
-    });
-    assert_eq!(
-        client.handle_read(IncomingData {
-            bytes: encode_packet(&unsuback),
-            received_at: Duration::ZERO
-        }),
-        Err(Error::ProtocolError)
+    match client.poll_read() {
+        Some(UserWriteOut::ReceivedMessage(message)) => assert_eq!(
+            message.unidentified_subscriptions,
+            vec![TopicFilter::try_from("plant/+/temperature").expect("valid topic filter")]
+        ),
+        other => panic!("expected received message, got {other:?}"),
+    }
+}
+
+#[test]
+fn automatic_subscription_identifiers_fill_gaps_once_the_largest_is_taken() {
+    let mut client = connected_client(
+        ClientSettings {
+            auto_subscription_identifiers: true,
+            ..ClientSettings::default()
+        },
+        ConnAckProperties::default(),
+    );
+    let identifier = |value| NonZero::new(value).expect("non-zero");
+    for (topic_filter, value) in [("plant/#", 268_435_455), ("alerts/#", 1), ("logs/#", 3)] {
+        subscribe_and_ack(
+            &mut client,
+            vec![make_subscription(topic_filter)],
+            Some(identifier(value)),
+            vec![sansio_mqtt_v5_types::SubAckReasonCode::SuccessQoS0],
+        );
+    }
+
+    subscribe_expecting_identifier(&mut client, "status/#", Some(identifier(2)));
+    subscribe_expecting_identifier(&mut client, "events/#", Some(identifier(4)));
+}
+
+#[test]
+fn automatic_subscription_identifiers_need_server_support() {
+    let settings = ClientSettings {
+        auto_subscription_identifiers: true,
+        ..ClientSettings::default()
+    };
+    let mut client = connected_client(
+        settings,
+        ConnAckProperties {
+            subscription_identifiers_available: Some(false),
+            ..ConnAckProperties::default()
+        },
     );
-    assert!(matches!(
-        client.poll_event(),
-        Some(sansio_mqtt_v5_protocol::DriverEventOut::CloseSocket)
-    ));
+
+    subscribe_expecting_identifier(&mut client, "plant/#", None);
+}
+
+#[test]
+fn subscription_identifiers_are_not_assigned_by_default() {
+    let mut client = connected_client(ClientSettings::default(), ConnAckProperties::default());
+
+    subscribe_expecting_identifier(&mut client, "plant/#", None);
 }
 
 // ── D1: Server-initiated DISCONNECT reason code ─────────────────────────────
@@ -5853,7 +8295,10 @@ fn server_disconnect_with_reason_code_forwarded_to_application() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     // Server sends DISCONNECT with a non-normal reason code.
     let server_disconnect = ControlPacket::Disconnect(Disconnect {
@@ -5905,7 +8350,10 @@ fn server_normal_disconnect_reason_code_forwarded() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     let server_disconnect = ControlPacket::Disconnect(Disconnect {
         reason_code: DisconnectReasonCode::NormalDisconnection,
@@ -5949,9 +8397,15 @@ fn client_initiated_disconnect_emits_disconnected_none() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
-    assert_eq!(client.handle_write(UserWriteIn::Disconnect), Ok(()));
+    assert_eq!(
+        client.handle_write(UserWriteIn::Disconnect(DisconnectOptions::default())),
+        Ok(())
+    );
     let event = client.poll_read();
     assert!(
         matches!(event, Some(UserWriteOut::Disconnected(None))),
@@ -5966,10 +8420,7 @@ fn client_initiated_disconnect_emits_disconnected_none() {
 /// `UserWriteOut::Auth` rather than triggering a protocol error.
 #[test]
 fn auth_packet_in_connected_state_forwarded_to_application() {
-    let mut client = Client::<Duration>::default();
-
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    let _ = client.poll_write().expect("CONNECT frame expected");
+    let mut client = connecting_with_scram();
     let connack = ControlPacket::ConnAck(ConnAck {
         kind: ConnAckKind::Other {
             reason_code: ConnackReasonCode::Success,
@@ -5983,16 +8434,19 @@ fn auth_packet_in_connected_state_forwarded_to_application() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     // Server sends AUTH to initiate re-authentication. [MQTT-4.12.0-2]
-    let auth_packet = ControlPacket::Auth(Auth {
-        reason_code: AuthReasonCode::ReAuthenticate,
-        properties: AuthProperties::default(),
-    });
+    let auth = ControlPacket::Auth(auth_packet(
+        AuthReasonCode::ReAuthenticate,
+        scram_authentication(None),
+    ));
     assert_eq!(
         client.handle_read(IncomingData {
-            bytes: encode_packet(&auth_packet),
+            bytes: encode_packet(&auth),
             received_at: Duration::ZERO
         }),
         Ok(()),
@@ -6017,30 +8471,66 @@ fn auth_packet_in_connected_state_forwarded_to_application() {
 /// when keep-alive is configured the timer is armed at
 /// `Duration::from_secs(keep_alive)`.
 fn make_connected_client_with_keep_alive(keep_alive_secs: Option<u16>) -> Client<Duration> {
-    let mut client = Client::<Duration>::default();
-
-    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
-    assert!(client.poll_write().is_some()); // drain CONNECT frame
-
     let server_keep_alive = keep_alive_secs.and_then(|s| NonZero::new(s).map(|_| s));
-    let connack = ControlPacket::ConnAck(ConnAck {
-        kind: ConnAckKind::Other {
-            reason_code: ConnackReasonCode::Success,
-        },
-        properties: ConnAckProperties {
+    connected_client(
+        ClientSettings::default(),
+        ConnAckProperties {
             server_keep_alive,
             ..ConnAckProperties::default()
         },
-    });
+    )
+}
+
+#[test]
+fn disconnect_options_are_sent_in_disconnect_packet() {
+    let mut client = make_connected_client_with_keep_alive(None);
+    let utf8 = |value: &str| Utf8String::try_from(value).expect("valid utf8");
+
     assert_eq!(
-        client.handle_read(IncomingData {
-            bytes: encode_packet(&connack),
-            received_at: Duration::ZERO
-        }),
+        client.handle_write(UserWriteIn::Disconnect(DisconnectOptions {
+            reason_code: DisconnectReasonCode::DisconnectWithWillMessage,
+            session_expiry_interval: Some(0),
+            reason_string: Some(utf8("failover")),
+            user_properties: vec![(utf8("site"), utf8("b"))],
+        })),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
-    client
+    let frame = client.poll_write().expect("DISCONNECT frame expected");
+    let packet = ControlPacket::parser::<_, ContextError, ContextError>(&ParserSettings::default())
+        .parse(frame.as_ref())
+        .expect("disconnect packet should decode");
+    assert_eq!(
+        packet,
+        ControlPacket::Disconnect(Disconnect {
+            reason_code: DisconnectReasonCode::DisconnectWithWillMessage,
+            properties: DisconnectProperties {
+                session_expiry_interval: Some(0),
+                reason_string: Some(utf8("failover")),
+                user_properties: vec![(utf8("site"), utf8("b"))],
+                server_reference: None,
+            },
+        })
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Disconnected(None))
+    ));
+}
+
+#[test]
+fn disconnect_cannot_raise_session_expiry_from_zero() {
+    // CONNECT carried no Session Expiry Interval, i.e. zero.
+    let mut client = make_connected_client_with_keep_alive(None);
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::Disconnect(DisconnectOptions {
+            session_expiry_interval: Some(60),
+            ..DisconnectOptions::default()
+        })),
+        Err(Error::ProtocolError)
+    );
+    assert_eq!(client.poll_write(), None);
+    assert!(client.poll_read().is_none(), "still connected");
 }
 
 #[test]
@@ -6072,7 +8562,10 @@ fn keep_alive_timer_armed_after_connack_uses_received_at_timestamp() {
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
     // Timer = received_at + interval = 100 + 30 = 130.
     assert_eq!(client.poll_timeout(), Some(Duration::from_secs(130)));
 }
@@ -6240,7 +8733,10 @@ fn socket_connected_preserves_connect_options_for_effective_limit_recomputation(
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     // A PUBLISH with topic alias 5 is within the user-configured limit of 10.
     // If the bug is present, effective_client_topic_alias_maximum is 0 and this
@@ -6308,7 +8804,10 @@ fn reconnect_from_disconnected_preserves_connect_options_for_effective_limit_rec
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     // Disconnect via SocketClosed → transitions to Disconnected state.
     assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
@@ -6344,7 +8843,10 @@ fn reconnect_from_disconnected_preserves_connect_options_for_effective_limit_rec
         }),
         Ok(())
     );
-    assert!(matches!(client.poll_read(), Some(UserWriteOut::Connected)));
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
 
     // A PUBLISH with topic alias 5 must be accepted after reconnect.
     let topic = Topic::try_new("test/topic").expect("valid topic");
@@ -0,0 +1,228 @@
+use core::num::NonZero;
+use sansio_mqtt_v5_protocol::BrokerMessage;
+use sansio_mqtt_v5_protocol::HandlerId;
+use sansio_mqtt_v5_protocol::Route;
+use sansio_mqtt_v5_protocol::Router;
+use sansio_mqtt_v5_protocol::SubscriptionHandle;
+use sansio_mqtt_v5_types::Topic;
+use sansio_mqtt_v5_types::TopicFilter;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn filter(value: &str) -> TopicFilter {
+    TopicFilter::try_from(value).expect("valid topic filter")
+}
+
+fn topic(value: &str) -> Topic {
+    Topic::new(bytes::Bytes::copy_from_slice(value.as_bytes()))
+}
+
+fn message(topic_name: &str, subscription_identifiers: &[u64]) -> BrokerMessage {
+    BrokerMessage {
+        topic: topic(topic_name),
+        subscription_identifiers: subscription_identifiers
+            .iter()
+            .map(|id| NonZero::new(*id).expect("non-zero subscription identifier"))
+            .collect(),
+        ..BrokerMessage::default()
+    }
+}
+
+type Recorder = Box<dyn FnMut(&BrokerMessage)>;
+
+fn identifier(value: u64) -> NonZero<u64> {
+    NonZero::new(value).expect("non-zero subscription identifier")
+}
+
+#[test]
+fn filters_route_by_topic_levels_and_wildcards() {
+    let mut router = Router::new();
+    let exact = router.add(filter("sport/tennis/player1"), ());
+    let single = router.add(filter("sport/+/player1"), ());
+    let multi = router.add(filter("sport/#"), ());
+    let everything = router.add(filter("#"), ());
+    let leading_single = router.add(filter("+/tennis/#"), ());
+    let system = router.add(filter("$SYS/#"), ());
+
+    assert_eq!(
+        router.matching_topic(&topic("sport/tennis/player1")),
+        vec![exact, single, multi, everything, leading_single]
+    );
+    assert_eq!(
+        router.matching_topic(&topic("sport")),
+        vec![multi, everything]
+    );
+    assert_eq!(
+        router.matching_topic(&topic("sport/tennis/player1/ranking")),
+        vec![multi, everything, leading_single]
+    );
+    assert_eq!(router.matching_topic(&topic("news")), vec![everything]);
+    // [MQTT-4.7.2-1] Leading wildcards skip `$` topics.
+    assert_eq!(
+        router.matching_topic(&topic("$SYS/broker/uptime")),
+        vec![system]
+    );
+}
+
+#[test]
+fn empty_levels_are_distinct_levels() {
+    let mut router = Router::new();
+    let leading_slash = router.add(filter("/+"), ());
+    let two_levels = router.add(filter("+/+"), ());
+    let one_level = router.add(filter("+"), ());
+
+    assert_eq!(
+        router.matching_topic(&topic("/finance")),
+        vec![leading_slash, two_levels]
+    );
+    assert_eq!(router.matching_topic(&topic("finance")), vec![one_level]);
+}
+
+#[test]
+fn overlapping_routes_deliver_once_per_handler() {
+    let calls = Rc::new(RefCell::new(Vec::new()));
+    let mut router: Router<Recorder> = Router::new();
+    let recorder = |name: &'static str| {
+        let calls = Rc::clone(&calls);
+        Box::new(move |message: &BrokerMessage| {
+            calls.borrow_mut().push((name, message.topic.to_string()));
+        }) as Recorder
+    };
+
+    let wide = router.add(filter("plant/#"), recorder("wide"));
+    assert!(router.add_route(wide, filter("plant/+/temperature")));
+    assert!(router.add_route(wide, identifier(7)));
+    let narrow = router.add(filter("plant/line-1/temperature"), recorder("narrow"));
+    let shared = router.add(
+        filter("$share/workers/plant/+/temperature"),
+        recorder("shared"),
+    );
+
+    let delivered = router.dispatch(&message("plant/line-1/temperature", &[7]));
+
+    assert_eq!(delivered, 3);
+    assert_eq!(
+        *calls.borrow(),
+        vec![
+            ("wide", String::from("plant/line-1/temperature")),
+            ("narrow", String::from("plant/line-1/temperature")),
+            ("shared", String::from("plant/line-1/temperature")),
+        ]
+    );
+    assert_eq!(router.len(), 3);
+    assert!(router.get(narrow).is_some());
+    assert!(router.get(shared).is_some());
+}
+
+#[test]
+fn subscription_identifiers_route_regardless_of_topic() {
+    let mut router = Router::new();
+    let alarms = router.add(identifier(1), ());
+    let metrics = router.add(identifier(2), ());
+    let by_filter = router.add(filter("alarms/#"), ());
+
+    assert_eq!(
+        router.matching(&message("anything/at/all", &[2, 1])),
+        vec![alarms, metrics]
+    );
+    assert_eq!(
+        router.matching(&message("alarms/fire", &[1])),
+        vec![alarms, by_filter]
+    );
+    assert_eq!(
+        router.matching(&message("alarms/fire", &[])),
+        vec![by_filter]
+    );
+    assert!(router.matching(&message("other", &[3])).is_empty());
+}
+
+#[test]
+fn subscription_handles_route_overlapping_filters_exactly() {
+    let mut router = Router::new();
+    let wide = router.add(filter("plant/#"), ());
+    let narrow = router.add(filter("plant/+/temperature"), ());
+    let shared = router.add(filter("$share/workers/plant/#"), ());
+    let by_identifier = router.add(identifier(3), ());
+    let delivered_by = |subscription_identifier: u64, filters: &[&str]| BrokerMessage {
+        subscriptions: vec![SubscriptionHandle {
+            subscription_identifier: identifier(subscription_identifier),
+            topic_filters: filters.iter().map(|value| filter(value)).collect(),
+        }],
+        ..message("plant/line-1/temperature", &[subscription_identifier])
+    };
+
+    assert_eq!(
+        router.matching(&message("plant/line-1/temperature", &[])),
+        vec![wide, narrow, shared]
+    );
+    assert_eq!(router.matching(&delivered_by(1, &["plant/#"])), vec![wide]);
+    assert_eq!(
+        router.matching(&delivered_by(2, &["$share/workers/plant/#"])),
+        vec![shared]
+    );
+    assert_eq!(
+        router.matching(&delivered_by(3, &["plant/+/temperature", "other/#"])),
+        vec![narrow, by_identifier]
+    );
+
+    assert!(router.remove_route(narrow, &Route::Filter(filter("plant/+/temperature"))));
+    assert_eq!(
+        router.matching(&delivered_by(3, &["plant/+/temperature"])),
+        vec![by_identifier]
+    );
+}
+
+#[test]
+fn unidentified_subscriptions_route_by_topic_alongside_handles() {
+    let mut router = Router::new();
+    let identified = router.add(filter("plant/#"), ());
+    let unidentified = router.add(filter("plant/+/temperature"), ());
+    let unrelated = router.add(filter("plant/+/pressure"), ());
+    let message = BrokerMessage {
+        subscriptions: vec![SubscriptionHandle {
+            subscription_identifier: identifier(1),
+            topic_filters: vec![filter("plant/#")],
+        }],
+        unidentified_subscriptions: vec![filter("plant/+/temperature")],
+        ..message("plant/line-1/temperature", &[1])
+    };
+
+    assert_eq!(router.matching(&message), vec![identified, unidentified]);
+    assert!(!router.matching(&message).contains(&unrelated));
+}
+
+#[test]
+fn removed_routes_and_handlers_stop_matching() {
+    let mut router = Router::new();
+    let first = router.add(filter("a/+/c"), "first");
+    assert!(router.add_route(first, filter("$share/group/a/+/c")));
+    assert!(router.add_route(first, identifier(9)));
+    let second = router.add(filter("a/b/#"), "second");
+
+    // The shared route still reaches the handler through the same filter.
+    assert!(router.remove_route(first, &Route::Filter(filter("a/+/c"))));
+    assert_eq!(router.matching(&message("a/b/c", &[])), vec![first, second]);
+    assert!(!router.remove_route(first, &Route::Filter(filter("a/+/c"))));
+
+    assert_eq!(router.remove(first), Some("first"));
+    assert_eq!(router.remove(first), None);
+    assert_eq!(router.matching(&message("a/b/c", &[9])), vec![second]);
+    assert_eq!(router.routes(first), None);
+    assert!(!router.add_route(first, filter("a/#")));
+
+    assert!(router.remove_route(second, &Route::Filter(filter("a/b/#"))));
+    assert_eq!(router.routes(second), Some(&[][..]));
+    assert!(router.matching(&message("a/b/c", &[])).is_empty());
+    assert_eq!(router.len(), 1);
+}
+
+#[test]
+fn handler_ids_are_not_reused() {
+    let mut router = Router::new();
+    let first: HandlerId = router.add(filter("a"), ());
+    router.remove(first);
+    let second = router.add(filter("a"), ());
+
+    assert_ne!(first, second);
+    assert_eq!(router.matching_topic(&topic("a")), vec![second]);
+}
@@ -0,0 +1,347 @@
+use bytes::Bytes;
+use core::time::Duration;
+use encode::Encodable;
+use sansio::Protocol;
+use sansio_mqtt_v5_protocol::Authenticator;
+use sansio_mqtt_v5_protocol::Client;
+use sansio_mqtt_v5_protocol::ConnectionOptions;
+use sansio_mqtt_v5_protocol::DriverEventIn;
+use sansio_mqtt_v5_protocol::DriverEventOut;
+use sansio_mqtt_v5_protocol::Error;
+use sansio_mqtt_v5_protocol::IncomingData;
+use sansio_mqtt_v5_protocol::Scram;
+use sansio_mqtt_v5_protocol::ScramMechanism;
+use sansio_mqtt_v5_protocol::UserWriteIn;
+use sansio_mqtt_v5_protocol::UserWriteOut;
+use sansio_mqtt_v5_types::Auth;
+use sansio_mqtt_v5_types::AuthProperties;
+use sansio_mqtt_v5_types::AuthReasonCode;
+use sansio_mqtt_v5_types::AuthenticationKind;
+use sansio_mqtt_v5_types::BinaryData;
+use sansio_mqtt_v5_types::ConnAck;
+use sansio_mqtt_v5_types::ConnAckKind;
+use sansio_mqtt_v5_types::ConnAckProperties;
+use sansio_mqtt_v5_types::ConnackReasonCode;
+use sansio_mqtt_v5_types::ControlPacket;
+use sansio_mqtt_v5_types::ParserSettings;
+use sansio_mqtt_v5_types::Utf8String;
+use winnow::Parser;
+use winnow::error::ContextError;
+
+// RFC 7677 §3 exchange; the SHA-512 transcript reuses its inputs.
+const CLIENT_NONCE: &str = "rOprNGfwEbeRWgbNEkqO";
+const CLIENT_FIRST: &str = "n,,n=user,r=rOprNGfwEbeRWgbNEkqO";
+const SERVER_FIRST: &str =
+    "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+const SHA256_CLIENT_FINAL: &str = "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+const SHA256_SERVER_FINAL: &str = "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+const SHA512_CLIENT_FINAL: &str = "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=gMGXRcevScNtxZ6/8lQYpGtnsNAc3mGcmNomv+xnoOMw+3R2xNJdMNnzMlTN8PPC6wdp6dybEmDYXYTxwnYPJQ==";
+const SHA512_SERVER_FINAL: &str =
+    "v=ZQnYEgWQMFmmsM8aQMF0nDDCy/AgCzkwk8CmMZYcMg0vSVlKDanekLtifDSeVGT4+5ZxXnJq199RVG2rR7N7Zw==";
+
+fn scram(mechanism: ScramMechanism) -> Scram {
+    Scram::new(mechanism, "user", "pencil", || CLIENT_NONCE.into())
+}
+
+fn data(message: &'static str) -> BinaryData {
+    BinaryData::try_from(Bytes::from_static(message.as_bytes())).expect("valid binary data")
+}
+
+fn text(data: Option<BinaryData>) -> String {
+    String::from_utf8(data.expect("authentication data").to_vec()).expect("valid utf8")
+}
+
+fn encode_packet(packet: &ControlPacket) -> Bytes {
+    let mut buffer = Vec::new();
+    packet.encode(&mut buffer).expect("packet should encode");
+    Bytes::from(buffer)
+}
+
+fn decode_packet(frame: &[u8]) -> ControlPacket {
+    ControlPacket::parser::<_, ContextError, ContextError>(&ParserSettings::default())
+        .parse(frame)
+        .expect("packet should decode")
+}
+
+fn server_authentication(mechanism: ScramMechanism, message: &'static str) -> AuthenticationKind {
+    AuthenticationKind::WithData {
+        method: Utf8String::try_from(mechanism.method()).expect("valid utf8"),
+        data: data(message),
+    }
+}
+
+fn read(client: &mut Client<Duration>, packet: ControlPacket) -> Result<(), Error> {
+    client.handle_read(IncomingData {
+        bytes: encode_packet(&packet),
+        received_at: Duration::ZERO,
+    })
+}
+
+#[test]
+fn sha256_matches_rfc7677_test_vector() {
+    let mut scram = scram(ScramMechanism::Sha256);
+
+    assert_eq!(scram.method().as_ref() as &str, "SCRAM-SHA-256");
+    assert_eq!(text(scram.start().expect("client-first")), CLIENT_FIRST);
+    assert_eq!(
+        text(
+            scram
+                .challenge(Some(&data(SERVER_FIRST)))
+                .expect("client-final")
+        ),
+        SHA256_CLIENT_FINAL
+    );
+    assert_eq!(scram.finish(Some(&data(SHA256_SERVER_FINAL))), Ok(()));
+}
+
+#[test]
+fn sha512_derives_the_expected_proof() {
+    let mut scram = scram(ScramMechanism::Sha512);
+
+    assert_eq!(scram.method().as_ref() as &str, "SCRAM-SHA-512");
+    assert_eq!(text(scram.start().expect("client-first")), CLIENT_FIRST);
+    assert_eq!(
+        text(
+            scram
+                .challenge(Some(&data(SERVER_FIRST)))
+                .expect("client-final")
+        ),
+        SHA512_CLIENT_FINAL
+    );
+    assert_eq!(scram.finish(Some(&data(SHA512_SERVER_FINAL))), Ok(()));
+}
+
+#[test]
+fn forged_server_signature_is_rejected() {
+    let mut scram = scram(ScramMechanism::Sha256);
+    scram.start().expect("client-first");
+    scram
+        .challenge(Some(&data(SERVER_FIRST)))
+        .expect("client-final");
+
+    assert_eq!(
+        scram.finish(Some(&data(SHA512_SERVER_FINAL))),
+        Err(Error::AuthenticationFailed)
+    );
+}
+
+#[test]
+fn server_nonce_must_extend_client_nonce() {
+    let mut scram = scram(ScramMechanism::Sha256);
+    scram.start().expect("client-first");
+
+    assert_eq!(
+        scram.challenge(Some(&data(
+            "r=somebodyElse,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+        ))),
+        Err(Error::AuthenticationFailed)
+    );
+}
+
+#[test]
+fn usernames_are_escaped() {
+    let mut scram = Scram::new(ScramMechanism::Sha256, "a=b,c", "pencil", || {
+        CLIENT_NONCE.into()
+    });
+
+    assert_eq!(
+        text(scram.start().expect("client-first")),
+        "n,,n=a=3Db=2Cc,r=rOprNGfwEbeRWgbNEkqO"
+    );
+}
+
+#[test]
+fn client_runs_scram_exchange_against_scripted_server() {
+    let mechanism = ScramMechanism::Sha256;
+    let mut client = Client::<Duration>::default();
+    client.set_authenticator(scram(mechanism));
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::Connect(ConnectionOptions::default())),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::OpenSocket)
+    ));
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+
+    let ControlPacket::Connect(connect) =
+        decode_packet(&client.poll_write().expect("CONNECT frame"))
+    else {
+        panic!("expected CONNECT");
+    };
+    assert_eq!(
+        connect.properties.authentication,
+        Some(server_authentication(mechanism, CLIENT_FIRST))
+    );
+
+    assert_eq!(
+        read(
+            &mut client,
+            ControlPacket::Auth(Auth {
+                reason_code: AuthReasonCode::ContinueAuthentication,
+                properties: AuthProperties {
+                    authentication: Some(server_authentication(mechanism, SERVER_FIRST)),
+                    ..AuthProperties::default()
+                },
+            }),
+        ),
+        Ok(())
+    );
+    // The challenge is answered internally, not surfaced to the application.
+    assert!(client.poll_read().is_none());
+    assert_eq!(
+        decode_packet(&client.poll_write().expect("AUTH frame")),
+        ControlPacket::Auth(Auth {
+            reason_code: AuthReasonCode::ContinueAuthentication,
+            properties: AuthProperties {
+                authentication: Some(server_authentication(mechanism, SHA256_CLIENT_FINAL)),
+                ..AuthProperties::default()
+            },
+        })
+    );
+
+    assert_eq!(
+        read(
+            &mut client,
+            ControlPacket::ConnAck(ConnAck {
+                kind: ConnAckKind::Other {
+                    reason_code: ConnackReasonCode::Success,
+                },
+                properties: ConnAckProperties {
+                    authentication: Some(server_authentication(mechanism, SHA256_SERVER_FINAL)),
+                    ..ConnAckProperties::default()
+                },
+            }),
+        ),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+}
+
+#[test]
+fn connack_with_bad_server_signature_fails_authentication() {
+    let mechanism = ScramMechanism::Sha256;
+    let mut client = Client::<Duration>::default();
+    client.set_authenticator(scram(mechanism));
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::Connect(ConnectionOptions::default())),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::OpenSocket)
+    ));
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+
+    assert_eq!(
+        read(
+            &mut client,
+            ControlPacket::Auth(Auth {
+                reason_code: AuthReasonCode::ContinueAuthentication,
+                properties: AuthProperties {
+                    authentication: Some(server_authentication(mechanism, SERVER_FIRST)),
+                    ..AuthProperties::default()
+                },
+            }),
+        ),
+        Ok(())
+    );
+    assert!(client.poll_write().is_some());
+
+    assert_eq!(
+        read(
+            &mut client,
+            ControlPacket::ConnAck(ConnAck {
+                kind: ConnAckKind::Other {
+                    reason_code: ConnackReasonCode::Success,
+                },
+                properties: ConnAckProperties {
+                    authentication: Some(server_authentication(mechanism, SHA512_SERVER_FINAL)),
+                    ..ConnAckProperties::default()
+                },
+            }),
+        ),
+        Err(Error::AuthenticationFailed)
+    );
+    assert!(client.poll_read().is_none());
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::CloseSocket)
+    ));
+}
+
+#[test]
+fn authenticator_restarts_exchange_when_credentials_are_due() {
+    let mechanism = ScramMechanism::Sha256;
+    let mut client = Client::<Duration>::default();
+    client.set_authenticator(scram(mechanism));
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::Connect(ConnectionOptions {
+            reauthenticate_after: Some(Duration::from_secs(3600)),
+            ..ConnectionOptions::default()
+        })),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::OpenSocket)
+    ));
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    assert_eq!(
+        read(
+            &mut client,
+            ControlPacket::Auth(Auth {
+                reason_code: AuthReasonCode::ContinueAuthentication,
+                properties: AuthProperties {
+                    authentication: Some(server_authentication(mechanism, SERVER_FIRST)),
+                    ..AuthProperties::default()
+                },
+            }),
+        ),
+        Ok(())
+    );
+    assert!(client.poll_write().is_some());
+    assert_eq!(
+        read(
+            &mut client,
+            ControlPacket::ConnAck(ConnAck {
+                kind: ConnAckKind::Other {
+                    reason_code: ConnackReasonCode::Success,
+                },
+                properties: ConnAckProperties {
+                    authentication: Some(server_authentication(mechanism, SHA256_SERVER_FINAL)),
+                    ..ConnAckProperties::default()
+                },
+            }),
+        ),
+        Ok(())
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+    assert_eq!(client.poll_timeout(), Some(Duration::from_secs(3600)));
+
+    assert_eq!(client.handle_timeout(Duration::from_secs(3600)), Ok(()));
+    // The authenticator answers the deadline itself.
+    assert!(client.poll_read().is_none());
+    assert_eq!(
+        decode_packet(&client.poll_write().expect("AUTH frame")),
+        ControlPacket::Auth(Auth {
+            reason_code: AuthReasonCode::ReAuthenticate,
+            properties: AuthProperties {
+                authentication: Some(server_authentication(mechanism, CLIENT_FIRST)),
+                ..AuthProperties::default()
+            },
+        })
+    );
+}
@@ -0,0 +1,754 @@
+use bytes::Bytes;
+use core::num::NonZero;
+use core::time::Duration;
+use encode::Encodable;
+use sansio::Protocol;
+use sansio_mqtt_v5_protocol::Client;
+use sansio_mqtt_v5_protocol::ClientMessage;
+use sansio_mqtt_v5_protocol::ClientSession;
+use sansio_mqtt_v5_protocol::ClientSettings;
+use sansio_mqtt_v5_protocol::ConnectionOptions;
+use sansio_mqtt_v5_protocol::DriverEventIn;
+use sansio_mqtt_v5_protocol::DriverEventOut;
+use sansio_mqtt_v5_protocol::Error;
+use sansio_mqtt_v5_protocol::IncomingData;
+use sansio_mqtt_v5_protocol::MemorySessionStore;
+use sansio_mqtt_v5_protocol::SessionChange;
+use sansio_mqtt_v5_protocol::SessionDecodeError;
+use sansio_mqtt_v5_protocol::SessionStore;
+use sansio_mqtt_v5_protocol::SubscribeOptions;
+use sansio_mqtt_v5_protocol::UnsubscribeOptions;
+use sansio_mqtt_v5_protocol::UserToken;
+use sansio_mqtt_v5_protocol::UserWriteIn;
+use sansio_mqtt_v5_protocol::UserWriteOut;
+use sansio_mqtt_v5_types::ConnAck;
+use sansio_mqtt_v5_types::ConnAckKind;
+use sansio_mqtt_v5_types::ConnAckProperties;
+use sansio_mqtt_v5_types::ConnackReasonCode;
+use sansio_mqtt_v5_types::ControlPacket;
+use sansio_mqtt_v5_types::Disconnect;
+use sansio_mqtt_v5_types::DisconnectProperties;
+use sansio_mqtt_v5_types::DisconnectReasonCode;
+use sansio_mqtt_v5_types::GuaranteedQoS;
+use sansio_mqtt_v5_types::Payload;
+use sansio_mqtt_v5_types::PubAck;
+use sansio_mqtt_v5_types::PubAckProperties;
+use sansio_mqtt_v5_types::PubAckReasonCode;
+use sansio_mqtt_v5_types::PubComp;
+use sansio_mqtt_v5_types::PubCompProperties;
+use sansio_mqtt_v5_types::PubCompReasonCode;
+use sansio_mqtt_v5_types::PubRec;
+use sansio_mqtt_v5_types::PubRecProperties;
+use sansio_mqtt_v5_types::PubRecReasonCode;
+use sansio_mqtt_v5_types::PubRel;
+use sansio_mqtt_v5_types::PubRelProperties;
+use sansio_mqtt_v5_types::PubRelReasonCode;
+use sansio_mqtt_v5_types::Publish;
+use sansio_mqtt_v5_types::PublishKind;
+use sansio_mqtt_v5_types::PublishProperties;
+use sansio_mqtt_v5_types::Qos;
+use sansio_mqtt_v5_types::RetainHandling;
+use sansio_mqtt_v5_types::SubAck;
+use sansio_mqtt_v5_types::SubAckProperties;
+use sansio_mqtt_v5_types::SubAckReasonCode;
+use sansio_mqtt_v5_types::Subscription;
+use sansio_mqtt_v5_types::Topic;
+use sansio_mqtt_v5_types::TopicFilter;
+use sansio_mqtt_v5_types::Utf8String;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+fn encode_packet(packet: &ControlPacket) -> Bytes {
+    let mut buffer = Vec::new();
+    packet.encode(&mut buffer).expect("packet should encode");
+    Bytes::from(buffer)
+}
+
+fn read(client: &mut Client<Duration>, packet: ControlPacket) {
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&packet),
+            received_at: Duration::ZERO,
+        }),
+        Ok(())
+    );
+}
+
+fn connect(client: &mut Client<Duration>, clean_start: bool, kind: ConnAckKind) {
+    assert_eq!(
+        client.handle_write(UserWriteIn::Connect(ConnectionOptions {
+            clean_start,
+            session_expiry_interval: Some(300),
+            ..ConnectionOptions::default()
+        })),
+        Ok(())
+    );
+    while client.poll_event().is_some() {}
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    read(
+        client,
+        ControlPacket::ConnAck(ConnAck {
+            kind,
+            properties: ConnAckProperties::default(),
+        }),
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+}
+
+fn topic() -> Topic {
+    Topic::try_from(Utf8String::try_from("plant/line-1").expect("valid utf8")).expect("valid topic")
+}
+
+fn publish(packet_id: u16, qos: GuaranteedQoS, dup: bool) -> Publish {
+    Publish {
+        kind: PublishKind::Repetible {
+            packet_id: NonZero::new(packet_id).expect("non-zero packet id"),
+            qos,
+            dup,
+        },
+        retain: false,
+        payload: Payload::from(&b"persisted"[..]),
+        topic: topic(),
+        properties: PublishProperties::default(),
+    }
+}
+
+/// A session with every kind of state: a QoS 1 publish awaiting PUBACK, a
+/// QoS 2 publish awaiting PUBCOMP, an inbound QoS 2 publish awaiting PUBREL,
+/// a granted subscription and a SUBSCRIBE awaiting SUBACK.
+fn busy_session() -> ClientSession {
+    let mut client = Client::<Duration>::with_settings(ClientSettings::default());
+    connect(
+        &mut client,
+        true,
+        ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+    );
+
+    for qos in [Qos::AtLeastOnce, Qos::ExactlyOnce] {
+        assert_eq!(
+            client.handle_write(UserWriteIn::PublishMessage(ClientMessage {
+                topic: topic(),
+                qos,
+                payload: Payload::from(&b"persisted"[..]),
+                ..ClientMessage::default()
+            })),
+            Ok(())
+        );
+    }
+    read(
+        &mut client,
+        ControlPacket::PubRec(PubRec {
+            packet_id: NonZero::new(2).expect("non-zero packet id"),
+            reason_code: PubRecReasonCode::Success,
+            properties: PubRecProperties::default(),
+        }),
+    );
+
+    read(
+        &mut client,
+        ControlPacket::Publish(Publish {
+            kind: PublishKind::Repetible {
+                packet_id: NonZero::new(7).expect("non-zero packet id"),
+                qos: GuaranteedQoS::ExactlyOnce,
+                dup: false,
+            },
+            retain: false,
+            payload: Payload::from(&b"inbound"[..]),
+            topic: topic(),
+            properties: PublishProperties::default(),
+        }),
+    );
+    let inbound_message_id = match client.poll_read() {
+        Some(UserWriteOut::ReceivedMessageWithRequiredAcknowledgement(id, _)) => id,
+        other => panic!("expected inbound QoS 2 message, got {other:?}"),
+    };
+    assert_eq!(
+        client.handle_write(UserWriteIn::AcknowledgeMessage(inbound_message_id)),
+        Ok(())
+    );
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::Subscribe(SubscribeOptions {
+            subscription: Subscription {
+                topic_filter: TopicFilter::try_from("plant/alarms").expect("valid topic filter"),
+                qos: Qos::ExactlyOnce,
+                no_local: true,
+                retain_as_published: true,
+                retain_handling: RetainHandling::DoNotSend,
+            },
+            extra_subscriptions: vec![],
+            subscription_identifier: NonZero::new(9),
+            user_properties: vec![],
+            token: None,
+        })),
+        Ok(())
+    );
+    read(
+        &mut client,
+        ControlPacket::SubAck(SubAck {
+            packet_id: NonZero::new(3).expect("non-zero packet id"),
+            reason_codes: vec![SubAckReasonCode::SuccessQoS2],
+            properties: SubAckProperties::default(),
+        }),
+    );
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::Subscribe(SubscribeOptions {
+            subscription: Subscription {
+                topic_filter: TopicFilter::try_from("plant/#").expect("valid topic filter"),
+                qos: Qos::AtLeastOnce,
+                no_local: false,
+                retain_as_published: false,
+                retain_handling: RetainHandling::SendRetained,
+            },
+            extra_subscriptions: vec![],
+            subscription_identifier: None,
+            user_properties: vec![],
+            token: None,
+        })),
+        Ok(())
+    );
+
+    client.session().clone()
+}
+
+#[test]
+fn session_round_trips_through_its_binary_encoding() {
+    let session = busy_session();
+    assert_ne!(session, ClientSession::default());
+
+    assert_eq!(ClientSession::from_bytes(&session.to_bytes()), Ok(session));
+}
+
+#[test]
+fn restored_session_replays_inflight_publish_on_resume() {
+    let bytes = busy_session().to_bytes();
+    let restored = ClientSession::from_bytes(&bytes).expect("valid session encoding");
+    let mut client =
+        Client::<Duration>::with_settings_and_session(ClientSettings::default(), restored);
+
+    connect(&mut client, false, ConnAckKind::ResumePreviousSession);
+
+    assert_eq!(
+        client.poll_write(),
+        Some(encode_packet(&ControlPacket::Publish(publish(
+            1,
+            GuaranteedQoS::AtLeastOnce,
+            true
+        ))))
+    );
+}
+
+#[test]
+fn session_decoding_rejects_foreign_and_damaged_input() {
+    let bytes = busy_session().to_bytes();
+
+    assert_eq!(
+        ClientSession::from_bytes(b"not a session"),
+        Err(SessionDecodeError::UnknownFormat)
+    );
+
+    let mut newer = bytes.clone();
+    newer[4] = u8::MAX;
+    assert_eq!(
+        ClientSession::from_bytes(&newer),
+        Err(SessionDecodeError::UnsupportedVersion(u8::MAX))
+    );
+
+    assert_eq!(
+        ClientSession::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(SessionDecodeError::Malformed)
+    );
+
+    let mut trailing = bytes;
+    trailing.push(0);
+    assert_eq!(
+        ClientSession::from_bytes(&trailing),
+        Err(SessionDecodeError::Malformed)
+    );
+}
+
+#[test]
+fn session_with_more_subscriptions_than_a_u16_count_round_trips() {
+    let count = u32::from(u16::MAX) + 1;
+    let mut encoded = b"SMQS\x01\x00\x01".to_vec();
+    encoded.extend_from_slice(&[0; 16]);
+    encoded.extend_from_slice(&count.to_be_bytes());
+    for index in 0..count {
+        let topic_filter = format!("plant/{index}");
+        encoded.extend_from_slice(&(topic_filter.len() as u16).to_be_bytes());
+        encoded.extend_from_slice(topic_filter.as_bytes());
+        encoded.push(0);
+        encoded.extend_from_slice(&0u64.to_be_bytes());
+    }
+    encoded.extend_from_slice(&[0; 4]);
+
+    let session = ClientSession::from_bytes(&encoded).expect("session decodes");
+
+    assert_eq!(session.subscriptions().count(), count as usize);
+    assert_eq!(ClientSession::from_bytes(&session.to_bytes()), Ok(session));
+}
+
+#[test]
+fn session_awaiting_an_unsubscribe_of_more_than_65535_filters_round_trips() {
+    let mut client = Client::<Duration>::with_settings(ClientSettings::default());
+    connect(
+        &mut client,
+        true,
+        ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+    );
+    assert_eq!(
+        client.handle_write(UserWriteIn::Unsubscribe(UnsubscribeOptions {
+            filter: TopicFilter::try_from("plant/0").expect("valid topic filter"),
+            extra_filters: (1..=u32::from(u16::MAX))
+                .map(|index| {
+                    TopicFilter::try_from(format!("plant/{index}").as_str())
+                        .expect("valid topic filter")
+                })
+                .collect(),
+            user_properties: vec![],
+            token: None,
+        })),
+        Ok(())
+    );
+
+    let session = client.session().clone();
+    assert_eq!(ClientSession::from_bytes(&session.to_bytes()), Ok(session));
+}
+
+#[test]
+fn session_round_trips_through_serde() {
+    let session = busy_session();
+
+    let json = serde_json::to_string(&session).expect("session serializes");
+    let restored: ClientSession = serde_json::from_str(&json).expect("session deserializes");
+
+    assert_eq!(restored, session);
+}
+
+/// Shares what it records with the test, refusing everything while failing.
+#[derive(Debug, Clone, Default)]
+struct RecordingStore {
+    changes: Arc<Mutex<Vec<SessionChange>>>,
+    failing: Arc<AtomicBool>,
+}
+
+impl RecordingStore {
+    fn changes(&self) -> Vec<SessionChange> {
+        self.changes.lock().expect("store lock").clone()
+    }
+
+    fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+}
+
+impl SessionStore for RecordingStore {
+    fn record(&mut self, change: &SessionChange) -> Result<(), Error> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(Error::SessionStoreFailed);
+        }
+        self.changes
+            .lock()
+            .expect("store lock")
+            .push(change.clone());
+        Ok(())
+    }
+}
+
+fn connected_client_with_store(store: RecordingStore) -> Client<Duration> {
+    let mut client = Client::<Duration>::with_settings(ClientSettings::default());
+    client.set_session_store(store);
+    connect(
+        &mut client,
+        false,
+        ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+    );
+    client
+}
+
+fn publish_qos2(client: &mut Client<Duration>) -> Result<(), Error> {
+    client.handle_write(UserWriteIn::PublishMessage(ClientMessage {
+        topic: topic(),
+        qos: Qos::ExactlyOnce,
+        payload: Payload::from(&b"persisted"[..]),
+        ..ClientMessage::default()
+    }))
+}
+
+#[test]
+fn session_store_records_outbound_changes_before_packets_are_polled() {
+    let store = RecordingStore::default();
+    let mut client = connected_client_with_store(store.clone());
+    let packet_id = NonZero::new(1).expect("non-zero packet id");
+    let publish = publish(1, GuaranteedQoS::ExactlyOnce, false);
+
+    assert_eq!(publish_qos2(&mut client), Ok(()));
+    assert_eq!(
+        store.changes(),
+        vec![SessionChange::OutboundPublished {
+            packet_id,
+            publish: publish.clone()
+        }]
+    );
+    assert_eq!(
+        client.poll_write(),
+        Some(encode_packet(&ControlPacket::Publish(publish)))
+    );
+
+    read(
+        &mut client,
+        ControlPacket::PubRec(PubRec {
+            packet_id,
+            reason_code: PubRecReasonCode::Success,
+            properties: PubRecProperties::default(),
+        }),
+    );
+    assert_eq!(
+        store.changes().last(),
+        Some(&SessionChange::OutboundReleased(packet_id))
+    );
+
+    read(
+        &mut client,
+        ControlPacket::PubComp(PubComp {
+            packet_id,
+            reason_code: PubCompReasonCode::Success,
+            properties: PubCompProperties::default(),
+        }),
+    );
+    assert_eq!(
+        store.changes().last(),
+        Some(&SessionChange::OutboundCompleted(packet_id))
+    );
+}
+
+#[test]
+fn session_store_records_inbound_qos2_until_pubrel() {
+    let store = RecordingStore::default();
+    let mut client = connected_client_with_store(store.clone());
+    let packet_id = NonZero::new(9).expect("non-zero packet id");
+
+    read(
+        &mut client,
+        ControlPacket::Publish(Publish {
+            kind: PublishKind::Repetible {
+                packet_id,
+                qos: GuaranteedQoS::ExactlyOnce,
+                dup: false,
+            },
+            retain: false,
+            payload: Payload::from(&b"inbound"[..]),
+            topic: topic(),
+            properties: PublishProperties::default(),
+        }),
+    );
+    // Awaiting the application's decision is not recorded: the server
+    // redelivers the message on resume.
+    assert_eq!(store.changes(), vec![]);
+    let Some(UserWriteOut::ReceivedMessageWithRequiredAcknowledgement(id, _)) = client.poll_read()
+    else {
+        panic!("expected inbound QoS 2 message");
+    };
+
+    assert_eq!(
+        client.handle_write(UserWriteIn::AcknowledgeMessage(id)),
+        Ok(())
+    );
+    read(
+        &mut client,
+        ControlPacket::PubRel(PubRel {
+            packet_id,
+            reason_code: PubRelReasonCode::Success,
+            properties: PubRelProperties::default(),
+        }),
+    );
+
+    assert_eq!(
+        store.changes(),
+        vec![
+            SessionChange::InboundAcknowledged {
+                packet_id,
+                reason_code: PubRecReasonCode::Success,
+            },
+            SessionChange::InboundReleased(packet_id),
+        ]
+    );
+}
+
+#[test]
+fn memory_session_store_rebuilds_a_resumable_session() {
+    let store = RecordingStore::default();
+    let mut client = connected_client_with_store(store.clone());
+    assert_eq!(publish_qos2(&mut client), Ok(()));
+
+    let mut memory = MemorySessionStore::default();
+    for change in store.changes() {
+        assert_eq!(memory.record(&change), Ok(()));
+    }
+    let mut restored = Client::<Duration>::with_settings_and_session(
+        ClientSettings::default(),
+        memory.session().clone(),
+    );
+    connect(&mut restored, false, ConnAckKind::ResumePreviousSession);
+
+    assert_eq!(
+        restored.poll_write(),
+        Some(encode_packet(&ControlPacket::Publish(publish(
+            1,
+            GuaranteedQoS::ExactlyOnce,
+            true
+        ))))
+    );
+}
+
+#[test]
+fn failing_session_store_withholds_packets_and_closes_the_connection() {
+    let store = RecordingStore::default();
+    let mut client = connected_client_with_store(store.clone());
+    store.set_failing(true);
+
+    assert_eq!(publish_qos2(&mut client), Err(Error::SessionStoreFailed));
+
+    assert_eq!(
+        client.poll_write(),
+        Some(encode_packet(&ControlPacket::Disconnect(Disconnect {
+            reason_code: DisconnectReasonCode::UnspecifiedError,
+            properties: DisconnectProperties::default(),
+        })))
+    );
+    assert_eq!(client.poll_write(), None);
+    assert!(matches!(
+        client.poll_event(),
+        Some(DriverEventOut::CloseSocket)
+    ));
+}
+
+#[test]
+fn failing_session_store_rolls_the_session_back_to_what_it_recorded() {
+    let store = RecordingStore::default();
+    let mut client = connected_client_with_store(store.clone());
+    assert_eq!(publish_qos2(&mut client), Ok(()));
+    assert!(client.poll_write().is_some());
+
+    store.set_failing(true);
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&ControlPacket::PubRec(PubRec {
+                packet_id: NonZero::new(1).expect("non-zero packet id"),
+                reason_code: PubRecReasonCode::Success,
+                properties: PubRecProperties::default(),
+            })),
+            received_at: Duration::ZERO,
+        }),
+        Err(Error::SessionStoreFailed)
+    );
+    while client.poll_write().is_some() {}
+    store.set_failing(false);
+
+    // The store never saw the PUBREC, so the PUBLISH is replayed rather
+    // than the PUBREL.
+    connect(&mut client, false, ConnAckKind::ResumePreviousSession);
+    assert_eq!(
+        client.poll_write(),
+        Some(encode_packet(&ControlPacket::Publish(publish(
+            1,
+            GuaranteedQoS::ExactlyOnce,
+            true
+        ))))
+    );
+}
+
+/// Connects `client` to a server accepting a single QoS 1 or QoS 2 publish
+/// in flight at a time.
+fn connect_with_receive_maximum_one(client: &mut Client<Duration>, kind: ConnAckKind) {
+    assert_eq!(
+        client.handle_write(UserWriteIn::Connect(ConnectionOptions {
+            session_expiry_interval: Some(300),
+            ..ConnectionOptions::default()
+        })),
+        Ok(())
+    );
+    while client.poll_event().is_some() {}
+    assert_eq!(client.handle_event(DriverEventIn::SocketConnected), Ok(()));
+    assert!(client.poll_write().is_some());
+    read(
+        client,
+        ControlPacket::ConnAck(ConnAck {
+            kind,
+            properties: ConnAckProperties {
+                receive_maximum: Some(NonZero::new(1).expect("non-zero receive maximum")),
+                ..ConnAckProperties::default()
+            },
+        }),
+    );
+    assert!(matches!(
+        client.poll_read(),
+        Some(UserWriteOut::Connected(_))
+    ));
+}
+
+fn publish_qos1(client: &mut Client<Duration>, payload: &'static [u8], token: u64) {
+    assert_eq!(
+        client.handle_write(UserWriteIn::PublishMessage(ClientMessage {
+            topic: topic(),
+            qos: Qos::AtLeastOnce,
+            payload: Payload::from(payload),
+            token: Some(UserToken(token)),
+            ..ClientMessage::default()
+        })),
+        Ok(())
+    );
+}
+
+fn puback(packet_id: u16) -> ControlPacket {
+    ControlPacket::PubAck(PubAck {
+        packet_id: NonZero::new(packet_id).expect("non-zero packet id"),
+        reason_code: PubAckReasonCode::Success,
+        properties: PubAckProperties::default(),
+    })
+}
+
+#[test]
+fn failing_session_store_keeps_events_and_pending_publishes_of_the_call() {
+    let store = RecordingStore::default();
+    let mut client = Client::<Duration>::with_settings(ClientSettings {
+        max_pending_publishes: 1,
+        ..ClientSettings::default()
+    });
+    client.set_session_store(store.clone());
+    connect_with_receive_maximum_one(
+        &mut client,
+        ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+    );
+    publish_qos1(&mut client, b"first", 1);
+    // Receive Maximum is reached: the second publish waits in the pending
+    // queue.
+    publish_qos1(&mut client, b"second", 2);
+    while client.poll_write().is_some() {}
+    while client.poll_read().is_some() {}
+
+    // The PUBACK completes the first publish and flushes the second one, but
+    // the store records neither.
+    store.set_failing(true);
+    assert_eq!(
+        client.handle_read(IncomingData {
+            bytes: encode_packet(&puback(1)),
+            received_at: Duration::ZERO,
+        }),
+        Err(Error::SessionStoreFailed)
+    );
+    while let Some(event) = client.poll_read() {
+        assert!(
+            !matches!(
+                event,
+                UserWriteOut::PublishAcknowledged(..) | UserWriteOut::PacketIdAssigned { .. }
+            ),
+            "{event:?} leaked from the failed call"
+        );
+    }
+    while let Some(packet) = client.poll_write() {
+        assert_ne!(
+            packet[0] & 0xf0,
+            0x30,
+            "PUBLISH leaked from the failed call"
+        );
+    }
+    store.set_failing(false);
+
+    // The first publish is replayed, and the second one is still pending
+    // behind it.
+    connect_with_receive_maximum_one(&mut client, ConnAckKind::ResumePreviousSession);
+    assert_eq!(client.poll_write().map(|packet| packet[0]), Some(0x3a));
+    assert_eq!(client.poll_write(), None);
+    read(&mut client, puback(1));
+    let events: Vec<_> = core::iter::from_fn(|| client.poll_read()).collect();
+    assert!(
+        events.iter().any(|event| matches!(
+            event,
+            UserWriteOut::PacketIdAssigned {
+                token: UserToken(2),
+                ..
+            }
+        )),
+        "{events:?}"
+    );
+    assert_eq!(client.poll_write().map(|packet| packet[0]), Some(0x32));
+}
+
+#[test]
+fn clean_start_reconnect_clears_the_session_store() {
+    let store = RecordingStore::default();
+    let mut client = connected_client_with_store(store.clone());
+    assert_eq!(publish_qos2(&mut client), Ok(()));
+    assert_eq!(client.handle_event(DriverEventIn::SocketClosed), Ok(()));
+    while client.poll_read().is_some() {}
+
+    connect(
+        &mut client,
+        true,
+        ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+    );
+
+    assert_eq!(store.changes().last(), Some(&SessionChange::Cleared));
+    let mut memory = MemorySessionStore::default();
+    for change in store.changes() {
+        assert_eq!(memory.record(&change), Ok(()));
+    }
+    assert_eq!(memory.session(), &ClientSession::default());
+}
+
+#[test]
+fn session_change_round_trips_through_its_binary_encoding() {
+    let packet_id = NonZero::new(3).expect("non-zero packet id");
+
+    for change in [
+        SessionChange::OutboundPublished {
+            packet_id,
+            publish: publish(3, GuaranteedQoS::ExactlyOnce, false),
+        },
+        SessionChange::OutboundReleased(packet_id),
+        SessionChange::OutboundCompleted(packet_id),
+        SessionChange::InboundAcknowledged {
+            packet_id,
+            reason_code: PubRecReasonCode::QuotaExceeded,
+        },
+        SessionChange::InboundReleased(packet_id),
+        SessionChange::Cleared,
+        SessionChange::SubscriptionAdded {
+            subscription: Subscription {
+                topic_filter: TopicFilter::try_from("plant/+").expect("valid topic filter"),
+                qos: Qos::ExactlyOnce,
+                no_local: true,
+                retain_as_published: true,
+                retain_handling: RetainHandling::DoNotSend,
+            },
+            subscription_identifier: NonZero::new(7),
+        },
+        SessionChange::SubscriptionRemoved(
+            TopicFilter::try_from("plant/+").expect("valid topic filter"),
+        ),
+    ] {
+        assert_eq!(SessionChange::from_bytes(&change.to_bytes()), Ok(change));
+    }
+    assert_eq!(
+        SessionChange::from_bytes(&[u8::MAX]),
+        Err(SessionDecodeError::Malformed)
+    );
+}
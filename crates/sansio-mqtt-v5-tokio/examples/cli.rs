@@ -32,7 +32,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .expect("Failed to resolve broker address");
     let subscription_filter = std::env::var("SUBSCRIPTION").unwrap_or(String::from("echo/#"));
     let topic = std::env::var("TOPIC").unwrap_or(String::from("echo"));
-    let subscription_filter = Utf8String::try_new(subscription_filter)?;
+    let subscription_filter = TopicFilter::try_new(subscription_filter)?;
     let topic = Topic::try_new(topic)?;
 
     tracing::info!(%broker_addr, "Connecting to address");
@@ -50,8 +50,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         tokio::select! {
             event = event_loop.poll() => {
                 match event? {
-                    Event::Connected => {
-                        tracing::info!("Connected to broker");
+                    Event::Connected(info) => {
+                        tracing::info!(session_present = info.session_present, "Connected to broker");
                         if !connected {
                             connected = true;
                             tracing::info!(%subscription_filter, "Subscribing to topic filter");
@@ -67,6 +67,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                                     extra_subscriptions: Vec::new(),
                                     subscription_identifier: None,
                                     user_properties: Vec::new(),
+                                    token: None,
                                 })
                                 .await?;
                         }
@@ -91,6 +92,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                         stdout.write_all(&message.payload).await?;
                         stdout.write_all(b"\n").await?;
                         stdout.flush().await?;
+                        client.ack(message_id).await?;
                     }
                     event => {
                         tracing::info!("Unhandled event received: {:?}", event);
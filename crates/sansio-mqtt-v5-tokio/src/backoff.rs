@@ -0,0 +1,82 @@
+//! Delays between reconnect attempts.
+
+use core::ops::RangeInclusive;
+use core::time::Duration;
+
+/// How long the event loop waits before each reconnect attempt.
+///
+/// Every delay stays within `range`; `range.end()` caps how long the event
+/// loop waits between attempts, not how many it makes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backoff {
+    pub algorithm: BackoffAlgorithm,
+    pub range: RangeInclusive<Duration>,
+    /// Seed for the random part of the jitter variants.
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackoffAlgorithm {
+    /// `range.start + slope * attempt`.
+    Linear { slope: Duration },
+    /// `range.start * factor^attempt`.
+    Exponential { factor: f64 },
+    /// Uniformly random in `range`, whatever the attempt.
+    Jitter,
+    /// The exponential delay plus a uniformly random amount up to that delay.
+    JitteredExponential { factor: f64 },
+}
+
+impl Backoff {
+    /// Waits `delay` before every attempt.
+    pub fn constant(delay: Duration) -> Self {
+        Self {
+            algorithm: BackoffAlgorithm::Linear {
+                slope: Duration::ZERO,
+            },
+            range: delay..=delay,
+            seed: 0,
+        }
+    }
+
+    /// The delay before attempt `attempt`, counting from 0.
+    ///
+    /// `random` is only used by the jitter variants; the event loop draws it
+    /// from an xorshift generator seeded with [`Self::seed`].
+    pub fn delay(&self, attempt: u32, random: u64) -> Duration {
+        let min = *self.range.start();
+        let max = (*self.range.end()).max(min);
+
+        let delay = match self.algorithm {
+            BackoffAlgorithm::Linear { slope } => min.saturating_add(slope.saturating_mul(attempt)),
+            BackoffAlgorithm::Exponential { factor } => exponential(min, max, factor, attempt),
+            BackoffAlgorithm::Jitter => min.saturating_add(uniform(max - min, random)),
+            BackoffAlgorithm::JitteredExponential { factor } => {
+                let delay = exponential(min, max, factor, attempt);
+                delay.saturating_add(uniform(delay, random))
+            }
+        };
+
+        delay.clamp(min, max)
+    }
+}
+
+/// `min * factor^attempt`, or `max` when that is not a valid duration.
+fn exponential(min: Duration, max: Duration, factor: f64, attempt: u32) -> Duration {
+    let exponent = i32::try_from(attempt).unwrap_or(i32::MAX);
+    Duration::try_from_secs_f64(min.as_secs_f64() * factor.powi(exponent)).unwrap_or(max)
+}
+
+/// A duration in `0..=span` picked by `random`.
+fn uniform(span: Duration, random: u64) -> Duration {
+    let nanos = u128::from(random) % span.as_nanos().saturating_add(1);
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+/// Advances an xorshift64 generator. `state` must not be 0.
+pub(crate) fn xorshift64(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
@@ -1,51 +1,238 @@
+use sansio_mqtt_v5_protocol::AuthPacket;
+use sansio_mqtt_v5_protocol::AuthenticationKind;
 use sansio_mqtt_v5_protocol::ClientMessage;
+use sansio_mqtt_v5_protocol::DisconnectOptions;
+use sansio_mqtt_v5_protocol::InboundMessageId;
+use sansio_mqtt_v5_protocol::IncomingRejectReason;
 use sansio_mqtt_v5_protocol::SubscribeOptions;
+use sansio_mqtt_v5_protocol::Subscription;
+use sansio_mqtt_v5_protocol::TopicFilter;
 use sansio_mqtt_v5_protocol::UnsubscribeOptions;
 use sansio_mqtt_v5_protocol::UserWriteIn;
+use sansio_mqtt_v5_types::SubAckReasonCode;
+use sansio_mqtt_v5_types::UnsubAckReasonCode;
 use tokio::sync::mpsc;
+use tokio::sync::oneshot;
 
 use crate::ClientError;
+use crate::PublishError;
+use crate::PublishOutcome;
+use crate::StreamOptions;
+use crate::SubscribeError;
+use crate::SubscriptionStream;
+use crate::UnsubscribeError;
+use crate::stream::StreamRequest;
+use crate::tracker::Reply;
+
+/// A write for the event loop, with where to send its outcome.
+#[derive(Debug)]
+pub(crate) struct Command {
+    pub(crate) write: UserWriteIn,
+    pub(crate) reply: Option<Reply>,
+}
 
 #[derive(Clone, Debug)]
 pub struct Client {
-    pub(crate) tx: mpsc::Sender<UserWriteIn>,
+    pub(crate) tx: mpsc::Sender<Command>,
 }
 
 impl Client {
-    pub(crate) fn new(tx: mpsc::Sender<UserWriteIn>) -> Self {
+    pub(crate) fn new(tx: mpsc::Sender<Command>) -> Self {
         Self { tx }
     }
 
+    /// A client whose writes are forwarded to `tx` instead of an event loop.
+    /// Nothing answers them, so the `*_and_wait` methods fail with
+    /// `Closed`. Must be called from within a tokio runtime.
     #[doc(hidden)]
     pub fn new_for_test(tx: mpsc::Sender<UserWriteIn>) -> Self {
-        Self { tx }
+        let (command_tx, mut command_rx) = mpsc::channel::<Command>(tx.max_capacity());
+        tokio::spawn(async move {
+            while let Some(Command { write, .. }) = command_rx.recv().await {
+                if tx.send(write).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Self::new(command_tx)
     }
 
-    pub async fn publish(&self, message: ClientMessage) -> Result<(), ClientError> {
+    async fn send(&self, write: UserWriteIn) -> Result<(), ClientError> {
         self.tx
-            .send(UserWriteIn::PublishMessage(message))
+            .send(Command { write, reply: None })
             .await
             .map_err(|_| ClientError::Closed)
     }
 
+    pub async fn publish(&self, message: ClientMessage) -> Result<(), ClientError> {
+        self.send(UserWriteIn::PublishMessage(message)).await
+    }
+
+    /// Publishes `message` and waits for the server to answer it: PUBACK
+    /// for QoS 1, PUBCOMP for QoS 2. A QoS 0 publish resolves once the
+    /// event loop has handed it to the connection.
+    ///
+    /// The event loop must be polled for this to resolve. The usual events
+    /// for the publish are still returned by
+    /// [`EventLoop::poll`](crate::EventLoop::poll).
+    pub async fn publish_and_wait(
+        &self,
+        message: ClientMessage,
+    ) -> Result<PublishOutcome, PublishError> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(Command {
+                write: UserWriteIn::PublishMessage(message),
+                reply: Some(Reply::Publish(tx)),
+            })
+            .await
+            .map_err(|_| PublishError::Closed)?;
+        rx.await.map_err(|_| PublishError::Closed)?
+    }
+
     pub async fn subscribe(&self, options: SubscribeOptions) -> Result<(), ClientError> {
+        self.send(UserWriteIn::Subscribe(options)).await
+    }
+
+    /// Subscribes and waits for SUBACK, returning its reason codes, one per
+    /// topic filter in request order. Fails if the server refused any of
+    /// them, or if the connection is lost first: the server does not answer
+    /// a SUBSCRIBE after a reconnect.
+    ///
+    /// The event loop must be polled for this to resolve.
+    pub async fn subscribe_and_wait(
+        &self,
+        options: SubscribeOptions,
+    ) -> Result<Vec<SubAckReasonCode>, SubscribeError> {
+        let (tx, rx) = oneshot::channel();
         self.tx
-            .send(UserWriteIn::Subscribe(options))
+            .send(Command {
+                write: UserWriteIn::Subscribe(options),
+                reply: Some(Reply::Subscribe(tx)),
+            })
             .await
-            .map_err(|_| ClientError::Closed)
+            .map_err(|_| SubscribeError::Closed)?;
+        rx.await.map_err(|_| SubscribeError::Closed)?
     }
 
-    pub async fn unsubscribe(&self, options: UnsubscribeOptions) -> Result<(), ClientError> {
+    /// Subscribes to `topic_filter` and, once the server granted it, returns
+    /// the messages it delivers as a stream; they are no longer returned by
+    /// [`EventLoop::poll`](crate::EventLoop::poll). Messages are matched by
+    /// the subscriptions that delivered them when the server reports them,
+    /// see
+    /// [`ClientSettings::auto_subscription_identifiers`](crate::ClientSettings::auto_subscription_identifiers),
+    /// and by topic otherwise.
+    ///
+    /// QoS 1 and QoS 2 messages are acknowledged once every stream they
+    /// matched has yielded them, even with
+    /// [`ConnectOptions::auto_ack`](crate::ConnectOptions::auto_ack): a slow
+    /// stream makes the server wait once the client's Receive Maximum of
+    /// them is unanswered. One that no stream has room for, e.g. because a
+    /// stream still holds messages from a session the server did not
+    /// resume, is returned by [`EventLoop::poll`](crate::EventLoop::poll)
+    /// instead.
+    ///
+    /// Fails as [`subscribe_and_wait`](Self::subscribe_and_wait) does. The
+    /// event loop must be polled for this to resolve and for the stream to
+    /// yield messages.
+    pub async fn subscribe_stream(
+        &self,
+        topic_filter: TopicFilter,
+        options: StreamOptions,
+    ) -> Result<SubscriptionStream, SubscribeError> {
+        let (tx, rx) = oneshot::channel();
+        let subscribe = SubscribeOptions {
+            subscription: Subscription {
+                topic_filter: topic_filter.clone(),
+                qos: options.qos,
+                no_local: options.no_local,
+                retain_as_published: options.retain_as_published,
+                retain_handling: options.retain_handling,
+            },
+            extra_subscriptions: Vec::new(),
+            subscription_identifier: None,
+            user_properties: options.user_properties,
+            token: None,
+        };
         self.tx
-            .send(UserWriteIn::Unsubscribe(options))
+            .send(Command {
+                write: UserWriteIn::Subscribe(subscribe),
+                reply: Some(Reply::Stream(StreamRequest {
+                    reply: tx,
+                    topic_filter,
+                    qos0_capacity: options.qos0_capacity,
+                    stream: None,
+                })),
+            })
             .await
-            .map_err(|_| ClientError::Closed)
+            .map_err(|_| SubscribeError::Closed)?;
+        rx.await.map_err(|_| SubscribeError::Closed)?
     }
 
-    pub async fn disconnect(&self) -> Result<(), ClientError> {
+    pub async fn unsubscribe(&self, options: UnsubscribeOptions) -> Result<(), ClientError> {
+        self.send(UserWriteIn::Unsubscribe(options)).await
+    }
+
+    /// Unsubscribes and waits for UNSUBACK, as
+    /// [`subscribe_and_wait`](Self::subscribe_and_wait) does for SUBACK.
+    /// `NoSubscriptionExisted` is not a refusal.
+    pub async fn unsubscribe_and_wait(
+        &self,
+        options: UnsubscribeOptions,
+    ) -> Result<Vec<UnsubAckReasonCode>, UnsubscribeError> {
+        let (tx, rx) = oneshot::channel();
         self.tx
-            .send(UserWriteIn::Disconnect)
+            .send(Command {
+                write: UserWriteIn::Unsubscribe(options),
+                reply: Some(Reply::Unsubscribe(tx)),
+            })
             .await
-            .map_err(|_| ClientError::Closed)
+            .map_err(|_| UnsubscribeError::Closed)?;
+        rx.await.map_err(|_| UnsubscribeError::Closed)?
+    }
+
+    /// Acknowledges a message returned with
+    /// [`Event::MessageWithRequiredAcknowledgement`](crate::Event::MessageWithRequiredAcknowledgement),
+    /// with PUBACK for QoS 1 or PUBREC for QoS 2.
+    pub async fn ack(&self, id: InboundMessageId) -> Result<(), ClientError> {
+        self.send(UserWriteIn::AcknowledgeMessage(id)).await
+    }
+
+    /// Refuses a message returned with
+    /// [`Event::MessageWithRequiredAcknowledgement`](crate::Event::MessageWithRequiredAcknowledgement),
+    /// answering with the Reason Code for `reason`. The server does not
+    /// resend it.
+    pub async fn reject(
+        &self,
+        id: InboundMessageId,
+        reason: IncomingRejectReason,
+    ) -> Result<(), ClientError> {
+        self.send(UserWriteIn::RejectMessage(id, reason)).await
+    }
+
+    /// Answers an [`Event::Auth`](crate::Event::Auth) challenge, or starts a
+    /// re-authentication once connected.
+    pub async fn auth(&self, auth: AuthPacket) -> Result<(), ClientError> {
+        self.send(UserWriteIn::Auth(auth)).await
+    }
+
+    /// Re-authenticates with fresh credentials, typically in answer to
+    /// [`Event::ReauthenticationRequired`](crate::Event::ReauthenticationRequired).
+    pub async fn reauthenticate(
+        &self,
+        authentication: AuthenticationKind,
+    ) -> Result<(), ClientError> {
+        self.send(UserWriteIn::Reauthenticate(authentication)).await
+    }
+
+    pub async fn disconnect(&self) -> Result<(), ClientError> {
+        self.disconnect_with(DisconnectOptions::default()).await
+    }
+
+    /// Disconnects with a specific reason code, properties or Session Expiry
+    /// Interval, e.g. `DisconnectWithWillMessage` during a controlled
+    /// failover.
+    pub async fn disconnect_with(&self, options: DisconnectOptions) -> Result<(), ClientError> {
+        self.send(UserWriteIn::Disconnect(options)).await
     }
 }
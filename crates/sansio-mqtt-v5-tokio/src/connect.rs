@@ -9,6 +9,7 @@ use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 
+use crate::Backoff;
 use crate::Client;
 use crate::ConnectError;
 use crate::EventLoop;
@@ -19,6 +20,31 @@ pub struct ConnectOptions {
     pub connection: ConnectionOptions,
     pub protocol_config: ClientSettings,
     pub command_channel_capacity: usize,
+    /// How many server redirects (`UseAnotherServer` / `ServerMoved`) the
+    /// event loop follows in a row before giving up with
+    /// [`EventLoopError::TooManyRedirects`](crate::EventLoopError::TooManyRedirects).
+    /// The count resets once a connection is accepted.
+    pub max_redirects: usize,
+    /// Reconnects to `addr` after the connection is lost, waiting as this
+    /// says before each attempt, until one is accepted. The same session is
+    /// used, so unacknowledged QoS 1 and QoS 2 publishes are resent when the
+    /// server resumes it. Commands sent in the meantime wait for the new
+    /// connection; publishes wait in the protocol's pending queue instead
+    /// with
+    /// [`ClientSettings::queue_publishes_while_offline`](sansio_mqtt_v5_protocol::ClientSettings::queue_publishes_while_offline).
+    ///
+    /// With `None` the event loop stops once the connection is closed. It
+    /// never reconnects after [`Client::disconnect`].
+    pub backoff: Option<Backoff>,
+    /// Acknowledge QoS 1 and QoS 2 messages as soon as they are received,
+    /// and return them as [`Event::Message`](crate::Event::Message).
+    ///
+    /// Without it they are returned as
+    /// [`Event::MessageWithRequiredAcknowledgement`](crate::Event::MessageWithRequiredAcknowledgement)
+    /// and the server sends no more of them once its Receive Maximum of
+    /// messages is left unanswered; see [`Client::ack`] and
+    /// [`Client::reject`].
+    pub auto_ack: bool,
 }
 
 impl Default for ConnectOptions {
@@ -28,6 +54,9 @@ impl Default for ConnectOptions {
             connection: ConnectionOptions::default(),
             protocol_config: ClientSettings::default(),
             command_channel_capacity: 16,
+            max_redirects: 3,
+            backoff: None,
+            auto_ack: false,
         }
     }
 }
@@ -35,9 +64,9 @@ impl Default for ConnectOptions {
 pub async fn connect(options: ConnectOptions) -> Result<(Client, EventLoop), ConnectError> {
     let mut stream = TcpStream::connect(options.addr).await?;
     let mut protocol =
-        ProtocolClient::<tokio::time::Instant>::with_settings(options.protocol_config);
+        ProtocolClient::<tokio::time::Instant>::with_settings(options.protocol_config.clone());
 
-    protocol.handle_write(UserWriteIn::Connect(options.connection))?;
+    protocol.handle_write(UserWriteIn::Connect(options.connection.clone()))?;
 
     while let Some(action) = protocol.poll_event() {
         if !matches!(action, DriverEventOut::OpenSocket) {
@@ -53,7 +82,7 @@ pub async fn connect(options: ConnectOptions) -> Result<(Client, EventLoop), Con
 
     let (tx, rx) = mpsc::channel(options.command_channel_capacity.max(1));
     let client = Client::new(tx);
-    let event_loop = EventLoop::new(stream, protocol, rx);
+    let event_loop = EventLoop::new(stream, protocol, rx, &options);
 
     Ok((client, event_loop))
 }
@@ -0,0 +1,97 @@
+use core::future::Future;
+use core::pin::Pin;
+
+use sansio_mqtt_v5_protocol::BrokerMessage;
+use sansio_mqtt_v5_protocol::HandlerId;
+use sansio_mqtt_v5_protocol::Route;
+use sansio_mqtt_v5_protocol::Router;
+
+use crate::Event;
+
+type HandlerFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+type Handler = Box<dyn FnMut(BrokerMessage) -> HandlerFuture + Send>;
+
+/// Routes received messages to async handlers with a [`Router`].
+///
+/// Handlers run one after the other, in the order they were added, and each
+/// is awaited before the next one starts, so every handler sees messages in
+/// the order the broker sent them.
+#[derive(Default)]
+pub struct Dispatcher {
+    router: Router<Handler>,
+}
+
+impl Dispatcher {
+    /// A dispatcher without handlers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `handler`, reachable through `route`.
+    pub fn route<F, Fut>(&mut self, route: impl Into<Route>, mut handler: F) -> HandlerId
+    where
+        F: FnMut(BrokerMessage) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.router.add(
+            route,
+            Box::new(move |message| Box::pin(handler(message)) as HandlerFuture),
+        )
+    }
+
+    /// Makes the handler `id` also reachable through `route`; see
+    /// [`Router::add_route`].
+    pub fn add_route(&mut self, id: HandlerId, route: impl Into<Route>) -> bool {
+        self.router.add_route(id, route)
+    }
+
+    /// Stops routing `route` to the handler `id`; see
+    /// [`Router::remove_route`].
+    pub fn remove_route(&mut self, id: HandlerId, route: &Route) -> bool {
+        self.router.remove_route(id, route)
+    }
+
+    /// Removes the handler `id`. Returns `false` if it was already removed.
+    pub fn remove(&mut self, id: HandlerId) -> bool {
+        self.router.remove(id).is_some()
+    }
+
+    /// Runs every handler matching `message` once and returns how many ran.
+    pub async fn dispatch(&mut self, message: &BrokerMessage) -> usize {
+        let matched = self.router.matching(message);
+        for id in &matched {
+            if let Some(handler) = self.router.get_mut(*id) {
+                handler(message.clone()).await;
+            }
+        }
+        matched.len()
+    }
+
+    /// Dispatches an [`Event::Message`] and returns every other event, or a
+    /// message no handler matched, to the caller.
+    ///
+    /// [`Event::MessageWithRequiredAcknowledgement`] is returned as well, since
+    /// its acknowledgement is up to the caller; with
+    /// [`ConnectOptions::auto_ack`](crate::ConnectOptions::auto_ack) those
+    /// messages arrive as [`Event::Message`] instead.
+    pub async fn handle_event(&mut self, event: Event) -> Option<Event> {
+        match event {
+            Event::Message(message) => {
+                if self.dispatch(&message).await == 0 {
+                    Some(Event::Message(message))
+                } else {
+                    None
+                }
+            }
+            event => Some(event),
+        }
+    }
+}
+
+impl core::fmt::Debug for Dispatcher {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Dispatcher")
+            .field("handlers", &self.router.len())
+            .finish()
+    }
+}
@@ -1,8 +1,76 @@
+use sansio_mqtt_v5_protocol::Utf8String;
+use sansio_mqtt_v5_types::PubRecReasonCode;
+use sansio_mqtt_v5_types::SubAckReasonCode;
+use sansio_mqtt_v5_types::UnsubAckReasonCode;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ClientError {
     Closed,
 }
 
+/// Why a publish sent with
+/// [`Client::publish_and_wait`](crate::Client::publish_and_wait) has no
+/// outcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublishError {
+    /// The event loop is gone.
+    Closed,
+    /// The connection was closed for good before the server answered.
+    Disconnected,
+    /// The server did not resume the session the publish was sent in.
+    SessionNotResumed,
+    /// The server rejected the QoS 2 publish in PUBREC.
+    Rejected(PubRecReasonCode),
+    /// The publish was discarded while waiting to be sent; see
+    /// [`Event::PendingPublishDropped`](crate::Event::PendingPublishDropped).
+    Dropped,
+    /// The publish outlived its Message Expiry Interval before it could be
+    /// resent.
+    Expired,
+    /// The protocol refused the publish before sending it, e.g. because it
+    /// breaks a limit the server set.
+    Protocol(sansio_mqtt_v5_protocol::Error),
+}
+
+/// Why a subscribe sent with
+/// [`Client::subscribe_and_wait`](crate::Client::subscribe_and_wait) did
+/// not succeed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscribeError {
+    /// The event loop is gone.
+    Closed,
+    /// The connection was lost before the server answered.
+    Disconnected,
+    /// The server refused at least one topic filter; `reason_codes` has one
+    /// entry per topic filter, in request order.
+    Refused {
+        reason_codes: Vec<SubAckReasonCode>,
+        reason_string: Option<Utf8String>,
+    },
+    /// The protocol refused the subscribe before sending it, e.g. because
+    /// the server does not support what it asks for.
+    Protocol(sansio_mqtt_v5_protocol::Error),
+}
+
+/// Why an unsubscribe sent with
+/// [`Client::unsubscribe_and_wait`](crate::Client::unsubscribe_and_wait)
+/// did not succeed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnsubscribeError {
+    /// The event loop is gone.
+    Closed,
+    /// The connection was lost before the server answered.
+    Disconnected,
+    /// The server refused at least one topic filter; `reason_codes` has one
+    /// entry per topic filter, in request order.
+    Refused {
+        reason_codes: Vec<UnsubAckReasonCode>,
+        reason_string: Option<Utf8String>,
+    },
+    /// The protocol refused the unsubscribe before sending it.
+    Protocol(sansio_mqtt_v5_protocol::Error),
+}
+
 #[derive(Debug)]
 pub enum ConnectError {
     Io(std::io::Error),
@@ -16,6 +84,15 @@ pub enum EventLoopError {
     Protocol(sansio_mqtt_v5_protocol::Error),
     UnexpectedDriverAction(sansio_mqtt_v5_protocol::DriverEventOut),
     ProtocolRequestedQuit,
+    /// The servers redirected the client more than
+    /// [`ConnectOptions::max_redirects`](crate::ConnectOptions::max_redirects)
+    /// times in a row.
+    TooManyRedirects,
+    /// The connection was closed and will not be reopened: the client
+    /// disconnected, or no
+    /// [`ConnectOptions::backoff`](crate::ConnectOptions::backoff)
+    /// is configured.
+    Disconnected,
 }
 
 impl core::fmt::Display for ClientError {
@@ -26,6 +103,48 @@ impl core::fmt::Display for ClientError {
     }
 }
 
+impl core::fmt::Display for PublishError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Closed => f.write_str("event loop is closed"),
+            Self::Disconnected => f.write_str("connection closed before the publish was answered"),
+            Self::SessionNotResumed => f.write_str("publish dropped: session not resumed"),
+            Self::Rejected(reason_code) => {
+                write!(f, "publish rejected by the server: {reason_code}")
+            }
+            Self::Dropped => f.write_str("publish dropped before it was sent"),
+            Self::Expired => f.write_str("publish expired before it was resent"),
+            Self::Protocol(err) => write!(f, "publish refused: {err}"),
+        }
+    }
+}
+
+impl core::fmt::Display for SubscribeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Closed => f.write_str("event loop is closed"),
+            Self::Disconnected => f.write_str("connection lost before SUBACK"),
+            Self::Refused { reason_codes, .. } => {
+                write!(f, "subscription refused by the server: {reason_codes:?}")
+            }
+            Self::Protocol(err) => write!(f, "subscribe refused: {err}"),
+        }
+    }
+}
+
+impl core::fmt::Display for UnsubscribeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Closed => f.write_str("event loop is closed"),
+            Self::Disconnected => f.write_str("connection lost before UNSUBACK"),
+            Self::Refused { reason_codes, .. } => {
+                write!(f, "unsubscribe refused by the server: {reason_codes:?}")
+            }
+            Self::Protocol(err) => write!(f, "unsubscribe refused: {err}"),
+        }
+    }
+}
+
 impl core::fmt::Display for ConnectError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
@@ -53,11 +172,16 @@ impl core::fmt::Display for EventLoopError {
                 )
             }
             Self::ProtocolRequestedQuit => f.write_str("protocol requested quit while running"),
+            Self::TooManyRedirects => f.write_str("too many server redirects"),
+            Self::Disconnected => f.write_str("connection closed"),
         }
     }
 }
 
 impl std::error::Error for ClientError {}
+impl std::error::Error for PublishError {}
+impl std::error::Error for SubscribeError {}
+impl std::error::Error for UnsubscribeError {}
 impl std::error::Error for ConnectError {}
 impl std::error::Error for EventLoopError {}
 
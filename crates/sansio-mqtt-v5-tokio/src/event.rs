@@ -1,37 +1,112 @@
 use core::num::NonZero;
+use core::time::Duration;
 
 use sansio_mqtt_v5_protocol::AuthPacket;
 use sansio_mqtt_v5_protocol::BrokerMessage;
+use sansio_mqtt_v5_protocol::ClientMessage;
+use sansio_mqtt_v5_protocol::ConnackReasonCode;
+use sansio_mqtt_v5_protocol::ConnectionInfo;
 use sansio_mqtt_v5_protocol::DisconnectReasonCode;
 use sansio_mqtt_v5_protocol::InboundMessageId;
+use sansio_mqtt_v5_protocol::Subscription;
+use sansio_mqtt_v5_protocol::UserToken;
 use sansio_mqtt_v5_protocol::UserWriteOut;
+use sansio_mqtt_v5_protocol::Utf8String;
 use sansio_mqtt_v5_types::PubAckReasonCode;
 use sansio_mqtt_v5_types::PubCompReasonCode;
 use sansio_mqtt_v5_types::PubRecReasonCode;
+use sansio_mqtt_v5_types::SubAckReasonCode;
+use sansio_mqtt_v5_types::UnsubAckReasonCode;
 
 #[derive(Debug)]
 pub enum Event {
-    Connected,
+    /// The broker accepted the connection; see [`ConnectionInfo`] for what it
+    /// reported in CONNACK.
+    Connected(ConnectionInfo),
+    /// The broker refused the connection with a CONNACK failure reason code.
+    ConnectionRefused {
+        reason_code: ConnackReasonCode,
+        reason_string: Option<Utf8String>,
+        server_reference: Option<Utf8String>,
+    },
     /// The connection has been closed.
     ///
     /// `reason_code` is `Some` when the server initiated the DISCONNECT with a
     /// reason code, and `None` when the client disconnected or the socket
     /// was closed without a server DISCONNECT packet.
     Disconnected(Option<DisconnectReasonCode>),
+    /// A publish, subscribe or unsubscribe carrying `token` was sent with
+    /// `packet_id`; later events for that packet id belong to it.
+    PacketIdAssigned {
+        token: UserToken,
+        packet_id: NonZero<u16>,
+    },
     Message(BrokerMessage),
     MessageWithRequiredAcknowledgement(InboundMessageId, BrokerMessage),
     PublishAcknowledged(NonZero<u16>, PubAckReasonCode),
     PublishCompleted(NonZero<u16>, PubCompReasonCode),
     PublishDroppedDueToSessionNotResumed(NonZero<u16>),
     PublishDroppedDueToBrokerRejectedPubRec(NonZero<u16>, PubRecReasonCode),
-    /// [MQTT-4.12.0-2] The server has initiated re-authentication via an AUTH
-    /// packet.
+    /// A publish waiting for a Receive Maximum slot was discarded before it
+    /// was sent; see
+    /// [`ClientSettings::max_pending_publishes`](sansio_mqtt_v5_protocol::ClientSettings::max_pending_publishes).
+    PendingPublishDropped(ClientMessage),
+    /// An unacknowledged publish outlived its Message Expiry Interval before
+    /// it could be resent after a reconnect.
+    PublishExpired(NonZero<u16>),
+    /// The broker acknowledged a subscribe request; `reason_codes` has one
+    /// entry per topic filter, in request order.
+    SubscribeAcknowledged {
+        packet_id: NonZero<u16>,
+        reason_codes: Vec<SubAckReasonCode>,
+        reason_string: Option<Utf8String>,
+        user_properties: Vec<(Utf8String, Utf8String)>,
+    },
+    /// Subscriptions of the previous session could not be sent again after
+    /// reconnecting to a new one, and were forgotten; see
+    /// [`ClientSettings::resubscribe_on_new_session`](sansio_mqtt_v5_protocol::ClientSettings::resubscribe_on_new_session).
+    ResubscribeFailed {
+        subscriptions: Vec<Subscription>,
+    },
+    /// The broker acknowledged an unsubscribe request; `reason_codes` has one
+    /// entry per topic filter, in request order.
+    UnsubscribeAcknowledged {
+        packet_id: NonZero<u16>,
+        reason_codes: Vec<UnsubAckReasonCode>,
+        reason_string: Option<Utf8String>,
+        user_properties: Vec<(Utf8String, Utf8String)>,
+    },
+    /// [MQTT-4.12.0-2] The server sent an AUTH packet, either as a challenge
+    /// while connecting or for re-authentication. Answer it with
+    /// [`Client::auth`](crate::Client::auth).
     Auth(AuthPacket),
+    /// The configured credential lifetime has elapsed; send fresh
+    /// credentials with
+    /// [`Client::reauthenticate`](crate::Client::reauthenticate).
+    ReauthenticationRequired,
+    /// The connection was lost and reconnect attempt `attempt`, counting
+    /// from 0 since the last accepted connection, starts in `delay`; see
+    /// [`ConnectOptions::backoff`](crate::ConnectOptions::backoff).
+    ///
+    /// An accepted attempt ends with [`Event::Connected`].
+    ReconnectScheduled {
+        attempt: u32,
+        delay: Duration,
+    },
+    /// Reconnect attempt `attempt` could not open a socket. Another attempt
+    /// is scheduled.
+    ReconnectFailed {
+        attempt: u32,
+        error: std::io::Error,
+    },
 }
 
 impl Event {
     pub fn from_protocol_output(output: UserWriteOut) -> Self {
         match output {
+            UserWriteOut::PacketIdAssigned { token, packet_id } => {
+                Self::PacketIdAssigned { token, packet_id }
+            }
             UserWriteOut::ReceivedMessage(message) => Self::Message(message),
             UserWriteOut::ReceivedMessageWithRequiredAcknowledgement(id, message) => {
                 Self::MessageWithRequiredAcknowledgement(id, message)
@@ -48,9 +123,46 @@ impl Event {
             UserWriteOut::PublishDroppedDueToBrokerRejectedPubRec(packet_id, reason_code) => {
                 Self::PublishDroppedDueToBrokerRejectedPubRec(packet_id, reason_code)
             }
-            UserWriteOut::Connected => Self::Connected,
+            UserWriteOut::PendingPublishDropped(message) => Self::PendingPublishDropped(message),
+            UserWriteOut::PublishExpired(packet_id) => Self::PublishExpired(packet_id),
+            UserWriteOut::SubscribeAcknowledged {
+                packet_id,
+                reason_codes,
+                reason_string,
+                user_properties,
+            } => Self::SubscribeAcknowledged {
+                packet_id,
+                reason_codes,
+                reason_string,
+                user_properties,
+            },
+            UserWriteOut::ResubscribeFailed { subscriptions } => {
+                Self::ResubscribeFailed { subscriptions }
+            }
+            UserWriteOut::UnsubscribeAcknowledged {
+                packet_id,
+                reason_codes,
+                reason_string,
+                user_properties,
+            } => Self::UnsubscribeAcknowledged {
+                packet_id,
+                reason_codes,
+                reason_string,
+                user_properties,
+            },
+            UserWriteOut::Connected(info) => Self::Connected(info),
+            UserWriteOut::ConnectionRefused {
+                reason_code,
+                reason_string,
+                server_reference,
+            } => Self::ConnectionRefused {
+                reason_code,
+                reason_string,
+                server_reference,
+            },
             UserWriteOut::Disconnected(reason_code) => Self::Disconnected(reason_code),
             UserWriteOut::Auth(auth) => Self::Auth(auth),
+            UserWriteOut::ReauthenticationRequired => Self::ReauthenticationRequired,
         }
     }
 }
@@ -1,97 +1,462 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
 use sansio::Protocol;
 use sansio_mqtt_v5_protocol::Client as ProtocolClient;
 use sansio_mqtt_v5_protocol::DriverEventIn;
 use sansio_mqtt_v5_protocol::DriverEventOut;
 use sansio_mqtt_v5_protocol::IncomingData;
+use sansio_mqtt_v5_protocol::UnsubscribeOptions;
 use sansio_mqtt_v5_protocol::UserWriteIn;
+use sansio_mqtt_v5_protocol::UserWriteOut;
+use sansio_mqtt_v5_protocol::Utf8String;
 use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
+use tokio::time::Instant;
 
+use crate::Backoff;
+use crate::ConnectOptions;
 use crate::Event;
 use crate::EventLoopError;
+use crate::backoff::xorshift64;
+use crate::client::Command;
+use crate::stream::Signal;
+use crate::stream::Streams;
+use crate::tracker::Reply;
+use crate::tracker::Tracker;
+
+#[derive(Debug)]
+enum SocketState {
+    Active(TcpStream),
+    /// Waiting to make reconnect attempt `attempt` at `wake_at`.
+    Offline {
+        attempt: u32,
+        wake_at: Instant,
+    },
+    /// Closed for good.
+    Closed,
+}
+
+/// What woke up an active event loop.
+#[allow(clippy::large_enum_variant)]
+enum Wake {
+    Read(std::io::Result<usize>),
+    Command(Option<Command>),
+    Signal(Signal),
+    Timeout,
+}
 
 #[derive(Debug)]
 pub struct EventLoop {
-    stream: TcpStream,
-    protocol: ProtocolClient<tokio::time::Instant>,
-    command_rx: mpsc::Receiver<UserWriteIn>,
+    socket: SocketState,
+    protocol: ProtocolClient<Instant>,
+    command_rx: mpsc::Receiver<Command>,
+    tracker: Tracker,
+    streams: Streams,
     read_buffer: [u8; 4096],
+    addr: SocketAddr,
+    /// The server the client was last redirected to, which reconnects go to
+    /// instead of `addr`.
+    server_reference: Option<Utf8String>,
+    max_redirects: usize,
+    redirects: usize,
+    backoff: Option<Backoff>,
+    /// xorshift64 state for the jitter of `backoff`.
+    rng: u64,
+    /// Reconnect attempts made since the last accepted connection.
+    reconnect_attempts: u32,
+    disconnect_requested: bool,
+    /// Whether the protocol is connected, and so can answer messages.
+    connected: bool,
+    /// Commands the protocol cannot take before it is connected, in the
+    /// order they were sent.
+    held: VecDeque<Command>,
+    /// Whether publishes go to the protocol while it is not connected, to
+    /// wait in its pending queue.
+    queue_publishes_while_offline: bool,
+    auto_ack: bool,
+    /// Events of the event loop itself, returned after the protocol's.
+    events: VecDeque<Event>,
 }
 
 impl EventLoop {
     pub(crate) fn new(
         stream: TcpStream,
-        protocol: ProtocolClient<tokio::time::Instant>,
-        command_rx: mpsc::Receiver<UserWriteIn>,
+        protocol: ProtocolClient<Instant>,
+        command_rx: mpsc::Receiver<Command>,
+        options: &ConnectOptions,
     ) -> Self {
+        // As sent in CONNECT.
+        let receive_maximum = [
+            options.connection.receive_maximum,
+            options.protocol_config.max_incoming_receive_maximum,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .map_or(u16::MAX, |receive_maximum| receive_maximum.get());
+        // xorshift never leaves 0.
+        let rng = options
+            .backoff
+            .as_ref()
+            .map_or(1, |backoff| backoff.seed.max(1));
         Self {
-            stream,
+            socket: SocketState::Active(stream),
             protocol,
             command_rx,
+            tracker: Tracker::default(),
+            streams: Streams::new(receive_maximum.into()),
             read_buffer: [0; 4096],
+            addr: options.addr,
+            server_reference: None,
+            max_redirects: options.max_redirects,
+            redirects: 0,
+            backoff: options.backoff.clone(),
+            rng,
+            reconnect_attempts: 0,
+            disconnect_requested: false,
+            connected: false,
+            held: VecDeque::new(),
+            queue_publishes_while_offline: options.protocol_config.queue_publishes_while_offline,
+            auto_ack: options.auto_ack,
+            events: VecDeque::new(),
         }
     }
 
-    pub async fn poll(&mut self) -> Result<Event, EventLoopError> {
+    fn next_event(&mut self) -> Option<Event> {
         loop {
-            if let Some(out) = self.protocol.poll_read() {
-                return Ok(Event::from_protocol_output(out));
+            let Some(out) = self.protocol.poll_read() else {
+                return self.events.pop_front();
+            };
+            match &out {
+                UserWriteOut::Connected(info) => {
+                    self.redirects = 0;
+                    self.reconnect_attempts = 0;
+                    self.connected = true;
+                    if !info.session_present {
+                        self.streams.session_not_resumed();
+                    }
+                }
+                UserWriteOut::Disconnected(_) => self.connected = false,
+                _ => {}
+            }
+            let Some(out) = self.streams.deliver(out) else {
+                continue;
+            };
+            let out = match out {
+                UserWriteOut::ReceivedMessageWithRequiredAcknowledgement(id, message)
+                    if self.auto_ack =>
+                {
+                    // Only fails when the connection closed right after the
+                    // message arrived.
+                    if let Err(error) = self
+                        .protocol
+                        .handle_write(UserWriteIn::AcknowledgeMessage(id))
+                    {
+                        tracing::warn!(%error, "could not acknowledge message");
+                    }
+                    UserWriteOut::ReceivedMessage(message)
+                }
+                out => out,
+            };
+            if let Some(out) = self.tracker.resolve(out) {
+                return Some(Event::from_protocol_output(out));
+            }
+        }
+    }
+
+    /// Called once the protocol knows the socket is closed: schedules a
+    /// reconnect, or stops for good.
+    fn socket_closed(&mut self) {
+        self.socket = SocketState::Closed;
+        self.connected = false;
+        self.tracker.connection_lost();
+        if !self.disconnect_requested && self.backoff.is_some() {
+            self.schedule_reconnect(self.reconnect_attempts);
+        } else {
+            self.stop();
+        }
+    }
+
+    /// Fails every reply still waiting, once the connection is closed for
+    /// good.
+    fn stop(&mut self) {
+        self.tracker.disconnected();
+        for command in self.held.drain(..) {
+            if let Some(reply) = command.reply {
+                reply.disconnected();
+            }
+        }
+    }
+
+    fn schedule_reconnect(&mut self, attempt: u32) {
+        let Some(backoff) = &self.backoff else {
+            return;
+        };
+        let delay = backoff.delay(attempt, xorshift64(&mut self.rng));
+        self.socket = SocketState::Offline {
+            attempt,
+            wake_at: Instant::now() + delay,
+        };
+        self.events
+            .push_back(Event::ReconnectScheduled { attempt, delay });
+    }
+
+    /// Handles an I/O error on the active socket, which is only fatal
+    /// without a backoff.
+    fn connection_lost(&mut self, error: std::io::Error) -> Result<(), EventLoopError> {
+        if self.backoff.is_none() {
+            _ = self.protocol.handle_event(DriverEventIn::SocketError);
+            self.socket = SocketState::Closed;
+            self.connected = false;
+            self.stop();
+            return Err(error.into());
+        }
+        tracing::debug!(%error, "connection lost");
+        self.protocol.handle_event(DriverEventIn::SocketClosed)?;
+        self.socket_closed();
+        Ok(())
+    }
+
+    /// Follows a redirect to `server_reference` once the protocol closed the
+    /// previous socket.
+    async fn redirect(&mut self, server_reference: Utf8String) -> Result<(), EventLoopError> {
+        self.socket = SocketState::Closed;
+        self.connected = false;
+        self.tracker.connection_lost();
+        self.redirects += 1;
+        if self.redirects > self.max_redirects {
+            self.stop();
+            return Err(EventLoopError::TooManyRedirects);
+        }
+        self.server_reference = Some(server_reference);
+        match open_socket(self.addr, self.server_reference.clone()).await {
+            Ok(stream) => {
+                self.socket = SocketState::Active(stream);
+                self.protocol.handle_event(DriverEventIn::SocketConnected)?;
+            }
+            // Following the redirect counts as the first reconnect attempt.
+            Err(error) if !self.disconnect_requested && self.backoff.is_some() => {
+                let attempt = self.reconnect_attempts;
+                self.events
+                    .push_back(Event::ReconnectFailed { attempt, error });
+                self.schedule_reconnect(attempt.saturating_add(1));
+            }
+            Err(error) => {
+                self.stop();
+                return Err(error.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes reconnect attempt `attempt`.
+    ///
+    /// Cancelling this leaves the attempt scheduled, to be made right away on
+    /// the next poll.
+    async fn reconnect(&mut self, attempt: u32) -> Result<(), EventLoopError> {
+        match open_socket(self.addr, self.server_reference.clone()).await {
+            Ok(stream) => {
+                self.reconnect_attempts = attempt.saturating_add(1);
+                self.socket = SocketState::Active(stream);
+                self.protocol.handle_event(DriverEventIn::SocketConnected)?;
             }
+            Err(error) => {
+                self.events
+                    .push_back(Event::ReconnectFailed { attempt, error });
+                self.schedule_reconnect(attempt.saturating_add(1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers a stream. Failing to only loses that answer, so it does not
+    /// stop the event loop.
+    fn handle_signal(&mut self, signal: Signal) {
+        let write = match signal {
+            Signal::Ack { id, session } if self.streams.is_current(session) => {
+                UserWriteIn::AcknowledgeMessage(id)
+            }
+            Signal::Ack { .. } => return,
+            Signal::Closed { id, unsubscribe } => match self.streams.close(id) {
+                Some(filter) if unsubscribe => UserWriteIn::Unsubscribe(UnsubscribeOptions {
+                    filter,
+                    extra_filters: Vec::new(),
+                    user_properties: Vec::new(),
+                    token: None,
+                }),
+                _ => return,
+            },
+        };
+        if let Err(error) = self.protocol.handle_write(write) {
+            tracing::warn!(%error, "could not answer a subscription stream");
+        }
+    }
+
+    /// Hands a command to the protocol, or holds it until the protocol is
+    /// connected when it could not take it yet.
+    ///
+    /// A write the protocol refuses fails its reply without stopping the
+    /// event loop; the protocol closes the connection itself when it must.
+    fn handle_command(&mut self, command: Command) {
+        let Command { write, mut reply } = command;
+        let waits_for_connection = match &write {
+            // Disconnecting and answering an AUTH challenge during CONNECT
+            // cannot wait.
+            UserWriteIn::Disconnect(_) | UserWriteIn::Auth(_) => false,
+            UserWriteIn::PublishMessage(_) => !self.queue_publishes_while_offline,
+            _ => true,
+        };
+        if !self.connected && waits_for_connection {
+            self.held.push_back(Command { write, reply });
+            return;
+        }
+        if matches!(write, UserWriteIn::Disconnect(_)) {
+            self.disconnect_requested = true;
+            // There is no connection to close, so stop reconnecting instead.
+            if !matches!(self.socket, SocketState::Active(_)) {
+                self.socket = SocketState::Closed;
+                self.stop();
+                return;
+            }
+        }
+        if let Some(Reply::Stream(request)) = &mut reply {
+            self.streams.open(request);
+        }
+        let (write, reply) = self.tracker.track(write, reply);
+        let token = match &write {
+            UserWriteIn::PublishMessage(message) => message.token,
+            UserWriteIn::Subscribe(options) => options.token,
+            UserWriteIn::Unsubscribe(options) => options.token,
+            _ => None,
+        };
+        if let Err(error) = self.protocol.handle_write_at(write, Instant::now()) {
+            tracing::warn!(%error, "the protocol refused a write");
+            if let Some(reply) = reply.or_else(|| self.tracker.refused(token)) {
+                reply.failed(error);
+            }
+            return;
+        }
+        if let Some(reply) = reply {
+            reply.sent();
+        }
+    }
+
+    pub async fn poll(&mut self) -> Result<Event, EventLoopError> {
+        'poll: loop {
+            if let Some(event) = self.next_event() {
+                return Ok(event);
+            }
+            while self.connected
+                && let Some(command) = self.held.pop_front()
+            {
+                self.handle_command(command);
+            }
+
+            let stream = match &mut self.socket {
+                SocketState::Active(stream) => stream,
+                SocketState::Offline { attempt, wake_at } => {
+                    let (attempt, wake_at) = (*attempt, *wake_at);
+                    // Commands still reach the protocol, so publishes can
+                    // wait in its pending queue.
+                    tokio::select! {
+                        () = tokio::time::sleep_until(wake_at) => self.reconnect(attempt).await?,
+                        Some(command) = self.command_rx.recv() => self.handle_command(command),
+                    }
+                    continue 'poll;
+                }
+                SocketState::Closed => return Err(EventLoopError::Disconnected),
+            };
 
             while let Some(frame) = self.protocol.poll_write() {
-                self.stream.write_all(&frame).await?;
+                if let Err(error) = stream.write_all(&frame).await {
+                    self.connection_lost(error)?;
+                    continue 'poll;
+                }
             }
 
-            while let Some(action) = self.protocol.poll_event() {
+            let mut next_action = None;
+            while let Some(action) = next_action.take().or_else(|| self.protocol.poll_event()) {
                 match action {
                     DriverEventOut::CloseSocket => {
-                        self.stream.shutdown().await?;
+                        let shutdown = match &mut self.socket {
+                            SocketState::Active(stream) => stream.shutdown().await,
+                            _ => Ok(()),
+                        };
                         self.protocol.handle_event(DriverEventIn::SocketClosed)?;
+                        // A redirect replaces the socket, so nothing is
+                        // scheduled for it.
+                        next_action = self.protocol.poll_event();
+                        if matches!(next_action, Some(DriverEventOut::OpenSocketTo(_))) {
+                            continue;
+                        }
+                        self.socket_closed();
+                        if self.backoff.is_none() {
+                            shutdown?;
+                        }
                     }
                     DriverEventOut::Quit => {
                         return Err(EventLoopError::ProtocolRequestedQuit);
                     }
+                    DriverEventOut::OpenSocketTo(server_reference) => {
+                        self.redirect(server_reference).await?;
+                        // Flush the CONNECT for the new socket before anything else.
+                        continue 'poll;
+                    }
                     DriverEventOut::OpenSocket => {
                         return Err(EventLoopError::UnexpectedDriverAction(action));
                     }
                 }
             }
 
-            if let Some(out) = self.protocol.poll_read() {
-                return Ok(Event::from_protocol_output(out));
+            if let Some(event) = self.next_event() {
+                return Ok(event);
             }
 
+            // Closing the socket may have scheduled a reconnect.
+            let SocketState::Active(stream) = &mut self.socket else {
+                continue 'poll;
+            };
             let timeout = self.protocol.poll_timeout();
-            tokio::select! {
-                read_result = self.stream.read(&mut self.read_buffer) => {
-                    match read_result {
-                        Ok(0) => self.protocol.handle_event(DriverEventIn::SocketClosed)?,
-                        Ok(n) => self.protocol.handle_read(IncomingData {
-                            bytes: self.read_buffer[..n].to_vec().into(),
-                            received_at: tokio::time::Instant::now(),
-                        })?,
-                        Err(e) => {
-                            _ = self.protocol.handle_event(DriverEventIn::SocketError);
-                            return Err(e.into());
-                        }
-                    }
-                }
-                command = self.command_rx.recv() => {
-                    if let Some(command) = command {
-                        self.protocol.handle_write(command)?;
-                    }
-                }
-                _ = maybe_sleep_until(timeout) => {
-                    self.protocol.handle_timeout(tokio::time::Instant::now())?;
+            let wake = tokio::select! {
+                read_result = stream.read(&mut self.read_buffer) => Wake::Read(read_result),
+                command = self.command_rx.recv() => Wake::Command(command),
+                signal = self.streams.signal(), if self.connected => Wake::Signal(signal),
+                _ = maybe_sleep_until(timeout) => Wake::Timeout,
+            };
+            match wake {
+                Wake::Read(Ok(0)) => {
+                    self.protocol.handle_event(DriverEventIn::SocketClosed)?;
+                    self.socket_closed();
                 }
+                Wake::Read(Ok(n)) => self.protocol.handle_read(IncomingData {
+                    bytes: self.read_buffer[..n].to_vec().into(),
+                    received_at: Instant::now(),
+                })?,
+                Wake::Read(Err(error)) => self.connection_lost(error)?,
+                Wake::Command(Some(command)) => self.handle_command(command),
+                Wake::Command(None) => {}
+                Wake::Signal(signal) => self.handle_signal(signal),
+                Wake::Timeout => self.protocol.handle_timeout(Instant::now())?,
             }
         }
     }
 }
 
-async fn maybe_sleep_until(deadline: Option<tokio::time::Instant>) {
+/// Opens a socket to `addr`, or to the server a redirect named instead.
+async fn open_socket(
+    addr: SocketAddr,
+    server_reference: Option<Utf8String>,
+) -> std::io::Result<TcpStream> {
+    match server_reference {
+        Some(server_reference) => crate::redirect::connect(&server_reference, addr.port()).await,
+        None => TcpStream::connect(addr).await,
+    }
+}
+
+async fn maybe_sleep_until(deadline: Option<Instant>) {
     if let Some(deadline) = deadline {
         tokio::time::sleep_until(deadline).await;
     } else {
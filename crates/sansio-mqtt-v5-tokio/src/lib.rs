@@ -1,17 +1,31 @@
 #![forbid(unsafe_code)]
 
+mod backoff;
 mod client;
 mod connect;
+mod dispatcher;
 mod error;
 mod event;
 mod event_loop;
+mod redirect;
+mod stream;
+mod tracker;
 
+pub use backoff::Backoff;
+pub use backoff::BackoffAlgorithm;
 pub use client::Client;
 pub use connect::ConnectOptions;
 pub use connect::connect;
+pub use dispatcher::Dispatcher;
 pub use error::ClientError;
 pub use error::ConnectError;
 pub use error::EventLoopError;
+pub use error::PublishError;
+pub use error::SubscribeError;
+pub use error::UnsubscribeError;
 pub use event::Event;
 pub use event_loop::EventLoop;
 pub use sansio_mqtt_v5_protocol::*;
+pub use stream::StreamOptions;
+pub use stream::SubscriptionStream;
+pub use tracker::PublishOutcome;
@@ -0,0 +1,49 @@
+//! Following server redirects
+//! ([§4.11](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901255)).
+
+use std::io;
+
+use tokio::net::TcpStream;
+
+/// Splits a Server Reference into `(host, port)` candidates.
+///
+/// The specification leaves the format open; this follows its examples: a
+/// space-separated list of `host[:port]`, with IPv6 literals in brackets.
+/// Entries without a port use `default_port`.
+fn candidates(server_reference: &str, default_port: u16) -> Vec<(&str, u16)> {
+    server_reference
+        .split_whitespace()
+        .filter_map(|entry| {
+            let (host, port) = match entry.strip_prefix('[') {
+                Some(rest) => {
+                    let (host, rest) = rest.split_once(']')?;
+                    (host, rest.strip_prefix(':'))
+                }
+                None => match entry.rsplit_once(':') {
+                    Some((host, port)) => (host, Some(port)),
+                    None => (entry, None),
+                },
+            };
+            let port = match port {
+                Some(port) => port.parse().ok()?,
+                None => default_port,
+            };
+            (!host.is_empty()).then_some((host, port))
+        })
+        .collect()
+}
+
+/// Connects to the first reachable server named by `server_reference`.
+pub(crate) async fn connect(server_reference: &str, default_port: u16) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("no usable server in reference {server_reference:?}"),
+    );
+    for (host, port) in candidates(server_reference, default_port) {
+        match TcpStream::connect((host, port)).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = err,
+        }
+    }
+    Err(last_error)
+}
@@ -0,0 +1,310 @@
+//! Per-subscription message streams.
+//!
+//! The event loop routes every received message through a [`Router`] of
+//! open streams before returning it as an [`Event`](crate::Event). QoS 1 and
+//! QoS 2 messages are acknowledged only once every stream they were routed
+//! to has yielded them, so a slow stream holds back the server through its
+//! Receive Maximum rather than growing its buffer.
+
+use core::pin::Pin;
+use core::task::Context;
+use core::task::Poll;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures_core::Stream;
+use sansio_mqtt_v5_protocol::BrokerMessage;
+use sansio_mqtt_v5_protocol::HandlerId;
+use sansio_mqtt_v5_protocol::InboundMessageId;
+use sansio_mqtt_v5_protocol::Qos;
+use sansio_mqtt_v5_protocol::RetainHandling;
+use sansio_mqtt_v5_protocol::Router;
+use sansio_mqtt_v5_protocol::UserWriteOut;
+use sansio_mqtt_v5_protocol::Utf8String;
+use sansio_mqtt_v5_types::SubAckReasonCode;
+use sansio_mqtt_v5_types::TopicFilter;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+
+use crate::SubscribeError;
+
+/// How [`Client::subscribe_stream`](crate::Client::subscribe_stream)
+/// subscribes, and how many QoS 0 messages its stream holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamOptions {
+    pub qos: Qos,
+    pub no_local: bool,
+    pub retain_as_published: bool,
+    pub retain_handling: RetainHandling,
+    pub user_properties: Vec<(Utf8String, Utf8String)>,
+    /// How many QoS 0 messages may wait in the stream. The server does not
+    /// hold them back, so further ones are dropped until the stream catches
+    /// up.
+    pub qos0_capacity: usize,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            qos: Qos::AtLeastOnce,
+            no_local: false,
+            retain_as_published: false,
+            retain_handling: RetainHandling::SendRetained,
+            user_properties: Vec::new(),
+            qos0_capacity: 64,
+        }
+    }
+}
+
+/// What streams ask of the event loop, from places that cannot wait.
+#[derive(Debug)]
+pub(crate) enum Signal {
+    /// Every stream a message was routed to has yielded it.
+    Ack { id: InboundMessageId, session: u64 },
+    /// A stream was dropped; `unsubscribe` once its SUBACK granted it.
+    Closed { id: HandlerId, unsubscribe: bool },
+}
+
+/// Acknowledges a message once the last stream holding it lets it go.
+#[derive(Debug)]
+struct AckOnDrop {
+    id: Option<InboundMessageId>,
+    session: u64,
+    signals: mpsc::UnboundedSender<Signal>,
+}
+
+impl Drop for AckOnDrop {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            _ = self.signals.send(Signal::Ack {
+                id,
+                session: self.session,
+            });
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Delivery {
+    message: BrokerMessage,
+    _ack: Option<Arc<AckOnDrop>>,
+}
+
+/// Messages delivered because of one subscription, returned by
+/// [`Client::subscribe_stream`](crate::Client::subscribe_stream).
+///
+/// Dropping it unsubscribes from its topic filter, unless another stream
+/// still uses the same filter. It ends once the event loop is dropped.
+#[derive(Debug)]
+pub struct SubscriptionStream {
+    id: HandlerId,
+    topic_filter: TopicFilter,
+    reason_code: SubAckReasonCode,
+    /// Whether the server granted the subscription.
+    subscribed: bool,
+    deliveries: mpsc::Receiver<Delivery>,
+    signals: mpsc::UnboundedSender<Signal>,
+}
+
+impl SubscriptionStream {
+    pub fn topic_filter(&self) -> &TopicFilter {
+        &self.topic_filter
+    }
+
+    /// The server's answer to the subscription, i.e. the QoS it granted.
+    pub fn reason_code(&self) -> SubAckReasonCode {
+        self.reason_code
+    }
+
+    pub(crate) fn granted(&mut self, reason_codes: &[SubAckReasonCode]) {
+        if let Some(reason_code) = reason_codes.first() {
+            self.reason_code = *reason_code;
+        }
+        self.subscribed = true;
+    }
+}
+
+impl Stream for SubscriptionStream {
+    type Item = BrokerMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<BrokerMessage>> {
+        self.deliveries
+            .poll_recv(cx)
+            .map(|delivery| delivery.map(|delivery| delivery.message))
+    }
+}
+
+impl Drop for SubscriptionStream {
+    fn drop(&mut self) {
+        _ = self.signals.send(Signal::Closed {
+            id: self.id,
+            unsubscribe: self.subscribed,
+        });
+    }
+}
+
+/// A stream asked for by the client, opened by the event loop when the
+/// SUBSCRIBE is sent so no message after SUBACK is missed.
+#[derive(Debug)]
+pub(crate) struct StreamRequest {
+    pub(crate) reply: oneshot::Sender<Result<SubscriptionStream, SubscribeError>>,
+    pub(crate) topic_filter: TopicFilter,
+    pub(crate) qos0_capacity: usize,
+    pub(crate) stream: Option<SubscriptionStream>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    deliveries: mpsc::Sender<Delivery>,
+    topic_filter: TopicFilter,
+}
+
+/// The open streams of an event loop.
+#[derive(Debug)]
+pub(crate) struct Streams {
+    router: Router<Entry>,
+    /// Open streams by topic filter.
+    filters: HashMap<TopicFilter, usize>,
+    signals_tx: mpsc::UnboundedSender<Signal>,
+    signals_rx: mpsc::UnboundedReceiver<Signal>,
+    /// The client's Receive Maximum: no more QoS 1 and QoS 2 messages than
+    /// this wait in a stream.
+    receive_maximum: usize,
+    /// Counts sessions the server did not resume, whose pending messages
+    /// must not be acknowledged.
+    session: u64,
+}
+
+impl Streams {
+    pub(crate) fn new(receive_maximum: usize) -> Self {
+        let (signals_tx, signals_rx) = mpsc::unbounded_channel();
+        Self {
+            router: Router::new(),
+            filters: HashMap::new(),
+            signals_tx,
+            signals_rx,
+            receive_maximum,
+            session: 0,
+        }
+    }
+
+    /// Opens the stream `request` asks for.
+    pub(crate) fn open(&mut self, request: &mut StreamRequest) {
+        let (deliveries, rx) = mpsc::channel((self.receive_maximum + request.qos0_capacity).max(1));
+        let id = self.router.add(
+            request.topic_filter.clone(),
+            Entry {
+                deliveries,
+                topic_filter: request.topic_filter.clone(),
+            },
+        );
+        *self
+            .filters
+            .entry(request.topic_filter.clone())
+            .or_default() += 1;
+        request.stream = Some(SubscriptionStream {
+            id,
+            topic_filter: request.topic_filter.clone(),
+            reason_code: SubAckReasonCode::SuccessQoS0,
+            subscribed: false,
+            deliveries: rx,
+            signals: self.signals_tx.clone(),
+        });
+    }
+
+    /// Forgets the pending messages of the previous session.
+    pub(crate) fn session_not_resumed(&mut self) {
+        self.session = self.session.wrapping_add(1);
+    }
+
+    /// Whether `session` is still the current one.
+    pub(crate) fn is_current(&self, session: u64) -> bool {
+        self.session == session
+    }
+
+    /// Hands a received message to the streams it matches, or returns it
+    /// when there are none, or when none of them had room for a QoS 1 or
+    /// QoS 2 one.
+    pub(crate) fn deliver(&mut self, out: UserWriteOut) -> Option<UserWriteOut> {
+        let (message, id) = match out {
+            UserWriteOut::ReceivedMessage(message) => (message, None),
+            UserWriteOut::ReceivedMessageWithRequiredAcknowledgement(id, message) => {
+                (message, Some(id))
+            }
+            out => return Some(out),
+        };
+        // Dropped streams are only forgotten once their signal is handled.
+        let matched: Vec<_> = self
+            .router
+            .matching(&message)
+            .into_iter()
+            .filter_map(|handler| self.router.get(handler))
+            .filter(|entry| !entry.deliveries.is_closed())
+            .collect();
+        if matched.is_empty() {
+            return Some(match id {
+                Some(id) => UserWriteOut::ReceivedMessageWithRequiredAcknowledgement(id, message),
+                None => UserWriteOut::ReceivedMessage(message),
+            });
+        }
+        let qos0 = id.is_none();
+        let ack = id.map(|id| {
+            Arc::new(AckOnDrop {
+                id: Some(id),
+                session: self.session,
+                signals: self.signals_tx.clone(),
+            })
+        });
+        let mut accepted = false;
+        for entry in matched {
+            // Leave room for the messages waiting to be acknowledged.
+            if qos0 && entry.deliveries.capacity() <= self.receive_maximum {
+                tracing::debug!(topic_filter = %entry.topic_filter, "stream full, dropping QoS 0 message");
+                continue;
+            }
+            let delivery = Delivery {
+                message: message.clone(),
+                _ack: ack.clone(),
+            };
+            match entry.deliveries.try_send(delivery) {
+                Ok(()) => accepted = true,
+                Err(_) => {
+                    tracing::warn!(topic_filter = %entry.topic_filter, "stream full, dropping message");
+                }
+            }
+        }
+        if accepted || qos0 {
+            return None;
+        }
+        // No stream holds the message, so it must not be acknowledged for
+        // them: the event loop returns it instead.
+        let id = ack
+            .and_then(Arc::into_inner)
+            .and_then(|mut ack| ack.id.take())?;
+        Some(UserWriteOut::ReceivedMessageWithRequiredAcknowledgement(
+            id, message,
+        ))
+    }
+
+    /// Waits for the next signal from a stream.
+    pub(crate) async fn signal(&mut self) -> Signal {
+        // `self` holds a sender, so the channel never closes.
+        self.signals_rx
+            .recv()
+            .await
+            .expect("streams keep their signal channel open")
+    }
+
+    /// Forgets the stream `id`. Returns its topic filter if no other stream
+    /// uses it.
+    pub(crate) fn close(&mut self, id: HandlerId) -> Option<TopicFilter> {
+        let entry = self.router.remove(id)?;
+        let count = self.filters.get_mut(&entry.topic_filter)?;
+        *count -= 1;
+        if *count > 0 {
+            return None;
+        }
+        self.filters.remove(&entry.topic_filter);
+        Some(entry.topic_filter)
+    }
+}
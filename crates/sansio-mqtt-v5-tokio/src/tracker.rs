@@ -0,0 +1,283 @@
+//! Answering the caller of a write once the server has answered it.
+
+use core::num::NonZero;
+use std::collections::HashMap;
+
+use sansio_mqtt_v5_protocol::Qos;
+use sansio_mqtt_v5_protocol::UserToken;
+use sansio_mqtt_v5_protocol::UserWriteIn;
+use sansio_mqtt_v5_protocol::UserWriteOut;
+use sansio_mqtt_v5_types::PubAckReasonCode;
+use sansio_mqtt_v5_types::PubCompReasonCode;
+use sansio_mqtt_v5_types::SubAckReasonCode;
+use sansio_mqtt_v5_types::UnsubAckReasonCode;
+use tokio::sync::oneshot;
+
+use crate::PublishError;
+use crate::SubscribeError;
+use crate::UnsubscribeError;
+use crate::stream::StreamRequest;
+
+/// How the server answered a publish sent with
+/// [`Client::publish_and_wait`](crate::Client::publish_and_wait).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishOutcome {
+    /// A QoS 0 publish was handed to the connection; there is no answer to
+    /// wait for.
+    Sent,
+    /// The server answered a QoS 1 publish with PUBACK.
+    Acknowledged(PubAckReasonCode),
+    /// The server answered a QoS 2 publish with PUBCOMP.
+    Completed(PubCompReasonCode),
+}
+
+/// Where the event loop sends the outcome of a write.
+#[derive(Debug)]
+pub(crate) enum Reply {
+    Publish(oneshot::Sender<Result<PublishOutcome, PublishError>>),
+    Subscribe(oneshot::Sender<Result<Vec<SubAckReasonCode>, SubscribeError>>),
+    Unsubscribe(oneshot::Sender<Result<Vec<UnsubAckReasonCode>, UnsubscribeError>>),
+    Stream(StreamRequest),
+}
+
+impl Reply {
+    /// Resolves a write there is nothing to wait for once it was handed to
+    /// the connection.
+    pub(crate) fn sent(self) {
+        if let Self::Publish(tx) = self {
+            _ = tx.send(Ok(PublishOutcome::Sent));
+        }
+    }
+
+    /// Fails a write the protocol refused.
+    pub(crate) fn failed(self, error: sansio_mqtt_v5_protocol::Error) {
+        match self {
+            Self::Publish(tx) => _ = tx.send(Err(PublishError::Protocol(error))),
+            Self::Subscribe(tx) => _ = tx.send(Err(SubscribeError::Protocol(error))),
+            Self::Unsubscribe(tx) => _ = tx.send(Err(UnsubscribeError::Protocol(error))),
+            Self::Stream(request) => _ = request.reply.send(Err(SubscribeError::Protocol(error))),
+        }
+    }
+
+    pub(crate) fn disconnected(self) {
+        match self {
+            Self::Publish(tx) => _ = tx.send(Err(PublishError::Disconnected)),
+            Self::Subscribe(tx) => _ = tx.send(Err(SubscribeError::Disconnected)),
+            Self::Unsubscribe(tx) => _ = tx.send(Err(UnsubscribeError::Disconnected)),
+            Self::Stream(request) => _ = request.reply.send(Err(SubscribeError::Disconnected)),
+        }
+    }
+
+    /// Whether the server answers the write in the connection it was sent
+    /// in, or not at all.
+    fn needs_connection(&self) -> bool {
+        matches!(
+            self,
+            Self::Subscribe(_) | Self::Unsubscribe(_) | Self::Stream(_)
+        )
+    }
+}
+
+/// [MQTT-3.9.3] [MQTT-3.11.3] Reason Codes below 0x80 report success.
+fn refused<R: Copy + Into<u8>>(reason_codes: &[R]) -> bool {
+    reason_codes.iter().any(|code| (*code).into() >= 0x80)
+}
+
+#[derive(Debug)]
+struct Tracked {
+    /// The token the caller gave the write, if any.
+    token: Option<UserToken>,
+    reply: Option<Reply>,
+}
+
+/// Follows writes from the event loop to the events answering them.
+///
+/// The event loop gives every write that carries a [`UserToken`] or waits
+/// for a [`Reply`] a token of its own, so tokens chosen by callers never
+/// clash with them, and hands the caller's token back in
+/// [`UserWriteOut::PacketIdAssigned`].
+#[derive(Debug, Default)]
+pub(crate) struct Tracker {
+    next_token: u64,
+    /// Writes not sent yet, by the token the event loop gave them.
+    unsent: HashMap<UserToken, Tracked>,
+    /// Writes waiting for their outcome, by Packet Identifier.
+    waiting: HashMap<NonZero<u16>, Reply>,
+}
+
+impl Tracker {
+    /// Prepares `write` for the protocol.
+    ///
+    /// Returns the reply to send once the protocol took the write, when
+    /// there is nothing else to wait for.
+    pub(crate) fn track(
+        &mut self,
+        write: UserWriteIn,
+        reply: Option<Reply>,
+    ) -> (UserWriteIn, Option<Reply>) {
+        match write {
+            // QoS 0 has no Packet Identifier, so nothing to follow.
+            UserWriteIn::PublishMessage(message) if message.qos == Qos::AtMostOnce => {
+                (UserWriteIn::PublishMessage(message), reply)
+            }
+            UserWriteIn::PublishMessage(mut message) => {
+                message.token = self.token_for(message.token, reply);
+                (UserWriteIn::PublishMessage(message), None)
+            }
+            UserWriteIn::Subscribe(mut options) => {
+                options.token = self.token_for(options.token, reply);
+                (UserWriteIn::Subscribe(options), None)
+            }
+            UserWriteIn::Unsubscribe(mut options) => {
+                options.token = self.token_for(options.token, reply);
+                (UserWriteIn::Unsubscribe(options), None)
+            }
+            write => (write, reply),
+        }
+    }
+
+    fn token_for(&mut self, token: Option<UserToken>, reply: Option<Reply>) -> Option<UserToken> {
+        if token.is_none() && reply.is_none() {
+            return None;
+        }
+        let own = UserToken(self.next_token);
+        self.next_token = self.next_token.wrapping_add(1);
+        self.unsent.insert(own, Tracked { token, reply });
+        Some(own)
+    }
+
+    /// Forgets the write given `token` by [`track`](Self::track), which the
+    /// protocol refused, and returns its reply.
+    pub(crate) fn refused(&mut self, token: Option<UserToken>) -> Option<Reply> {
+        self.unsent.remove(&token?)?.reply
+    }
+
+    /// Resolves the replies `out` answers and turns it back into what the
+    /// caller expects, or `None` when the caller expects nothing.
+    pub(crate) fn resolve(&mut self, out: UserWriteOut) -> Option<UserWriteOut> {
+        match out {
+            UserWriteOut::PacketIdAssigned { token, packet_id } => {
+                let Some(tracked) = self.unsent.remove(&token) else {
+                    return Some(UserWriteOut::PacketIdAssigned { token, packet_id });
+                };
+                if let Some(reply) = tracked.reply {
+                    self.waiting.insert(packet_id, reply);
+                }
+                tracked
+                    .token
+                    .map(|token| UserWriteOut::PacketIdAssigned { token, packet_id })
+            }
+            UserWriteOut::PendingPublishDropped(mut message) => {
+                if let Some(tracked) = message.token.and_then(|token| self.unsent.remove(&token)) {
+                    message.token = tracked.token;
+                    if let Some(Reply::Publish(tx)) = tracked.reply {
+                        _ = tx.send(Err(PublishError::Dropped));
+                    }
+                }
+                Some(UserWriteOut::PendingPublishDropped(message))
+            }
+            UserWriteOut::PublishAcknowledged(packet_id, reason_code) => {
+                self.publish_outcome(packet_id, Ok(PublishOutcome::Acknowledged(reason_code)));
+                Some(out)
+            }
+            UserWriteOut::PublishCompleted(packet_id, reason_code) => {
+                self.publish_outcome(packet_id, Ok(PublishOutcome::Completed(reason_code)));
+                Some(out)
+            }
+            UserWriteOut::PublishDroppedDueToSessionNotResumed(packet_id) => {
+                self.publish_outcome(packet_id, Err(PublishError::SessionNotResumed));
+                Some(out)
+            }
+            UserWriteOut::PublishDroppedDueToBrokerRejectedPubRec(packet_id, reason_code) => {
+                self.publish_outcome(packet_id, Err(PublishError::Rejected(reason_code)));
+                Some(out)
+            }
+            UserWriteOut::PublishExpired(packet_id) => {
+                self.publish_outcome(packet_id, Err(PublishError::Expired));
+                Some(out)
+            }
+            UserWriteOut::SubscribeAcknowledged {
+                packet_id,
+                ref reason_codes,
+                ref reason_string,
+                ..
+            } => {
+                let result = if refused(reason_codes) {
+                    Err(SubscribeError::Refused {
+                        reason_codes: reason_codes.clone(),
+                        reason_string: reason_string.clone(),
+                    })
+                } else {
+                    Ok(reason_codes)
+                };
+                match self.waiting.remove(&packet_id) {
+                    Some(Reply::Subscribe(tx)) => _ = tx.send(result.cloned()),
+                    Some(Reply::Stream(mut request)) => {
+                        let stream = result.and_then(|reason_codes| {
+                            let mut stream = request.stream.take().ok_or(SubscribeError::Closed)?;
+                            stream.granted(reason_codes);
+                            Ok(stream)
+                        });
+                        // A stream nobody waits for anymore unsubscribes when dropped.
+                        _ = request.reply.send(stream);
+                    }
+                    _ => {}
+                }
+                Some(out)
+            }
+            UserWriteOut::UnsubscribeAcknowledged {
+                packet_id,
+                ref reason_codes,
+                ref reason_string,
+                ..
+            } => {
+                if let Some(Reply::Unsubscribe(tx)) = self.waiting.remove(&packet_id) {
+                    _ = tx.send(if refused(reason_codes) {
+                        Err(UnsubscribeError::Refused {
+                            reason_codes: reason_codes.clone(),
+                            reason_string: reason_string.clone(),
+                        })
+                    } else {
+                        Ok(reason_codes.clone())
+                    });
+                }
+                Some(out)
+            }
+            out => Some(out),
+        }
+    }
+
+    fn publish_outcome(
+        &mut self,
+        packet_id: NonZero<u16>,
+        outcome: Result<PublishOutcome, PublishError>,
+    ) {
+        if let Some(Reply::Publish(tx)) = self.waiting.remove(&packet_id) {
+            _ = tx.send(outcome);
+        }
+    }
+
+    /// Fails the replies the server can no longer send once the connection
+    /// is lost: SUBACK and UNSUBACK, unlike publish acknowledgements, are
+    /// not resent in a resumed session.
+    pub(crate) fn connection_lost(&mut self) {
+        for tracked in self.unsent.values_mut() {
+            // The caller's token still has to be handed back.
+            if let Some(reply) = tracked.reply.take_if(|reply| reply.needs_connection()) {
+                reply.disconnected();
+            }
+        }
+        for (_, reply) in self.waiting.extract_if(|_, reply| reply.needs_connection()) {
+            reply.disconnected();
+        }
+    }
+
+    /// Fails every reply still waiting, once the connection is closed for
+    /// good.
+    pub(crate) fn disconnected(&mut self) {
+        let unsent = self.unsent.drain().filter_map(|(_, tracked)| tracked.reply);
+        for reply in unsent.chain(self.waiting.drain().map(|(_, reply)| reply)) {
+            reply.disconnected();
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use core::num::NonZero;
+use core::time::Duration;
+
+use encode::Encodable;
+use sansio_mqtt_v5_tokio::Client;
+use sansio_mqtt_v5_tokio::ConnectOptions;
+use sansio_mqtt_v5_tokio::Event;
+use sansio_mqtt_v5_tokio::EventLoop;
+use sansio_mqtt_v5_tokio::IncomingRejectReason;
+use sansio_mqtt_v5_tokio::connect;
+use sansio_mqtt_v5_types::ConnAck;
+use sansio_mqtt_v5_types::ConnAckKind;
+use sansio_mqtt_v5_types::ConnAckProperties;
+use sansio_mqtt_v5_types::ConnackReasonCode;
+use sansio_mqtt_v5_types::ControlPacket;
+use sansio_mqtt_v5_types::GuaranteedQoS;
+use sansio_mqtt_v5_types::Payload;
+use sansio_mqtt_v5_types::Publish;
+use sansio_mqtt_v5_types::PublishKind;
+use sansio_mqtt_v5_types::PublishProperties;
+use sansio_mqtt_v5_types::Qos;
+use sansio_mqtt_v5_types::Topic;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+
+fn encode(packet: &ControlPacket) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    packet.encode(&mut buffer).expect("packet should encode");
+    buffer
+}
+
+/// Reads one packet.
+async fn read_packet(stream: &mut TcpStream) -> Vec<u8> {
+    let mut buffer = [0; 1024];
+    let read = stream.read(&mut buffer).await.expect("read packet");
+    assert!(read > 0, "expected a packet");
+    buffer[..read].to_vec()
+}
+
+/// Accepts one client and accepts its CONNECT.
+async fn accept(listener: &TcpListener) -> TcpStream {
+    let (mut stream, _) = listener.accept().await.expect("accept");
+    assert_eq!(read_packet(&mut stream).await[0], 0x10, "expected CONNECT");
+    let connack = encode(&ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    }));
+    stream.write_all(&connack).await.expect("write CONNACK");
+    stream
+}
+
+fn publish(packet_id: u16, qos: GuaranteedQoS) -> Vec<u8> {
+    encode(&ControlPacket::Publish(Publish {
+        kind: PublishKind::Repetible {
+            packet_id: NonZero::new(packet_id).expect("non-zero packet id"),
+            qos,
+            dup: false,
+        },
+        retain: false,
+        payload: Payload::new(b"27.5".as_slice()),
+        topic: Topic::try_new("sensors/temperature").expect("valid topic"),
+        properties: PublishProperties::default(),
+    }))
+}
+
+async fn next_event(event_loop: &mut EventLoop) -> Event {
+    tokio::time::timeout(Duration::from_secs(5), event_loop.poll())
+        .await
+        .expect("event before timeout")
+        .expect("event loop still running")
+}
+
+async fn connected(options: ConnectOptions) -> (Client, EventLoop) {
+    let (client, mut event_loop) = connect(options).await.expect("connect");
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Connected(_)
+    ));
+    (client, event_loop)
+}
+
+#[tokio::test]
+async fn acknowledged_and_rejected_messages_are_answered() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        stream
+            .write_all(&publish(5, GuaranteedQoS::AtLeastOnce))
+            .await
+            .expect("write");
+        let puback = read_packet(&mut stream).await;
+        stream
+            .write_all(&publish(6, GuaranteedQoS::ExactlyOnce))
+            .await
+            .expect("write");
+        let pubrec = read_packet(&mut stream).await;
+        (puback, pubrec)
+    });
+
+    let (client, mut event_loop) = connected(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await;
+
+    let Event::MessageWithRequiredAcknowledgement(id, message) = next_event(&mut event_loop).await
+    else {
+        panic!("expected a message requiring acknowledgement");
+    };
+    assert_eq!(message.payload, Payload::new(b"27.5".as_slice()));
+    client.ack(id).await.expect("ack");
+
+    let Event::MessageWithRequiredAcknowledgement(id, _) = next_event(&mut event_loop).await else {
+        panic!("expected a message requiring acknowledgement");
+    };
+    client
+        .reject(id, IncomingRejectReason::NotAuthorized)
+        .await
+        .expect("reject");
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Disconnected(_)
+    ));
+
+    let (puback, pubrec) = broker.await.expect("broker");
+    assert_eq!(puback[0], 0x40, "expected PUBACK");
+    assert_eq!(puback[2..4], [0, 5]);
+    // A success Reason Code may be left out.
+    assert!(puback.get(4).is_none_or(|reason_code| *reason_code == 0x00));
+    assert_eq!(pubrec[0], 0x50, "expected PUBREC");
+    assert_eq!(pubrec[2..5], [0, 6, 0x87]);
+}
+
+#[tokio::test]
+async fn auto_ack_acknowledges_messages_on_arrival() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        stream
+            .write_all(&publish(7, GuaranteedQoS::ExactlyOnce))
+            .await
+            .expect("write");
+        read_packet(&mut stream).await
+    });
+
+    let (_client, mut event_loop) = connected(ConnectOptions {
+        addr,
+        auto_ack: true,
+        ..ConnectOptions::default()
+    })
+    .await;
+
+    let event = next_event(&mut event_loop).await;
+    assert!(
+        matches!(&event, Event::Message(message) if message.qos == Qos::ExactlyOnce),
+        "got {event:?}"
+    );
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Disconnected(_)
+    ));
+
+    let pubrec = broker.await.expect("broker");
+    assert_eq!(pubrec[0], 0x50, "expected PUBREC");
+    assert_eq!(pubrec[2..4], [0, 7]);
+}
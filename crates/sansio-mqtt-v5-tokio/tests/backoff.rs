@@ -0,0 +1,92 @@
+use core::time::Duration;
+
+use sansio_mqtt_v5_tokio::Backoff;
+use sansio_mqtt_v5_tokio::BackoffAlgorithm;
+
+fn backoff(algorithm: BackoffAlgorithm, min_secs: u64, max_secs: u64) -> Backoff {
+    Backoff {
+        algorithm,
+        range: Duration::from_secs(min_secs)..=Duration::from_secs(max_secs),
+        seed: 0,
+    }
+}
+
+fn delays(backoff: &Backoff, attempts: core::ops::Range<u32>, random: u64) -> Vec<u64> {
+    attempts
+        .map(|attempt| backoff.delay(attempt, random).as_secs())
+        .collect()
+}
+
+#[test]
+fn constant_waits_the_same_every_attempt() {
+    let backoff = Backoff::constant(Duration::from_secs(3));
+
+    assert_eq!(delays(&backoff, 0..3, 0), vec![3, 3, 3]);
+    assert_eq!(backoff.delay(u32::MAX, u64::MAX), Duration::from_secs(3));
+}
+
+#[test]
+fn linear_grows_by_slope_up_to_the_cap() {
+    let backoff = backoff(
+        BackoffAlgorithm::Linear {
+            slope: Duration::from_secs(10),
+        },
+        5,
+        30,
+    );
+
+    assert_eq!(delays(&backoff, 0..5, 0), vec![5, 15, 25, 30, 30]);
+    assert_eq!(backoff.delay(u32::MAX, 0), Duration::from_secs(30));
+}
+
+#[test]
+fn exponential_multiplies_by_factor_up_to_the_cap() {
+    let backoff = backoff(BackoffAlgorithm::Exponential { factor: 2.0 }, 1, 60);
+
+    assert_eq!(delays(&backoff, 0..8, 0), vec![1, 2, 4, 8, 16, 32, 60, 60]);
+    assert_eq!(backoff.delay(u32::MAX, 0), Duration::from_secs(60));
+}
+
+#[test]
+fn invalid_factors_stay_within_range() {
+    for factor in [-2.0, f64::NAN, f64::INFINITY] {
+        let backoff = backoff(BackoffAlgorithm::Exponential { factor }, 1, 60);
+        for attempt in [1, 2, 1000] {
+            let delay = backoff.delay(attempt, 0);
+            assert!(backoff.range.contains(&delay), "{factor}: {delay:?}");
+        }
+    }
+}
+
+#[test]
+fn jitter_is_uniform_over_the_range() {
+    let backoff = backoff(BackoffAlgorithm::Jitter, 5, 60);
+
+    assert_eq!(backoff.delay(0, 0), Duration::from_secs(5));
+    assert_eq!(backoff.delay(7, 55_000_000_000), Duration::from_secs(60));
+    assert_eq!(backoff.delay(7, 55_000_000_001), Duration::from_secs(5));
+    for random in [1, 12_345, u64::MAX / 3, u64::MAX] {
+        assert!(backoff.range.contains(&backoff.delay(0, random)));
+    }
+}
+
+#[test]
+fn jittered_exponential_adds_up_to_the_exponential_delay() {
+    let backoff = backoff(BackoffAlgorithm::JitteredExponential { factor: 2.0 }, 1, 60);
+
+    assert_eq!(backoff.delay(3, 0), Duration::from_secs(8));
+    assert_eq!(backoff.delay(3, 8_000_000_000), Duration::from_secs(16));
+    assert_eq!(backoff.delay(10, u64::MAX), Duration::from_secs(60));
+    for random in [1, 12_345, u64::MAX / 3, u64::MAX] {
+        for attempt in 0..20 {
+            assert!(backoff.range.contains(&backoff.delay(attempt, random)));
+        }
+    }
+}
+
+#[test]
+fn inverted_range_uses_its_start() {
+    let backoff = backoff(BackoffAlgorithm::Jitter, 10, 1);
+
+    assert_eq!(backoff.delay(0, u64::MAX), Duration::from_secs(10));
+}
@@ -0,0 +1,107 @@
+use core::num::NonZero;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use sansio_mqtt_v5_tokio::BrokerMessage;
+use sansio_mqtt_v5_tokio::Dispatcher;
+use sansio_mqtt_v5_tokio::Event;
+use sansio_mqtt_v5_tokio::Topic;
+use sansio_mqtt_v5_tokio::TopicFilter;
+
+fn message(topic: &str, subscription_identifiers: &[u64]) -> BrokerMessage {
+    BrokerMessage {
+        topic: Topic::try_new(topic.to_owned()).expect("valid topic"),
+        subscription_identifiers: subscription_identifiers
+            .iter()
+            .map(|id| NonZero::new(*id).expect("non-zero subscription identifier"))
+            .collect(),
+        ..BrokerMessage::default()
+    }
+}
+
+fn filter(value: &str) -> TopicFilter {
+    TopicFilter::try_from(value).expect("valid topic filter")
+}
+
+type Log = Arc<Mutex<Vec<(&'static str, String)>>>;
+
+fn recorder(
+    log: &Log,
+    name: &'static str,
+) -> impl FnMut(BrokerMessage) -> std::pin::Pin<Box<dyn Future<Output = ()> + Send>> + Send + 'static
+{
+    let log = Arc::clone(log);
+    move |message: BrokerMessage| {
+        let log = Arc::clone(&log);
+        Box::pin(async move {
+            tokio::task::yield_now().await;
+            log.lock()
+                .expect("log lock")
+                .push((name, message.topic.to_string()));
+        })
+    }
+}
+
+#[tokio::test]
+async fn overlapping_handlers_each_run_once_in_order() {
+    let log = Log::default();
+    let mut dispatcher = Dispatcher::new();
+    let sensors = dispatcher.route(filter("sensors/#"), recorder(&log, "sensors"));
+    assert!(dispatcher.add_route(sensors, filter("sensors/+/temperature")));
+    dispatcher.route(
+        NonZero::new(4).expect("non-zero subscription identifier"),
+        recorder(&log, "by-identifier"),
+    );
+    dispatcher.route(filter("alarms/#"), recorder(&log, "alarms"));
+
+    assert_eq!(
+        dispatcher
+            .dispatch(&message("sensors/kitchen/temperature", &[4]))
+            .await,
+        2
+    );
+    assert_eq!(dispatcher.dispatch(&message("alarms/smoke", &[])).await, 1);
+    assert_eq!(
+        *log.lock().expect("log lock"),
+        vec![
+            ("sensors", String::from("sensors/kitchen/temperature")),
+            ("by-identifier", String::from("sensors/kitchen/temperature")),
+            ("alarms", String::from("alarms/smoke")),
+        ]
+    );
+
+    assert!(dispatcher.remove(sensors));
+    assert!(!dispatcher.remove(sensors));
+    assert_eq!(
+        dispatcher
+            .dispatch(&message("sensors/kitchen/temperature", &[]))
+            .await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn handle_event_hands_back_what_it_did_not_dispatch() {
+    let log = Log::default();
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.route(filter("sensors/#"), recorder(&log, "sensors"));
+
+    assert!(
+        dispatcher
+            .handle_event(Event::Message(message("sensors/a", &[])))
+            .await
+            .is_none()
+    );
+    let unrouted = dispatcher
+        .handle_event(Event::Message(message("other", &[])))
+        .await;
+    assert!(
+        matches!(&unrouted, Some(Event::Message(message)) if message.topic.to_string() == "other"),
+        "expected the unrouted message back, got {unrouted:?}"
+    );
+    let other = dispatcher
+        .handle_event(Event::ReauthenticationRequired)
+        .await;
+    assert!(matches!(other, Some(Event::ReauthenticationRequired)));
+    assert_eq!(log.lock().expect("log lock").len(), 1);
+}
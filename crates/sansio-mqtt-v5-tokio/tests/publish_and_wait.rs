@@ -0,0 +1,328 @@
+use core::num::NonZero;
+use core::time::Duration;
+
+use encode::Encodable;
+use sansio_mqtt_v5_tokio::Client;
+use sansio_mqtt_v5_tokio::ClientMessage;
+use sansio_mqtt_v5_tokio::ConnectOptions;
+use sansio_mqtt_v5_tokio::Event;
+use sansio_mqtt_v5_tokio::EventLoop;
+use sansio_mqtt_v5_tokio::PublishError;
+use sansio_mqtt_v5_tokio::PublishOutcome;
+use sansio_mqtt_v5_tokio::UserToken;
+use sansio_mqtt_v5_tokio::connect;
+use sansio_mqtt_v5_types::ConnAck;
+use sansio_mqtt_v5_types::ConnAckKind;
+use sansio_mqtt_v5_types::ConnAckProperties;
+use sansio_mqtt_v5_types::ConnackReasonCode;
+use sansio_mqtt_v5_types::ControlPacket;
+use sansio_mqtt_v5_types::PubAck;
+use sansio_mqtt_v5_types::PubAckProperties;
+use sansio_mqtt_v5_types::PubAckReasonCode;
+use sansio_mqtt_v5_types::PubComp;
+use sansio_mqtt_v5_types::PubCompProperties;
+use sansio_mqtt_v5_types::PubCompReasonCode;
+use sansio_mqtt_v5_types::PubRec;
+use sansio_mqtt_v5_types::PubRecProperties;
+use sansio_mqtt_v5_types::PubRecReasonCode;
+use sansio_mqtt_v5_types::Qos;
+use sansio_mqtt_v5_types::Topic;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+fn encode(packet: &ControlPacket) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    packet.encode(&mut buffer).expect("packet should encode");
+    buffer
+}
+
+fn packet_id(value: u16) -> NonZero<u16> {
+    NonZero::new(value).expect("non-zero packet id")
+}
+
+/// Reads one packet and returns its first byte.
+async fn read_packet_type(stream: &mut TcpStream) -> u8 {
+    let mut buffer = [0; 1024];
+    let read = stream.read(&mut buffer).await.expect("read packet");
+    assert!(read > 0, "expected a packet");
+    buffer[0]
+}
+
+/// Accepts one client and accepts its CONNECT.
+async fn accept(listener: &TcpListener) -> TcpStream {
+    let (mut stream, _) = listener.accept().await.expect("accept");
+    assert_eq!(
+        read_packet_type(&mut stream).await,
+        0x10,
+        "expected CONNECT"
+    );
+    let connack = encode(&ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    }));
+    stream.write_all(&connack).await.expect("write CONNACK");
+    stream
+}
+
+/// Waits for the connection, then polls `event_loop` until it stops,
+/// collecting its events.
+async fn run(mut event_loop: EventLoop) -> JoinHandle<Vec<Event>> {
+    let event = event_loop.poll().await.expect("connected event");
+    assert!(matches!(event, Event::Connected(_)), "got {event:?}");
+    tokio::spawn(async move {
+        let mut events = Vec::new();
+        while let Ok(event) = event_loop.poll().await {
+            events.push(event);
+        }
+        events
+    })
+}
+
+fn message(qos: Qos) -> ClientMessage {
+    ClientMessage {
+        qos,
+        topic: Topic::try_new("sensors/temperature").expect("valid topic"),
+        ..ClientMessage::default()
+    }
+}
+
+async fn publish_and_wait(
+    client: &Client,
+    message: ClientMessage,
+) -> Result<PublishOutcome, PublishError> {
+    tokio::time::timeout(Duration::from_secs(5), client.publish_and_wait(message))
+        .await
+        .expect("outcome before timeout")
+}
+
+#[tokio::test]
+async fn publishes_resolve_with_the_server_answer() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0x32,
+            "expected QoS 1 PUBLISH"
+        );
+        let puback = ControlPacket::PubAck(PubAck {
+            packet_id: packet_id(1),
+            reason_code: PubAckReasonCode::Success,
+            properties: PubAckProperties::default(),
+        });
+        stream.write_all(&encode(&puback)).await.expect("write");
+
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0x34,
+            "expected QoS 2 PUBLISH"
+        );
+        let pubrec = ControlPacket::PubRec(PubRec {
+            packet_id: packet_id(2),
+            reason_code: PubRecReasonCode::Success,
+            properties: PubRecProperties::default(),
+        });
+        stream.write_all(&encode(&pubrec)).await.expect("write");
+        assert_eq!(read_packet_type(&mut stream).await, 0x62, "expected PUBREL");
+        let pubcomp = ControlPacket::PubComp(PubComp {
+            packet_id: packet_id(2),
+            reason_code: PubCompReasonCode::Success,
+            properties: PubCompProperties::default(),
+        });
+        stream.write_all(&encode(&pubcomp)).await.expect("write");
+
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0x34,
+            "expected QoS 2 PUBLISH"
+        );
+        let pubrec = ControlPacket::PubRec(PubRec {
+            packet_id: packet_id(3),
+            reason_code: PubRecReasonCode::QuotaExceeded,
+            properties: PubRecProperties::default(),
+        });
+        stream.write_all(&encode(&pubrec)).await.expect("write");
+
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0x30,
+            "expected QoS 0 PUBLISH"
+        );
+        stream
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let events = run(event_loop).await;
+
+    assert_eq!(
+        publish_and_wait(
+            &client,
+            ClientMessage {
+                token: Some(UserToken(7)),
+                ..message(Qos::AtLeastOnce)
+            }
+        )
+        .await,
+        Ok(PublishOutcome::Acknowledged(PubAckReasonCode::Success))
+    );
+    assert_eq!(
+        publish_and_wait(&client, message(Qos::ExactlyOnce)).await,
+        Ok(PublishOutcome::Completed(PubCompReasonCode::Success))
+    );
+    assert_eq!(
+        publish_and_wait(&client, message(Qos::ExactlyOnce)).await,
+        Err(PublishError::Rejected(PubRecReasonCode::QuotaExceeded))
+    );
+    assert_eq!(
+        publish_and_wait(&client, message(Qos::AtMostOnce)).await,
+        Ok(PublishOutcome::Sent)
+    );
+
+    let _stream = broker.await.expect("broker");
+    client.disconnect().await.expect("disconnect");
+    let events = events.await.expect("event loop");
+    // Only the caller's own token is reported.
+    let assigned: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::PacketIdAssigned { token, packet_id } => Some((*token, packet_id.get())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(assigned, vec![(UserToken(7), 1)]);
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, Event::PublishAcknowledged(..))),
+        "acknowledgements are still reported: {events:?}"
+    );
+}
+
+#[tokio::test]
+async fn closed_connection_fails_waiting_publishes() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0x32,
+            "expected QoS 1 PUBLISH"
+        );
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let _events = run(event_loop).await;
+
+    assert_eq!(
+        publish_and_wait(&client, message(Qos::AtLeastOnce)).await,
+        Err(PublishError::Disconnected)
+    );
+    broker.await.expect("broker");
+}
+
+#[tokio::test]
+async fn dropped_event_loop_fails_publishes() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    let broker = tokio::spawn(async move { accept(&listener).await });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let _stream = broker.await.expect("broker");
+    drop(event_loop);
+
+    assert_eq!(
+        publish_and_wait(&client, message(Qos::AtLeastOnce)).await,
+        Err(PublishError::Closed)
+    );
+}
+
+#[tokio::test]
+async fn refused_publishes_fail_without_stopping_the_event_loop() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0x32,
+            "expected QoS 1 PUBLISH"
+        );
+        let puback = ControlPacket::PubAck(PubAck {
+            packet_id: packet_id(1),
+            reason_code: PubAckReasonCode::Success,
+            properties: PubAckProperties::default(),
+        });
+        stream.write_all(&encode(&puback)).await.expect("write");
+        stream
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let _events = run(event_loop).await;
+
+    // The server allows no Topic Alias.
+    let aliased = ClientMessage {
+        topic_alias: NonZero::new(1),
+        ..message(Qos::AtLeastOnce)
+    };
+    assert!(matches!(
+        publish_and_wait(&client, aliased).await,
+        Err(PublishError::Protocol(_))
+    ));
+    assert_eq!(
+        publish_and_wait(&client, message(Qos::AtLeastOnce)).await,
+        Ok(PublishOutcome::Acknowledged(PubAckReasonCode::Success))
+    );
+
+    let _stream = broker.await.expect("broker");
+}
+
+#[tokio::test]
+async fn client_for_test_forwards_writes_without_an_event_loop() {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+    let client = Client::new_for_test(tx);
+
+    assert!(client.publish(message(Qos::AtMostOnce)).await.is_ok());
+    assert!(matches!(
+        rx.recv().await,
+        Some(sansio_mqtt_v5_protocol::UserWriteIn::PublishMessage(_))
+    ));
+
+    // Nothing answers the forwarded writes.
+    let waiting =
+        tokio::spawn(async move { publish_and_wait(&client, message(Qos::AtLeastOnce)).await });
+    assert!(rx.recv().await.is_some());
+    assert_eq!(
+        waiting.await.expect("publish task"),
+        Err(PublishError::Closed)
+    );
+}
@@ -0,0 +1,395 @@
+use core::num::NonZero;
+use core::time::Duration;
+
+use encode::Encodable;
+use sansio_mqtt_v5_tokio::Backoff;
+use sansio_mqtt_v5_tokio::BackoffAlgorithm;
+use sansio_mqtt_v5_tokio::ClientMessage;
+use sansio_mqtt_v5_tokio::ClientSettings;
+use sansio_mqtt_v5_tokio::ConnectOptions;
+use sansio_mqtt_v5_tokio::ConnectionOptions;
+use sansio_mqtt_v5_tokio::Event;
+use sansio_mqtt_v5_tokio::EventLoop;
+use sansio_mqtt_v5_tokio::EventLoopError;
+use sansio_mqtt_v5_tokio::PendingPublishOverflow;
+use sansio_mqtt_v5_tokio::connect;
+use sansio_mqtt_v5_types::ConnAck;
+use sansio_mqtt_v5_types::ConnAckKind;
+use sansio_mqtt_v5_types::ConnAckProperties;
+use sansio_mqtt_v5_types::ConnackReasonCode;
+use sansio_mqtt_v5_types::ControlPacket;
+use sansio_mqtt_v5_types::PubAck;
+use sansio_mqtt_v5_types::PubAckProperties;
+use sansio_mqtt_v5_types::PubAckReasonCode;
+use sansio_mqtt_v5_types::Qos;
+use sansio_mqtt_v5_types::Topic;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+
+fn encode(packet: &ControlPacket) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    packet.encode(&mut buffer).expect("packet should encode");
+    buffer
+}
+
+fn connack(kind: ConnAckKind) -> Vec<u8> {
+    encode(&ControlPacket::ConnAck(ConnAck {
+        kind,
+        properties: ConnAckProperties::default(),
+    }))
+}
+
+/// Accepts one client, reads its CONNECT and answers with `reply`.
+async fn answer_connect(listener: &TcpListener, reply: &[u8]) -> TcpStream {
+    let (mut stream, _) = listener.accept().await.expect("accept");
+    let mut buffer = [0; 1024];
+    let read = stream.read(&mut buffer).await.expect("read CONNECT");
+    assert!(read > 0, "expected a CONNECT packet");
+    stream.write_all(reply).await.expect("write CONNACK");
+    stream
+}
+
+fn accepted() -> Vec<u8> {
+    connack(ConnAckKind::Other {
+        reason_code: ConnackReasonCode::Success,
+    })
+}
+
+fn options(addr: std::net::SocketAddr, backoff: Option<Backoff>) -> ConnectOptions {
+    ConnectOptions {
+        addr,
+        backoff,
+        ..ConnectOptions::default()
+    }
+}
+
+async fn next_event(event_loop: &mut EventLoop) -> Event {
+    tokio::time::timeout(Duration::from_secs(5), event_loop.poll())
+        .await
+        .expect("event before timeout")
+        .expect("event")
+}
+
+#[tokio::test]
+async fn lost_connection_resumes_session_and_resends_publish() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut first = answer_connect(&listener, &accepted()).await;
+        let mut buffer = [0; 1024];
+        let read = first.read(&mut buffer).await.expect("read PUBLISH");
+        // PUBLISH, QoS 1, DUP clear.
+        assert_eq!(buffer[..read].first(), Some(&0x32));
+        drop(first);
+
+        let mut second =
+            answer_connect(&listener, &connack(ConnAckKind::ResumePreviousSession)).await;
+        let read = second.read(&mut buffer).await.expect("read PUBLISH");
+        // [MQTT-3.3.1-1] Resent with DUP set.
+        assert_eq!(buffer[..read].first(), Some(&0x3a));
+        let puback = encode(&ControlPacket::PubAck(PubAck {
+            packet_id: NonZero::new(1).expect("non-zero"),
+            reason_code: PubAckReasonCode::Success,
+            properties: PubAckProperties::default(),
+        }));
+        second.write_all(&puback).await.expect("write PUBACK");
+        second
+    });
+
+    let (client, mut event_loop) = connect(ConnectOptions {
+        connection: ConnectionOptions {
+            session_expiry_interval: Some(60),
+            ..ConnectionOptions::default()
+        },
+        ..options(addr, Some(Backoff::constant(Duration::from_millis(10))))
+    })
+    .await
+    .expect("connect");
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Connected(_)
+    ));
+
+    client
+        .publish(ClientMessage {
+            qos: Qos::AtLeastOnce,
+            topic: Topic::try_new("sensors/temperature").expect("valid topic"),
+            ..ClientMessage::default()
+        })
+        .await
+        .expect("publish");
+
+    let event = next_event(&mut event_loop).await;
+    assert!(
+        matches!(event, Event::Disconnected(None)),
+        "expected Disconnected, got {event:?}"
+    );
+    let event = next_event(&mut event_loop).await;
+    assert!(
+        matches!(
+            event,
+            Event::ReconnectScheduled { attempt: 0, delay } if delay == Duration::from_millis(10)
+        ),
+        "expected ReconnectScheduled, got {event:?}"
+    );
+    let event = next_event(&mut event_loop).await;
+    assert!(
+        matches!(event, Event::Connected(ref info) if info.session_present),
+        "expected a resumed session, got {event:?}"
+    );
+    let event = next_event(&mut event_loop).await;
+    assert!(
+        matches!(
+            event,
+            Event::PublishAcknowledged(_, PubAckReasonCode::Success)
+        ),
+        "expected PublishAcknowledged, got {event:?}"
+    );
+
+    let _stream = broker.await.expect("broker");
+}
+
+#[tokio::test]
+async fn failed_attempts_are_reported_and_backed_off() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    // Nobody listens on `addr` once the first client is in.
+    let broker = tokio::spawn(async move { answer_connect(&listener, &accepted()).await });
+
+    let backoff = Backoff {
+        algorithm: BackoffAlgorithm::Exponential { factor: 2.0 },
+        range: Duration::from_millis(5)..=Duration::from_secs(1),
+        seed: 0,
+    };
+    let (_client, mut event_loop) = connect(options(addr, Some(backoff)))
+        .await
+        .expect("connect");
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Connected(_)
+    ));
+    drop(broker.await.expect("broker"));
+
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Disconnected(None)
+    ));
+    for attempt in 0..2 {
+        let event = next_event(&mut event_loop).await;
+        assert!(
+            matches!(
+                event,
+                Event::ReconnectScheduled { attempt: a, delay }
+                    if a == attempt && delay == Duration::from_millis(5 << attempt)
+            ),
+            "expected ReconnectScheduled for attempt {attempt}, got {event:?}"
+        );
+        let event = next_event(&mut event_loop).await;
+        assert!(
+            matches!(event, Event::ReconnectFailed { attempt: a, .. } if a == attempt),
+            "expected ReconnectFailed for attempt {attempt}, got {event:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn closed_connection_without_backoff_ends_the_event_loop() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    let broker = tokio::spawn(async move { drop(answer_connect(&listener, &accepted()).await) });
+
+    let (_client, mut event_loop) = connect(options(addr, None)).await.expect("connect");
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Connected(_)
+    ));
+    broker.await.expect("broker");
+
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Disconnected(None)
+    ));
+    assert!(matches!(
+        event_loop.poll().await,
+        Err(EventLoopError::Disconnected)
+    ));
+}
+
+#[tokio::test]
+async fn client_disconnect_is_not_reconnected() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    let broker = tokio::spawn(async move { answer_connect(&listener, &accepted()).await });
+
+    let (client, mut event_loop) = connect(options(
+        addr,
+        Some(Backoff::constant(Duration::from_millis(10))),
+    ))
+    .await
+    .expect("connect");
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Connected(_)
+    ));
+    let _stream = broker.await.expect("broker");
+
+    client.disconnect().await.expect("disconnect");
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Disconnected(None)
+    ));
+    assert!(matches!(
+        event_loop.poll().await,
+        Err(EventLoopError::Disconnected)
+    ));
+}
+
+#[tokio::test]
+async fn commands_sent_while_reconnecting_wait_for_connack() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    let (connecting_tx, connecting_rx) = oneshot::channel();
+    let (published_tx, published_rx) = oneshot::channel();
+
+    let broker = tokio::spawn(async move {
+        drop(answer_connect(&listener, &accepted()).await);
+
+        let (mut second, _) = listener.accept().await.expect("accept");
+        let mut buffer = [0; 1024];
+        let read = second.read(&mut buffer).await.expect("read CONNECT");
+        assert_eq!(buffer[..read].first(), Some(&0x10));
+        connecting_tx.send(()).expect("test waiting");
+        published_rx.await.expect("publish sent");
+        // Let the event loop take the publish before CONNACK.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        second.write_all(&accepted()).await.expect("write CONNACK");
+        let read = second.read(&mut buffer).await.expect("read PUBLISH");
+        assert_eq!(buffer[..read].first(), Some(&0x32));
+        second
+    });
+
+    let (client, mut event_loop) = connect(options(
+        addr,
+        Some(Backoff::constant(Duration::from_millis(10))),
+    ))
+    .await
+    .expect("connect");
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Connected(_)
+    ));
+    tokio::spawn(async move {
+        connecting_rx.await.expect("broker connecting");
+        client
+            .publish(ClientMessage {
+                qos: Qos::AtLeastOnce,
+                topic: Topic::try_new("sensors/temperature").expect("valid topic"),
+                ..ClientMessage::default()
+            })
+            .await
+            .expect("publish");
+        published_tx.send(()).expect("broker waiting");
+    });
+
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Disconnected(None)
+    ));
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::ReconnectScheduled { .. }
+    ));
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Connected(_)
+    ));
+    tokio::spawn(async move { while event_loop.poll().await.is_ok() {} });
+
+    let _stream = broker.await.expect("broker");
+}
+
+/// Connects with a backoff long enough to stay offline for the test, and
+/// returns the event loop once it lost its connection.
+async fn offline(options: ConnectOptions) -> (sansio_mqtt_v5_tokio::Client, EventLoop) {
+    let listener = TcpListener::bind(options.addr).await.expect("bind");
+    let broker = tokio::spawn(async move { drop(answer_connect(&listener, &accepted()).await) });
+    let (client, mut event_loop) = connect(options).await.expect("connect");
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Connected(_)
+    ));
+    broker.await.expect("broker");
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::Disconnected(None)
+    ));
+    assert!(matches!(
+        next_event(&mut event_loop).await,
+        Event::ReconnectScheduled { .. }
+    ));
+    (client, event_loop)
+}
+
+async fn free_addr() -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    listener.local_addr().expect("local addr")
+}
+
+#[tokio::test]
+async fn disconnect_while_reconnecting_ends_the_event_loop() {
+    let (client, mut event_loop) = offline(options(
+        free_addr().await,
+        Some(Backoff::constant(Duration::from_secs(60))),
+    ))
+    .await;
+
+    client.disconnect().await.expect("disconnect");
+
+    let result = tokio::time::timeout(Duration::from_secs(5), event_loop.poll())
+        .await
+        .expect("event loop stopped before the reconnect");
+    assert!(matches!(result, Err(EventLoopError::Disconnected)));
+}
+
+#[tokio::test]
+async fn publishes_while_reconnecting_reach_the_pending_queue() {
+    let (client, mut event_loop) = offline(ConnectOptions {
+        protocol_config: ClientSettings {
+            queue_publishes_while_offline: true,
+            max_pending_publishes: 1,
+            pending_publish_overflow: PendingPublishOverflow::DropOldest,
+            ..ClientSettings::default()
+        },
+        ..options(
+            free_addr().await,
+            Some(Backoff::constant(Duration::from_secs(60))),
+        )
+    })
+    .await;
+
+    for topic in ["sensors/temperature", "sensors/humidity"] {
+        client
+            .publish(ClientMessage {
+                qos: Qos::AtLeastOnce,
+                topic: Topic::try_new(topic).expect("valid topic"),
+                ..ClientMessage::default()
+            })
+            .await
+            .expect("publish");
+    }
+
+    // The queue holds one publish, so the second one pushes out the first.
+    let event = next_event(&mut event_loop).await;
+    assert!(
+        matches!(
+            &event,
+            Event::PendingPublishDropped(message)
+                if message.topic == Topic::try_new("sensors/temperature").expect("valid topic")
+        ),
+        "expected PendingPublishDropped, got {event:?}"
+    );
+}
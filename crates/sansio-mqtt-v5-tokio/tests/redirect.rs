@@ -0,0 +1,236 @@
+use core::time::Duration;
+
+use encode::Encodable;
+use sansio_mqtt_v5_tokio::Backoff;
+use sansio_mqtt_v5_tokio::ConnectOptions;
+use sansio_mqtt_v5_tokio::Event;
+use sansio_mqtt_v5_tokio::EventLoop;
+use sansio_mqtt_v5_tokio::EventLoopError;
+use sansio_mqtt_v5_tokio::connect;
+use sansio_mqtt_v5_types::ConnAck;
+use sansio_mqtt_v5_types::ConnAckKind;
+use sansio_mqtt_v5_types::ConnAckProperties;
+use sansio_mqtt_v5_types::ConnackReasonCode;
+use sansio_mqtt_v5_types::ControlPacket;
+use sansio_mqtt_v5_types::Utf8String;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+
+fn connack(reason_code: ConnackReasonCode, server_reference: Option<String>) -> Vec<u8> {
+    let packet = ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other { reason_code },
+        properties: ConnAckProperties {
+            server_reference: server_reference
+                .map(|reference| Utf8String::try_from(reference).expect("valid utf8")),
+            ..ConnAckProperties::default()
+        },
+    });
+    let mut buffer = Vec::new();
+    packet.encode(&mut buffer).expect("packet should encode");
+    buffer
+}
+
+/// Accepts one client, reads its CONNECT and answers with `reply`.
+async fn answer_connect(listener: &TcpListener, reply: &[u8]) -> TcpStream {
+    let (mut stream, _) = listener.accept().await.expect("accept");
+    let mut buffer = [0; 1024];
+    let read = stream.read(&mut buffer).await.expect("read CONNECT");
+    assert!(read > 0, "expected a CONNECT packet");
+    stream.write_all(reply).await.expect("write CONNACK");
+    stream
+}
+
+fn options(addr: std::net::SocketAddr) -> ConnectOptions {
+    ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    }
+}
+
+async fn next_event(event_loop: &mut EventLoop) -> Event {
+    tokio::time::timeout(Duration::from_secs(5), event_loop.poll())
+        .await
+        .expect("event before timeout")
+        .expect("event")
+}
+
+#[tokio::test]
+async fn connack_redirect_reconnects_to_referenced_server() {
+    let moved = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let target = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let moved_addr = moved.local_addr().expect("local addr");
+    let reference = target.local_addr().expect("local addr").to_string();
+
+    let servers = tokio::spawn(async move {
+        drop(
+            answer_connect(
+                &moved,
+                &connack(ConnackReasonCode::UseAnotherServer, Some(reference)),
+            )
+            .await,
+        );
+        answer_connect(&target, &connack(ConnackReasonCode::Success, None)).await
+    });
+
+    let (_client, mut event_loop) = connect(options(moved_addr)).await.expect("connect");
+
+    let event = event_loop.poll().await.expect("refusal event");
+    assert!(
+        matches!(
+            event,
+            Event::ConnectionRefused {
+                reason_code: ConnackReasonCode::UseAnotherServer,
+                server_reference: Some(_),
+                ..
+            }
+        ),
+        "expected ConnectionRefused, got {event:?}"
+    );
+    let event = event_loop.poll().await.expect("connected event");
+    assert!(
+        matches!(event, Event::Connected(_)),
+        "expected Connected, got {event:?}"
+    );
+
+    let _target_stream = servers.await.expect("servers");
+}
+
+#[tokio::test]
+async fn redirect_loop_is_capped() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    // Keeps sending the client back to itself; the port comes from the
+    // original address.
+    tokio::spawn(async move {
+        loop {
+            drop(
+                answer_connect(
+                    &listener,
+                    &connack(ConnackReasonCode::ServerMoved, Some("127.0.0.1".into())),
+                )
+                .await,
+            );
+        }
+    });
+
+    let (_client, mut event_loop) = connect(ConnectOptions {
+        max_redirects: 2,
+        ..options(addr)
+    })
+    .await
+    .expect("connect");
+
+    let mut refusals = 0;
+    let error = loop {
+        match event_loop.poll().await {
+            Ok(Event::ConnectionRefused { .. }) => refusals += 1,
+            Ok(event) => panic!("unexpected event {event:?}"),
+            Err(error) => break error,
+        }
+    };
+    assert!(
+        matches!(error, EventLoopError::TooManyRedirects),
+        "expected TooManyRedirects, got {error:?}"
+    );
+    assert_eq!(refusals, 3);
+}
+
+#[tokio::test]
+async fn reconnects_go_to_the_server_redirected_to() {
+    let moved = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let target = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let moved_addr = moved.local_addr().expect("local addr");
+    let reference = target.local_addr().expect("local addr").to_string();
+
+    let servers = tokio::spawn(async move {
+        drop(
+            answer_connect(
+                &moved,
+                &connack(ConnackReasonCode::ServerMoved, Some(reference)),
+            )
+            .await,
+        );
+        // Nobody answers at the original address any more.
+        drop(moved);
+        let accepted = connack(ConnackReasonCode::Success, None);
+        drop(answer_connect(&target, &accepted).await);
+        answer_connect(&target, &accepted).await
+    });
+
+    let (_client, mut event_loop) = connect(ConnectOptions {
+        backoff: Some(Backoff::constant(Duration::from_millis(10))),
+        ..options(moved_addr)
+    })
+    .await
+    .expect("connect");
+
+    let mut events = Vec::new();
+    let mut connections = 0;
+    while connections < 2 {
+        let event = next_event(&mut event_loop).await;
+        if matches!(event, Event::Connected(_)) {
+            connections += 1;
+        }
+        events.push(event);
+    }
+    // Only the lost connection schedules a reconnect, not the redirect.
+    let scheduled: Vec<_> = events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| matches!(event, Event::ReconnectScheduled { .. }))
+        .map(|(index, _)| index)
+        .collect();
+    let first_connection = events
+        .iter()
+        .position(|event| matches!(event, Event::Connected(_)))
+        .expect("connected");
+    assert_eq!(scheduled.len(), 1, "got {events:?}");
+    assert!(scheduled[0] > first_connection, "got {events:?}");
+
+    let _target_stream = servers.await.expect("servers");
+}
+
+#[tokio::test]
+async fn unreachable_redirect_is_retried_with_the_backoff() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    let unreachable = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let reference = unreachable.local_addr().expect("local addr").to_string();
+    drop(unreachable);
+
+    let server = tokio::spawn(async move {
+        answer_connect(
+            &listener,
+            &connack(ConnackReasonCode::UseAnotherServer, Some(reference)),
+        )
+        .await
+    });
+
+    let (_client, mut event_loop) = connect(ConnectOptions {
+        backoff: Some(Backoff::constant(Duration::from_secs(60))),
+        ..options(addr)
+    })
+    .await
+    .expect("connect");
+
+    let event = next_event(&mut event_loop).await;
+    assert!(
+        matches!(event, Event::ConnectionRefused { .. }),
+        "got {event:?}"
+    );
+    let event = next_event(&mut event_loop).await;
+    assert!(
+        matches!(event, Event::ReconnectFailed { attempt: 0, .. }),
+        "got {event:?}"
+    );
+    let event = next_event(&mut event_loop).await;
+    assert!(
+        matches!(event, Event::ReconnectScheduled { attempt: 1, .. }),
+        "got {event:?}"
+    );
+
+    let _stream = server.await.expect("server");
+}
@@ -0,0 +1,318 @@
+use core::num::NonZero;
+use core::time::Duration;
+
+use encode::Encodable;
+use sansio_mqtt_v5_tokio::Backoff;
+use sansio_mqtt_v5_tokio::Client;
+use sansio_mqtt_v5_tokio::ConnectOptions;
+use sansio_mqtt_v5_tokio::Event;
+use sansio_mqtt_v5_tokio::EventLoop;
+use sansio_mqtt_v5_tokio::SubscribeError;
+use sansio_mqtt_v5_tokio::SubscribeOptions;
+use sansio_mqtt_v5_tokio::UnsubscribeError;
+use sansio_mqtt_v5_tokio::UnsubscribeOptions;
+use sansio_mqtt_v5_tokio::connect;
+use sansio_mqtt_v5_types::ConnAck;
+use sansio_mqtt_v5_types::ConnAckKind;
+use sansio_mqtt_v5_types::ConnAckProperties;
+use sansio_mqtt_v5_types::ConnackReasonCode;
+use sansio_mqtt_v5_types::ControlPacket;
+use sansio_mqtt_v5_types::Qos;
+use sansio_mqtt_v5_types::RetainHandling;
+use sansio_mqtt_v5_types::SubAck;
+use sansio_mqtt_v5_types::SubAckProperties;
+use sansio_mqtt_v5_types::SubAckReasonCode;
+use sansio_mqtt_v5_types::Subscription;
+use sansio_mqtt_v5_types::TopicFilter;
+use sansio_mqtt_v5_types::UnsubAck;
+use sansio_mqtt_v5_types::UnsubAckProperties;
+use sansio_mqtt_v5_types::UnsubAckReasonCode;
+use sansio_mqtt_v5_types::Utf8String;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+fn encode(packet: &ControlPacket) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    packet.encode(&mut buffer).expect("packet should encode");
+    buffer
+}
+
+fn packet_id(value: u16) -> NonZero<u16> {
+    NonZero::new(value).expect("non-zero packet id")
+}
+
+/// Reads one packet and returns its first byte.
+async fn read_packet_type(stream: &mut TcpStream) -> u8 {
+    let mut buffer = [0; 1024];
+    let read = stream.read(&mut buffer).await.expect("read packet");
+    assert!(read > 0, "expected a packet");
+    buffer[0]
+}
+
+/// Accepts one client and accepts its CONNECT.
+async fn accept(listener: &TcpListener) -> TcpStream {
+    let (mut stream, _) = listener.accept().await.expect("accept");
+    assert_eq!(
+        read_packet_type(&mut stream).await,
+        0x10,
+        "expected CONNECT"
+    );
+    let connack = encode(&ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    }));
+    stream.write_all(&connack).await.expect("write CONNACK");
+    stream
+}
+
+/// Waits for the connection, then polls `event_loop` until it stops.
+async fn run(mut event_loop: EventLoop) -> JoinHandle<()> {
+    let event = event_loop.poll().await.expect("connected event");
+    assert!(matches!(event, Event::Connected(_)), "got {event:?}");
+    tokio::spawn(async move { while event_loop.poll().await.is_ok() {} })
+}
+
+fn filter(value: &str) -> TopicFilter {
+    TopicFilter::try_from(value).expect("valid topic filter")
+}
+
+fn subscription(topic_filter: &str) -> Subscription {
+    Subscription {
+        topic_filter: filter(topic_filter),
+        qos: Qos::AtLeastOnce,
+        no_local: false,
+        retain_as_published: false,
+        retain_handling: RetainHandling::SendRetained,
+    }
+}
+
+fn subscribe(topic_filter: &str, extra: &[&str]) -> SubscribeOptions {
+    SubscribeOptions {
+        subscription: subscription(topic_filter),
+        extra_subscriptions: extra.iter().map(|filter| subscription(filter)).collect(),
+        subscription_identifier: None,
+        user_properties: Vec::new(),
+        token: None,
+    }
+}
+
+fn unsubscribe(topic_filter: &str) -> UnsubscribeOptions {
+    UnsubscribeOptions {
+        filter: filter(topic_filter),
+        extra_filters: Vec::new(),
+        user_properties: Vec::new(),
+        token: None,
+    }
+}
+
+async fn subscribe_and_wait(
+    client: &Client,
+    options: SubscribeOptions,
+) -> Result<Vec<SubAckReasonCode>, SubscribeError> {
+    tokio::time::timeout(Duration::from_secs(5), client.subscribe_and_wait(options))
+        .await
+        .expect("SUBACK before timeout")
+}
+
+async fn unsubscribe_and_wait(
+    client: &Client,
+    options: UnsubscribeOptions,
+) -> Result<Vec<UnsubAckReasonCode>, UnsubscribeError> {
+    tokio::time::timeout(Duration::from_secs(5), client.unsubscribe_and_wait(options))
+        .await
+        .expect("UNSUBACK before timeout")
+}
+
+#[tokio::test]
+async fn subscriptions_resolve_with_the_server_reason_codes() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0x82,
+            "expected SUBSCRIBE"
+        );
+        let suback = ControlPacket::SubAck(SubAck {
+            packet_id: packet_id(1),
+            properties: SubAckProperties::default(),
+            reason_codes: vec![SubAckReasonCode::SuccessQoS1, SubAckReasonCode::SuccessQoS0],
+        });
+        stream.write_all(&encode(&suback)).await.expect("write");
+
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0x82,
+            "expected SUBSCRIBE"
+        );
+        let suback = ControlPacket::SubAck(SubAck {
+            packet_id: packet_id(2),
+            properties: SubAckProperties {
+                reason_string: Some(Utf8String::try_from("no access").expect("valid utf8")),
+                ..SubAckProperties::default()
+            },
+            reason_codes: vec![
+                SubAckReasonCode::SuccessQoS1,
+                SubAckReasonCode::NotAuthorized,
+            ],
+        });
+        stream.write_all(&encode(&suback)).await.expect("write");
+
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0xa2,
+            "expected UNSUBSCRIBE"
+        );
+        let unsuback = ControlPacket::UnsubAck(UnsubAck {
+            packet_id: packet_id(3),
+            properties: UnsubAckProperties::default(),
+            reason_codes: vec![UnsubAckReasonCode::NoSubscriptionExisted],
+        });
+        stream.write_all(&encode(&unsuback)).await.expect("write");
+        stream
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let _events = run(event_loop).await;
+
+    assert_eq!(
+        subscribe_and_wait(&client, subscribe("sensors/+", &["alarms/#"])).await,
+        Ok(vec![
+            SubAckReasonCode::SuccessQoS1,
+            SubAckReasonCode::SuccessQoS0
+        ])
+    );
+    assert_eq!(
+        subscribe_and_wait(&client, subscribe("sensors/+", &["admin/#"])).await,
+        Err(SubscribeError::Refused {
+            reason_codes: vec![
+                SubAckReasonCode::SuccessQoS1,
+                SubAckReasonCode::NotAuthorized
+            ],
+            reason_string: Some(Utf8String::try_from("no access").expect("valid utf8")),
+        })
+    );
+    assert_eq!(
+        unsubscribe_and_wait(&client, unsubscribe("missing/#")).await,
+        Ok(vec![UnsubAckReasonCode::NoSubscriptionExisted])
+    );
+
+    let _stream = broker.await.expect("broker");
+}
+
+#[tokio::test]
+async fn closed_connection_fails_waiting_unsubscribes() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0xa2,
+            "expected UNSUBSCRIBE"
+        );
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let _events = run(event_loop).await;
+
+    assert_eq!(
+        unsubscribe_and_wait(&client, unsubscribe("sensors/+")).await,
+        Err(UnsubscribeError::Disconnected)
+    );
+    broker.await.expect("broker");
+}
+
+#[tokio::test]
+async fn lost_connection_fails_waiting_subscribes_before_reconnecting() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    // The SUBACK never comes, and nobody answers the reconnect.
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0x82,
+            "expected SUBSCRIBE"
+        );
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        backoff: Some(Backoff::constant(Duration::from_secs(60))),
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let events = run(event_loop).await;
+
+    assert_eq!(
+        subscribe_and_wait(&client, subscribe("sensors/+", &[])).await,
+        Err(SubscribeError::Disconnected)
+    );
+    broker.await.expect("broker");
+    events.abort();
+}
+
+#[tokio::test]
+async fn refused_subscribes_fail_without_stopping_the_event_loop() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        assert_eq!(
+            read_packet_type(&mut stream).await,
+            0x82,
+            "expected SUBSCRIBE"
+        );
+        let suback = ControlPacket::SubAck(SubAck {
+            packet_id: packet_id(1),
+            properties: SubAckProperties::default(),
+            reason_codes: vec![SubAckReasonCode::SuccessQoS1],
+        });
+        stream.write_all(&encode(&suback)).await.expect("write");
+        stream
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let _events = run(event_loop).await;
+
+    // [MQTT-3.8.3-4] No Local cannot be set on a Shared Subscription.
+    let mut shared = subscribe("$share/group/sensors/+", &[]);
+    shared.subscription.no_local = true;
+    assert!(matches!(
+        subscribe_and_wait(&client, shared).await,
+        Err(SubscribeError::Protocol(_))
+    ));
+    assert_eq!(
+        subscribe_and_wait(&client, subscribe("sensors/+", &[])).await,
+        Ok(vec![SubAckReasonCode::SuccessQoS1])
+    );
+
+    let _stream = broker.await.expect("broker");
+}
@@ -0,0 +1,415 @@
+use core::num::NonZero;
+use core::pin::Pin;
+use core::time::Duration;
+
+use encode::Encodable;
+use futures_core::Stream;
+use sansio_mqtt_v5_tokio::Backoff;
+use sansio_mqtt_v5_tokio::BrokerMessage;
+use sansio_mqtt_v5_tokio::ConnectOptions;
+use sansio_mqtt_v5_tokio::ConnectionOptions;
+use sansio_mqtt_v5_tokio::Event;
+use sansio_mqtt_v5_tokio::EventLoop;
+use sansio_mqtt_v5_tokio::StreamOptions;
+use sansio_mqtt_v5_tokio::SubscribeError;
+use sansio_mqtt_v5_tokio::SubscribeOptions;
+use sansio_mqtt_v5_tokio::SubscriptionStream;
+use sansio_mqtt_v5_tokio::connect;
+use sansio_mqtt_v5_types::ConnAck;
+use sansio_mqtt_v5_types::ConnAckKind;
+use sansio_mqtt_v5_types::ConnAckProperties;
+use sansio_mqtt_v5_types::ConnackReasonCode;
+use sansio_mqtt_v5_types::ControlPacket;
+use sansio_mqtt_v5_types::GuaranteedQoS;
+use sansio_mqtt_v5_types::Payload;
+use sansio_mqtt_v5_types::Publish;
+use sansio_mqtt_v5_types::PublishKind;
+use sansio_mqtt_v5_types::PublishProperties;
+use sansio_mqtt_v5_types::Qos;
+use sansio_mqtt_v5_types::RetainHandling;
+use sansio_mqtt_v5_types::SubAck;
+use sansio_mqtt_v5_types::SubAckProperties;
+use sansio_mqtt_v5_types::SubAckReasonCode;
+use sansio_mqtt_v5_types::Subscription;
+use sansio_mqtt_v5_types::Topic;
+use sansio_mqtt_v5_types::TopicFilter;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+fn encode(packet: &ControlPacket) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    packet.encode(&mut buffer).expect("packet should encode");
+    buffer
+}
+
+/// Reads one packet.
+async fn read_packet(stream: &mut TcpStream) -> Vec<u8> {
+    let mut buffer = [0; 1024];
+    let read = stream.read(&mut buffer).await.expect("read packet");
+    assert!(read > 0, "expected a packet");
+    buffer[..read].to_vec()
+}
+
+/// Accepts one client and accepts its CONNECT.
+async fn accept(listener: &TcpListener) -> TcpStream {
+    let (mut stream, _) = listener.accept().await.expect("accept");
+    assert_eq!(read_packet(&mut stream).await[0], 0x10, "expected CONNECT");
+    let connack = encode(&ControlPacket::ConnAck(ConnAck {
+        kind: ConnAckKind::Other {
+            reason_code: ConnackReasonCode::Success,
+        },
+        properties: ConnAckProperties::default(),
+    }));
+    stream.write_all(&connack).await.expect("write CONNACK");
+    stream
+}
+
+/// Reads the SUBSCRIBE and answers it with `reason_code`.
+async fn answer_subscribe(stream: &mut TcpStream, packet_id: u16, reason_code: SubAckReasonCode) {
+    assert_eq!(read_packet(stream).await[0], 0x82, "expected SUBSCRIBE");
+    let suback = encode(&ControlPacket::SubAck(SubAck {
+        packet_id: NonZero::new(packet_id).expect("non-zero packet id"),
+        properties: SubAckProperties::default(),
+        reason_codes: vec![reason_code],
+    }));
+    stream.write_all(&suback).await.expect("write SUBACK");
+}
+
+fn topic(value: &'static str) -> Topic {
+    Topic::try_new(value).expect("valid topic")
+}
+
+fn publish(name: &'static str, kind: PublishKind) -> Vec<u8> {
+    encode(&ControlPacket::Publish(Publish {
+        kind,
+        retain: false,
+        payload: Payload::new(b"27.5".as_slice()),
+        topic: topic(name),
+        properties: PublishProperties::default(),
+    }))
+}
+
+/// Waits for the connection, then polls `event_loop` until it stops,
+/// collecting its events.
+async fn run(mut event_loop: EventLoop) -> JoinHandle<Vec<Event>> {
+    let event = event_loop.poll().await.expect("connected event");
+    assert!(matches!(event, Event::Connected(_)), "got {event:?}");
+    tokio::spawn(async move {
+        let mut events = Vec::new();
+        while let Ok(event) = event_loop.poll().await {
+            events.push(event);
+        }
+        events
+    })
+}
+
+fn filter(value: &str) -> TopicFilter {
+    TopicFilter::try_from(value).expect("valid topic filter")
+}
+
+async fn next(stream: &mut SubscriptionStream) -> Option<BrokerMessage> {
+    let next = core::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx));
+    tokio::time::timeout(Duration::from_secs(5), next)
+        .await
+        .expect("message before timeout")
+}
+
+#[tokio::test]
+async fn streams_yield_their_messages_and_unsubscribe_when_dropped() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        answer_subscribe(&mut stream, 1, SubAckReasonCode::SuccessQoS0).await;
+        for topic in ["sensors/temperature", "alarms/fire"] {
+            stream
+                .write_all(&publish(topic, PublishKind::FireAndForget))
+                .await
+                .expect("write");
+        }
+        assert_eq!(
+            read_packet(&mut stream).await[0],
+            0xa2,
+            "expected UNSUBSCRIBE"
+        );
+        stream
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let events = run(event_loop).await;
+
+    let mut stream = client
+        .subscribe_stream(
+            filter("sensors/+"),
+            StreamOptions {
+                qos: Qos::AtMostOnce,
+                ..StreamOptions::default()
+            },
+        )
+        .await
+        .expect("subscribed");
+    assert_eq!(stream.reason_code(), SubAckReasonCode::SuccessQoS0);
+    let message = next(&mut stream).await.expect("a message");
+    assert_eq!(message.topic, topic("sensors/temperature"));
+    drop(stream);
+
+    let _stream = broker.await.expect("broker");
+    client.disconnect().await.expect("disconnect");
+    let topics: Vec<_> = events
+        .await
+        .expect("event loop")
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::Message(message) => Some(message.topic),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(topics, vec![topic("alarms/fire")]);
+}
+
+#[tokio::test]
+async fn messages_are_acknowledged_once_yielded() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    let (waited_tx, waited_rx) = oneshot::channel();
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        answer_subscribe(&mut stream, 1, SubAckReasonCode::SuccessQoS1).await;
+        let kind = PublishKind::Repetible {
+            packet_id: NonZero::new(9).expect("non-zero packet id"),
+            qos: GuaranteedQoS::AtLeastOnce,
+            dup: false,
+        };
+        stream
+            .write_all(&publish("sensors/temperature", kind))
+            .await
+            .expect("write");
+        let mut buffer = [0; 16];
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), stream.read(&mut buffer))
+                .await
+                .is_err(),
+            "nothing is acknowledged before the stream yields the message"
+        );
+        waited_tx.send(()).expect("test waiting");
+        let puback = read_packet(&mut stream).await;
+        (stream, puback)
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        // Streams acknowledge their own messages.
+        auto_ack: true,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let _events = run(event_loop).await;
+
+    let mut stream = client
+        .subscribe_stream(filter("sensors/#"), StreamOptions::default())
+        .await
+        .expect("subscribed");
+    waited_rx.await.expect("broker waited");
+    let message = next(&mut stream).await.expect("a message");
+    assert_eq!(message.qos, Qos::AtLeastOnce);
+
+    let (_stream, puback) = broker.await.expect("broker");
+    assert_eq!(puback[0], 0x40, "expected PUBACK");
+    assert_eq!(puback[2..4], [0, 9]);
+}
+
+#[tokio::test]
+async fn refused_streams_fail_and_leave_messages_to_the_event_loop() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        answer_subscribe(&mut stream, 1, SubAckReasonCode::NotAuthorized).await;
+        stream
+            .write_all(&publish("admin/users", PublishKind::FireAndForget))
+            .await
+            .expect("write");
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let events = run(event_loop).await;
+
+    let result = client
+        .subscribe_stream(filter("admin/#"), StreamOptions::default())
+        .await;
+    assert_eq!(
+        result.map(|stream| stream.reason_code()),
+        Err(SubscribeError::Refused {
+            reason_codes: vec![SubAckReasonCode::NotAuthorized],
+            reason_string: None,
+        })
+    );
+
+    broker.await.expect("broker");
+    let events = events.await.expect("event loop");
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, Event::Message(message) if message.topic == topic("admin/users"))),
+        "got {events:?}"
+    );
+}
+
+#[tokio::test]
+async fn messages_no_stream_has_room_for_are_not_acknowledged() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    let qos1 = || {
+        publish(
+            "sensors/temperature",
+            PublishKind::Repetible {
+                packet_id: NonZero::new(9).expect("non-zero packet id"),
+                qos: GuaranteedQoS::AtLeastOnce,
+                dup: false,
+            },
+        )
+    };
+
+    let broker = tokio::spawn(async move {
+        let mut first = accept(&listener).await;
+        answer_subscribe(&mut first, 1, SubAckReasonCode::SuccessQoS1).await;
+        first.write_all(&qos1()).await.expect("write");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        drop(first);
+
+        // The stream still holds the message of the previous session, so it
+        // has no room for this one.
+        let mut second = accept(&listener).await;
+        second.write_all(&qos1()).await.expect("write");
+        let mut buffer = [0; 16];
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), second.read(&mut buffer))
+                .await
+                .is_err(),
+            "nothing is acknowledged"
+        );
+        second
+    });
+
+    let (client, mut event_loop) = connect(ConnectOptions {
+        addr,
+        connection: ConnectionOptions {
+            receive_maximum: NonZero::new(1),
+            ..ConnectionOptions::default()
+        },
+        backoff: Some(Backoff::constant(Duration::from_millis(10))),
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let event = event_loop.poll().await.expect("connected event");
+    assert!(matches!(event, Event::Connected(_)), "got {event:?}");
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok(event) = event_loop.poll().await {
+            _ = events_tx.send(event);
+        }
+    });
+
+    let _stream = client
+        .subscribe_stream(
+            filter("sensors/#"),
+            StreamOptions {
+                qos0_capacity: 0,
+                ..StreamOptions::default()
+            },
+        )
+        .await
+        .expect("subscribed");
+
+    let _second = broker.await.expect("broker");
+    let mut returned = false;
+    while let Ok(event) = events_rx.try_recv() {
+        returned |= matches!(event, Event::MessageWithRequiredAcknowledgement(..));
+    }
+    assert!(returned, "the message is returned by the event loop");
+}
+
+#[tokio::test]
+async fn streams_without_an_identifier_match_messages_carrying_other_identifiers() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+
+    let broker = tokio::spawn(async move {
+        let mut stream = accept(&listener).await;
+        answer_subscribe(&mut stream, 1, SubAckReasonCode::SuccessQoS0).await;
+        answer_subscribe(&mut stream, 2, SubAckReasonCode::SuccessQoS0).await;
+        // One message for both overlapping subscriptions, reporting only
+        // the identifier of `sensors/#`.
+        let message = encode(&ControlPacket::Publish(Publish {
+            kind: PublishKind::FireAndForget,
+            retain: false,
+            payload: Payload::new(b"27.5".as_slice()),
+            topic: topic("sensors/temperature"),
+            properties: PublishProperties {
+                subscription_identifiers: vec![NonZero::new(1).expect("non-zero")],
+                ..PublishProperties::default()
+            },
+        }));
+        stream.write_all(&message).await.expect("write");
+        stream
+    });
+
+    let (client, event_loop) = connect(ConnectOptions {
+        addr,
+        ..ConnectOptions::default()
+    })
+    .await
+    .expect("connect");
+    let _events = run(event_loop).await;
+
+    client
+        .subscribe_and_wait(SubscribeOptions {
+            subscription: Subscription {
+                topic_filter: filter("sensors/#"),
+                qos: Qos::AtMostOnce,
+                no_local: false,
+                retain_as_published: false,
+                retain_handling: RetainHandling::SendRetained,
+            },
+            extra_subscriptions: Vec::new(),
+            subscription_identifier: NonZero::new(1),
+            user_properties: Vec::new(),
+            token: None,
+        })
+        .await
+        .expect("subscribed");
+    let mut stream = client
+        .subscribe_stream(
+            filter("sensors/+"),
+            StreamOptions {
+                qos: Qos::AtMostOnce,
+                ..StreamOptions::default()
+            },
+        )
+        .await
+        .expect("subscribed");
+
+    let message = next(&mut stream).await.expect("a message");
+    assert_eq!(message.topic, topic("sensors/temperature"));
+    let _stream = broker.await.expect("broker");
+}
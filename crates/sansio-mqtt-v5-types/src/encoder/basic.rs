@@ -111,6 +111,17 @@ where
     }
 }
 
+impl<E: ByteEncoder> Encodable<E> for TopicFilter
+where
+    EncodeError: From<E::Error>,
+{
+    type Error = EncodeError;
+
+    fn encode(&self, encoder: &mut E) -> Result<(), Self::Error> {
+        Utf8String::encode(self.as_ref(), encoder)
+    }
+}
+
 impl<E: ByteEncoder> Encodable<E> for FormatIndicator {
     type Error = E::Error;
 
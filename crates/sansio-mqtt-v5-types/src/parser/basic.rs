@@ -185,6 +185,34 @@ impl Topic {
     }
 }
 
+impl TopicFilter {
+    /// Returns a parser for a Topic Filter (UTF-8 string with well-placed
+    /// wildcards, [§4.7.1](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901242),
+    /// [MQTT-4.7.1-1], [MQTT-4.7.1-2], optionally a Shared Subscription,
+    /// [§4.8.2](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901250)).
+    #[inline]
+    pub fn parser<'input, Input, Error>(
+        parser_settings: &ParserSettings,
+    ) -> impl Parser<Input, Self, Error> + use<'input, Input, Error>
+    where
+        Input: StreamIsPartial + Stream<Token = u8, Slice = &'input [u8]> + BytesSource,
+        Error: ParserError<Input>
+            + FromExternalError<Input, Utf8Error>
+            + FromExternalError<Input, Utf8StringError>
+            + FromExternalError<Input, TopicFilterError>
+            + AddContext<Input, StrContext>,
+    {
+        combinator::trace(
+            type_name::<Self>(),
+            Utf8String::parser(parser_settings).try_map(Self::try_from),
+        )
+        .context(StrContext::Label(type_name::<Self>()))
+        .context(StrContext::Expected(StrContextValue::Description(
+            "a topic filter string",
+        )))
+    }
+}
+
 impl ControlPacketType {
     /// Parses the 4-bit Control Packet Type nibble from the Fixed
     /// Header ([§2.1.2](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901022)).
@@ -288,7 +316,8 @@ impl Subscription {
         ByteError: ParserError<ByteInput>
             + FromExternalError<ByteInput, Utf8Error>
             + AddContext<ByteInput, StrContext>
-            + FromExternalError<ByteInput, Utf8StringError>,
+            + FromExternalError<ByteInput, Utf8StringError>
+            + FromExternalError<ByteInput, TopicFilterError>,
         BitError: ParserError<Bits<ByteInput>>
             + ErrorConvert<ByteError>
             + FromExternalError<Bits<ByteInput>, InvalidQosError>
@@ -298,7 +327,7 @@ impl Subscription {
         combinator::trace(
             type_name::<Self>(),
             (
-                Utf8String::parser(parser_settings),
+                TopicFilter::parser(parser_settings),
                 bits::bits::<_, _, BitError, _, _>((
                     bits::pattern(0u8, 2usize),
                     RetainHandling::parser,
@@ -25,6 +25,7 @@ impl ControlPacket {
             + FromExternalError<ByteInput, InvalidReasonCode>
             + FromExternalError<ByteInput, Utf8StringError>
             + FromExternalError<ByteInput, TopicError>
+            + FromExternalError<ByteInput, TopicFilterError>
             + FromExternalError<ByteInput, TryFromIntError>
             + FromExternalError<ByteInput, BinaryDataError>
             + AddContext<ByteInput, StrContext>,
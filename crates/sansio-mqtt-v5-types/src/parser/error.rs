@@ -46,6 +46,13 @@ pub enum DecodeError {
     #[error(transparent)]
     Topic(#[from] TopicError),
 
+    /// A Topic Filter misplaced a wildcard character or was a malformed
+    /// Shared Subscription
+    /// ([§4.7.1](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901242),
+    /// [§4.8.2](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901250)).
+    #[error(transparent)]
+    TopicFilter(#[from] TopicFilterError),
+
     /// A property identifier was not one defined by
     /// [§2.2.2.2](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901029).
     #[error(transparent)]
@@ -125,7 +132,7 @@ impl DecodeError {
     /// spec rule was violated — the packet merely exceeded a ceiling
     /// this implementation chose.
     ///
-    /// For [`DecodeError::Topic`] and
+    /// For [`DecodeError::Topic`], [`DecodeError::TopicFilter`] and
     /// [`DecodeError::InvalidRetainHandling`] the spec does not label
     /// the receive-side failure explicitly; both values parse cleanly
     /// and are rejected for being disallowed, so by the §4.13
@@ -144,9 +151,10 @@ impl DecodeError {
             | Self::InvalidReasonCode(_)
             | Self::InvalidControlPacketType(_) => DisconnectReasonCode::MalformedPacket,
             // Parsed, but the value is not allowed by the protocol.
-            Self::Topic(_) | Self::InvalidRetainHandling(_) | Self::OutOfRange(_) => {
-                DisconnectReasonCode::ProtocolError
-            }
+            Self::Topic(_)
+            | Self::TopicFilter(_)
+            | Self::InvalidRetainHandling(_)
+            | Self::OutOfRange(_) => DisconnectReasonCode::ProtocolError,
             Self::Properties(PropertiesError::UnsupportedProperty(_)) => {
                 DisconnectReasonCode::MalformedPacket
             }
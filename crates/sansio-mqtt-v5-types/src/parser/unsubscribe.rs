@@ -45,6 +45,7 @@ impl Unsubscribe {
             + FromExternalError<ByteInput, UnknownFormatIndicatorError>
             + FromExternalError<ByteInput, Utf8StringError>
             + FromExternalError<ByteInput, TopicError>
+            + FromExternalError<ByteInput, TopicFilterError>
             + FromExternalError<ByteInput, TryFromIntError>
             + FromExternalError<ByteInput, BinaryDataError>
             + AddContext<ByteInput, StrContext>,
@@ -59,13 +60,13 @@ impl Unsubscribe {
                     "topics",
                     combinator::repeat_till(
                         1..=parser_settings.max_subscriptions_len as usize,
-                        Utf8String::parser(parser_settings),
+                        TopicFilter::parser(parser_settings),
                         combinator::eof,
                     ),
                 ),
             )
                 .map(move |(packet_id, properties, (topics, _))| {
-                    let mut topics: Vec<Utf8String> = topics;
+                    let mut topics: Vec<TopicFilter> = topics;
                     let filter = topics
                         .drain(..1)
                         .next()
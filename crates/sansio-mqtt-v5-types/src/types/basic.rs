@@ -91,6 +91,33 @@ pub struct TopicError;
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct Topic(Utf8String);
 
+/// Error returned when constructing a [`TopicFilter`] from a value that
+/// misplaces a wildcard character, is not a well-formed Shared
+/// Subscription, is empty, or is not a valid [`Utf8String`].
+///
+/// See [§4.7 — Topic Names and Topic Filters](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901241)
+/// and [§4.8.2 — Shared Subscriptions](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901250).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("invalid MQTT topic filter")]
+pub struct TopicFilterError;
+
+/// MQTT v5.0 Topic Filter
+/// ([§4.7 — Topic Names and Topic Filters](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901241)).
+///
+/// A [`Utf8String`] carried by `SUBSCRIBE` and `UNSUBSCRIBE` that may
+/// contain wildcards. Invariants enforced by the constructors:
+///
+/// * It is at least one character long ([MQTT-4.7.3-1]).
+/// * The multi-level wildcard `#` is the last character and occupies a whole
+///   level ([MQTT-4.7.1-1]).
+/// * The single-level wildcard `+` occupies a whole level ([MQTT-4.7.1-2]).
+/// * A filter starting with `$share/` is a Shared Subscription
+///   `$share/{ShareName}/{filter}`: the ShareName is at least one character
+///   long, contains none of `/`, `+` or `#`, and is followed by a `/` and a
+///   non-empty Topic Filter ([MQTT-4.8.2-1], [MQTT-4.8.2-2]).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TopicFilter(Utf8String);
+
 impl Payload {
     /// Constructs a [`Payload`] from any value convertible into
     /// [`bytes::Bytes`].
@@ -270,6 +297,128 @@ impl Topic {
     }
 }
 
+impl TopicFilter {
+    const SHARE_PREFIX: &'static str = "$share/";
+
+    /// Constructs a [`TopicFilter`] from any value convertible into
+    /// [`bytes::Bytes`].
+    ///
+    /// Returns an error when the filter is not a valid MQTT UTF-8 string,
+    /// misplaces a wildcard, or is a malformed Shared Subscription.
+    #[inline]
+    pub fn try_new(value: impl Into<bytes::Bytes>) -> Result<Self, TopicFilterError> {
+        let utf8 = Utf8String::try_new(value).map_err(|_| TopicFilterError)?;
+        Self::try_from(utf8)
+    }
+
+    /// Constructs a [`TopicFilter`] from any value convertible into
+    /// [`bytes::Bytes`].
+    ///
+    /// Panics with `"TopicFilter::new received an invalid MQTT topic filter"`
+    /// when validation fails.
+    #[inline]
+    pub fn new(value: impl Into<bytes::Bytes>) -> Self {
+        Self::try_new(value).expect("TopicFilter::new received an invalid MQTT topic filter")
+    }
+
+    /// Creates a [`TopicFilter`] without validating wildcard placement or
+    /// Shared Subscription syntax.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure the inner value satisfies every invariant listed
+    /// on [`TopicFilter`].
+    #[inline]
+    pub unsafe fn new_unchecked(value: Utf8String) -> Self {
+        Self(value)
+    }
+
+    /// Consumes the [`TopicFilter`] and returns the underlying
+    /// [`Utf8String`].
+    #[inline]
+    pub fn into_inner(self) -> Utf8String {
+        self.0
+    }
+
+    /// Returns `true` for a Shared Subscription (`$share/{ShareName}/{filter}`,
+    /// [§4.8.2](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901250)).
+    #[inline]
+    pub fn is_shared(&self) -> bool {
+        self.share_name().is_some()
+    }
+
+    /// Returns the ShareName of a Shared Subscription, or `None` for a
+    /// non-shared filter.
+    #[inline]
+    pub fn share_name(&self) -> Option<&str> {
+        Self::split_shared(&self.0).map(|(share_name, _)| share_name)
+    }
+
+    /// Returns the Topic Filter matched against Topic Names: the part after
+    /// the ShareName for a Shared Subscription, or the whole filter
+    /// otherwise.
+    #[inline]
+    pub fn filter(&self) -> &str {
+        let value: &str = &self.0;
+        Self::split_shared(value).map_or(value, |(_, filter)| filter)
+    }
+
+    /// Returns `true` for a filter containing `+` or `#`.
+    #[inline]
+    pub fn has_wildcards(&self) -> bool {
+        self.filter().contains(['+', '#'])
+    }
+
+    /// Returns `true` when `topic` matches this filter
+    /// ([§4.7](https://docs.oasis-open.org/mqtt/mqtt/v5.0/mqtt-v5.0.html#_Toc3901241)).
+    ///
+    /// A Shared Subscription matches through its inner
+    /// [`filter`](Self::filter). A filter starting with a wildcard does not
+    /// match Topic Names starting with `$` ([MQTT-4.7.2-1]); `#` also
+    /// matches the parent level, so `sport/#` matches `sport`.
+    pub fn matches(&self, topic: &Topic) -> bool {
+        let filter = self.filter();
+        let topic: &str = topic;
+        if topic.starts_with('$') && filter.starts_with(['+', '#']) {
+            return false;
+        }
+
+        let mut topic_levels = topic.split('/');
+        for level in filter.split('/') {
+            if level == "#" {
+                return true;
+            }
+            match topic_levels.next() {
+                Some(topic_level) if level == "+" || level == topic_level => {}
+                _ => return false,
+            }
+        }
+        topic_levels.next().is_none()
+    }
+
+    /// Splits `$share/{ShareName}/{filter}` into its ShareName and filter.
+    #[inline]
+    fn split_shared(value: &str) -> Option<(&str, &str)> {
+        value.strip_prefix(Self::SHARE_PREFIX)?.split_once('/')
+    }
+
+    /// Checks [MQTT-4.7.3-1], [MQTT-4.7.1-1] and [MQTT-4.7.1-2].
+    fn is_valid_filter(filter: &str) -> bool {
+        let mut levels = filter.split('/').peekable();
+        while let Some(level) = levels.next() {
+            let valid = match level {
+                "+" => true,
+                "#" => levels.peek().is_none(),
+                level => !level.contains(['+', '#']),
+            };
+            if !valid {
+                return false;
+            }
+        }
+        !filter.is_empty()
+    }
+}
+
 impl core::convert::AsRef<bytes::Bytes> for Payload {
     #[inline]
     fn as_ref(&self) -> &bytes::Bytes {
@@ -411,6 +560,74 @@ impl core::fmt::Display for Topic {
     }
 }
 
+impl core::convert::AsRef<Utf8String> for TopicFilter {
+    #[inline]
+    fn as_ref(&self) -> &Utf8String {
+        &self.0
+    }
+}
+
+impl core::ops::Deref for TopicFilter {
+    type Target = Utf8String;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl core::borrow::Borrow<Utf8String> for TopicFilter {
+    #[inline]
+    fn borrow(&self) -> &Utf8String {
+        &self.0
+    }
+}
+
+impl TryFrom<Utf8String> for TopicFilter {
+    type Error = TopicFilterError;
+
+    #[inline]
+    fn try_from(value: Utf8String) -> Result<Self, Self::Error> {
+        let value_str: &str = &value;
+        let valid = match value_str.strip_prefix(Self::SHARE_PREFIX) {
+            // [MQTT-4.8.2-1] [MQTT-4.8.2-2]
+            Some(shared) => shared.split_once('/').is_some_and(|(share_name, filter)| {
+                !share_name.is_empty()
+                    && !share_name.contains(['+', '#'])
+                    && Self::is_valid_filter(filter)
+            }),
+            None => Self::is_valid_filter(value_str),
+        };
+        if !valid {
+            return Err(TopicFilterError);
+        }
+        // SAFETY: Invariants have been checked above.
+        Ok(unsafe { Self::new_unchecked(value) })
+    }
+}
+
+impl<'a> TryFrom<&'a str> for TopicFilter {
+    type Error = TopicFilterError;
+
+    #[inline]
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        Self::try_new(bytes::Bytes::copy_from_slice(value.as_bytes()))
+    }
+}
+
+impl From<TopicFilter> for Utf8String {
+    #[inline]
+    fn from(value: TopicFilter) -> Self {
+        value.0
+    }
+}
+
+impl core::fmt::Display for TopicFilter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Display::fmt(self.as_ref(), f)
+    }
+}
+
 impl core::convert::AsRef<str> for Utf8String {
     #[inline]
     fn as_ref(&self) -> &str {
@@ -814,6 +1031,10 @@ mod marker_trait_guards {
     impl MustNotImplementOrd for TopicError {}
     impl MustNotImplementPartialOrd for TopicError {}
 
+    impl MustNotImplementHash for TopicFilterError {}
+    impl MustNotImplementOrd for TopicFilterError {}
+    impl MustNotImplementPartialOrd for TopicFilterError {}
+
     fn assert_not_hash<T: MustNotImplementHash>() {}
     fn assert_not_ord<T: MustNotImplementOrd>() {}
     fn assert_not_partial_ord<T: MustNotImplementPartialOrd>() {}
@@ -835,5 +1056,9 @@ mod marker_trait_guards {
         assert_not_hash::<TopicError>();
         assert_not_ord::<TopicError>();
         assert_not_partial_ord::<TopicError>();
+
+        assert_not_hash::<TopicFilterError>();
+        assert_not_ord::<TopicFilterError>();
+        assert_not_partial_ord::<TopicFilterError>();
     }
 }
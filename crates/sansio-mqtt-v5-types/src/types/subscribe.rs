@@ -51,7 +51,7 @@ pub struct Subscription {
     /// Topic Filter, which may contain wildcards; MUST be a valid
     /// UTF-8 string ([MQTT-3.8.3-1], [MQTT-4.7.1-1],
     /// [MQTT-4.7.1-2]).
-    pub topic_filter: Utf8String,
+    pub topic_filter: TopicFilter,
     /// Maximum QoS the subscriber wishes to receive.
     pub qos: Qos,
     /// No Local option: if true, Application Messages MUST NOT be
@@ -22,9 +22,9 @@ pub struct Unsubscribe {
     pub properties: UnsubscribeProperties,
     /// First Topic Filter to unsubscribe from
     /// ([MQTT-3.10.3-1]).
-    pub filter: Utf8String,
+    pub filter: TopicFilter,
     /// Additional Topic Filters beyond the first, in wire order.
-    pub extra_filters: Vec<Utf8String>,
+    pub extra_filters: Vec<TopicFilter>,
 }
 
 /// Fixed-header flags byte for `UNSUBSCRIBE`
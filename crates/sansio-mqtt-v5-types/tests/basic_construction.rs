@@ -3,6 +3,8 @@ use rstest::rstest;
 use sansio_mqtt_v5_types::BinaryData;
 use sansio_mqtt_v5_types::Payload;
 use sansio_mqtt_v5_types::Topic;
+use sansio_mqtt_v5_types::TopicFilter;
+use sansio_mqtt_v5_types::TopicFilterError;
 use sansio_mqtt_v5_types::Utf8String;
 use sansio_mqtt_v5_types::Utf8StringError;
 
@@ -129,3 +131,92 @@ fn topic_boundary_lengths() {
     assert_eq!(topic_inner.as_bytes().len(), u16::MAX as usize);
     assert!(Topic::try_new(max_plus_one).is_err());
 }
+
+#[rstest]
+#[case("sport/tennis/player1", true)]
+#[case("sport/#", true)]
+#[case("#", true)]
+#[case("+", true)]
+#[case("+/tennis/#", true)]
+#[case("sport/+/player1", true)]
+#[case("/", true)]
+#[case("$SYS/#", true)]
+#[case("$share/group/sport/#", true)]
+#[case("$share/group/+", true)]
+#[case("$share", true)]
+#[case("", false)]
+#[case("a/#/b", false)]
+#[case("sport/tennis#", false)]
+#[case("a+/b", false)]
+#[case("sport+", false)]
+#[case("$share/", false)]
+#[case("$share/group", false)]
+#[case("$share//sport", false)]
+#[case("$share/group/", false)]
+#[case("$share/gr+up/sport", false)]
+#[case("$share/gr#up/sport", false)]
+#[case("$share/group/a/#/b", false)]
+fn topic_filter_try_from_validates_wildcards_and_share_syntax(
+    #[case] input: &str,
+    #[case] is_valid: bool,
+) {
+    let result = TopicFilter::try_from(input);
+    assert_eq!(result.is_ok(), is_valid, "{input:?}");
+    if !is_valid {
+        assert_eq!(result, Err(TopicFilterError));
+    }
+}
+
+#[test]
+#[should_panic]
+fn topic_filter_new_panics_on_invalid_input() {
+    let _ = TopicFilter::new("home/#/kitchen");
+}
+
+#[rstest]
+#[case("$share/consumers/sport/#", Some("consumers"), "sport/#")]
+#[case("$share/consumers//", Some("consumers"), "/")]
+#[case("sport/#", None, "sport/#")]
+#[case("$shared/sport", None, "$shared/sport")]
+fn topic_filter_exposes_share_name_and_inner_filter(
+    #[case] input: &str,
+    #[case] share_name: Option<&str>,
+    #[case] filter: &str,
+) {
+    let topic_filter = TopicFilter::try_from(input).expect("valid topic filter");
+    assert_eq!(topic_filter.share_name(), share_name);
+    assert_eq!(topic_filter.is_shared(), share_name.is_some());
+    assert_eq!(topic_filter.filter(), filter);
+}
+
+#[rstest]
+#[case("sport/tennis/player1/#", "sport/tennis/player1", true)]
+#[case("sport/tennis/player1/#", "sport/tennis/player1/ranking", true)]
+#[case("sport/tennis/player1/#", "sport/tennis/player1/score/wimbledon", true)]
+#[case("sport/#", "sport", true)]
+#[case("#", "sport/tennis", true)]
+#[case("sport/tennis/+", "sport/tennis/player1", true)]
+#[case("sport/tennis/+", "sport/tennis/player1/ranking", false)]
+#[case("sport/+", "sport", false)]
+#[case("sport/+", "sport/", true)]
+#[case("+/+", "/finance", true)]
+#[case("/+", "/finance", true)]
+#[case("+", "/finance", false)]
+#[case("sport/tennis", "sport/tennis", true)]
+#[case("sport/tennis", "sport/Tennis", false)]
+#[case("sport", "sport/tennis", false)]
+#[case("#", "$SYS/monitor/Clients", false)]
+#[case("+/monitor/Clients", "$SYS/monitor/Clients", false)]
+#[case("$SYS/#", "$SYS/monitor/Clients", true)]
+#[case("$SYS/monitor/+", "$SYS/monitor/Clients", true)]
+#[case("$share/group/sport/+", "sport/tennis", true)]
+#[case("$share/group/#", "$SYS/monitor", false)]
+fn topic_filter_matches_topic_names(
+    #[case] filter: &str,
+    #[case] topic: &str,
+    #[case] matches: bool,
+) {
+    let filter = TopicFilter::try_from(filter).expect("valid topic filter");
+    let topic = Topic::try_new(Bytes::copy_from_slice(topic.as_bytes())).expect("valid topic");
+    assert_eq!(filter.matches(&topic), matches, "{filter} vs {topic}");
+}
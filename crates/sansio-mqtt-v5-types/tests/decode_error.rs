@@ -7,9 +7,9 @@
 //! mapping: with an opaque error type every case below would collapse
 //! to a single indistinguishable failure.
 //!
-//! Every fixture is a minimal CONNECT — protocol name `MQTT`, version
-//! 5, Clean Start, keep alive 60, empty Client Identifier — differing
-//! only in its property section.
+//! Unless stated otherwise, every fixture is a minimal CONNECT —
+//! protocol name `MQTT`, version 5, Clean Start, keep alive 60, empty
+//! Client Identifier — differing only in its property section.
 
 use rstest::rstest;
 use sansio_mqtt_v5_types::*;
@@ -144,6 +144,41 @@ fn reserved_packet_type_is_malformed() {
     );
 }
 
+/// A SUBSCRIBE (Packet Identifier 1, no properties, one QoS 0
+/// subscription) whose Topic Filter parses cleanly as a string but
+/// breaks §4.7.1 or §4.8.2 is a Protocol Error.
+#[rstest]
+#[case::misplaced_multi_level_wildcard(b"a/#/b")]
+#[case::partial_level_single_level_wildcard(b"a+/b")]
+#[case::empty_share_name(b"$share/")]
+fn invalid_subscribe_topic_filter_is_protocol_error(#[case] filter: &[u8]) {
+    let mut bytes = vec![0x82, 6 + filter.len() as u8, 0, 1, 0, 0, filter.len() as u8];
+    bytes.extend_from_slice(filter);
+    bytes.push(0);
+
+    let error = decode_err(&bytes);
+
+    assert!(
+        matches!(error, DecodeError::TopicFilter(TopicFilterError)),
+        "expected TopicFilter, got {error:?}"
+    );
+    assert_eq!(
+        error.disconnect_reason_code(),
+        DisconnectReasonCode::ProtocolError
+    );
+}
+
+/// UNSUBSCRIBE carries bare Topic Filters and applies the same rules.
+#[test]
+fn invalid_unsubscribe_topic_filter_is_protocol_error() {
+    let error = decode_err(&[0xA2, 9, 0, 1, 0, 0, 4, b'a', b'/', b'#', b'b']);
+
+    assert!(
+        matches!(error, DecodeError::TopicFilter(TopicFilterError)),
+        "expected TopicFilter, got {error:?}"
+    );
+}
+
 /// A caller-configured ceiling is not a spec violation, so it maps to
 /// Implementation specific error rather than Malformed / Protocol Error.
 ///
@@ -443,7 +443,7 @@ fn subscribe_with_all_valid_properties_roundtrip() {
     let subscribe = Subscribe {
         packet_id: NonZero::new(1).unwrap(),
         subscription: Subscription {
-            topic_filter: Utf8String::try_from("test/+").unwrap(),
+            topic_filter: TopicFilter::try_from("test/+").unwrap(),
             qos: Qos::AtLeastOnce,
             no_local: false,
             retain_as_published: false,
@@ -549,7 +549,7 @@ fn unsubscribe_with_all_valid_properties_roundtrip() {
                 Utf8String::try_from("value").unwrap(),
             )],
         },
-        filter: Utf8String::try_from("test/+").unwrap(),
+        filter: TopicFilter::try_from("test/+").unwrap(),
         extra_filters: Vec::new(),
     };
 
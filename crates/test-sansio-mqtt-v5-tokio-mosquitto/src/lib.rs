@@ -138,7 +138,7 @@ pub fn authenticated_connect_options(
 pub fn sub(topic: &str) -> SubscribeOptions {
     SubscribeOptions {
         subscription: Subscription {
-            topic_filter: Utf8String::try_from(topic).expect("valid topic filter"),
+            topic_filter: TopicFilter::try_from(topic).expect("valid topic filter"),
             qos: Qos::AtMostOnce,
             no_local: false,
             retain_as_published: false,
@@ -147,6 +147,7 @@ pub fn sub(topic: &str) -> SubscribeOptions {
         extra_subscriptions: vec![],
         subscription_identifier: None,
         user_properties: vec![],
+        token: None,
     }
 }
 
@@ -154,7 +155,7 @@ pub fn sub(topic: &str) -> SubscribeOptions {
 pub fn sub_qos1(topic: &str) -> SubscribeOptions {
     SubscribeOptions {
         subscription: Subscription {
-            topic_filter: Utf8String::try_from(topic).expect("valid topic filter"),
+            topic_filter: TopicFilter::try_from(topic).expect("valid topic filter"),
             qos: Qos::AtLeastOnce,
             no_local: false,
             retain_as_published: false,
@@ -163,6 +164,7 @@ pub fn sub_qos1(topic: &str) -> SubscribeOptions {
         extra_subscriptions: vec![],
         subscription_identifier: None,
         user_properties: vec![],
+        token: None,
     }
 }
 
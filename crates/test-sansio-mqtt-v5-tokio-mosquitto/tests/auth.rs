@@ -15,7 +15,7 @@ async fn valid_credentials_accepted() {
 
     let event = event_loop.poll().await.expect("poll");
     assert!(
-        matches!(event, Event::Connected),
+        matches!(event, Event::Connected(_)),
         "expected Connected, got {event:?}"
     );
 }
@@ -35,8 +35,15 @@ async fn invalid_credentials_rejected() {
 
     let result = event_loop.poll().await;
     assert!(
-        result.is_err(),
-        "expected Err for wrong password, got {result:?}"
+        matches!(
+            result,
+            Err(EventLoopError::Protocol(Error::ConnectionRefused {
+                reason_code: ConnackReasonCode::BadUserNameOrPassword
+                    | ConnackReasonCode::NotAuthorized,
+                ..
+            }))
+        ),
+        "expected ConnectionRefused for wrong password, got {result:?}"
     );
 }
 
@@ -50,7 +57,10 @@ async fn anonymous_rejected() {
 
     let result = event_loop.poll().await;
     assert!(
-        result.is_err(),
-        "expected Err for anonymous connection, got {result:?}"
+        matches!(
+            result,
+            Err(EventLoopError::Protocol(Error::ConnectionRefused { .. }))
+        ),
+        "expected ConnectionRefused for anonymous connection, got {result:?}"
     );
 }
@@ -12,7 +12,7 @@ async fn connect_and_disconnect() {
 
     let event = event_loop.poll().await.expect("poll");
     assert!(
-        matches!(event, Event::Connected),
+        matches!(event, Event::Connected(_)),
         "expected Connected, got {event:?}"
     );
 
@@ -35,7 +35,7 @@ async fn publish_qos0() {
         .expect("connect subscriber");
     assert!(matches!(
         el_sub.poll().await.expect("subscriber connected"),
-        Event::Connected
+        Event::Connected(_)
     ));
     client_sub
         .subscribe(sub("test/qos0"))
@@ -52,7 +52,7 @@ async fn publish_qos0() {
         .expect("connect publisher");
     assert!(matches!(
         el_pub.poll().await.expect("publisher connected"),
-        Event::Connected
+        Event::Connected(_)
     ));
     client_pub
         .publish(msg("test/qos0", b"hello-qos0", Qos::AtMostOnce))
@@ -83,7 +83,7 @@ async fn publish_qos1() {
         .expect("connect subscriber");
     assert!(matches!(
         el_sub.poll().await.expect("subscriber connected"),
-        Event::Connected
+        Event::Connected(_)
     ));
     client_sub
         .subscribe(sub("test/qos1"))
@@ -98,7 +98,7 @@ async fn publish_qos1() {
         .expect("connect publisher");
     assert!(matches!(
         el_pub.poll().await.expect("publisher connected"),
-        Event::Connected
+        Event::Connected(_)
     ));
     client_pub
         .publish(msg("test/qos1", b"hello-qos1", Qos::AtLeastOnce))
@@ -131,7 +131,7 @@ async fn publish_qos2() {
         .expect("connect subscriber");
     assert!(matches!(
         el_sub.poll().await.expect("subscriber connected"),
-        Event::Connected
+        Event::Connected(_)
     ));
     client_sub
         .subscribe(sub("test/qos2"))
@@ -146,7 +146,7 @@ async fn publish_qos2() {
         .expect("connect publisher");
     assert!(matches!(
         el_pub.poll().await.expect("publisher connected"),
-        Event::Connected
+        Event::Connected(_)
     ));
     client_pub
         .publish(msg("test/qos2", b"hello-qos2", Qos::ExactlyOnce))
@@ -187,7 +187,7 @@ async fn keep_alive_maintains_connection() {
     let (_client, mut event_loop) = connect(opts).await.expect("connect");
     assert!(matches!(
         event_loop.poll().await.expect("connected"),
-        Event::Connected
+        Event::Connected(_)
     ));
 
     // Poll for 7 seconds. PINGREQ/PINGRESP are transparent — no Event emitted.
@@ -13,7 +13,7 @@ async fn clean_start_clears_session() {
     assert!(
         matches!(
             el1.poll().await.expect("connected phase 1"),
-            Event::Connected
+            Event::Connected(_)
         ),
         "expected Connected"
     );
@@ -31,7 +31,7 @@ async fn clean_start_clears_session() {
         .expect("connect publisher");
     assert!(matches!(
         el_pub.poll().await.expect("publisher connected"),
-        Event::Connected
+        Event::Connected(_)
     ));
     client_pub
         .publish(msg("test/clean-start", b"queued", Qos::AtLeastOnce))
@@ -49,7 +49,7 @@ async fn clean_start_clears_session() {
     assert!(
         matches!(
             el3.poll().await.expect("connected phase 3"),
-            Event::Connected
+            Event::Connected(info) if info.session_present
         ),
         "expected Connected after clean reconnect"
     );
@@ -73,7 +73,7 @@ async fn session_resumption() {
     assert!(
         matches!(
             el1.poll().await.expect("connected phase 1"),
-            Event::Connected
+            Event::Connected(_)
         ),
         "expected Connected"
     );
@@ -91,7 +91,7 @@ async fn session_resumption() {
         .expect("connect publisher");
     assert!(matches!(
         el_pub.poll().await.expect("publisher connected"),
-        Event::Connected
+        Event::Connected(_)
     ));
     client_pub
         .publish(msg("test/resume", b"queued-for-resume", Qos::AtLeastOnce))
@@ -109,7 +109,7 @@ async fn session_resumption() {
     assert!(
         matches!(
             el3.poll().await.expect("connected phase 3"),
-            Event::Connected
+            Event::Connected(info) if info.session_present
         ),
         "expected Connected on resume"
     );
@@ -135,7 +135,7 @@ async fn will_message_delivered() {
         .expect("connect subscriber");
     assert!(matches!(
         el_sub.poll().await.expect("subscriber connected"),
-        Event::Connected
+        Event::Connected(_)
     ));
     client_sub
         .subscribe(sub("will/gone"))
@@ -170,7 +170,7 @@ async fn will_message_delivered() {
     assert!(
         matches!(
             el_will.poll().await.expect("will sender connected"),
-            Event::Connected
+            Event::Connected(_)
         ),
         "expected will sender Connected"
     );